@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+
+use ch32_can_rs::{
+    hal, nb, Can, CanFifo, CanFilter, CanFrame, CanMode, ExtendedId, Id, Instance, StandardId,
+    TxQueue,
+};
+use hal::interrupt::typelevel::Interrupt;
+use hal::peripherals::CAN1;
+use hal::println;
+use panic_halt as _;
+
+const BITRATE: u32 = 500_000;
+
+/// Echoes waiting for a free transmit mailbox. Frames received while it is full are
+/// not echoed.
+const ECHO_QUEUE_LEN: usize = 16;
+
+/// Frames echoed between two progress lines
+const REPORT_EVERY: u32 = 1000;
+
+#[qingke_rt::entry]
+fn main() -> ! {
+    hal::debug::SDIPrint::enable();
+    let mut config = hal::Config::default();
+    config.rcc = hal::rcc::Config::SYSCLK_FREQ_96MHZ_HSI;
+    let p = hal::init(config);
+
+    let can = Can::new(
+        p.CAN1,
+        p.PB8,
+        p.PB9,
+        CanFifo::Fifo0,
+        CanMode::Normal,
+        BITRATE,
+    );
+    can.add_filter(CanFilter::accept_all());
+
+    // From here on, frames are read by the receive interrupt into a software queue
+    can.enable_interrupts();
+    unsafe {
+        <CAN1 as Instance>::TxInterrupt::enable();
+        <CAN1 as Instance>::Rx0Interrupt::enable();
+        <CAN1 as Instance>::Rx1Interrupt::enable();
+        <CAN1 as Instance>::SceInterrupt::enable();
+    }
+
+    println!("Echoing every frame with its identifier plus one.");
+
+    let mut echoes = TxQueue::<ECHO_QUEUE_LEN>::new();
+    let (mut echoed, mut dropped) = (0u32, 0u32);
+    loop {
+        match can.receive() {
+            Ok(frame) => {
+                let echo = CanFrame::new(echo_id(*frame.id()), frame.data()).unwrap();
+                if echoes.push(echo).is_err() {
+                    dropped += 1;
+                }
+            }
+            Err(nb::Error::Other(error)) => println!("Bus error: {error}"),
+            Err(nb::Error::WouldBlock) => {}
+        }
+
+        // Mailboxes free up in the background, refill them on every pass
+        let loaded = can.transmit_queued(&mut echoes) as u32;
+        if loaded > 0 && (echoed + loaded) / REPORT_EVERY != echoed / REPORT_EVERY {
+            println!("Echoed {} frames, {dropped} dropped.", echoed + loaded);
+        }
+        echoed += loaded;
+    }
+}
+
+/// Identifier plus one, wrapping around within the same format.
+fn echo_id(id: Id) -> Id {
+    match id {
+        Id::Standard(id) => {
+            let raw = (id.as_raw() + 1) & StandardId::MAX.as_raw();
+            StandardId::new(raw).unwrap().into()
+        }
+        Id::Extended(id) => {
+            let raw = (id.as_raw() + 1) & ExtendedId::MAX.as_raw();
+            ExtendedId::new(raw).unwrap().into()
+        }
+    }
+}
+
+// All four vectors share the same priority, so they never preempt each other
+
+#[qingke_rt::interrupt]
+fn USB_HP_CAN1_TX() {
+    unsafe { Can::<CAN1>::on_interrupt() };
+}
+
+#[qingke_rt::interrupt]
+fn USB_LP_CAN1_RX0() {
+    unsafe { Can::<CAN1>::on_interrupt() };
+}
+
+#[qingke_rt::interrupt]
+fn CAN1_RX1() {
+    unsafe { Can::<CAN1>::on_interrupt() };
+}
+
+#[qingke_rt::interrupt]
+fn CAN1_SCE() {
+    unsafe { Can::<CAN1>::on_interrupt() };
+}
@@ -0,0 +1,163 @@
+#![no_std]
+#![no_main]
+
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use ch32_can_rs::{
+    hal, Can, CanFifo, CanFilter, CanMode, Gateway, GatewayAction, GatewayDirection, GatewayRule,
+    Id, Instance, StandardId,
+};
+use critical_section::Mutex;
+use hal::interrupt::typelevel::Interrupt;
+use hal::peripherals::{CAN1, CAN2};
+use hal::usart::UartTx;
+use panic_halt as _;
+use qingke::riscv;
+
+const BITRATE: u32 = 500_000;
+
+/// Identifiers forwarded from CAN1 to CAN2, `SOURCE_BASE` to `SOURCE_BASE + 7`
+const SOURCE_BASE: u16 = 0x100;
+const RANGE_LEN: usize = 8;
+
+/// Identifier the frame with `SOURCE_BASE` is sent with on CAN2, and so on
+const TARGET_BASE: u16 = 0x500;
+
+/// Frames waiting for a free mailbox, per direction
+const QUEUE_LEN: usize = 16;
+
+/// The base bits of the identifier are replaced, the offset within the range kept.
+static RULES: [GatewayRule; 1] = [GatewayRule {
+    direction: GatewayDirection::FirstToSecond,
+    id: Id::Standard(StandardId::new(SOURCE_BASE).unwrap()),
+    id_mask: !(RANGE_LEN as u32 - 1),
+    action: GatewayAction::Translate(Id::Standard(StandardId::new(TARGET_BASE).unwrap())),
+}];
+
+static GATEWAY: Mutex<RefCell<Option<Gateway<'static, CAN1, CAN2, QUEUE_LEN>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Counters written over USART1 (TX on PA9), at 115200 baud.
+struct Counters<'d>(UartTx<'d, hal::peripherals::USART1, hal::mode::Blocking>);
+
+impl Write for Counters<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0
+            .blocking_write(s.as_bytes())
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+#[qingke_rt::entry]
+fn main() -> ! {
+    let mut config = hal::Config::default();
+    config.rcc = hal::rcc::Config::SYSCLK_FREQ_96MHZ_HSI;
+    let p = hal::init(config);
+
+    let uart = UartTx::new_blocking(p.USART1, p.PA9, Default::default()).unwrap();
+    let mut out = Counters(uart);
+
+    let can1 = Can::new(
+        p.CAN1,
+        p.PB8,
+        p.PB9,
+        CanFifo::Fifo0,
+        CanMode::Normal,
+        BITRATE,
+    );
+    let can2 = Can::new(
+        p.CAN2,
+        p.PB12,
+        p.PB13,
+        CanFifo::Fifo0,
+        CanMode::Normal,
+        BITRATE,
+    );
+
+    // Only the range reaches the gateway, CAN2 receives nothing as it has no filter
+    let source = StandardId::new(SOURCE_BASE).unwrap();
+    let range_mask = !(RANGE_LEN as u32 - 1);
+    can1.add_filter(CanFilter::matching(0, source, range_mask));
+
+    let gateway = Gateway::new(can1, can2, &RULES);
+    critical_section::with(|cs| GATEWAY.borrow_ref_mut(cs).replace(gateway));
+    unsafe {
+        <CAN1 as Instance>::TxInterrupt::enable();
+        <CAN1 as Instance>::Rx0Interrupt::enable();
+        <CAN1 as Instance>::Rx1Interrupt::enable();
+        <CAN1 as Instance>::SceInterrupt::enable();
+        <CAN2 as Instance>::TxInterrupt::enable();
+        <CAN2 as Instance>::Rx0Interrupt::enable();
+        <CAN2 as Instance>::Rx1Interrupt::enable();
+        <CAN2 as Instance>::SceInterrupt::enable();
+    }
+
+    writeln!(
+        out,
+        "Forwarding 0x{SOURCE_BASE:03X}-0x{:03X} from CAN1 to CAN2 as 0x{TARGET_BASE:03X}-0x{:03X}.",
+        SOURCE_BASE + RANGE_LEN as u16 - 1,
+        TARGET_BASE + RANGE_LEN as u16 - 1,
+    )
+    .ok();
+
+    loop {
+        riscv::asm::delay(96_000_000); // About 1 s at 96 MHz
+
+        let (forwarded, dropped) = critical_section::with(|cs| {
+            let gateway = GATEWAY.borrow_ref(cs);
+            let gateway = gateway.as_ref().unwrap();
+            (gateway.forwarded(), gateway.dropped())
+        });
+        writeln!(out, "forwarded={forwarded} dropped={dropped}").ok();
+    }
+}
+
+/// Every CAN interrupt of both peripherals forwards through the gateway.
+fn on_interrupt() {
+    critical_section::with(|cs| {
+        if let Some(gateway) = GATEWAY.borrow_ref_mut(cs).as_mut() {
+            gateway.on_interrupt();
+        }
+    });
+}
+
+#[qingke_rt::interrupt]
+fn USB_HP_CAN1_TX() {
+    on_interrupt();
+}
+
+#[qingke_rt::interrupt]
+fn USB_LP_CAN1_RX0() {
+    on_interrupt();
+}
+
+#[qingke_rt::interrupt]
+fn CAN1_RX1() {
+    on_interrupt();
+}
+
+#[qingke_rt::interrupt]
+fn CAN1_SCE() {
+    on_interrupt();
+}
+
+#[qingke_rt::interrupt]
+fn CAN2_TX() {
+    on_interrupt();
+}
+
+#[qingke_rt::interrupt]
+fn CAN2_RX0() {
+    on_interrupt();
+}
+
+#[qingke_rt::interrupt]
+fn CAN2_RX1() {
+    on_interrupt();
+}
+
+#[qingke_rt::interrupt]
+fn CAN2_SCE() {
+    on_interrupt();
+}
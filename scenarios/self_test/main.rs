@@ -0,0 +1,241 @@
+#![no_std]
+#![no_main]
+
+use ch32_can_rs::embedded_can::Frame;
+use ch32_can_rs::timing::CIA_SAMPLE_POINT_PERMILL;
+use ch32_can_rs::{
+    hal, nb, Bitrate, Can, CanConfig, CanEvent, CanFifo, CanFilter, CanFrame, CanMode, ExtendedId,
+    Id, Instance, StandardId,
+};
+use hal::println;
+use panic_halt as _;
+use qingke::riscv;
+
+/// Receive attempts before a frame is considered lost, about 10 ms at 96 MHz
+const RECEIVE_POLLS: u32 = 1000;
+
+/// Depth of a hardware receive FIFO
+const FIFO_DEPTH: usize = 3;
+
+enum Outcome {
+    Pass,
+    Fail(&'static str),
+}
+
+#[qingke_rt::entry]
+fn main() -> ! {
+    hal::debug::SDIPrint::enable();
+    let mut config = hal::Config::default();
+    config.rcc = hal::rcc::Config::SYSCLK_FREQ_96MHZ_HSI;
+    let p = hal::init(config);
+
+    println!("Running self test in silent loopback mode.");
+
+    let mut can = Can::new(
+        p.CAN1,
+        p.PB8,
+        p.PB9,
+        CanFifo::Fifo1,
+        CanMode::SilentLoopback,
+        500_000,
+    );
+    can.add_filter(CanFilter::accept_all());
+
+    let (mut passed, mut failed) = (0, 0);
+    let mut report = |name: &str, outcome: Outcome| match outcome {
+        Outcome::Pass => {
+            passed += 1;
+            println!("PASS {name}");
+        }
+        Outcome::Fail(reason) => {
+            failed += 1;
+            println!("FAIL {name}: {reason}");
+        }
+    };
+
+    report("bit timing 500 kbit/s", bit_timing(&can, 500_000));
+    report(
+        "reconfigure 250 kbit/s",
+        reconfigure(&mut can, Bitrate::K250),
+    );
+    report("reconfigure 1 Mbit/s", reconfigure(&mut can, Bitrate::M1));
+    report(
+        "reconfigure 500 kbit/s",
+        reconfigure(&mut can, Bitrate::K500),
+    );
+    report("standard frame", standard_frame(&can));
+    report("extended frame", extended_frame(&can));
+    report("filter on identifier and mask", filter_mask(&can));
+    report("filter on identifier format", filter_format(&can));
+    report("remote frame", remote_frame(&can));
+    report("receive FIFO overrun", fifo_overrun(&can));
+
+    println!("Done: {passed} passed, {failed} failed.");
+
+    loop {
+        riscv::asm::delay(50000000);
+    }
+}
+
+/// Polls for a received frame for about 10 ms.
+fn wait_frame<T: Instance>(can: &Can<'_, T>) -> Option<CanFrame> {
+    for _ in 0..RECEIVE_POLLS {
+        match can.receive() {
+            Ok(frame) => return Some(frame),
+            Err(nb::Error::WouldBlock) => riscv::asm::delay(1000),
+            Err(nb::Error::Other(_)) => {}
+        }
+    }
+
+    None
+}
+
+/// Discards frames left over by a previous case.
+fn drain<T: Instance>(can: &Can<'_, T>) {
+    while wait_frame(can).is_some() {}
+    while can.take_event().is_some() {}
+}
+
+/// Sends `frame` and checks it is received back unchanged.
+fn round_trip<T: Instance>(can: &Can<'_, T>, frame: &CanFrame) -> Outcome {
+    drain(can);
+    if nb::block!(can.transmit(frame)).is_err() {
+        return Outcome::Fail("transmit failed");
+    }
+
+    match wait_frame(can) {
+        None => Outcome::Fail("frame not received"),
+        Some(received) if received.id() != frame.id() => Outcome::Fail("identifier differs"),
+        Some(received) if received.data() != frame.data() => Outcome::Fail("data differs"),
+        Some(_) => Outcome::Pass,
+    }
+}
+
+/// Sends `frame` and checks it is not received back.
+fn rejected<T: Instance>(can: &Can<'_, T>, frame: &CanFrame) -> bool {
+    drain(can);
+    nb::block!(can.transmit(frame)).is_ok() && wait_frame(can).is_none()
+}
+
+fn bit_timing<T: Instance>(can: &Can<'_, T>, bitrate: u32) -> Outcome {
+    let clock = can.clock_frequency().0;
+    let timing = can.bit_timing();
+
+    if timing.bitrate(clock) != bitrate {
+        return Outcome::Fail("achieved bitrate differs");
+    }
+    if timing
+        .sample_point_permill()
+        .abs_diff(CIA_SAMPLE_POINT_PERMILL)
+        > 25
+    {
+        return Outcome::Fail("sample point off by more than 2.5%");
+    }
+
+    Outcome::Pass
+}
+
+fn reconfigure<T: Instance>(can: &mut Can<'_, T>, bitrate: Bitrate) -> Outcome {
+    if can
+        .reconfigure(CanMode::SilentLoopback, &CanConfig::new(bitrate))
+        .is_err()
+    {
+        return Outcome::Fail("bit timing not achievable");
+    }
+    if let Outcome::Fail(reason) = bit_timing(can, bitrate.bps()) {
+        return Outcome::Fail(reason);
+    }
+
+    let frame = CanFrame::new(StandardId::new(0x321).unwrap(), &[0x55; 8]).unwrap();
+    round_trip(can, &frame)
+}
+
+fn standard_frame<T: Instance>(can: &Can<'_, T>) -> Outcome {
+    let data = [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+    let frame = CanFrame::new(StandardId::new(0x7FF).unwrap(), &data).unwrap();
+
+    round_trip(can, &frame)
+}
+
+fn extended_frame<T: Instance>(can: &Can<'_, T>) -> Outcome {
+    let data = [0xFE, 0xDC, 0xBA, 0x98, 0x76, 0x54, 0x32, 0x10];
+    let frame = CanFrame::new(ExtendedId::new(0x1ABC_DEF0).unwrap(), &data).unwrap();
+
+    round_trip(can, &frame)
+}
+
+fn filter_mask<T: Instance>(can: &Can<'_, T>) -> Outcome {
+    let id = StandardId::new(0x100).unwrap();
+    can.add_filter(CanFilter::matching(0, id, 0x7F0));
+
+    let matching = CanFrame::new(StandardId::new(0x10A).unwrap(), &[1; 8]).unwrap();
+    let other = CanFrame::new(StandardId::new(0x20A).unwrap(), &[2; 8]).unwrap();
+    let outcome = match round_trip(can, &matching) {
+        Outcome::Pass if !rejected(can, &other) => Outcome::Fail("other identifier accepted"),
+        outcome => outcome,
+    };
+
+    can.add_filter(CanFilter::accept_all());
+    outcome
+}
+
+fn filter_format<T: Instance>(can: &Can<'_, T>) -> Outcome {
+    let id = StandardId::new(0x100).unwrap();
+    can.add_filter(CanFilter::matching(0, id, 0x7FF));
+
+    let extended = CanFrame::new(ExtendedId::new(0x100).unwrap(), &[3; 8]).unwrap();
+    let outcome = match rejected(can, &extended) {
+        true => Outcome::Pass,
+        false => Outcome::Fail("extended frame accepted by standard filter"),
+    };
+
+    can.add_filter(CanFilter::accept_all());
+    outcome
+}
+
+fn remote_frame<T: Instance>(can: &Can<'_, T>) -> Outcome {
+    let id: Id = StandardId::new(0x123).unwrap().into();
+    let frame = CanFrame::new_remote(id, 4).unwrap();
+
+    drain(can);
+    if nb::block!(can.transmit(&frame)).is_err() {
+        return Outcome::Fail("transmit failed");
+    }
+    match wait_frame(can) {
+        None => Outcome::Fail("frame not received"),
+        Some(received) if !received.is_remote_frame() => Outcome::Fail("received as data frame"),
+        Some(received) if Frame::dlc(&received) != 4 => Outcome::Fail("DLC differs"),
+        Some(received) if received.id() != frame.id() => Outcome::Fail("identifier differs"),
+        Some(_) => Outcome::Pass,
+    }
+}
+
+/// Sends more frames than the FIFO holds without reading it, and expects an overrun
+/// event and the first frames to be kept.
+fn fifo_overrun<T: Instance>(can: &Can<'_, T>) -> Outcome {
+    drain(can);
+    for n in 0..=FIFO_DEPTH as u8 {
+        let frame = CanFrame::new(StandardId::new(0x400 + n as u16).unwrap(), &[n; 8]).unwrap();
+        if nb::block!(can.transmit(&frame)).is_err() {
+            return Outcome::Fail("transmit failed");
+        }
+        riscv::asm::delay(100000); // Let the frame loop back, about 1 ms
+    }
+
+    let mut overrun = false;
+    while let Some(event) = can.take_event() {
+        overrun |= event == CanEvent::Overrun;
+    }
+    if !overrun {
+        return Outcome::Fail("no overrun event");
+    }
+
+    let mut kept = 0;
+    while wait_frame(can).is_some() {
+        kept += 1;
+    }
+    match kept {
+        FIFO_DEPTH => Outcome::Pass,
+        _ => Outcome::Fail("FIFO did not hold exactly 3 frames"),
+    }
+}
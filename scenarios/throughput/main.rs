@@ -0,0 +1,251 @@
+#![no_std]
+#![no_main]
+
+use ch32_can_rs::{
+    hal, nb, systick_micros, Bitrate, Can, CanConfig, CanFifo, CanFilter, CanFrame, CanMode,
+    Instance, MailboxState, StandardId, TxQueue,
+};
+use hal::interrupt::typelevel::Interrupt;
+use hal::peripherals::CAN1;
+use hal::println;
+use panic_halt as _;
+use qingke::riscv;
+
+/// Silent loopback needs no other node. In normal mode, another node must
+/// acknowledge the frames, without sending any itself.
+const BENCH_MODE: CanMode = CanMode::SilentLoopback;
+
+const BITRATES: [Bitrate; 4] = [Bitrate::K125, Bitrate::K250, Bitrate::K500, Bitrate::M1];
+
+/// Frames sent back to back per run
+const BURST_LEN: u32 = 2000;
+
+/// Every `STALL_EVERY` frames received, the application stalls for `STALL_CYCLES`,
+/// about 500 µs at 96 MHz, standing in for occasional work on the main loop
+const STALL_EVERY: u32 = 50;
+const STALL_CYCLES: u32 = 48_000;
+
+/// Empty receive polls after the last frame is sent before a run ends, about 10 ms
+/// at 96 MHz
+const QUIET_POLLS: u32 = 1000;
+
+/// Frames waiting for a free transmit mailbox in buffered runs
+const TX_QUEUE_LEN: usize = 8;
+
+const BURST_ID: u16 = 0x100;
+
+/// Frames loaded through each transmit path when timing them, three at a time
+const LOAD_FRAMES: u32 = 999;
+
+/// Core clock, to convert the time spent loading mailboxes into cycles
+const SYSCLK_MHZ: u32 = 96;
+
+#[derive(Copy, Clone)]
+enum TxPath {
+    /// [Can::transmit]: in a critical section, with read-modify-writes of the mailbox
+    Checked,
+    /// [Can::transmit_unchecked]: one status read and four writes
+    Unchecked,
+}
+
+#[derive(Copy, Clone)]
+enum Driver {
+    /// Frames are read straight from the 3-deep hardware FIFO
+    Polling,
+    /// Frames are read by the receive interrupt into a software queue, and sent
+    /// through a [TxQueue]
+    Buffered,
+}
+
+struct Stats {
+    received: u32,
+    /// Bit times between the start of the first and the last frame received
+    span_bits: u32,
+}
+
+#[qingke_rt::entry]
+fn main() -> ! {
+    hal::debug::SDIPrint::enable();
+    let mut config = hal::Config::default();
+    config.rcc = hal::rcc::Config::SYSCLK_FREQ_96MHZ_HSI;
+    let p = hal::init(config);
+
+    let mut can = Can::new(p.CAN1, p.PB8, p.PB9, CanFifo::Fifo0, BENCH_MODE, 500_000);
+    can.add_filter(CanFilter::accept_all());
+    can.enable_time_triggered_mode(false); // Timestamp received frames in bit times
+
+    // Before interrupts are enabled, nothing else loads the mailboxes
+    for path in [TxPath::Checked, TxPath::Unchecked] {
+        let cycles = load_cycles(&can, path);
+        let path = match path {
+            TxPath::Checked => "checked",
+            TxPath::Unchecked => "unchecked",
+        };
+        println!("{path} transmit: {cycles} cycles per frame");
+    }
+
+    println!("Sending {BURST_LEN} frames per run.");
+
+    // Interrupts can't be disabled again, so polling runs first
+    for driver in [Driver::Polling, Driver::Buffered] {
+        if let Driver::Buffered = driver {
+            can.enable_interrupts();
+            unsafe {
+                <CAN1 as Instance>::TxInterrupt::enable();
+                <CAN1 as Instance>::Rx0Interrupt::enable();
+                <CAN1 as Instance>::Rx1Interrupt::enable();
+                <CAN1 as Instance>::SceInterrupt::enable();
+            }
+        }
+
+        for bitrate in BITRATES {
+            if can
+                .reconfigure(BENCH_MODE, &CanConfig::new(bitrate))
+                .is_err()
+            {
+                println!("{} bit/s: bit timing not achievable", bitrate.bps());
+                continue;
+            }
+
+            let stats = run(&can, driver);
+            let lost = BURST_LEN.saturating_sub(stats.received);
+            let frames_per_second = match stats.span_bits {
+                0 => 0,
+                span_bits => {
+                    stats.received.saturating_sub(1) as u64 * bitrate.bps() as u64
+                        / span_bits as u64
+                }
+            };
+            let driver = match driver {
+                Driver::Polling => "polling",
+                Driver::Buffered => "buffered",
+            };
+            println!(
+                "{driver} {} bit/s: {frames_per_second} frames/s, {lost} of {BURST_LEN} lost",
+                bitrate.bps()
+            );
+        }
+    }
+
+    println!("Done.");
+
+    loop {
+        riscv::asm::delay(50000000);
+    }
+}
+
+/// Keeps the transmit mailboxes full until [BURST_LEN] frames are sent, while
+/// receiving them back.
+fn run<T: Instance>(can: &Can<'_, T>, driver: Driver) -> Stats {
+    drain(can);
+
+    let mut queue = TxQueue::<TX_QUEUE_LEN>::new();
+    let (mut sent, mut quiet) = (0, 0);
+    let mut stats = Stats {
+        received: 0,
+        span_bits: 0,
+    };
+    let mut last_time = None::<u16>;
+    while quiet < QUIET_POLLS {
+        match driver {
+            Driver::Polling => {
+                while sent < BURST_LEN && can.transmit(&burst_frame(sent)).is_ok() {
+                    sent += 1;
+                }
+            }
+            Driver::Buffered => {
+                while sent < BURST_LEN && queue.push(burst_frame(sent)).is_ok() {
+                    sent += 1;
+                }
+                can.transmit_queued(&mut queue);
+            }
+        }
+
+        match can.receive() {
+            Ok(frame) => {
+                quiet = 0;
+                let time = frame.hardware_timestamp().unwrap_or(0);
+                if let Some(last_time) = last_time {
+                    stats.span_bits += time.wrapping_sub(last_time) as u32;
+                }
+                last_time = Some(time);
+                stats.received += 1;
+                if stats.received.is_multiple_of(STALL_EVERY) {
+                    riscv::asm::delay(STALL_CYCLES);
+                }
+            }
+            Err(nb::Error::Other(_)) => {} // Overrun, counted from received frames
+            Err(nb::Error::WouldBlock) if sent == BURST_LEN && queue.is_empty() => {
+                quiet += 1;
+                riscv::asm::delay(1000);
+            }
+            Err(nb::Error::WouldBlock) => {}
+        }
+    }
+
+    stats
+}
+
+/// Average cycles taken by `path` to load a frame into a free mailbox, timing loads
+/// into the three empty mailboxes together.
+fn load_cycles<T: Instance>(can: &Can<'_, T>, path: TxPath) -> u32 {
+    drain(can);
+
+    let frames: [CanFrame; 3] = core::array::from_fn(|n| burst_frame(n as u32));
+    let (mut micros, mut loaded) = (0, 0);
+    while loaded < LOAD_FRAMES {
+        // Sent frames are received back, 3 fit in the FIFO
+        while can.receive().is_ok() {}
+        if can.tx_mailbox_status().mailboxes != [MailboxState::Empty; 3] {
+            continue;
+        }
+
+        let start = systick_micros();
+        for frame in &frames {
+            let result = match path {
+                TxPath::Checked => can.transmit(frame).map(|_| ()),
+                TxPath::Unchecked => unsafe { can.transmit_unchecked(frame) }.map(|_| ()),
+            };
+            loaded += result.is_ok() as u32;
+        }
+        micros += systick_micros().wrapping_sub(start);
+    }
+
+    micros * SYSCLK_MHZ / loaded
+}
+
+fn burst_frame(n: u32) -> CanFrame {
+    CanFrame::new(StandardId::new(BURST_ID).unwrap(), &[n as u8; 8]).unwrap()
+}
+
+/// Discards frames and events left over by a previous run.
+fn drain<T: Instance>(can: &Can<'_, T>) {
+    for _ in 0..QUIET_POLLS {
+        if can.receive().is_err() {
+            riscv::asm::delay(1000);
+        }
+    }
+    while can.take_event().is_some() {}
+}
+
+// All four vectors share the same priority, so they never preempt each other
+
+#[qingke_rt::interrupt]
+fn USB_HP_CAN1_TX() {
+    unsafe { Can::<CAN1>::on_interrupt() };
+}
+
+#[qingke_rt::interrupt]
+fn USB_LP_CAN1_RX0() {
+    unsafe { Can::<CAN1>::on_interrupt() };
+}
+
+#[qingke_rt::interrupt]
+fn CAN1_RX1() {
+    unsafe { Can::<CAN1>::on_interrupt() };
+}
+
+#[qingke_rt::interrupt]
+fn CAN1_SCE() {
+    unsafe { Can::<CAN1>::on_interrupt() };
+}
@@ -0,0 +1,389 @@
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+
+use ch32_can_rs::{
+    hal, nb, BusState, Can, CanConfig, CanFifo, CanFilter, CanFrame, CanMode, Id, Instance,
+    StandardId, TxHandle, TxStatus,
+};
+use hal::println;
+use hal::usart::UartTx;
+use panic_halt as _;
+use qingke::riscv;
+
+#[derive(PartialEq)]
+enum ScenarioRole {
+    Initiator,
+    Responder,
+}
+
+const SCENARIO_ROLE: ScenarioRole = ScenarioRole::Initiator;
+
+/// Bitrate both boards start at
+const BITRATE: u32 = 500_000;
+
+/// Bitrates the throughput test runs at, ending at [BITRATE]
+const THROUGHPUT_BITRATES: [u32; 4] = [125_000, 250_000, 1_000_000, 500_000];
+
+/// Frames sent per throughput test
+const BURST_LEN: u32 = 500;
+
+/// Time the responder spends at half the bitrate in the bus-off test
+const DETUNE_MS: u32 = 200;
+
+// Requests of the initiator, answered by the responder with the identifier plus one
+const PING: u16 = 0x700;
+const SET_BITRATE: u16 = 0x702;
+const DETUNE: u16 = 0x704;
+const BURST_END: u16 = 0x706;
+
+/// Throughput test frames, with priority over [BURST_END] so it can't overtake them
+const BURST: u16 = 0x6F0;
+
+// Arbitration test frames, in the order they win the bus
+const ARBITRATE: u16 = 0x010;
+const FILLER: u16 = 0x011;
+const CONTENDER_RESPONDER: u16 = 0x012;
+const CONTENDER_INITIATOR: u16 = 0x013;
+
+/// Result lines written over USART1 (TX on PA9), at 115200 baud.
+struct Results<'d>(UartTx<'d, hal::peripherals::USART1, hal::mode::Blocking>);
+
+impl Write for Results<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0
+            .blocking_write(s.as_bytes())
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+#[qingke_rt::entry]
+fn main() -> ! {
+    hal::debug::SDIPrint::enable();
+    let mut config = hal::Config::default();
+    config.rcc = hal::rcc::Config::SYSCLK_FREQ_96MHZ_HSI;
+    let p = hal::init(config);
+
+    println!("Creating CAN in normal mode.");
+
+    let can = Can::new(
+        p.CAN1,
+        p.PB8,
+        p.PB9,
+        CanFifo::Fifo1,
+        CanMode::Normal,
+        BITRATE,
+    );
+    can.add_filter(CanFilter::accept_all());
+
+    println!("Init CAN normal mode & adding filter OK.");
+
+    match SCENARIO_ROLE {
+        ScenarioRole::Initiator => {
+            let uart = UartTx::new_blocking(p.USART1, p.PA9, Default::default()).unwrap();
+            initiator(can, Results(uart))
+        }
+        ScenarioRole::Responder => responder(can),
+    }
+}
+
+fn initiator<T: Instance>(mut can: Can<'_, T>, mut out: Results<'_>) -> ! {
+    println!("Waiting for the responder.");
+    while request(&can, PING, &[]).is_none() {
+        delay_ms(500);
+    }
+    println!("Responder found, running tests.");
+
+    let mut results = [false; 3 + THROUGHPUT_BITRATES.len()];
+    results[0] = ack(&can, &mut out, BITRATE);
+    results[1] = arbitration(&can, &mut out, BITRATE);
+    results[2] = bus_off(&mut can, &mut out, BITRATE);
+    for (result, bitrate) in results[3..].iter_mut().zip(THROUGHPUT_BITRATES) {
+        *result = throughput(&mut can, &mut out, bitrate);
+    }
+
+    let passed = results.iter().filter(|passed| **passed).count();
+    let failed = results.len() - passed;
+    writeln!(out, "test=done passed={passed} failed={failed}").ok();
+
+    println!("Done: {passed} passed, {failed} failed.");
+    loop {
+        riscv::asm::delay(50000000);
+    }
+}
+
+fn responder<T: Instance>(mut can: Can<'_, T>) -> ! {
+    can.enable_time_triggered_mode(false); // Timestamps the burst frames in bit times
+
+    let mut bitrate = BITRATE;
+    let (mut count, mut span_bits, mut last_time) = (0u32, 0u32, None::<u16>);
+
+    println!("Waiting for requests.");
+    loop {
+        let frame = match can.receive() {
+            Ok(frame) => frame,
+            Err(_) => continue,
+        };
+        let Id::Standard(id) = *frame.id() else {
+            continue;
+        };
+        let argument = u32::from_le_bytes(frame.data()[..4].try_into().unwrap());
+
+        match id.as_raw() {
+            PING => reply(&can, PING, frame.data()),
+            SET_BITRATE => {
+                reply(&can, SET_BITRATE, &[]);
+                delay_ms(1); // Let the reply leave at the old bitrate
+                bitrate = argument;
+                reconfigure(&mut can, bitrate);
+                println!("Switched to {bitrate} bit/s.");
+            }
+            DETUNE => {
+                reply(&can, DETUNE, &[]);
+                delay_ms(1);
+                reconfigure(&mut can, bitrate / 2);
+                delay_ms(argument);
+                reconfigure(&mut can, bitrate);
+            }
+            ARBITRATE => {
+                nb::block!(can.transmit(&new_frame(CONTENDER_RESPONDER, &[0; 8]))).ok();
+            }
+            BURST => {
+                let time = frame.hardware_timestamp().unwrap_or(0);
+                if let Some(last_time) = last_time {
+                    span_bits += time.wrapping_sub(last_time) as u32;
+                }
+                last_time = Some(time);
+                count += 1;
+            }
+            BURST_END => {
+                let mut data = [0; 8];
+                data[..4].copy_from_slice(&count.to_le_bytes());
+                data[4..].copy_from_slice(&span_bits.to_le_bytes());
+                reply(&can, BURST_END, &data);
+                (count, span_bits, last_time) = (0, 0, None);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The responder acknowledges a frame and echoes it back.
+fn ack<T: Instance>(can: &Can<'_, T>, out: &mut Results<'_>, bitrate: u32) -> bool {
+    drain(can);
+    let ping = new_frame(PING, &[0xA5; 8]);
+    let acked = nb::block!(can.transmit_tracked(&ping))
+        .ok()
+        .and_then(|handle| wait_sent(can, handle, 10))
+        == Some(TxStatus::Sent);
+    let echoed = wait_for(can, PING + 1, 50).is_some_and(|echo| echo.data() == ping.data());
+
+    let passed = acked && echoed;
+    report(
+        out,
+        "ack",
+        bitrate,
+        passed,
+        format_args!(" acked={acked} echoed={echoed}"),
+    );
+    passed
+}
+
+/// Both boards start a frame right after the filler frame; the responder's, with the
+/// lower identifier, must win and the initiator's be retransmitted after it.
+fn arbitration<T: Instance>(can: &Can<'_, T>, out: &mut Results<'_>, bitrate: u32) -> bool {
+    drain(can);
+    let mut handle = None;
+    for raw_id in [ARBITRATE, FILLER, CONTENDER_INITIATOR] {
+        handle = nb::block!(can.transmit_tracked(&new_frame(raw_id, &[0; 8]))).ok();
+    }
+
+    let (mut lost, mut sent) = (false, false);
+    for _ in 0..1000 {
+        lost |= can.receive_id(id(CONTENDER_RESPONDER)).is_ok();
+        if let Some(handle) = handle {
+            if let Ok(status) = can.poll_tx_result(handle) {
+                sent = status == TxStatus::Sent;
+                break;
+            }
+        }
+        riscv::asm::delay(960); // 10 µs, less than a frame at any bitrate
+    }
+
+    let passed = lost && sent;
+    report(
+        out,
+        "arbitration",
+        bitrate,
+        passed,
+        format_args!(" lost={lost} sent={sent}"),
+    );
+    passed
+}
+
+/// The responder switches to half the bitrate for a while, making every frame sent
+/// fail until the initiator goes bus-off. It must recover once reinitialized.
+fn bus_off<T: Instance>(can: &mut Can<'_, T>, out: &mut Results<'_>, bitrate: u32) -> bool {
+    if request(can, DETUNE, &DETUNE_MS.to_le_bytes()).is_none() {
+        report(
+            out,
+            "bus_off",
+            bitrate,
+            false,
+            format_args!(" detuned=false"),
+        );
+        return false;
+    }
+    delay_ms(5); // Let the responder reconfigure
+
+    nb::block!(can.transmit(&new_frame(PING, &[0; 8]))).ok();
+    let mut bus_off_ms = None;
+    for ms in 0..DETUNE_MS / 2 {
+        if can.bus_state() == BusState::BusOff {
+            bus_off_ms = Some(ms);
+            break;
+        }
+        delay_ms(1);
+    }
+
+    delay_ms(DETUNE_MS); // Wait for the responder to return
+    reconfigure(can, bitrate); // Leaves bus-off after 128 x 11 recessive bits
+    let recovered = request(can, PING, &[]).is_some();
+
+    let passed = bus_off_ms.is_some() && recovered;
+    let bus_off_ms = bus_off_ms.unwrap_or(0);
+    report(
+        out,
+        "bus_off",
+        bitrate,
+        passed,
+        format_args!(" bus_off_ms={bus_off_ms} recovered={recovered}"),
+    );
+    passed
+}
+
+/// Sends [BURST_LEN] frames back to back at `bitrate`, measuring frames received and
+/// time taken in bit times with the responder's time-triggered mode timer.
+fn throughput<T: Instance>(can: &mut Can<'_, T>, out: &mut Results<'_>, bitrate: u32) -> bool {
+    if request(can, SET_BITRATE, &bitrate.to_le_bytes()).is_none() {
+        report(
+            out,
+            "throughput",
+            bitrate,
+            false,
+            format_args!(" switched=false"),
+        );
+        return false;
+    }
+    delay_ms(5); // Let the responder reconfigure
+    reconfigure(can, bitrate);
+
+    for n in 0..BURST_LEN {
+        nb::block!(can.transmit(&new_frame(BURST, &[n as u8; 8]))).ok();
+    }
+    let Some(answer) = request(can, BURST_END, &[]) else {
+        report(
+            out,
+            "throughput",
+            bitrate,
+            false,
+            format_args!(" answered=false"),
+        );
+        return false;
+    };
+
+    let received = u32::from_le_bytes(answer.data()[..4].try_into().unwrap());
+    let span_bits = u32::from_le_bytes(answer.data()[4..].try_into().unwrap());
+    let lost = BURST_LEN.saturating_sub(received);
+    let frames_per_second = match span_bits {
+        0 => 0,
+        _ => received.saturating_sub(1) as u64 * bitrate as u64 / span_bits as u64,
+    };
+
+    let passed = lost == 0 && frames_per_second > 0;
+    report(
+        out,
+        "throughput",
+        bitrate,
+        passed,
+        format_args!(" received={received} lost={lost} fps={frames_per_second}"),
+    );
+    passed
+}
+
+/// Writes a result line, e.g. `test=ack bitrate=500000 result=PASS acked=true`,
+/// followed by `details` as space-separated `key=value` pairs.
+fn report(
+    out: &mut Results<'_>,
+    test: &str,
+    bitrate: u32,
+    passed: bool,
+    details: core::fmt::Arguments,
+) {
+    let result = if passed { "PASS" } else { "FAIL" };
+    writeln!(
+        out,
+        "test={test} bitrate={bitrate} result={result}{details}"
+    )
+    .ok();
+}
+
+/// Sends request `request_id` and waits up to 50 ms for the responder's answer.
+fn request<T: Instance>(can: &Can<'_, T>, request_id: u16, data: &[u8]) -> Option<CanFrame> {
+    drain(can);
+    nb::block!(can.transmit(&new_frame(request_id, data))).ok()?;
+    wait_for(can, request_id + 1, 50)
+}
+
+/// Answers request `request_id`.
+fn reply<T: Instance>(can: &Can<'_, T>, request_id: u16, data: &[u8]) {
+    nb::block!(can.transmit(&new_frame(request_id + 1, data))).ok();
+}
+
+/// Polls for a frame with identifier `raw_id` every 100 µs, for up to `timeout_ms`.
+fn wait_for<T: Instance>(can: &Can<'_, T>, raw_id: u16, timeout_ms: u32) -> Option<CanFrame> {
+    for _ in 0..timeout_ms * 10 {
+        match can.receive_id(id(raw_id)) {
+            Ok(frame) => return Some(frame),
+            Err(_) => riscv::asm::delay(9600),
+        }
+    }
+
+    None
+}
+
+/// Polls the outcome of a transmission every 100 µs, for up to `timeout_ms`.
+fn wait_sent<T: Instance>(can: &Can<'_, T>, handle: TxHandle, timeout_ms: u32) -> Option<TxStatus> {
+    for _ in 0..timeout_ms * 10 {
+        match can.poll_tx_result(handle) {
+            Ok(status) => return Some(status),
+            Err(_) => riscv::asm::delay(9600),
+        }
+    }
+
+    None
+}
+
+/// Discards received frames and bus errors.
+fn drain<T: Instance>(can: &Can<'_, T>) {
+    while !matches!(can.receive(), Err(nb::Error::WouldBlock)) {}
+}
+
+fn reconfigure<T: Instance>(can: &mut Can<'_, T>, bitrate: u32) {
+    can.reconfigure(CanMode::Normal, &CanConfig::new(bitrate))
+        .unwrap();
+}
+
+fn new_frame(raw_id: u16, data: &[u8]) -> CanFrame {
+    CanFrame::new(StandardId::new(raw_id).unwrap(), data).unwrap()
+}
+
+fn id(raw_id: u16) -> Id {
+    StandardId::new(raw_id).unwrap().into()
+}
+
+/// Busy waits for `ms` milliseconds at 96 MHz.
+fn delay_ms(ms: u32) {
+    riscv::asm::delay(ms * 96_000);
+}
@@ -0,0 +1,49 @@
+//! Use of the protocol modules with other CAN controllers.
+
+use embedded_can::Frame;
+
+use crate::enums::{CanError, CanErrorKind};
+use crate::frame::CanFrame;
+
+/// Wraps any [embedded_can::nb::Can] controller, e.g. an external MCP2515 or a test
+/// double, to exchange [CanFrame]s and [CanError]s as expected by the protocol
+/// modules such as [crate::isotp::IsoTp] and [crate::Scheduler].
+///
+/// Frames are converted on the way in and out; errors keep their
+/// [embedded_can::ErrorKind], [CanErrorKind::Other] standing for the kinds without a
+/// match.
+pub struct CanAdapter<C>(pub C);
+
+impl<C> CanAdapter<C> {
+    pub fn release(self) -> C {
+        self.0
+    }
+}
+
+impl<C> embedded_can::nb::Can for CanAdapter<C>
+where
+    C: embedded_can::nb::Can,
+{
+    type Frame = CanFrame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &CanFrame) -> nb::Result<Option<CanFrame>, CanError> {
+        let foreign = match frame.is_remote {
+            true => C::Frame::new_remote(*frame.id(), frame.dlc()),
+            false => C::Frame::new(*frame.id(), &frame.data()[..frame.dlc()]),
+        }
+        .ok_or(nb::Error::Other(CanErrorKind::Other.into()))?;
+
+        self.0
+            .transmit(&foreign)
+            .map(|replaced| replaced.map(|replaced| CanFrame::from_frame(&replaced)))
+            .map_err(|error| error.map(|error| embedded_can::Error::kind(&error).into()))
+    }
+
+    fn receive(&mut self) -> nb::Result<CanFrame, CanError> {
+        self.0
+            .receive()
+            .map(|frame| CanFrame::from_frame(&frame))
+            .map_err(|error| error.map(|error| embedded_can::Error::kind(&error).into()))
+    }
+}
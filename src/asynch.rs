@@ -0,0 +1,196 @@
+//! Async halves of the driver, woken by the CAN interrupts.
+//!
+//! Requires interrupts to be serviced, either with [crate::Can::enable_interrupts]
+//! and [crate::Can::on_interrupt] or with [crate::Can::split_interrupt_resources].
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use embassy_time::{with_timeout, Duration, TimeoutError};
+use futures_core::Stream;
+
+use crate::can::{self, Instance};
+use crate::enums::{CanError, CanErrorKind, CanFifo, RequestError, TxHandle, TxStatus};
+use crate::frame::CanFrame;
+use crate::interrupt;
+
+/// Async transmit half.
+pub struct CanTx<'a, T: Instance> {
+    _phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Instance> CanTx<'a, T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Queues a frame for transmission, waiting while the software queue is full.
+    ///
+    /// Whenever a transmit mailbox frees up, the queued frame with the highest
+    /// arbitration priority is loaded first, so frames leave in the same order they
+    /// would win on the bus regardless of which task submitted them first. See
+    /// [crate::Can::set_tx_order] to keep submission order instead.
+    pub async fn write(&mut self, frame: &CanFrame) {
+        poll_fn(|cx| {
+            T::state().tx_waker.register(cx.waker());
+
+            match interrupt::enqueue_frame::<T>(*frame) {
+                Ok(()) => Poll::Ready(()),
+                Err(_) => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Like [CanTx::write], giving up once `timeout` has elapsed. The frame is
+    /// either queued or not sent at all, so the call can be retried safely.
+    pub async fn transmit_timeout(
+        &mut self,
+        frame: &CanFrame,
+        timeout: Duration,
+    ) -> Result<(), TimeoutError> {
+        with_timeout(timeout, self.write(frame)).await
+    }
+
+    /// Loads a frame into a transmit mailbox once one is free and no frames are
+    /// waiting in the software queue, returning a handle to [CanTx::flush] it.
+    pub async fn write_tracked(&mut self, frame: &CanFrame) -> TxHandle {
+        poll_fn(|cx| {
+            T::state().tx_waker.register(cx.waker());
+
+            match interrupt::transmit_direct::<T>(frame, false) {
+                Ok(handle) => Poll::Ready(handle),
+                Err(_) => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Waits until the frame identified by `handle` has left its mailbox, returning
+    /// the outcome. If the mailbox was reused before the outcome could be read,
+    /// [TxStatus::OtherError] is returned.
+    pub async fn flush(&mut self, handle: TxHandle) -> TxStatus {
+        poll_fn(|cx| {
+            T::state().tx_waker.register(cx.waker());
+
+            match T::state().tx_result(handle) {
+                Ok(Some(status)) => Poll::Ready(status),
+                Ok(None) => Poll::Pending,
+                Err(()) => Poll::Ready(TxStatus::OtherError),
+            }
+        })
+        .await
+    }
+
+    /// Waits until the software queue is empty and all transmit mailboxes are done.
+    pub async fn flush_all(&mut self) {
+        poll_fn(|cx| {
+            T::state().tx_waker.register(cx.waker());
+
+            match interrupt::tx_idle::<T>() {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Queues `frame` and waits on `rx` for the first response with identifier
+    /// `expected_id`, giving up with [RequestError::Timeout] once `timeout` has
+    /// elapsed. See [crate::Can::request].
+    pub async fn request(
+        &mut self,
+        rx: &mut CanRx<'_, T>,
+        frame: &CanFrame,
+        expected_id: impl Into<embedded_can::Id>,
+        timeout: Duration,
+    ) -> Result<CanFrame, RequestError> {
+        let expected_id = expected_id.into();
+        while T::state().take_deferred(Some(expected_id)).is_some() {}
+
+        with_timeout(timeout, async {
+            self.write(frame).await;
+            loop {
+                match rx.receive_id(expected_id).await {
+                    Err(error) if error.kind == CanErrorKind::Overrun => continue,
+                    result => return result,
+                }
+            }
+        })
+        .await
+        .map_err(|_| RequestError::Timeout)?
+        .map_err(RequestError::from)
+    }
+}
+
+/// Async receive half.
+pub struct CanRx<'a, T: Instance> {
+    fifo: CanFifo,
+    _phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Instance> CanRx<'a, T> {
+    pub(crate) fn new(fifo: CanFifo) -> Self {
+        Self {
+            fifo,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Waits for a received frame or a bus error.
+    pub async fn read(&mut self) -> Result<CanFrame, CanError> {
+        poll_fn(|cx| self.poll_read(cx)).await
+    }
+
+    /// Like [CanRx::read], giving up once `timeout` has elapsed without a frame or
+    /// bus error. No frame is lost when the timeout hits.
+    pub async fn receive_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Result<CanFrame, CanError>, TimeoutError> {
+        with_timeout(timeout, self.read()).await
+    }
+
+    /// Waits for a frame with identifier `id` or a bus error, see
+    /// [crate::Can::receive_id]. Frames with other identifiers are set aside for
+    /// [CanRx::read].
+    pub async fn receive_id(
+        &mut self,
+        id: impl Into<embedded_can::Id>,
+    ) -> Result<CanFrame, CanError> {
+        let id = id.into();
+        poll_fn(|cx| {
+            T::state().rx_waker.register(cx.waker());
+
+            match can::receive_frame_id::<T>(&self.fifo, id) {
+                Ok(frame) => Poll::Ready(Ok(frame)),
+                Err(nb::Error::Other(error)) => Poll::Ready(Err(error)),
+                Err(nb::Error::WouldBlock) => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    fn poll_read(&self, cx: &mut Context<'_>) -> Poll<Result<CanFrame, CanError>> {
+        T::state().rx_waker.register(cx.waker());
+
+        match can::receive_frame::<T>(&self.fifo) {
+            Ok(frame) => Poll::Ready(Ok(frame)),
+            Err(nb::Error::Other(error)) => Poll::Ready(Err(error)),
+            Err(nb::Error::WouldBlock) => Poll::Pending,
+        }
+    }
+}
+
+/// Endless stream of received frames and bus errors, for use with stream combinators.
+impl<'a, T: Instance> Stream for CanRx<'a, T> {
+    type Item = Result<CanFrame, CanError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_read(cx).map(Some)
+    }
+}
@@ -0,0 +1,159 @@
+//! Compact binary log of frames, errors and bus state changes, written to any
+//! [embedded_io::Write] sink such as a UART or a USB CDC port.
+//!
+//! The log is a sequence of records, all integers little-endian:
+//!
+//! | Offset | Size | Field                                                      |
+//! |--------|------|------------------------------------------------------------|
+//! | 0      | 1    | [SYNC], to find the next record after a lost byte          |
+//! | 1      | 1    | Record type: [FRAME], [ERROR] or [BUS_STATE]              |
+//! | 2      | 4    | Time in ms                                                 |
+//! | 6      | ...  | Payload of the record type                                 |
+//!
+//! A [FRAME] payload is a flags byte, the identifier in 4 bytes, then the data
+//! bytes, none for remote frames. The flags are the DLC in bits 0-3,
+//! [FLAG_TRANSMITTED] for frames sent by this node, [FLAG_REMOTE] and
+//! [FLAG_EXTENDED].
+//!
+//! An [ERROR] payload is a single byte, the kind of the error:
+//!
+//! | Code | Error kind                             |
+//! |------|----------------------------------------|
+//! | 1    | [crate::CanErrorKind::Overrun]         |
+//! | 2    | [crate::CanErrorKind::Bit]             |
+//! | 3    | [crate::CanErrorKind::Stuff]           |
+//! | 4    | [crate::CanErrorKind::Crc]             |
+//! | 5    | [crate::CanErrorKind::Form]            |
+//! | 6    | [crate::CanErrorKind::Acknowledge]     |
+//! | 7    | [crate::CanErrorKind::BusOff]          |
+//! | 8    | [crate::CanErrorKind::BusPassive]      |
+//! | 9    | [crate::CanErrorKind::BusWarning]      |
+//! | 10   | [crate::CanErrorKind::Other]           |
+//!
+//! A [BUS_STATE] payload is a single byte, `0` for [BusState::ErrorActive], `1` for
+//! [BusState::ErrorPassive] and `2` for [BusState::BusOff].
+
+use embedded_can::Id;
+use embedded_io::Write;
+
+use crate::frame::CanFrame;
+use crate::{BusState, CanError};
+
+/// First byte of every record.
+pub const SYNC: u8 = 0xA5;
+
+/// Record type of a frame.
+pub const FRAME: u8 = 0x01;
+/// Record type of an error.
+pub const ERROR: u8 = 0x02;
+/// Record type of a bus state change.
+pub const BUS_STATE: u8 = 0x03;
+
+/// Frame flag of a frame sent by this node.
+pub const FLAG_TRANSMITTED: u8 = 1 << 5;
+/// Frame flag of a remote frame.
+pub const FLAG_REMOTE: u8 = 1 << 6;
+/// Frame flag of an extended identifier.
+pub const FLAG_EXTENDED: u8 = 1 << 7;
+
+/// Longest record written, a frame with 8 bytes.
+pub const MAX_RECORD_LEN: usize = 19;
+
+/// What a record logs.
+#[derive(Copy, Clone)]
+pub enum LogRecord<'a> {
+    /// Frame received from the bus
+    Received(&'a CanFrame),
+    /// Frame sent by this node
+    Transmitted(&'a CanFrame),
+    /// Error reported by the controller
+    Error(CanError),
+    /// Fault confinement state the controller entered
+    BusState(BusState),
+}
+
+/// Encodes `record` logged at `time_ms` into `bytes`, and returns its length.
+pub fn encode(record: LogRecord<'_>, time_ms: u32, bytes: &mut [u8; MAX_RECORD_LEN]) -> usize {
+    let (kind, payload_len) = match record {
+        LogRecord::Received(frame) => (FRAME, encode_frame(frame, 0, &mut bytes[6..])),
+        LogRecord::Transmitted(frame) => (
+            FRAME,
+            encode_frame(frame, FLAG_TRANSMITTED, &mut bytes[6..]),
+        ),
+        LogRecord::Error(error) => {
+            bytes[6] = error.kind.code();
+            (ERROR, 1)
+        }
+        LogRecord::BusState(state) => {
+            bytes[6] = match state {
+                BusState::ErrorActive => 0,
+                BusState::ErrorPassive => 1,
+                BusState::BusOff => 2,
+            };
+            (BUS_STATE, 1)
+        }
+    };
+
+    bytes[0] = SYNC;
+    bytes[1] = kind;
+    bytes[2..6].copy_from_slice(&time_ms.to_le_bytes());
+
+    6 + payload_len
+}
+
+/// Writes the payload of a frame record into `payload`, and returns its length.
+fn encode_frame(frame: &CanFrame, flags: u8, payload: &mut [u8]) -> usize {
+    let (raw_id, extended) = match *frame.id() {
+        Id::Standard(id) => (id.as_raw() as u32, 0),
+        Id::Extended(id) => (id.as_raw(), FLAG_EXTENDED),
+    };
+    let (data_len, remote) = match frame.is_remote {
+        true => (0, FLAG_REMOTE),
+        false => (frame.dlc(), 0),
+    };
+
+    payload[0] = frame.dlc() as u8 | flags | remote | extended;
+    payload[1..5].copy_from_slice(&raw_id.to_le_bytes());
+    payload[5..5 + data_len].copy_from_slice(&frame.data()[..data_len]);
+
+    5 + data_len
+}
+
+/// Streams records to `W` in the binary log format.
+pub struct BinaryLogger<W> {
+    writer: W,
+    /// Last state logged by [BinaryLogger::log_bus_state]
+    bus_state: Option<BusState>,
+}
+
+impl<W: Write> BinaryLogger<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            bus_state: None,
+        }
+    }
+
+    pub fn release(self) -> W {
+        self.writer
+    }
+
+    /// Logs `record` at `time_ms`, e.g. the [CanFrame::timestamp] of a frame.
+    pub fn log(&mut self, record: LogRecord<'_>, time_ms: u32) -> Result<(), W::Error> {
+        let mut bytes = [0; MAX_RECORD_LEN];
+        let len = encode(record, time_ms, &mut bytes);
+        self.writer.write_all(&bytes[..len])
+    }
+
+    /// Logs `state`, e.g. from [crate::Can::bus_state] polled at `time_ms`, only if
+    /// it differs from the last one logged.
+    pub fn log_bus_state(&mut self, state: BusState, time_ms: u32) -> Result<(), W::Error> {
+        if self.bus_state == Some(state) {
+            return Ok(());
+        }
+
+        self.log(LogRecord::BusState(state), time_ms)?;
+        self.bus_state = Some(state);
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+//! Firmware update protocol for bootloaders, with the flash accesses left to the
+//! application through [Flash].
+//!
+//! Requests and responses are messages carried by any transport, e.g. ISO-TP with
+//! [Bootloader::serve]. Integers are little-endian. A request is a command byte and
+//! its arguments:
+//!
+//! | Command | Arguments |
+//! |---|---|
+//! | [ERASE] | address: u32, length: u32 |
+//! | [PROGRAM] | address: u32, data |
+//! | [VERIFY] | address: u32, length: u32, CRC-32 of the data: u32 |
+//! | [BOOT] | none |
+//!
+//! The response is the command with bit 6 set followed by a [Status] byte, and for
+//! [VERIFY] by the CRC-32 computed over the flash.
+
+use embedded_hal::delay::DelayNs;
+
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+use crate::isotp::{IsoTp, IsoTpError};
+
+#[cfg(test)]
+mod tests;
+
+pub const ERASE: u8 = 0x01;
+pub const PROGRAM: u8 = 0x02;
+pub const VERIFY: u8 = 0x03;
+/// Leaves the bootloader for the application, once the response is sent.
+pub const BOOT: u8 = 0x04;
+
+const RESPONSE_FLAG: u8 = 0x40;
+
+/// Longest response.
+pub const MAX_RESPONSE_LEN: usize = 6;
+
+/// Outcome of a request.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum Status {
+    Ok = 0x00,
+    UnknownCommand = 0x01,
+    /// The arguments are missing or too long
+    InvalidLength = 0x02,
+    /// The range is not within the application area
+    OutOfRange = 0x03,
+    /// The flash hook reported a failure
+    FlashError = 0x04,
+    /// The CRC-32 of the flash differs from the expected one
+    VerifyFailed = 0x05,
+}
+
+/// Flash of the device, written by the bootloader.
+pub trait Flash {
+    /// Erases the pages covering `len` bytes from `address`, returning `false` on
+    /// failure.
+    fn erase(&mut self, address: u32, len: u32) -> bool;
+
+    /// Writes `data` from `address`, returning `false` on failure.
+    fn program(&mut self, address: u32, data: &[u8]) -> bool;
+
+    /// Fills `data` from `address`, returning `false` on failure.
+    fn read(&mut self, address: u32, data: &mut [u8]) -> bool;
+}
+
+/// Bootloader protocol handler, restricted to the application area so it can't
+/// overwrite itself.
+pub struct Bootloader {
+    app_start: u32,
+    app_len: u32,
+    boot_requested: bool,
+}
+
+impl Bootloader {
+    /// Accepts flash accesses to the `app_len` bytes from `app_start`.
+    pub fn new(app_start: u32, app_len: u32) -> Self {
+        Self {
+            app_start,
+            app_len,
+            boot_requested: false,
+        }
+    }
+
+    /// Whether [BOOT] was received, leaving it to the caller to start the
+    /// application after sending the response.
+    pub fn boot_requested(&self) -> bool {
+        self.boot_requested
+    }
+
+    /// Handles a request, writing the response to `response` and returning its
+    /// length.
+    pub fn handle(
+        &mut self,
+        request: &[u8],
+        flash: &mut impl Flash,
+        response: &mut [u8; MAX_RESPONSE_LEN],
+    ) -> usize {
+        let (&command, args) = request.split_first().unwrap_or((&0, &[]));
+        let word = |index: usize| {
+            args.get(index * 4..index * 4 + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+
+        let (status, crc) = match (command, word(0), word(1)) {
+            (ERASE, Some(address), Some(len)) if args.len() == 8 => (
+                self.access(address, len, || flash.erase(address, len)),
+                None,
+            ),
+            (PROGRAM, Some(address), _) if args.len() > 4 => {
+                let data = &args[4..];
+                let len = data.len() as u32;
+                (
+                    self.access(address, len, || flash.program(address, data)),
+                    None,
+                )
+            }
+            (VERIFY, Some(address), Some(len)) if args.len() == 12 => {
+                let expected = word(2).unwrap();
+                let mut crc = None;
+                let status = self.access(address, len, || {
+                    crc = flash_crc(flash, address, len);
+                    crc.is_some()
+                });
+                match crc {
+                    Some(crc) if crc != expected => (Status::VerifyFailed, Some(crc)),
+                    crc => (status, crc),
+                }
+            }
+            (BOOT, _, _) if args.is_empty() => {
+                self.boot_requested = true;
+                (Status::Ok, None)
+            }
+            (ERASE | PROGRAM | VERIFY | BOOT, _, _) => (Status::InvalidLength, None),
+            _ => (Status::UnknownCommand, None),
+        };
+
+        response[0] = command | RESPONSE_FLAG;
+        response[1] = status as u8;
+        match crc {
+            Some(crc) => {
+                response[2..6].copy_from_slice(&crc.to_le_bytes());
+                6
+            }
+            None => 2,
+        }
+    }
+
+    /// Runs `access` if the range is within the application area.
+    fn access(&self, address: u32, len: u32, access: impl FnOnce() -> bool) -> Status {
+        let end = self.app_start as u64 + self.app_len as u64;
+        if address < self.app_start || address as u64 + len as u64 > end {
+            return Status::OutOfRange;
+        }
+
+        match access() {
+            true => Status::Ok,
+            false => Status::FlashError,
+        }
+    }
+
+    /// Answers the requests received over `transport` until [BOOT], using `buf` for
+    /// the requests. Its length bounds the data of [PROGRAM].
+    ///
+    /// The driver of `transport` must send frames in submission order, see [IsoTp].
+    pub fn serve<C, D>(
+        &mut self,
+        transport: &mut IsoTp<C, D>,
+        flash: &mut impl Flash,
+        buf: &mut [u8],
+    ) -> Result<(), IsoTpError>
+    where
+        C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+        D: DelayNs,
+    {
+        while !self.boot_requested {
+            let len = match transport.receive(buf) {
+                Ok(len) => len,
+                // Idle, or a broken transfer the host will retry
+                Err(
+                    IsoTpError::Timeout
+                    | IsoTpError::UnexpectedFrame
+                    | IsoTpError::WrongSequenceNumber
+                    | IsoTpError::BufferTooSmall,
+                ) => continue,
+                Err(error) => return Err(error),
+            };
+
+            let mut response = [0; MAX_RESPONSE_LEN];
+            let response_len = self.handle(&buf[..len], flash, &mut response);
+            transport.send(&response[..response_len])?;
+        }
+
+        Ok(())
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `len` bytes of flash from `address`.
+fn flash_crc(flash: &mut impl Flash, address: u32, len: u32) -> Option<u32> {
+    let mut crc = !0u32;
+    let mut chunk = [0; 64];
+    let mut offset = 0;
+    while offset < len {
+        let chunk = &mut chunk[..(len - offset).min(64) as usize];
+        if !flash.read(address + offset, chunk) {
+            return None;
+        }
+
+        for &byte in chunk.iter() {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+            }
+        }
+        offset += chunk.len() as u32;
+    }
+
+    Some(!crc)
+}
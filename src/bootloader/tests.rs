@@ -0,0 +1,209 @@
+//! Host tests of the bootloader requests, against flash held in memory.
+
+use super::*;
+
+const APP_START: u32 = 0x0800_4000;
+const APP_LEN: u32 = 1024;
+
+/// Application area in memory, failing accesses from `fail_at` on.
+struct MemoryFlash {
+    bytes: [u8; APP_LEN as usize],
+    fail_at: Option<u32>,
+    accesses: usize,
+}
+
+impl MemoryFlash {
+    fn new() -> Self {
+        Self {
+            bytes: [0xFF; APP_LEN as usize],
+            fail_at: None,
+            accesses: 0,
+        }
+    }
+
+    /// `len` bytes at `address`, `None` past `fail_at`. Panics outside of the
+    /// application area.
+    fn range(&mut self, address: u32, len: usize) -> Option<&mut [u8]> {
+        self.accesses += 1;
+        if self
+            .fail_at
+            .is_some_and(|fail_at| address + len as u32 > fail_at)
+        {
+            return None;
+        }
+
+        let start = (address - APP_START) as usize;
+        Some(&mut self.bytes[start..start + len])
+    }
+}
+
+impl Flash for MemoryFlash {
+    fn erase(&mut self, address: u32, len: u32) -> bool {
+        self.range(address, len as usize)
+            .map(|bytes| bytes.fill(0xFF))
+            .is_some()
+    }
+
+    fn program(&mut self, address: u32, data: &[u8]) -> bool {
+        self.range(address, data.len())
+            .map(|bytes| bytes.copy_from_slice(data))
+            .is_some()
+    }
+
+    fn read(&mut self, address: u32, data: &mut [u8]) -> bool {
+        self.range(address, data.len())
+            .map(|bytes| data.copy_from_slice(bytes))
+            .is_some()
+    }
+}
+
+/// Request of `command` with arguments `words`, then `data`.
+fn request(command: u8, words: &[u32], data: &[u8]) -> Vec<u8> {
+    let mut request = vec![command];
+    for word in words {
+        request.extend_from_slice(&word.to_le_bytes());
+    }
+    request.extend_from_slice(data);
+
+    request
+}
+
+/// Response of `bootloader` to `request`.
+fn handle(bootloader: &mut Bootloader, flash: &mut MemoryFlash, request: &[u8]) -> Vec<u8> {
+    let mut response = [0; MAX_RESPONSE_LEN];
+    let len = bootloader.handle(request, flash, &mut response);
+
+    response[..len].to_vec()
+}
+
+#[test]
+fn crc_of_flash() {
+    let mut flash = MemoryFlash::new();
+    flash.bytes[..9].copy_from_slice(b"123456789");
+    assert_eq!(flash_crc(&mut flash, APP_START, 9), Some(0xCBF4_3926));
+    assert_eq!(flash_crc(&mut flash, APP_START, 0), Some(0));
+
+    // Read in chunks of 64 bytes
+    for (i, byte) in flash.bytes[..256].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    flash.accesses = 0;
+    assert_eq!(flash_crc(&mut flash, APP_START, 256), Some(0x2905_8C73));
+    assert_eq!(flash.accesses, 4);
+
+    flash.fail_at = Some(APP_START + 200);
+    assert_eq!(flash_crc(&mut flash, APP_START, 256), None);
+}
+
+#[test]
+fn program_then_verify() {
+    let mut bootloader = Bootloader::new(APP_START, APP_LEN);
+    let mut flash = MemoryFlash::new();
+    let data = *b"123456789";
+
+    let erase = request(ERASE, &[APP_START, APP_LEN], &[]);
+    assert_eq!(handle(&mut bootloader, &mut flash, &erase), [0x41, 0x00]);
+    let program = request(PROGRAM, &[APP_START + 16], &data);
+    assert_eq!(handle(&mut bootloader, &mut flash, &program), [0x42, 0x00]);
+    assert_eq!(flash.bytes[16..25], data);
+
+    let verify = request(VERIFY, &[APP_START + 16, 9, 0xCBF4_3926], &[]);
+    assert_eq!(
+        handle(&mut bootloader, &mut flash, &verify),
+        [0x43, 0x00, 0x26, 0x39, 0xF4, 0xCB]
+    );
+    let verify = request(VERIFY, &[APP_START + 16, 9, 0], &[]);
+    assert_eq!(
+        handle(&mut bootloader, &mut flash, &verify),
+        [0x43, Status::VerifyFailed as u8, 0x26, 0x39, 0xF4, 0xCB]
+    );
+
+    assert!(!bootloader.boot_requested());
+    assert_eq!(handle(&mut bootloader, &mut flash, &[BOOT]), [0x44, 0x00]);
+    assert!(bootloader.boot_requested());
+}
+
+#[test]
+fn ranges_outside_the_application_rejected() {
+    let mut bootloader = Bootloader::new(APP_START, APP_LEN);
+    let mut flash = MemoryFlash::new();
+    let out_of_range = [0x41, Status::OutOfRange as u8];
+
+    // The whole area, up to its last byte
+    let erase = |address, len| request(ERASE, &[address, len], &[]);
+    assert_eq!(
+        handle(&mut bootloader, &mut flash, &erase(APP_START, APP_LEN))[1],
+        0
+    );
+    assert_eq!(
+        handle(
+            &mut bootloader,
+            &mut flash,
+            &erase(APP_START + APP_LEN - 1, 1)
+        )[1],
+        0
+    );
+    flash.accesses = 0;
+
+    for (address, len) in [
+        (APP_START - 1, 1),
+        (APP_START - 1, 2),
+        (APP_START, APP_LEN + 1),
+        (APP_START + APP_LEN, 1),
+        // The end isn't computed on 32 bits, where it wraps around
+        (APP_START + 16, u32::MAX),
+        (u32::MAX, 2),
+    ] {
+        assert_eq!(
+            handle(&mut bootloader, &mut flash, &erase(address, len)),
+            out_of_range
+        );
+    }
+    let program = request(PROGRAM, &[APP_START + APP_LEN - 2], &[0; 3]);
+    assert_eq!(
+        handle(&mut bootloader, &mut flash, &program),
+        [0x42, Status::OutOfRange as u8]
+    );
+    let verify = request(VERIFY, &[APP_START - 4, 8, 0], &[]);
+    assert_eq!(
+        handle(&mut bootloader, &mut flash, &verify),
+        [0x43, Status::OutOfRange as u8]
+    );
+    assert_eq!(flash.accesses, 0);
+}
+
+#[test]
+fn malformed_requests() {
+    let mut bootloader = Bootloader::new(APP_START, APP_LEN);
+    let mut flash = MemoryFlash::new();
+    let invalid = Status::InvalidLength as u8;
+
+    let mut response = |request: &[u8]| handle(&mut bootloader, &mut flash, request)[1];
+    assert_eq!(response(&request(ERASE, &[APP_START], &[])), invalid);
+    assert_eq!(response(&request(ERASE, &[APP_START, 4], &[0])), invalid);
+    // No data to program
+    assert_eq!(response(&request(PROGRAM, &[APP_START], &[])), invalid);
+    assert_eq!(response(&request(VERIFY, &[APP_START, 4], &[])), invalid);
+    assert_eq!(response(&[BOOT, 0]), invalid);
+    assert_eq!(response(&[0x7F]), Status::UnknownCommand as u8);
+    assert_eq!(response(&[]), Status::UnknownCommand as u8);
+    assert!(!bootloader.boot_requested());
+}
+
+#[test]
+fn flash_failures_reported() {
+    let mut bootloader = Bootloader::new(APP_START, APP_LEN);
+    let mut flash = MemoryFlash::new();
+    flash.fail_at = Some(APP_START);
+
+    let program = request(PROGRAM, &[APP_START], &[1, 2]);
+    assert_eq!(
+        handle(&mut bootloader, &mut flash, &program),
+        [0x42, Status::FlashError as u8]
+    );
+    let verify = request(VERIFY, &[APP_START, 2, 0], &[]);
+    assert_eq!(
+        handle(&mut bootloader, &mut flash, &verify),
+        [0x43, Status::FlashError as u8]
+    );
+}
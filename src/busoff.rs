@@ -0,0 +1,85 @@
+//! Power policy applied when the peripheral goes Bus Off.
+
+use core::convert::Infallible;
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy_time::{Duration, Timer};
+
+use crate::can::Instance;
+use crate::registers::{Registers, Timeout};
+use crate::transceiver::CanTransceiver;
+
+/// Task parking a node that went Bus Off, returned by [crate::Can::bus_off_supervisor].
+///
+/// A faulty node keeps disturbing the bus if it rejoins as soon as it is allowed
+/// to. Instead, whenever the peripheral goes Bus Off, the supervisor puts it to
+/// sleep and the transceiver in standby, waits for the back-off period, then
+/// re-enables the transceiver and restarts the peripheral. The peripheral rejoins
+/// the bus once it has seen 128 occurrences of 11 recessive bits.
+///
+/// Requires interrupts to be serviced, see [crate::Can::enable_interrupts]. Leave
+/// the wake-up interrupt and automatic wake-up disabled, or bus traffic ends the
+/// back-off early.
+pub struct BusOffSupervisor<T: Instance, X: CanTransceiver> {
+    transceiver: X,
+    backoff: Duration,
+    init_timeout: Timeout,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance, X: CanTransceiver> BusOffSupervisor<T, X> {
+    pub(crate) fn new(transceiver: X, backoff: Duration, init_timeout: Timeout) -> Self {
+        Self {
+            transceiver,
+            backoff,
+            init_timeout,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Supervises the peripheral forever, only returning if the transceiver fails.
+    pub async fn run(&mut self) -> Result<Infallible, X::Error> {
+        loop {
+            poll_fn(|cx| {
+                T::state().bus_off_waker.register(cx.waker());
+
+                match Registers(T::regs()).is_bus_off() || T::state().forced_bus_off() {
+                    true => Poll::Ready(()),
+                    false => Poll::Pending,
+                }
+            })
+            .await;
+
+            Registers(T::regs()).enter_sleep_mode(self.init_timeout); // Left anyway by init mode
+            self.transceiver.standby()?;
+
+            loop {
+                Timer::after(self.backoff).await;
+
+                self.transceiver.enable()?;
+                Timer::after_micros(self.transceiver.wake_delay_us() as u64).await;
+
+                // Going through init mode restarts the Bus Off recovery sequence. If
+                // the controller doesn't acknowledge in time, e.g. with the bus held
+                // dominant, it is retried after another back-off
+                let regs = Registers(T::regs());
+                if regs.enter_init_mode(self.init_timeout)
+                    && regs.leave_init_mode(self.init_timeout)
+                {
+                    break;
+                }
+                self.transceiver.standby()?;
+            }
+            T::state().clear_forced_bus_off();
+            while Registers(T::regs()).is_bus_off() {
+                Timer::after_millis(1).await;
+            }
+        }
+    }
+
+    pub fn release(self) -> X {
+        self.transceiver
+    }
+}
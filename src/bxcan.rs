@@ -0,0 +1,378 @@
+//! Compatibility layer with the API of the [bxcan](https://docs.rs/bxcan) crate used
+//! on STM32, for porting application code by only swapping the constructor.
+//!
+//! Covers the polling API: frames are read from the hardware FIFOs directly, so
+//! interrupts must not be enabled on the wrapped driver. Like the rest of the
+//! driver, remote frames are not supported.
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+pub use embedded_can::{ExtendedId, Id, StandardId};
+
+use crate::can::{self as driver, Instance};
+pub use crate::enums::CanFifo as Fifo;
+use crate::enums::{CanFilter, CanFilterMode};
+use crate::frame::CanFrame;
+use crate::interrupt;
+use crate::registers::Registers;
+
+const IDE: u32 = 1 << 2;
+const RTR: u32 = 1 << 1;
+
+/// Error returned when a receive FIFO overflowed and frames were lost.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct OverrunError {
+    _priv: (),
+}
+
+/// Payload of a data frame, up to 8 bytes.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct Data {
+    len: u8,
+    bytes: [u8; 8],
+}
+
+impl Data {
+    /// Returns `None` if `data` is longer than 8 bytes.
+    pub fn new(data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+
+        let mut bytes = [0; 8];
+        bytes[..data.len()].copy_from_slice(data);
+
+        Some(Self {
+            len: data.len() as u8,
+            bytes,
+        })
+    }
+
+    pub const fn empty() -> Self {
+        Self {
+            len: 0,
+            bytes: [0; 8],
+        }
+    }
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+macro_rules! data_from_array {
+    ($($len:literal),*) => {
+        $(
+            impl From<[u8; $len]> for Data {
+                fn from(bytes: [u8; $len]) -> Self {
+                    Self::new(&bytes).unwrap()
+                }
+            }
+        )*
+    };
+}
+
+data_from_array!(0, 1, 2, 3, 4, 5, 6, 7, 8);
+
+/// Data frame.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct Frame {
+    id: Id,
+    data: Data,
+}
+
+impl Frame {
+    pub fn new_data(id: impl Into<Id>, data: impl Into<Data>) -> Self {
+        Self {
+            id: id.into(),
+            data: data.into(),
+        }
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn dlc(&self) -> u8 {
+        self.data.len
+    }
+
+    /// Always `Some`, remote frames are not supported.
+    pub fn data(&self) -> Option<&Data> {
+        Some(&self.data)
+    }
+
+    pub fn is_standard(&self) -> bool {
+        matches!(self.id, Id::Standard(_))
+    }
+
+    pub fn is_extended(&self) -> bool {
+        matches!(self.id, Id::Extended(_))
+    }
+
+    pub fn is_data_frame(&self) -> bool {
+        true
+    }
+
+    pub fn is_remote_frame(&self) -> bool {
+        false
+    }
+
+    fn to_can_frame(self) -> CanFrame {
+        CanFrame::new(self.id, &self.data).unwrap()
+    }
+
+    fn from_can_frame(frame: &CanFrame) -> Self {
+        Self {
+            id: *frame.id(),
+            data: Data::new(&frame.data()[..frame.dlc()]).unwrap(),
+        }
+    }
+}
+
+/// Filter matching the identifier bits selected by a mask.
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct Mask32 {
+    id: u32,
+    mask: u32,
+}
+
+impl Mask32 {
+    pub fn accept_all() -> Self {
+        Self { id: 0, mask: 0 }
+    }
+
+    /// Accepts standard frames whose identifier matches `id` on the bits set in `mask`.
+    pub fn frames_with_std_id(id: StandardId, mask: StandardId) -> Self {
+        Self {
+            id: (id.as_raw() as u32) << 21,
+            mask: (mask.as_raw() as u32) << 21 | IDE,
+        }
+    }
+
+    /// Accepts extended frames whose identifier matches `id` on the bits set in `mask`.
+    pub fn frames_with_ext_id(id: ExtendedId, mask: ExtendedId) -> Self {
+        Self {
+            id: id.as_raw() << 3 | IDE,
+            mask: mask.as_raw() << 3 | IDE,
+        }
+    }
+}
+
+/// Filter entry matching a single identifier exactly.
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct ListEntry32(u32);
+
+impl ListEntry32 {
+    pub fn data_frames_with_id(id: impl Into<Id>) -> Self {
+        Self(filter_id(id.into()))
+    }
+
+    pub fn remote_frames_with_id(id: impl Into<Id>) -> Self {
+        Self(filter_id(id.into()) | RTR)
+    }
+}
+
+fn filter_id(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => (id.as_raw() as u32) << 21,
+        Id::Extended(id) => id.as_raw() << 3 | IDE,
+    }
+}
+
+/// Configuration of a single filter bank.
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum BankConfig {
+    List32([ListEntry32; 2]),
+    Mask32(Mask32),
+}
+
+impl From<Mask32> for BankConfig {
+    fn from(filter: Mask32) -> Self {
+        Self::Mask32(filter)
+    }
+}
+
+impl From<[ListEntry32; 2]> for BankConfig {
+    fn from(filters: [ListEntry32; 2]) -> Self {
+        Self::List32(filters)
+    }
+}
+
+/// Polling CAN driver with the method names of `bxcan::Can`.
+pub struct Can<'d, T: Instance> {
+    can: driver::Can<'d, T>,
+}
+
+impl<'d, T: Instance> Can<'d, T> {
+    /// Wraps a driver created with [crate::Can::new], on which interrupts must not
+    /// be enabled.
+    pub fn new(can: driver::Can<'d, T>) -> Self {
+        Self { can }
+    }
+
+    pub fn free(self) -> driver::Can<'d, T> {
+        self.can
+    }
+
+    pub fn modify_filters(&mut self) -> MasterFilters<'_, T> {
+        MasterFilters {
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Puts `frame` in a free transmit mailbox. Unlike bxcan, a pending frame of
+    /// lower priority is never replaced, so this always returns `Ok(None)` or
+    /// `Err(WouldBlock)`.
+    pub fn transmit(&mut self, frame: &Frame) -> nb::Result<Option<Frame>, Infallible> {
+        transmit::<T>(frame)
+    }
+
+    pub fn is_transmitter_idle(&self) -> bool {
+        Registers(T::regs()).all_mailboxes_empty()
+    }
+
+    /// Returns a frame from FIFO 0, or else from FIFO 1.
+    pub fn receive(&mut self) -> nb::Result<Frame, OverrunError> {
+        match receive::<T>(Fifo::Fifo0) {
+            Err(nb::Error::WouldBlock) => receive::<T>(Fifo::Fifo1),
+            result => result,
+        }
+    }
+
+    pub fn sleep(&mut self) {
+        self.can.sleep();
+    }
+
+    pub fn wakeup(&mut self) {
+        self.can.wakeup();
+    }
+
+    pub fn split(self) -> (Tx<'d, T>, Rx0<T>, Rx1<T>) {
+        (
+            Tx { can: self.can },
+            Rx0 {
+                _phantom: PhantomData,
+            },
+            Rx1 {
+                _phantom: PhantomData,
+            },
+        )
+    }
+}
+
+/// Transmit half returned by [Can::split].
+pub struct Tx<'d, T: Instance> {
+    can: driver::Can<'d, T>,
+}
+
+impl<'d, T: Instance> Tx<'d, T> {
+    /// See [Can::transmit].
+    pub fn transmit(&mut self, frame: &Frame) -> nb::Result<Option<Frame>, Infallible> {
+        transmit::<T>(frame)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        Registers(T::regs()).all_mailboxes_empty()
+    }
+
+    /// Gives back the driver. The receive halves must not be used anymore.
+    pub fn free(self) -> driver::Can<'d, T> {
+        self.can
+    }
+}
+
+/// FIFO 0 receive half returned by [Can::split].
+pub struct Rx0<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> Rx0<T> {
+    pub fn receive(&mut self) -> nb::Result<Frame, OverrunError> {
+        receive::<T>(Fifo::Fifo0)
+    }
+}
+
+/// FIFO 1 receive half returned by [Can::split].
+pub struct Rx1<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> Rx1<T> {
+    pub fn receive(&mut self) -> nb::Result<Frame, OverrunError> {
+        receive::<T>(Fifo::Fifo1)
+    }
+}
+
+/// Filter bank configuration returned by [Can::modify_filters].
+pub struct MasterFilters<'a, T: Instance> {
+    _phantom: PhantomData<&'a mut T>,
+}
+
+impl<T: Instance> MasterFilters<'_, T> {
+    /// Disables all filter banks of this peripheral, no frames are received anymore.
+    pub fn clear(&mut self) -> &mut Self {
+        Registers(T::filter_regs()).set_active_filters(0, T::filter_banks());
+        self
+    }
+
+    /// Configures filter bank `index` and routes the frames it accepts to `fifo`.
+    pub fn enable_bank(
+        &mut self,
+        index: u8,
+        fifo: Fifo,
+        config: impl Into<BankConfig>,
+    ) -> &mut Self {
+        let (mode, id_value, id_mask) = match config.into() {
+            BankConfig::Mask32(filter) => (CanFilterMode::IdMask, filter.id, filter.mask),
+            BankConfig::List32([first, second]) => (CanFilterMode::IdList, first.0, second.0),
+        };
+        let filter = CanFilter {
+            bank: index as usize,
+            mode,
+            id_value,
+            id_mask,
+        };
+
+        driver::check_filter_bank::<T>(filter.bank);
+        Registers(T::filter_regs()).add_filter(filter, &fifo);
+        self
+    }
+
+    pub fn disable_bank(&mut self, index: u8) -> &mut Self {
+        driver::check_filter_bank::<T>(index as usize);
+        Registers(T::filter_regs()).set_active_filters(0, 1 << index);
+        self
+    }
+}
+
+fn transmit<T: Instance>(frame: &Frame) -> nb::Result<Option<Frame>, Infallible> {
+    match interrupt::transmit_direct::<T>(&frame.to_can_frame(), true) {
+        Ok(_) => Ok(None),
+        Err(_) => Err(nb::Error::WouldBlock),
+    }
+}
+
+fn receive<T: Instance>(fifo: Fifo) -> nb::Result<Frame, OverrunError> {
+    let regs = Registers(T::regs());
+    if regs.take_fifo_overrun(&fifo) {
+        return Err(nb::Error::Other(OverrunError { _priv: () }));
+    }
+    if !regs.fifo_has_messages_pending(&fifo) {
+        return Err(nb::Error::WouldBlock);
+    }
+
+    Ok(Frame::from_can_frame(&regs.read_frame_fifo(&fifo)))
+}
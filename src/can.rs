@@ -1,36 +1,103 @@
+use core::cell::Cell;
+use core::marker::PhantomData;
+
+#[cfg(feature = "async")]
+use crate::asynch::{CanRx, CanTx};
+#[cfg(feature = "async")]
+use crate::busoff::BusOffSupervisor;
 use crate::enums::*;
 use crate::frame::CanFrame;
 use crate::hal;
+use crate::interface::CanInterface;
+use crate::interrupt::{self, InterruptResources};
 use crate::pac;
-use crate::registers::Registers;
-use crate::util;
+use crate::pool::{FramePool, PooledFrame};
+use crate::registers::{Registers, Timeout};
+use crate::timing;
+use crate::transceiver::CanTransceiver;
+use crate::txqueue::TxQueue;
 
 pub struct Can<'d, T: Instance> {
     _peri: hal::PeripheralRef<'d, T>,
     fifo: CanFifo,
-    last_mailbox_used: usize,
+    last_tx: Cell<Option<TxHandle>>,
+    bit_timing: timing::NominalBitTiming,
+    tx_timeout_us: u32,
+    init_timeout_us: u32,
 }
 
 impl<'d, T: Instance> Can<'d, T> {
-    /// Assumes AFIO & PORTB clocks have been enabled by HAL.
+    /// Assumes AFIO & GPIO port clocks have been enabled by HAL.
+    ///
+    /// The pin remap is selected from `rx` and `tx`: PA11/PA12, PB8/PB9 or, on
+    /// CH32V303/307, PD0/PD1 on CAN1, and PB12/PB13 or PB5/PB6 on CAN2.
+    ///
+    /// CAN2 has no filter banks of its own, it uses those of CAN1, whose clock is
+    /// enabled for it. When both controllers are used, create CAN1 first, as its
+    /// reset also clears the filters configured for CAN2. See [Can::set_filter_split].
     ///
-    /// CAN_RX is mapped to PB8, and CAN_TX is mapped to PB9.
+    /// Bit timing is computed from the APB1 clock frequency recorded by `hal::init`,
+    /// so it follows whatever clock tree was configured. Panics if the clocks were
+    /// never initialized.
     pub fn new(
         peri: impl hal::Peripheral<P = T> + 'd,
         rx: impl hal::Peripheral<P = impl RxPin<T>> + 'd,
         tx: impl hal::Peripheral<P = impl TxPin<T>> + 'd,
         fifo: CanFifo,
         mode: CanMode,
-        bitrate: u32,
+        bitrate: impl Into<Bitrate>,
+    ) -> Self {
+        Self::new_with_config(peri, rx, tx, fifo, mode, CanConfig::new(bitrate))
+    }
+
+    /// Same as [Can::new], with bit timing parameters taken from `config`.
+    pub fn new_with_config(
+        peri: impl hal::Peripheral<P = T> + 'd,
+        rx: impl hal::Peripheral<P = impl RxPin<T>> + 'd,
+        tx: impl hal::Peripheral<P = impl TxPin<T>> + 'd,
+        fifo: CanFifo,
+        mode: CanMode,
+        config: CanConfig,
     ) -> Self {
         hal::into_ref!(peri, rx, tx);
 
+        let can_clock = T::frequency().0; // APB1 clock, as configured in RCC
+        if can_clock == 0 {
+            fail!("CAN clock frequency is unknown, call hal::init before creating the driver.");
+        }
+
+        // Configure bit timing parameters and CAN operating mode
+        let bit_timings = match config.bit_timing {
+            Some(bit_timing) => bit_timing
+                .to_nominal()
+                .unwrap_or_else(|| fail!("Bit timing parameters are out of the hardware's range.")),
+            None => config
+                .bitrate
+                .and_then(|bitrate| {
+                    timing::calc_can_timings_within(
+                        can_clock,
+                        bitrate.bps(),
+                        config.sample_point_permill,
+                        config.max_deviation_ppm,
+                    )
+                })
+                .unwrap_or_else(|| {
+                    fail!("Bit timing parameters weren't satisfied for CAN clock rate and desired bitrate.")
+                })
+                .with_sync_jump_width(config.sync_jump_width)
+                .unwrap_or_else(|| fail!("Sync jump width is out of range for the bit timing.")),
+        };
+
         let this = Self {
             _peri: peri,
             fifo,
-            last_mailbox_used: usize::MAX,
+            last_tx: Cell::new(None),
+            bit_timing: bit_timings,
+            tx_timeout_us: config.tx_timeout_us,
+            init_timeout_us: config.init_timeout_us,
         };
         T::enable_and_reset(); // Enable CAN peripheral
+        T::enable_filters();
 
         rx.set_mode_cnf(
             pac::gpio::vals::Mode::INPUT,
@@ -40,23 +107,169 @@ impl<'d, T: Instance> Can<'d, T> {
             pac::gpio::vals::Mode::OUTPUT_50MHZ,
             pac::gpio::vals::Cnf::PULL_IN__AF_PUSH_PULL_OUT,
         );
-        T::remap(0b10); // CAN_RX is mapped to PB8, and CAN_TX is mapped to PB9
+        let remap = rx.remap();
+        if tx.remap() != remap {
+            fail!("CAN RX and TX pins must belong to the same remap.");
+        }
+        T::remap(remap);
 
-        Registers(T::regs()).enter_init_mode(); // CAN enter initialization mode
+        if !Registers(T::regs()).configure(bit_timings, mode, this.init_timeout()) {
+            fail!("CAN controller did not acknowledge initialization mode in time.");
+        }
+        Registers(T::regs()).enable_error_flags(); // Polled errors are reported on change
 
-        // Configure bit timing parameters and CAN operating mode
-        let bit_timings = util::calc_can_timings(T::frequency().0, bitrate).expect(
-            "Bit timing parameters weren't satisfied for CAN clock rate and desired bitrate.",
-        );
-        Registers(T::regs()).set_bit_timing_and_mode(bit_timings, mode);
+        this
+    }
 
-        Registers(T::regs()).leave_init_mode(); // Exit CAN initialization mode
+    /// Changes the operating mode and bit timing, e.g. on request of a host. The
+    /// controller goes through initialization mode, so it leaves bus-off and frames
+    /// in flight may be lost. Nothing is changed if the bit timing of `config`
+    /// can't be achieved.
+    ///
+    /// The timeouts of `config` apply from this call on. If the controller doesn't
+    /// acknowledge initialization mode in time, it is left as is, possibly still in
+    /// initialization mode, and [ConfigError::InitTimeout] is returned.
+    pub fn reconfigure(&mut self, mode: CanMode, config: &CanConfig) -> Result<(), ConfigError> {
+        let bit_timing = match config.bit_timing {
+            Some(bit_timing) => bit_timing.to_nominal(),
+            None => config
+                .bitrate
+                .and_then(|bitrate| {
+                    timing::calc_can_timings_within(
+                        T::frequency().0,
+                        bitrate.bps(),
+                        config.sample_point_permill,
+                        config.max_deviation_ppm,
+                    )
+                })
+                .and_then(|bit_timing| bit_timing.with_sync_jump_width(config.sync_jump_width)),
+        }
+        .ok_or(ConfigError::InvalidBitTiming)?;
 
-        this
+        self.tx_timeout_us = config.tx_timeout_us;
+        self.init_timeout_us = config.init_timeout_us;
+        if !Registers(T::regs()).configure(bit_timing, mode, self.init_timeout()) {
+            return Err(ConfigError::InitTimeout);
+        }
+        T::state().clear_forced_bus_off();
+        self.bit_timing = bit_timing;
+
+        Ok(())
+    }
+
+    /// Bit timing in use, see [crate::NominalBitTiming::sample_point_permill] and
+    /// [crate::NominalBitTiming::bitrate] for the sample point and bitrate actually
+    /// achieved.
+    pub fn bit_timing(&self) -> timing::NominalBitTiming {
+        self.bit_timing
+    }
+
+    /// Frequency of the clock feeding the peripheral (APB1).
+    pub fn clock_frequency(&self) -> hal::time::Hertz {
+        T::frequency()
     }
 
     pub fn add_filter(&self, filter: CanFilter) {
-        Registers(T::regs()).add_filter(filter, &self.fifo);
+        check_filter_bank::<T>(filter.bank);
+        Registers(T::filter_regs()).add_filter(filter, &self.fifo);
+    }
+
+    /// Adds `filter` on the lowest filter bank of this peripheral not in use, ignoring
+    /// `filter.bank`.
+    pub fn add_filter_on_free_bank(&self, filter: CanFilter) -> Result<(), NoFreeFilter> {
+        let free = T::filter_banks() & !Registers(T::filter_regs()).active_filters();
+        if free == 0 {
+            return Err(NoFreeFilter);
+        }
+
+        self.add_filter(CanFilter {
+            bank: free.trailing_zeros() as usize,
+            ..filter
+        });
+
+        Ok(())
+    }
+
+    pub fn bus_state(&self) -> BusState {
+        if T::state().forced_bus_off() {
+            return BusState::BusOff;
+        }
+
+        Registers(T::regs()).bus_state()
+    }
+
+    /// Bus state and error counters, e.g. for periodic telemetry.
+    pub fn health(&self) -> BusHealth {
+        let (tx_errors, rx_errors) = Registers(T::regs()).error_counters();
+
+        BusHealth {
+            state: self.bus_state(),
+            tx_errors,
+            rx_errors,
+        }
+    }
+
+    /// Forces a condition that is hard to reproduce on a real bus, so error handling
+    /// paths can be exercised deterministically. Errors and events are reported
+    /// right away, through [Can::receive] once split, [Can::take_event] and
+    /// [Can::on_error], as if the peripheral had raised them.
+    ///
+    /// A forced Bus Off lasts until the controller goes through initialization
+    /// mode, on [Can::reconfigure] or when recovered by a [BusOffSupervisor].
+    ///
+    /// Panics if an [InjectedFault::LastErrorCode] is not between 1 and 6.
+    #[cfg(feature = "fault-injection")]
+    pub fn inject_fault(&self, fault: InjectedFault) {
+        interrupt::inject_fault::<T>(fault);
+    }
+
+    /// Assigns filter banks `0..can2_start_bank` to CAN1 and the banks from
+    /// `can2_start_bank` to 27 to CAN2. The hardware default is 14.
+    ///
+    /// The split is shared by both controllers, so it can be set from either. Filter
+    /// bank numbers are absolute: [Can::add_filter] panics if the bank belongs to
+    /// the other controller.
+    #[cfg(feature = "_can2")]
+    pub fn set_filter_split(&self, can2_start_bank: u8) {
+        if can2_start_bank as usize > FILTER_BANKS {
+            fail!("CAN2 start filter bank is out of range.");
+        }
+
+        Registers(pac::CAN1).set_can2_start_bank(can2_start_bank);
+    }
+
+    /// Adds an [CanFilterMode::IdMask] filter that spreads matching frames over both
+    /// receive FIFOs, doubling the hardware buffering available for bursts.
+    ///
+    /// The filter is installed on banks `filter.bank` and `filter.bank + 1`: frames
+    /// whose standard (or extended base) ID is even go to FIFO 0, odd ones to FIFO 1.
+    /// Frames with the same ID therefore keep their order, but frames from
+    /// different FIFOs may be returned out of arrival order. Both FIFOs raise
+    /// interrupts once they are enabled, and [Can::receive] reads from both.
+    ///
+    /// With interrupts split, the two receive handlers must not preempt each other
+    /// if a pool is set with [Can::set_rx_pool].
+    pub fn add_burst_filter(&self, filter: CanFilter) {
+        if matches!(filter.mode, CanFilterMode::IdList) {
+            fail!("CAN burst filters must be in IdMask mode.");
+        }
+
+        const STID_LSB: u32 = 1 << 21;
+        for fifo in [CanFifo::Fifo0, CanFifo::Fifo1] {
+            let bank_filter = CanFilter {
+                bank: filter.bank + fifo.val(),
+                mode: CanFilterMode::IdMask,
+                id_value: (filter.id_value & !STID_LSB) | (fifo.val() as u32) << 21,
+                id_mask: filter.id_mask | STID_LSB,
+            };
+            check_filter_bank::<T>(bank_filter.bank);
+            Registers(T::filter_regs()).add_filter(bank_filter, &fifo);
+        }
+
+        T::state().mark_burst();
+        if T::state().is_split() {
+            Registers(T::regs()).enable_interrupts(&self.fifo.other());
+        }
     }
 
     /// Puts a frame in the transmit buffer to be sent on the bus.
@@ -66,39 +279,688 @@ impl<'d, T: Instance> Can<'d, T> {
     /// Returns `Err(WouldBlock)` if the transmit buffer is full and no frame can be
     /// replaced.
     pub fn transmit(&self, frame: &CanFrame) -> nb::Result<Option<CanFrame>, CanError> {
-        let mailbox_num = match Registers(T::regs()).find_free_mailbox() {
-            Some(n) => n,
-            None => return Err(nb::Error::WouldBlock),
-        };
-
-        Registers(T::regs()).write_frame_mailbox(mailbox_num, frame);
+        self.transmit_tracked(frame)?;
 
         // Success in readying packet for transmit. No packets can be replaced in the
         // transmit buffer so return None in accordance with embedded-can.
         Ok(None)
     }
 
-    /// Retrieves status of the last frame transmission
-    pub fn transmit_status(&self) -> TxStatus {
-        if self.last_mailbox_used > 2 {
-            return TxStatus::OtherError;
+    /// Puts a frame in the transmit buffer like [Can::transmit], returning a handle
+    /// to follow this specific frame with [Can::poll_tx_result].
+    ///
+    /// Frames waiting in the software queue of the async [CanTx::write] are bypassed,
+    /// except in [TxOrder::Fifo] mode.
+    pub fn transmit_tracked(&self, frame: &CanFrame) -> nb::Result<TxHandle, CanError> {
+        let handle = interrupt::transmit_direct::<T>(frame, true)?;
+        self.last_tx.set(Some(handle));
+
+        Ok(handle)
+    }
+
+    /// Loads `frame` into a free transmit mailbox with as few register accesses as
+    /// possible, for high-rate interrupt handlers like the one of a [crate::Gateway]:
+    /// one status read and four writes, outside of a critical section. The frame is
+    /// written as is, it was validated when constructed. The throughput scenario
+    /// prints the cycles it takes per frame next to those of [Can::transmit].
+    ///
+    /// The software transmit queue and [TxOrder] are bypassed. Returns
+    /// `Err(WouldBlock)` if all mailboxes are full.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may load the mailboxes of this peripheral meanwhile: no other
+    /// transmit call from a context that can preempt this one, and no frame waiting
+    /// in the queue of [CanTx::write] that the transmit interrupt could load.
+    #[inline]
+    pub unsafe fn transmit_unchecked(&self, frame: &CanFrame) -> nb::Result<TxHandle, CanError> {
+        let regs = Registers(T::regs());
+        let mailbox_num = regs.find_free_mailbox().ok_or(nb::Error::WouldBlock)?;
+
+        let handle = T::state().next_tx_handle(mailbox_num);
+        regs.write_frame_mailbox_unchecked(mailbox_num, frame, T::state().tx_time_append());
+        self.last_tx.set(Some(handle));
+
+        Ok(handle)
+    }
+
+    /// Moves frames from `queue` into free transmit mailboxes, highest priority
+    /// first, returning how many were loaded. Call it again whenever a mailbox frees
+    /// up, e.g. on [CanEvent::TxComplete].
+    pub fn transmit_queued<const N: usize>(&self, queue: &mut TxQueue<N>) -> usize {
+        let mut loaded = 0;
+        while let Some(frame) = queue.peek() {
+            if interrupt::transmit_direct::<T>(frame, true).is_err() {
+                break;
+            }
+            queue.pop();
+            loaded += 1;
         }
 
-        Registers(T::regs()).transmit_status(self.last_mailbox_used)
+        loaded
     }
 
-    /// Returns a received frame if available.
+    /// Selects the order in which frames are sent, see [TxOrder]. Defaults to
+    /// [TxOrder::Priority].
+    pub fn set_tx_order(&self, order: TxOrder) {
+        interrupt::set_tx_order::<T>(order);
+    }
+
+    /// Returns the outcome of the frame identified by `handle`, or `Err(WouldBlock)`
+    /// while it is still pending in its mailbox.
+    ///
+    /// Outcomes are recorded by the transmit interrupt once the driver is split, or
+    /// read from the status registers otherwise. If the mailbox was reused before
+    /// the outcome was polled, [TxStatus::OtherError] is returned.
+    pub fn poll_tx_result(&self, handle: TxHandle) -> nb::Result<TxStatus, CanError> {
+        if !T::state().is_split() {
+            interrupt::poll_events::<T>(&self.fifo);
+        }
+
+        match T::state().tx_result(handle) {
+            Ok(Some(status)) => Ok(status),
+            Ok(None) => Err(nb::Error::WouldBlock),
+            Err(()) => Ok(TxStatus::OtherError),
+        }
+    }
+
+    /// Same as [Can::poll_tx_result], with a failure returned as `Err(Other)`, e.g.
+    /// `nb::block!(can.poll_tx_outcome(handle))?`.
+    pub fn poll_tx_outcome(&self, handle: TxHandle) -> nb::Result<TxOk, TxError> {
+        let status = match self.poll_tx_result(handle) {
+            Ok(status) => status,
+            Err(_) => return Err(nb::Error::WouldBlock),
+        };
+
+        status
+            .outcome(handle.mailbox, self.tx_timestamp(handle))
+            .map_err(nb::Error::Other)
+    }
+
+    /// State of the transmit mailboxes, e.g. to find out why frames are not going
+    /// out: lost arbitration to higher priority traffic, or bus errors.
+    pub fn tx_mailbox_status(&self) -> TxMailboxStatus {
+        Registers(T::regs()).tx_mailbox_status()
+    }
+
+    /// Returns the timer value captured at the start of the frame identified by
+    /// `handle`, once it has been sent in time-triggered mode and as long as its
+    /// mailbox hasn't been reused.
+    pub fn tx_timestamp(&self, handle: TxHandle) -> Option<u16> {
+        if !Registers(T::regs()).time_triggered_mode() {
+            return None;
+        }
+        if !T::state().is_split() {
+            interrupt::poll_events::<T>(&self.fifo);
+        }
+
+        T::state().tx_time(handle)
+    }
+
+    /// Returns the time source value when the frame identified by `handle` was
+    /// confirmed sent or failed, see [Can::set_time_source], as long as its mailbox
+    /// hasn't been reused. Without interrupts, it is when the outcome was polled.
+    pub fn tx_confirmed_at(&self, handle: TxHandle) -> Option<u32> {
+        if !T::state().has_time_source() {
+            return None;
+        }
+        if !T::state().is_split() {
+            interrupt::poll_events::<T>(&self.fifo);
+        }
+
+        T::state().tx_confirm_time(handle)
+    }
+
+    /// Retrieves status of the last frame transmission, waiting for it to complete
+    /// for up to [CanConfig::tx_timeout_us]. With [Can::set_wfi_wait], time spent
+    /// asleep between two interrupts is not counted.
+    pub fn transmit_status(&self) -> TxStatus {
+        let handle = match self.last_tx.get() {
+            Some(handle) => handle,
+            None => return TxStatus::OtherError,
+        };
+
+        let timeout = timeout_us(self.tx_timeout_us);
+        let mut polls: u32 = 0;
+        interrupt::wait_until::<T, _>(|| match self.poll_tx_result(handle) {
+            Ok(status) => Some(status),
+            Err(_) if polls == timeout.polls => Some(TxStatus::TimeoutError),
+            Err(_) => {
+                polls += 1;
+                (timeout.pause)();
+                None
+            }
+        })
+    }
+
+    /// Same as [Can::transmit_status], as a [TxOutcome]. If no frame was sent yet,
+    /// the error reports mailbox 0.
+    pub fn transmit_outcome(&self) -> TxOutcome {
+        let mailbox = self.last_tx.get().map_or(0, |handle| handle.mailbox);
+        let timestamp = self
+            .last_tx
+            .get()
+            .and_then(|handle| self.tx_timestamp(handle));
+
+        self.transmit_status().outcome(mailbox, timestamp)
+    }
+
+    /// Returns a received frame if available. A bus error found since the last call,
+    /// e.g. while polling events with [Can::take_event], is returned first, once.
+    ///
+    /// Once the driver has been split with [Can::split_interrupt_resources], frames
+    /// and bus errors are taken from the queues filled by the interrupt handlers.
     pub fn receive(&self) -> nb::Result<CanFrame, CanError> {
-        if !Registers(T::regs()).fifo_has_messages_pending(&self.fifo) {
-            return nb::Result::Err(nb::Error::WouldBlock);
+        receive_frame::<T>(&self.fifo)
+    }
+
+    /// Waits for a received frame or a bus error.
+    pub fn receive_blocking(&self) -> Result<CanFrame, CanError> {
+        interrupt::wait_until::<T, _>(|| match self.receive() {
+            Ok(frame) => Some(Ok(frame)),
+            Err(nb::Error::Other(error)) => Some(Err(error)),
+            Err(nb::Error::WouldBlock) => None,
+        })
+    }
+
+    /// Returns the oldest received frame with identifier `id` if available.
+    ///
+    /// Frames with other identifiers received in the meantime are set aside and
+    /// returned first by [Can::receive], in order. Up to 8 frames can be set aside;
+    /// past that, a skipped frame is dropped and [CanErrorKind::Overrun] is returned.
+    pub fn receive_id(&self, id: impl Into<embedded_can::Id>) -> nb::Result<CanFrame, CanError> {
+        receive_frame_id::<T>(&self.fifo, id.into())
+    }
+
+    /// Transmits `frame` and waits for the first response with identifier
+    /// `expected_id`, giving up with [RequestError::Timeout] after `timeout_ms`,
+    /// checking every 100 µs.
+    ///
+    /// A frame with that identifier set aside before the call is stale and dropped.
+    /// Unrelated frames received in the meantime are set aside for [Can::receive],
+    /// see [Can::receive_id]; past 8, they are dropped without failing the request.
+    pub fn request(
+        &self,
+        frame: &CanFrame,
+        expected_id: impl Into<embedded_can::Id>,
+        timeout_ms: u32,
+        delay: &mut impl embedded_hal::delay::DelayNs,
+    ) -> Result<CanFrame, RequestError> {
+        let expected_id = expected_id.into();
+        while T::state().take_deferred(Some(expected_id)).is_some() {}
+
+        let mut sent = false;
+        for _ in 0..=timeout_ms.saturating_mul(10) {
+            if !sent {
+                match self.transmit(frame) {
+                    Ok(_) => sent = true,
+                    Err(nb::Error::Other(error)) => return Err(error.into()),
+                    Err(nb::Error::WouldBlock) => {}
+                }
+            }
+
+            if sent {
+                match self.receive_id(expected_id) {
+                    Ok(response) => return Ok(response),
+                    Err(nb::Error::Other(error)) if error.kind == CanErrorKind::Overrun => {}
+                    Err(nb::Error::Other(error)) => return Err(error.into()),
+                    Err(nb::Error::WouldBlock) => {}
+                }
+            }
+            delay.delay_us(100);
         }
 
-        let frame = Registers(T::regs()).read_frame_fifo(&self.fifo);
+        Err(RequestError::Timeout)
+    }
+
+    /// Takes the next pending [CanEvent].
+    ///
+    /// Without interrupts, the status registers are inspected on every call; once
+    /// the driver is split, events raised by the interrupt handlers are returned.
+    pub fn take_event(&self) -> Option<CanEvent> {
+        if !T::state().is_split() {
+            interrupt::poll_events::<T>(&self.fifo);
+        }
+
+        T::state().take_event()
+    }
+
+    /// Registers a callback run from the receive interrupt for every frame.
+    ///
+    /// Frames handed to the callback are not queued for [Can::receive].
+    pub fn on_rx(&self, callback: fn(&CanFrame)) {
+        T::state().set_rx_callback(Some(callback));
+    }
+
+    /// Registers a callback run from the transmit interrupt for every completed
+    /// request, with its outcome and, in time-triggered mode, the time it started on
+    /// the bus, e.g. to measure the latency from the request.
+    pub fn on_tx_complete(&self, callback: fn(TxCompletion)) {
+        T::state().set_tx_callback(Some(callback));
+    }
+
+    /// Registers a callback run from the status change & error interrupt for every
+    /// bus error.
+    pub fn on_error(&self, callback: fn(CanError)) {
+        T::state().set_error_callback(Some(callback));
+    }
+
+    /// Registers a monotonic time source sampled for every received frame, see
+    /// [CanFrame::timestamp]. It runs in the receive interrupt once interrupts are
+    /// enabled, so it must be cheap and must not block, e.g. a free-running timer
+    /// counter read.
+    pub fn set_time_source(&self, now: fn() -> u32) {
+        T::state().set_time_source(Some(now));
+    }
+
+    /// Timestamps received frames and transmit confirmations in microseconds, with
+    /// [crate::systick_micros] as time source. The HAL's SysTick time driver must be
+    /// running.
+    pub fn enable_systick_timestamps(&self) {
+        self.set_time_source(crate::systick::systick_micros);
+    }
+
+    /// Removes all callbacks registered with [Can::on_rx], [Can::on_tx_complete] and
+    /// [Can::on_error].
+    pub fn clear_callbacks(&self) {
+        T::state().set_rx_callback(None);
+        T::state().set_tx_callback(None);
+        T::state().set_error_callback(None);
+    }
+
+    /// Enables time-triggered communication mode: the 16-bit CAN bit-time counter is
+    /// captured at the start of every frame, see [CanFrame::hardware_timestamp] and
+    /// [Can::tx_timestamp].
+    ///
+    /// With `append_tx_timestamp` set, the hardware also overwrites the last two data
+    /// bytes of every transmitted frame with the counter value, where TTCAN-style
+    /// schedules expect it; such frames are always sent with 8 data bytes.
+    ///
+    /// The peripheral briefly goes through init mode, dropping off the bus meanwhile.
+    /// Panics if the controller doesn't acknowledge initialization mode within
+    /// [CanConfig::init_timeout_us].
+    pub fn enable_time_triggered_mode(&self, append_tx_timestamp: bool) {
+        T::state().set_tx_time_append(append_tx_timestamp);
+        self.set_time_triggered_mode(true);
+    }
 
-        Ok(frame)
+    pub fn disable_time_triggered_mode(&self) {
+        T::state().set_tx_time_append(false);
+        self.set_time_triggered_mode(false);
+    }
+
+    fn set_time_triggered_mode(&self, enabled: bool) {
+        let regs = Registers(T::regs());
+        let acknowledged = regs.enter_init_mode(self.init_timeout()) && {
+            regs.set_time_triggered_mode(enabled);
+            regs.leave_init_mode(self.init_timeout())
+        };
+        if !acknowledged {
+            fail!("CAN controller did not acknowledge initialization mode in time.");
+        }
+    }
+
+    fn init_timeout(&self) -> Timeout {
+        timeout_us(self.init_timeout_us)
+    }
+
+    /// Puts the peripheral in sleep mode, waiting until it is acknowledged. A frame
+    /// being transmitted or received on the bus is completed first.
+    ///
+    /// Returns `false` if sleep mode wasn't acknowledged within
+    /// [CanConfig::init_timeout_us], e.g. while the bus is held dominant.
+    pub fn sleep(&self) -> bool {
+        Registers(T::regs()).enter_sleep_mode(self.init_timeout())
+    }
+
+    /// Takes the peripheral out of sleep mode, waiting until it has synchronized to
+    /// the bus again (11 consecutive recessive bits).
+    ///
+    /// Returns `false` if it didn't synchronize within [CanConfig::init_timeout_us].
+    /// The request stays pending, so the peripheral leaves sleep mode once the bus
+    /// goes recessive, see [Can::is_sleeping].
+    pub fn wakeup(&self) -> bool {
+        Registers(T::regs()).leave_sleep_mode(self.init_timeout())
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        Registers(T::regs()).is_sleeping()
+    }
+
+    /// Puts the peripheral in sleep mode like [Can::sleep], then `transceiver` in
+    /// standby mode. Returns whether sleep mode was acknowledged.
+    pub fn sleep_with_transceiver<X: CanTransceiver>(
+        &self,
+        transceiver: &mut X,
+    ) -> Result<bool, X::Error> {
+        let asleep = self.sleep();
+        transceiver.standby()?;
+
+        Ok(asleep)
+    }
+
+    /// Enables `transceiver`, waits for its wake-up delay, then takes the peripheral
+    /// out of sleep mode like [Can::wakeup]. Returns whether leaving sleep mode was
+    /// acknowledged.
+    pub fn wakeup_with_transceiver<X: CanTransceiver>(
+        &self,
+        transceiver: &mut X,
+        delay: &mut impl embedded_hal::delay::DelayNs,
+    ) -> Result<bool, X::Error> {
+        transceiver.enable()?;
+        delay.delay_us(transceiver.wake_delay_us());
+
+        Ok(self.wakeup())
+    }
+
+    /// Parks the peripheral for a low-power period: only `wake_filter` is left
+    /// active, the wake-up interrupt is enabled and the peripheral is put to sleep.
+    ///
+    /// Any bus activity wakes the peripheral up, but once awake it only accepts the
+    /// frames matching `wake_filter`, so the application can check whether the
+    /// traffic concerns it before resuming. `wake_filter` should use a spare bank,
+    /// as its previous configuration is overwritten. Hand the returned token to
+    /// [Can::resume] to restore the filters that were active before.
+    ///
+    /// If sleep mode isn't acknowledged within [CanConfig::init_timeout_us], the
+    /// peripheral is left awake with only `wake_filter` active, see
+    /// [Can::is_sleeping].
+    pub fn enter_low_power(&self, wake_filter: CanFilter) -> WakeToken {
+        let token = WakeToken {
+            active_filters: Registers(T::filter_regs()).active_filters() & T::filter_banks(),
+        };
+
+        Registers(T::filter_regs()).set_active_filters(0, T::filter_banks());
+        self.add_filter(wake_filter);
+        Registers(T::regs()).set_wakeup_interrupt(true);
+        Registers(T::regs()).enter_sleep_mode(self.init_timeout());
+
+        token
+    }
+
+    /// Leaves the low-power period started with [Can::enter_low_power], waking the
+    /// peripheral up if needed and reactivating the filters that were active before.
+    ///
+    /// The filters are restored in any case. Returns `false` if leaving sleep mode
+    /// wasn't acknowledged within [CanConfig::init_timeout_us], see [Can::wakeup].
+    pub fn resume(&self, token: WakeToken) -> bool {
+        let awake = match Registers(T::regs()).is_sleeping() {
+            true => Registers(T::regs()).leave_sleep_mode(self.init_timeout()),
+            false => true,
+        };
+
+        Registers(T::filter_regs()).set_active_filters(token.active_filters, T::filter_banks());
+        awake
+    }
+
+    /// Returns a task that parks the node for `backoff` whenever it goes Bus Off,
+    /// putting the peripheral to sleep and `transceiver` in standby meanwhile. Pass
+    /// `()` as `transceiver` if it can't be controlled. See [BusOffSupervisor].
+    #[cfg(feature = "async")]
+    pub fn bus_off_supervisor<X: CanTransceiver>(
+        &self,
+        transceiver: X,
+        backoff: embassy_time::Duration,
+    ) -> BusOffSupervisor<T, X> {
+        BusOffSupervisor::new(transceiver, backoff, self.init_timeout())
+    }
+
+    /// Enables the wake-up interrupt, raised on the status change & error vector when
+    /// bus activity is detected while the peripheral is in sleep mode.
+    ///
+    /// When it fires, the handler takes the peripheral out of sleep mode and raises
+    /// [CanEvent::Wakeup]. The frame that caused the wake-up is not received.
+    pub fn enable_wakeup_interrupt(&self) {
+        Registers(T::regs()).set_wakeup_interrupt(true);
+    }
+
+    pub fn disable_wakeup_interrupt(&self) {
+        Registers(T::regs()).set_wakeup_interrupt(false);
+    }
+
+    /// Makes the hardware leave sleep mode by itself as soon as bus activity is
+    /// detected, instead of waiting for [Can::wakeup].
+    ///
+    /// The frame that caused the wake-up is still lost, as the receiver only
+    /// synchronizes to the bus once it is awake, so the wake-up interrupt is enabled
+    /// as well to report each wake-up as [CanEvent::Wakeup]. Senders should repeat
+    /// the first frame, or open with a dedicated wake-up frame.
+    pub fn enable_automatic_wakeup(&self) {
+        Registers(T::regs()).set_automatic_wakeup(true);
+        Registers(T::regs()).set_wakeup_interrupt(true);
+    }
+
+    /// Reverts [Can::enable_automatic_wakeup]. The wake-up interrupt stays enabled.
+    pub fn disable_automatic_wakeup(&self) {
+        Registers(T::regs()).set_automatic_wakeup(false);
+    }
+
+    /// Splits the driver into async transmit and receive halves.
+    #[cfg(feature = "async")]
+    pub fn split(&mut self) -> (CanTx<'_, T>, CanRx<'_, T>) {
+        (CanTx::new(), CanRx::new(self.fifo))
+    }
+
+    /// Makes the receive interrupt decode frames straight into `pool`, to be read
+    /// with [Can::receive_pooled] instead of [Can::receive].
+    ///
+    /// Panics if interrupts are already enabled.
+    pub fn set_rx_pool<const N: usize>(&self, pool: &'static FramePool<N>) {
+        T::state().set_pool(pool.slots());
+    }
+
+    /// Returns the next frame the receive interrupt stored in the pool set with
+    /// [Can::set_rx_pool]. Its slot is reused once the returned frame is dropped.
+    pub fn receive_pooled(&self) -> nb::Result<PooledFrame, CanError> {
+        let state = T::state();
+        if let Some(error) = state.take_error() {
+            return Err(nb::Error::Other(error));
+        }
+
+        state.pop_pooled().ok_or(nb::Error::WouldBlock)
+    }
+
+    /// Follows identifier `id`: from now on, the receive interrupt keeps the last
+    /// frame received with it in the returned [Watch], instead of queuing it for
+    /// [Can::receive] or the callback. Up to 4 identifiers can be followed at once.
+    ///
+    /// Frames are only kept once interrupts are enabled, see [Can::enable_interrupts].
+    pub fn watch(&self, id: impl Into<embedded_can::Id>) -> Result<Watch<T>, WatchFull> {
+        let slot = T::state().watch(id.into()).ok_or(WatchFull)?;
+
+        Ok(Watch {
+            slot,
+            _instance: PhantomData,
+        })
+    }
+
+    /// Stops following the identifier of `watch`, whose frames are queued again.
+    pub fn unwatch(&self, watch: Watch<T>) {
+        T::state().unwatch(watch.slot);
+    }
+
+    /// Makes [Can::transmit_status] and [Can::receive_blocking] sleep with `wfi`
+    /// between checks instead of spinning, once interrupts are enabled.
+    ///
+    /// The core is then woken up by the CAN interrupts, or any other, so the CAN
+    /// vectors must be unmasked in the PFIC. With [Can::transmit_status], the timeout
+    /// then counts wake-ups. Init and sleep mode changes keep spinning, as the
+    /// peripheral raises no interrupt when it acknowledges them.
+    pub fn set_wfi_wait(&self, enabled: bool) {
+        T::state().set_wfi(enabled);
+    }
+
+    /// Enables the CAN interrupts, to be serviced by [Can::on_interrupt].
+    ///
+    /// From then on, frames and bus errors are read from the software queues the
+    /// interrupt handler fills, as with [Can::split_interrupt_resources].
+    ///
+    /// Panics if interrupts were already enabled for the same peripheral.
+    pub fn enable_interrupts(&self) {
+        T::state().mark_split();
+        Registers(T::regs()).enable_interrupts(&self.fifo);
+        if T::state().is_burst() {
+            Registers(T::regs()).enable_interrupts(&self.fifo.other());
+        }
+    }
+
+    /// Services all CAN interrupt sources: completed transmissions, both receive
+    /// FIFOs, and status changes & errors. Can be called from any of the four CAN
+    /// interrupt vectors, or from a single one they are multiplexed onto.
+    ///
+    /// # Safety
+    ///
+    /// Must not be called from contexts that can preempt each other, and must not be
+    /// mixed with the objects returned by [Can::split_interrupt_resources].
+    pub unsafe fn on_interrupt() {
+        interrupt::service_all::<T>();
+    }
+
+    /// Enables the CAN interrupts and splits the driver into one object per
+    /// interrupt vector plus this main-context handle, see [InterruptResources].
+    ///
+    /// Only the receive FIFO this instance was created with raises interrupts, or
+    /// both once [Can::add_burst_filter] is used.
+    /// Interrupt vectors still have to be unmasked in the PFIC, which RTIC does
+    /// for bound tasks.
+    ///
+    /// Panics if called more than once for the same peripheral.
+    pub fn split_interrupt_resources(self) -> InterruptResources<'d, T> {
+        self.enable_interrupts();
+
+        InterruptResources {
+            can: self,
+            tx: interrupt::TxIsr::new(),
+            rx0: interrupt::Rx0Isr::new(),
+            rx1: interrupt::Rx1Isr::new(),
+            sce: interrupt::SceIsr::new(),
+        }
     }
 }
 
+/// Loads `frame` into a free transmit mailbox.
+///
+/// Must run in a critical section, the transmit interrupt also loads mailboxes.
+pub(crate) fn transmit_frame<T: Instance>(frame: &CanFrame) -> nb::Result<TxHandle, CanError> {
+    let mailbox_num = match Registers(T::regs()).find_free_mailbox() {
+        Some(n) => n,
+        None => return Err(nb::Error::WouldBlock),
+    };
+
+    let handle = T::state().next_tx_handle(mailbox_num);
+    Registers(T::regs()).write_frame_mailbox(mailbox_num, frame, T::state().tx_time_append());
+
+    Ok(handle)
+}
+
+/// Takes a frame set aside by [Can::receive_id], or else a newly received one.
+pub(crate) fn receive_frame<T: Instance>(fifo: &CanFifo) -> nb::Result<CanFrame, CanError> {
+    if let Some(frame) = T::state().take_deferred(None) {
+        return Ok(frame);
+    }
+
+    receive_new_frame::<T>(fifo)
+}
+
+/// Last frame received with an identifier followed with [Can::watch].
+pub struct Watch<T: Instance> {
+    slot: usize,
+    _instance: PhantomData<T>,
+}
+
+impl<T: Instance> Watch<T> {
+    /// Last frame received, and how long ago in units of the time source if one is
+    /// set, see [Can::set_time_source]. `None` until a frame is received.
+    pub fn get(&self) -> Option<(CanFrame, Option<u32>)> {
+        let frame = T::state().watched(self.slot)?;
+        let age = frame
+            .timestamp
+            .zip(T::state().now())
+            .map(|(received, now)| now.wrapping_sub(received));
+
+        Some((frame, age))
+    }
+}
+
+/// Error returned by [Can::watch] when all slots are in use.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct WatchFull;
+
+/// Takes the oldest received frame with identifier `id`, setting the others aside
+/// for [receive_frame]. If there is no room left to set a frame aside, it is
+/// dropped and [CanErrorKind::Overrun] is returned.
+pub(crate) fn receive_frame_id<T: Instance>(
+    fifo: &CanFifo,
+    id: embedded_can::Id,
+) -> nb::Result<CanFrame, CanError> {
+    let state = T::state();
+    if let Some(frame) = state.take_deferred(Some(id)) {
+        return Ok(frame);
+    }
+
+    loop {
+        let frame = receive_new_frame::<T>(fifo)?;
+        if frame.id == id {
+            return Ok(frame);
+        }
+        if state.defer(frame).is_err() {
+            return Err(nb::Error::Other(CanError::overrun(*fifo)));
+        }
+    }
+}
+
+/// Takes a received frame from the software queue or the hardware FIFO, checking
+/// the other FIFO as well in burst mode.
+fn receive_new_frame<T: Instance>(fifo: &CanFifo) -> nb::Result<CanFrame, CanError> {
+    let state = T::state();
+    let fifos = [*fifo, fifo.other()];
+    let fifos = if state.is_burst() {
+        &fifos[..]
+    } else {
+        &fifos[..1]
+    };
+
+    // Without interrupts, errors are stored when the status registers are polled
+    if let Some(error) = state.take_error() {
+        return Err(nb::Error::Other(error));
+    }
+
+    if state.is_split() {
+        return fifos
+            .iter()
+            .find_map(|fifo| state.pop_frame(fifo))
+            .ok_or(nb::Error::WouldBlock);
+    }
+
+    let fifo = match fifos
+        .iter()
+        .find(|fifo| Registers(T::regs()).fifo_has_messages_pending(fifo))
+    {
+        Some(fifo) => fifo,
+        None => return nb::Result::Err(nb::Error::WouldBlock),
+    };
+
+    let timestamp = state.now();
+    let mut frame = Registers(T::regs()).read_frame_fifo(fifo);
+    frame.timestamp = timestamp;
+
+    Ok(frame)
+}
+
+/// Timeout of at least `us` microseconds, checking about once per microsecond of
+/// core clock.
+fn timeout_us(us: u32) -> Timeout {
+    Timeout {
+        polls: us,
+        pause: pause_1us,
+    }
+}
+
+fn pause_1us() {
+    riscv::asm::delay(hal::rcc::clocks().hclk.0 / 1_000_000);
+}
+
 /// These trait methods are only usable within the embedded_can context.
 /// Under normal use of the [Can] instance,
 impl<'d, T> embedded_can::nb::Can for Can<'d, T>
@@ -124,25 +986,142 @@ where
     }
 }
 
+impl<'d, T: Instance> CanInterface for Can<'d, T> {
+    fn add_id_filter(&mut self, id: embedded_can::Id, mask: u32) -> Result<(), NoFreeFilter> {
+        self.add_filter_on_free_bank(CanFilter::matching(0, id, mask))
+    }
+
+    fn clear_filters(&mut self) {
+        Registers(T::filter_regs()).set_active_filters(0, T::filter_banks());
+    }
+
+    fn bus_state(&self) -> BusState {
+        Can::bus_state(self)
+    }
+}
+
+pub(crate) fn check_filter_bank<T: Instance>(bank: usize) {
+    if bank >= FILTER_BANKS || T::filter_banks() & (1 << bank) == 0 {
+        fail!("CAN filter bank is out of range or assigned to the other controller.");
+    }
+}
+
+#[cfg(feature = "_can2")]
+fn can1_filter_banks() -> u32 {
+    (1 << Registers(pac::CAN1).can2_start_bank()) - 1
+}
+
 pub trait SealedInstance: hal::RccPeripheral {
     fn regs() -> pac::can::Can;
+    fn state() -> &'static interrupt::State;
     /// Either `0b00`, `0b10` or `b11` on CAN1. `0` or `1` on CAN2.
     fn remap(rm: u8) -> ();
+    /// Register block holding the filter banks.
+    fn filter_regs() -> pac::can::Can {
+        Self::regs()
+    }
+    /// Bit `n` is set if filter bank `n` is assigned to this controller.
+    fn filter_banks() -> u32 {
+        (1 << FILTER_BANKS) - 1
+    }
+    /// Makes the filter banks accessible, called once the controller is enabled.
+    fn enable_filters() {}
 }
 
-pub trait Instance: SealedInstance + 'static {}
-pub trait RxPin<T: Instance>: hal::gpio::Pin {}
-pub trait TxPin<T: Instance>: hal::gpio::Pin {}
+pub trait Instance: SealedInstance + 'static {
+    /// Transmit interrupt vector, to unmask in the PFIC.
+    type TxInterrupt: hal::interrupt::typelevel::Interrupt;
+    /// FIFO 0 interrupt vector.
+    type Rx0Interrupt: hal::interrupt::typelevel::Interrupt;
+    /// FIFO 1 interrupt vector.
+    type Rx1Interrupt: hal::interrupt::typelevel::Interrupt;
+    /// Status change & error interrupt vector.
+    type SceInterrupt: hal::interrupt::typelevel::Interrupt;
+}
+pub trait RxPin<T: Instance>: hal::gpio::Pin {
+    /// Remap selecting this pin, see [SealedInstance::remap].
+    fn remap(&self) -> u8;
+}
+pub trait TxPin<T: Instance>: hal::gpio::Pin {
+    /// Remap selecting this pin, see [SealedInstance::remap].
+    fn remap(&self) -> u8;
+}
+
+macro_rules! impl_pins {
+    ($instance:ident, $rx:ident, $tx:ident, $remap:expr) => {
+        impl RxPin<hal::peripherals::$instance> for hal::peripherals::$rx {
+            fn remap(&self) -> u8 {
+                $remap
+            }
+        }
+        impl TxPin<hal::peripherals::$instance> for hal::peripherals::$tx {
+            fn remap(&self) -> u8 {
+                $remap
+            }
+        }
+    };
+}
 
 impl SealedInstance for hal::peripherals::CAN1 {
     fn regs() -> pac::can::Can {
         pac::CAN1
     }
+    fn state() -> &'static interrupt::State {
+        static STATE: interrupt::State = interrupt::State::new();
+        &STATE
+    }
     fn remap(rm: u8) {
         pac::AFIO.pcfr1().modify(|w| w.set_can1_rm(rm));
     }
+    #[cfg(feature = "_can2")]
+    fn filter_banks() -> u32 {
+        can1_filter_banks()
+    }
+}
+impl Instance for hal::peripherals::CAN1 {
+    type TxInterrupt = hal::interrupt::typelevel::USB_HP_CAN1_TX;
+    type Rx0Interrupt = hal::interrupt::typelevel::USB_LP_CAN1_RX0;
+    type Rx1Interrupt = hal::interrupt::typelevel::CAN1_RX1;
+    type SceInterrupt = hal::interrupt::typelevel::CAN1_SCE;
+}
+
+impl_pins!(CAN1, PA11, PA12, 0b00);
+impl_pins!(CAN1, PB8, PB9, 0b10);
+// Left out on the families where some packages lack these pins
+#[cfg(any(feature = "ch32v303", feature = "ch32v307"))]
+impl_pins!(CAN1, PD0, PD1, 0b11);
+
+#[cfg(feature = "_can2")]
+impl SealedInstance for hal::peripherals::CAN2 {
+    fn regs() -> pac::can::Can {
+        pac::CAN2
+    }
+    fn state() -> &'static interrupt::State {
+        static STATE: interrupt::State = interrupt::State::new();
+        &STATE
+    }
+    fn remap(rm: u8) {
+        pac::AFIO.pcfr1().modify(|w| w.set_can2_rm(rm != 0));
+    }
+    fn filter_regs() -> pac::can::Can {
+        pac::CAN1
+    }
+    fn filter_banks() -> u32 {
+        ((1 << FILTER_BANKS) - 1) & !can1_filter_banks()
+    }
+    fn enable_filters() {
+        pac::RCC.apb1pcenr().modify(|w| w.set_can1en(true)); // Filter banks are clocked with CAN1
+    }
+}
+#[cfg(feature = "_can2")]
+impl Instance for hal::peripherals::CAN2 {
+    type TxInterrupt = hal::interrupt::typelevel::CAN2_TX;
+    type Rx0Interrupt = hal::interrupt::typelevel::CAN2_RX0;
+    type Rx1Interrupt = hal::interrupt::typelevel::CAN2_RX1;
+    type SceInterrupt = hal::interrupt::typelevel::CAN2_SCE;
 }
-impl Instance for hal::peripherals::CAN1 {}
 
-impl RxPin<hal::peripherals::CAN1> for hal::peripherals::PB8 {}
-impl TxPin<hal::peripherals::CAN1> for hal::peripherals::PB9 {}
+#[cfg(feature = "_can2")]
+impl_pins!(CAN2, PB12, PB13, 0);
+#[cfg(feature = "_can2")]
+impl_pins!(CAN2, PB5, PB6, 1);
@@ -0,0 +1,124 @@
+//! Traffic log in the format of `candump -L`, readable by `canplayer` and the other
+//! can-utils, written to any [embedded_io::Write] sink such as a UART.
+//!
+//! Each frame is a line `(seconds.microseconds) interface id#data`, e.g.
+//! `(0000012.345000) can0 123#DEADBEEF`. Timestamps are in ms, so the last three
+//! digits are always zero.
+
+use embedded_can::Id;
+use embedded_io::Write;
+
+use crate::frame::CanFrame;
+
+/// Longest interface name written.
+pub const MAX_INTERFACE_LEN: usize = 16;
+
+/// Longest line written, an extended frame with 8 bytes, newline included.
+pub const MAX_LINE_LEN: usize = 47 + MAX_INTERFACE_LEN;
+
+/// Encodes `frame` seen at `time_ms` into `line`, newline included, and returns its
+/// length. Panics if `interface` is longer than [MAX_INTERFACE_LEN].
+pub fn write_line(
+    frame: &CanFrame,
+    time_ms: u32,
+    interface: &str,
+    line: &mut [u8; MAX_LINE_LEN],
+) -> usize {
+    if interface.len() > MAX_INTERFACE_LEN {
+        fail!("Interface names are up to 16 bytes long.");
+    }
+
+    let mut len = 0;
+    let mut push = |bytes: &[u8]| {
+        line[len..len + bytes.len()].copy_from_slice(bytes);
+        len += bytes.len();
+    };
+
+    push(b"(");
+    push(&decimal(time_ms / 1000, 10));
+    push(b".");
+    push(&decimal(time_ms % 1000, 3)[7..]);
+    push(b"000) ");
+    push(interface.as_bytes());
+    push(b" ");
+    match *frame.id() {
+        Id::Standard(id) => push(&hex(id.as_raw() as u32)[5..]),
+        Id::Extended(id) => push(&hex(id.as_raw())),
+    }
+    push(b"#");
+    for &byte in &frame.data()[..frame.dlc()] {
+        push(&hex(byte as u32)[6..]);
+    }
+    push(b"\n");
+
+    len
+}
+
+/// `value` in 8 upper-case hex digits.
+fn hex(value: u32) -> [u8; 8] {
+    core::array::from_fn(|index| b"0123456789ABCDEF"[(value >> ((7 - index) * 4)) as usize & 0xF])
+}
+
+/// Last `digits` decimal digits of `value`, right-aligned in 10 bytes.
+fn decimal(value: u32, digits: usize) -> [u8; 10] {
+    let mut bytes = [b'0'; 10];
+    let mut value = value;
+    for byte in bytes.iter_mut().rev().take(digits) {
+        *byte = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+
+    bytes
+}
+
+/// Streams frames to `W` as a candump log.
+pub struct CandumpLogger<W> {
+    writer: W,
+    interface: &'static str,
+    log_transmitted: bool,
+}
+
+impl<W: Write> CandumpLogger<W> {
+    /// Logs the received frames under `interface`, e.g. `"can0"`. Panics if it is
+    /// longer than [MAX_INTERFACE_LEN].
+    pub fn new(writer: W, interface: &'static str) -> Self {
+        if interface.len() > MAX_INTERFACE_LEN {
+            fail!("Interface names are up to 16 bytes long.");
+        }
+
+        Self {
+            writer,
+            interface,
+            log_transmitted: false,
+        }
+    }
+
+    /// Also logs the frames passed to [CandumpLogger::log_transmitted]. The format
+    /// doesn't tell them apart from the received ones.
+    pub fn with_transmitted(self) -> Self {
+        Self {
+            log_transmitted: true,
+            ..self
+        }
+    }
+
+    pub fn release(self) -> W {
+        self.writer
+    }
+
+    /// Logs a frame received at `time_ms`, e.g. its [CanFrame::timestamp].
+    pub fn log(&mut self, frame: &CanFrame, time_ms: u32) -> Result<(), W::Error> {
+        let mut line = [0; MAX_LINE_LEN];
+        let len = write_line(frame, time_ms, self.interface, &mut line);
+        self.writer.write_all(&line[..len])
+    }
+
+    /// Logs a frame transmitted at `time_ms`, if enabled with
+    /// [CandumpLogger::with_transmitted].
+    pub fn log_transmitted(&mut self, frame: &CanFrame, time_ms: u32) -> Result<(), W::Error> {
+        match self.log_transmitted {
+            true => self.log(frame, time_ms),
+            false => Ok(()),
+        }
+    }
+}
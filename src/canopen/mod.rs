@@ -0,0 +1,43 @@
+//! Minimal CANopen (CiA 301) device stack: network management, heartbeat,
+//! expedited SDO transfers and PDOs.
+//!
+//! Like [crate::isotp], the state machines do no I/O: they are handed the received
+//! frames and the current time in ms, and return the frames to transmit.
+
+use embedded_can::StandardId;
+
+use crate::frame::CanFrame;
+
+mod nmt;
+mod pdo;
+mod sdo;
+
+pub use nmt::{HeartbeatConsumer, NmtCommand, NmtEvent, NmtSlave, NmtState};
+pub use pdo::{is_sync, rpdo_cob_id, tpdo_cob_id, PdoMapping, Rpdo, Tpdo, TransmissionType};
+pub use sdo::{ObjectDictionary, SdoAbort, SdoClient, SdoError, SdoServer, SDO_RX, SDO_TX};
+
+/// Function code of the NMT commands from the master.
+pub const NMT: u16 = 0x000;
+/// Function code of the SYNC object.
+pub const SYNC: u16 = 0x080;
+/// Function code of the heartbeat and boot-up messages, added to the node ID.
+pub const HEARTBEAT: u16 = 0x700;
+
+/// Identifier of function code `function` for node `node_id`.
+pub fn cob_id(function: u16, node_id: u8) -> StandardId {
+    StandardId::new(function + node_id as u16).unwrap()
+}
+
+/// Splits a received standard identifier into its function code and node ID.
+pub(crate) fn split_cob_id(frame: &CanFrame) -> Option<(u16, u8)> {
+    match *frame.id() {
+        embedded_can::Id::Standard(id) => Some((id.as_raw() & 0x780, (id.as_raw() & 0x7F) as u8)),
+        embedded_can::Id::Extended(_) => None,
+    }
+}
+
+fn check_node_id(node_id: u8) {
+    if !(1..=127).contains(&node_id) {
+        fail!("CANopen node ID must be 1-127.");
+    }
+}
@@ -0,0 +1,233 @@
+use super::{check_node_id, cob_id, split_cob_id, HEARTBEAT, NMT};
+use crate::frame::CanFrame;
+
+/// NMT state of a node, as sent in its heartbeat.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum NmtState {
+    /// Sent once in the boot-up message
+    Initialising = 0x00,
+    Stopped = 0x04,
+    Operational = 0x05,
+    PreOperational = 0x7F,
+}
+
+impl NmtState {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value & 0x7F {
+            0x00 => Some(NmtState::Initialising),
+            0x04 => Some(NmtState::Stopped),
+            0x05 => Some(NmtState::Operational),
+            0x7F => Some(NmtState::PreOperational),
+            _ => None,
+        }
+    }
+}
+
+/// Command of the NMT master.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum NmtCommand {
+    Start = 0x01,
+    Stop = 0x02,
+    EnterPreOperational = 0x80,
+    ResetNode = 0x81,
+    ResetCommunication = 0x82,
+}
+
+impl NmtCommand {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(NmtCommand::Start),
+            0x02 => Some(NmtCommand::Stop),
+            0x80 => Some(NmtCommand::EnterPreOperational),
+            0x81 => Some(NmtCommand::ResetNode),
+            0x82 => Some(NmtCommand::ResetCommunication),
+            _ => None,
+        }
+    }
+
+    /// NMT master frame sending this command to `node_id`, 0 for all nodes.
+    pub fn frame(self, node_id: u8) -> CanFrame {
+        CanFrame::new(cob_id(NMT, 0), &[self as u8, node_id]).unwrap()
+    }
+}
+
+/// Change requested by the NMT master.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum NmtEvent {
+    /// The node entered this state
+    StateChanged(NmtState),
+    /// Reset the application, then call [NmtSlave::boot_up]
+    ResetNode,
+    /// Reset the communication parameters, then call [NmtSlave::boot_up]
+    ResetCommunication,
+}
+
+/// NMT slave state machine with heartbeat production.
+pub struct NmtSlave {
+    node_id: u8,
+    state: NmtState,
+    heartbeat_period_ms: u16,
+    last_heartbeat_ms: u32,
+}
+
+impl NmtSlave {
+    /// `heartbeat_period_ms` is the producer heartbeat time (object 0x1017), 0 to
+    /// disable the heartbeat.
+    pub fn new(node_id: u8, heartbeat_period_ms: u16) -> Self {
+        check_node_id(node_id);
+
+        Self {
+            node_id,
+            state: NmtState::Initialising,
+            heartbeat_period_ms,
+            last_heartbeat_ms: 0,
+        }
+    }
+
+    pub fn node_id(&self) -> u8 {
+        self.node_id
+    }
+
+    pub fn state(&self) -> NmtState {
+        self.state
+    }
+
+    pub fn heartbeat_period_ms(&self) -> u16 {
+        self.heartbeat_period_ms
+    }
+
+    pub fn set_heartbeat_period_ms(&mut self, period_ms: u16) {
+        self.heartbeat_period_ms = period_ms;
+    }
+
+    /// Enters the pre-operational state, returning the boot-up message to send.
+    pub fn boot_up(&mut self, now_ms: u32) -> CanFrame {
+        self.last_heartbeat_ms = now_ms;
+        self.state = NmtState::PreOperational;
+
+        CanFrame::new(
+            cob_id(HEARTBEAT, self.node_id),
+            &[NmtState::Initialising as u8],
+        )
+        .unwrap()
+    }
+
+    /// Handles a received frame, returning the change requested by an NMT command
+    /// addressed to this node.
+    pub fn on_frame(&mut self, frame: &CanFrame) -> Option<NmtEvent> {
+        if split_cob_id(frame)? != (NMT, 0) || frame.dlc() < 2 {
+            return None;
+        }
+        let data = frame.data();
+        if data[1] != 0 && data[1] != self.node_id {
+            return None;
+        }
+        if self.state == NmtState::Initialising {
+            return None;
+        }
+
+        let state = match NmtCommand::from_u8(data[0])? {
+            NmtCommand::Start => NmtState::Operational,
+            NmtCommand::Stop => NmtState::Stopped,
+            NmtCommand::EnterPreOperational => NmtState::PreOperational,
+            NmtCommand::ResetNode => {
+                self.state = NmtState::Initialising;
+                return Some(NmtEvent::ResetNode);
+            }
+            NmtCommand::ResetCommunication => {
+                self.state = NmtState::Initialising;
+                return Some(NmtEvent::ResetCommunication);
+            }
+        };
+
+        self.state = state;
+        Some(NmtEvent::StateChanged(state))
+    }
+
+    /// Returns the heartbeat to send, once per heartbeat period.
+    pub fn poll(&mut self, now_ms: u32) -> Option<CanFrame> {
+        if self.heartbeat_period_ms == 0 || self.state == NmtState::Initialising {
+            return None;
+        }
+        if now_ms.wrapping_sub(self.last_heartbeat_ms) < self.heartbeat_period_ms as u32 {
+            return None;
+        }
+
+        self.last_heartbeat_ms = now_ms;
+        Some(CanFrame::new(cob_id(HEARTBEAT, self.node_id), &[self.state as u8]).unwrap())
+    }
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+struct Monitored {
+    node_id: u8,
+    timeout_ms: u16,
+    last_ms: Option<u32>,
+    state: Option<NmtState>,
+}
+
+/// Heartbeat consumer monitoring `N` nodes (object 0x1016).
+///
+/// Monitoring of a node starts with its first heartbeat.
+pub struct HeartbeatConsumer<const N: usize> {
+    nodes: [Monitored; N],
+}
+
+impl<const N: usize> HeartbeatConsumer<N> {
+    /// Monitors each node ID with its consumer heartbeat time in ms.
+    pub fn new(nodes: [(u8, u16); N]) -> Self {
+        Self {
+            nodes: nodes.map(|(node_id, timeout_ms)| {
+                check_node_id(node_id);
+                Monitored {
+                    node_id,
+                    timeout_ms,
+                    last_ms: None,
+                    state: None,
+                }
+            }),
+        }
+    }
+
+    /// Last state reported by `node_id`, `None` if unknown or timed out.
+    pub fn state(&self, node_id: u8) -> Option<NmtState> {
+        self.nodes
+            .iter()
+            .find(|node| node.node_id == node_id)
+            .and_then(|node| node.state)
+    }
+
+    /// Handles a received frame, returning the node whose state changed.
+    pub fn on_frame(&mut self, frame: &CanFrame, now_ms: u32) -> Option<(u8, NmtState)> {
+        let (function, node_id) = split_cob_id(frame)?;
+        if function != HEARTBEAT || frame.dlc() < 1 {
+            return None;
+        }
+        let state = NmtState::from_u8(frame.data()[0])?;
+        let node = self.nodes.iter_mut().find(|node| node.node_id == node_id)?;
+
+        node.last_ms = Some(now_ms);
+        match node.state.replace(state) == Some(state) {
+            true => None,
+            false => Some((node_id, state)),
+        }
+    }
+
+    /// Returns a node whose heartbeat timed out, once per timeout.
+    pub fn poll(&mut self, now_ms: u32) -> Option<u8> {
+        let node = self.nodes.iter_mut().find(|node| {
+            node.timeout_ms != 0
+                && node
+                    .last_ms
+                    .is_some_and(|last| now_ms.wrapping_sub(last) > node.timeout_ms as u32)
+        })?;
+
+        node.last_ms = None;
+        node.state = None;
+        Some(node.node_id)
+    }
+}
@@ -0,0 +1,269 @@
+use embedded_can::{Id, StandardId};
+
+use super::{check_node_id, split_cob_id, ObjectDictionary, SdoAbort, SYNC};
+use crate::frame::CanFrame;
+
+/// Object dictionary entry mapped into a PDO.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct PdoMapping {
+    pub index: u16,
+    pub subindex: u8,
+    /// Length of the entry in bytes, 1-4
+    pub len: u8,
+}
+
+impl PdoMapping {
+    pub const fn new(index: u16, subindex: u8, len: u8) -> Self {
+        Self {
+            index,
+            subindex,
+            len,
+        }
+    }
+}
+
+/// When a TPDO is transmitted.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum TransmissionType {
+    /// After every n-th SYNC, n being 1-240
+    Synchronous(u8),
+    /// On [Tpdo::trigger] and on expiry of the event timer
+    Event,
+}
+
+/// Default identifier of TPDO `pdo` (1-4) of node `node_id`.
+pub fn tpdo_cob_id(pdo: u8, node_id: u8) -> StandardId {
+    pdo_cob_id(0x180, pdo, node_id)
+}
+
+/// Default identifier of RPDO `pdo` (1-4) of node `node_id`.
+pub fn rpdo_cob_id(pdo: u8, node_id: u8) -> StandardId {
+    pdo_cob_id(0x200, pdo, node_id)
+}
+
+fn pdo_cob_id(base: u16, pdo: u8, node_id: u8) -> StandardId {
+    check_node_id(node_id);
+    if !(1..=4).contains(&pdo) {
+        fail!("Default PDO identifiers only exist for PDOs 1-4.");
+    }
+
+    StandardId::new(base + (pdo as u16 - 1) * 0x100 + node_id as u16).unwrap()
+}
+
+/// Whether `frame` is a SYNC object.
+pub fn is_sync(frame: &CanFrame) -> bool {
+    split_cob_id(frame) == Some((SYNC, 0))
+}
+
+fn check_mapping(mapping: &[PdoMapping]) -> usize {
+    let len = mapping.iter().map(|entry| entry.len as usize).sum();
+    if len > 8 || mapping.iter().any(|entry| !(1..=4).contains(&entry.len)) {
+        fail!("PDO mapping must be of 1-4 byte entries fitting in 8 bytes.");
+    }
+
+    len
+}
+
+/// Transmit PDO packing `M` object dictionary entries.
+///
+/// PDOs must only be exchanged in the [super::NmtState::Operational] state.
+pub struct Tpdo<const M: usize> {
+    cob_id: Id,
+    mapping: [PdoMapping; M],
+    len: usize,
+    transmission: TransmissionType,
+    event_timer_ms: u16,
+    inhibit_time_ms: u16,
+    last_ms: Option<u32>,
+    syncs: u8,
+    triggered: bool,
+}
+
+impl<const M: usize> Tpdo<M> {
+    /// Event-driven TPDO without event timer nor inhibit time.
+    pub fn new(cob_id: impl Into<Id>, mapping: [PdoMapping; M]) -> Self {
+        Self {
+            cob_id: cob_id.into(),
+            len: check_mapping(&mapping),
+            mapping,
+            transmission: TransmissionType::Event,
+            event_timer_ms: 0,
+            inhibit_time_ms: 0,
+            last_ms: None,
+            syncs: 0,
+            triggered: false,
+        }
+    }
+
+    pub fn with_transmission(self, transmission: TransmissionType) -> Self {
+        if let TransmissionType::Synchronous(0 | 241..) = transmission {
+            fail!("Synchronous TPDOs must be sent every 1-240 SYNCs.");
+        }
+
+        Self {
+            transmission,
+            ..self
+        }
+    }
+
+    /// Transmits the TPDO every `period_ms` when no event triggered it, 0 to disable.
+    pub fn with_event_timer(self, period_ms: u16) -> Self {
+        Self {
+            event_timer_ms: period_ms,
+            ..self
+        }
+    }
+
+    /// Minimum time between two transmissions of an event-driven TPDO.
+    pub fn with_inhibit_time(self, time_ms: u16) -> Self {
+        Self {
+            inhibit_time_ms: time_ms,
+            ..self
+        }
+    }
+
+    /// Requests the transmission of an event-driven TPDO, e.g. as a mapped value
+    /// changed.
+    pub fn trigger(&mut self) {
+        self.triggered = true;
+    }
+
+    /// Returns the frame of an event-driven TPDO when triggered or when its event
+    /// timer expired. Call it regularly with the current time in ms.
+    pub fn poll(
+        &mut self,
+        now_ms: u32,
+        dictionary: &mut impl ObjectDictionary,
+    ) -> Result<Option<CanFrame>, SdoAbort> {
+        if self.transmission != TransmissionType::Event {
+            return Ok(None);
+        }
+
+        let elapsed = self.last_ms.map(|last| now_ms.wrapping_sub(last));
+        if elapsed.is_some_and(|elapsed| elapsed < self.inhibit_time_ms as u32) {
+            return Ok(None);
+        }
+        let timer_expired = self.event_timer_ms != 0
+            && elapsed.is_none_or(|elapsed| elapsed >= self.event_timer_ms as u32);
+        if !self.triggered && !timer_expired {
+            return Ok(None);
+        }
+
+        self.triggered = false;
+        self.last_ms = Some(now_ms);
+        self.frame(dictionary).map(Some)
+    }
+
+    /// Handles a SYNC object, returning the frame of a synchronous TPDO when due.
+    pub fn on_sync(
+        &mut self,
+        dictionary: &mut impl ObjectDictionary,
+    ) -> Result<Option<CanFrame>, SdoAbort> {
+        let TransmissionType::Synchronous(every) = self.transmission else {
+            return Ok(None);
+        };
+
+        self.syncs += 1;
+        if self.syncs < every {
+            return Ok(None);
+        }
+
+        self.syncs = 0;
+        self.frame(dictionary).map(Some)
+    }
+
+    fn frame(&self, dictionary: &mut impl ObjectDictionary) -> Result<CanFrame, SdoAbort> {
+        let mut data = [0; 8];
+        let mut offset = 0;
+        for entry in &self.mapping {
+            let mut value = [0; 4];
+            dictionary.read(entry.index, entry.subindex, &mut value)?;
+            let len = entry.len as usize;
+            data[offset..offset + len].copy_from_slice(&value[..len]);
+            offset += len;
+        }
+
+        Ok(CanFrame::new(self.cob_id, &data[..self.len]).unwrap())
+    }
+}
+
+/// Receive PDO unpacking `M` object dictionary entries.
+///
+/// Synchronous RPDOs are stored on reception and written to the dictionary on
+/// the next SYNC. PDOs must only be exchanged in the
+/// [super::NmtState::Operational] state.
+pub struct Rpdo<const M: usize> {
+    cob_id: Id,
+    mapping: [PdoMapping; M],
+    len: usize,
+    synchronous: bool,
+    pending: Option<[u8; 8]>,
+}
+
+impl<const M: usize> Rpdo<M> {
+    /// RPDO written to the dictionary on reception.
+    pub fn new(cob_id: impl Into<Id>, mapping: [PdoMapping; M]) -> Self {
+        Self {
+            cob_id: cob_id.into(),
+            len: check_mapping(&mapping),
+            mapping,
+            synchronous: false,
+            pending: None,
+        }
+    }
+
+    /// Defers writing the received values to the next SYNC.
+    pub fn synchronous(self) -> Self {
+        Self {
+            synchronous: true,
+            ..self
+        }
+    }
+
+    /// Handles a received frame, returning whether it was this RPDO. Frames shorter
+    /// than the mapping are dropped.
+    pub fn on_frame(
+        &mut self,
+        frame: &CanFrame,
+        dictionary: &mut impl ObjectDictionary,
+    ) -> Result<bool, SdoAbort> {
+        if *frame.id() != self.cob_id || frame.dlc() < self.len {
+            return Ok(false);
+        }
+
+        let mut data = [0; 8];
+        data.copy_from_slice(frame.data());
+        match self.synchronous {
+            true => self.pending = Some(data),
+            false => self.write(&data, dictionary)?,
+        }
+
+        Ok(true)
+    }
+
+    /// Handles a SYNC object, writing the values of a synchronous RPDO received since
+    /// the previous one.
+    pub fn on_sync(&mut self, dictionary: &mut impl ObjectDictionary) -> Result<(), SdoAbort> {
+        match self.pending.take() {
+            Some(data) => self.write(&data, dictionary),
+            None => Ok(()),
+        }
+    }
+
+    fn write(
+        &self,
+        data: &[u8; 8],
+        dictionary: &mut impl ObjectDictionary,
+    ) -> Result<(), SdoAbort> {
+        let mut offset = 0;
+        for entry in &self.mapping {
+            let len = entry.len as usize;
+            dictionary.write(entry.index, entry.subindex, &data[offset..offset + len])?;
+            offset += len;
+        }
+
+        Ok(())
+    }
+}
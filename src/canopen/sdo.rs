@@ -0,0 +1,251 @@
+use embedded_hal::delay::DelayNs;
+
+use super::{check_node_id, cob_id, split_cob_id};
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+
+#[cfg(test)]
+mod tests;
+
+/// Function code of the SDO responses of a server, added to its node ID.
+pub const SDO_TX: u16 = 0x580;
+/// Function code of the SDO requests to a server, added to its node ID.
+pub const SDO_RX: u16 = 0x600;
+
+const INITIATE_DOWNLOAD: u8 = 0x20;
+const DOWNLOAD_RESPONSE: u8 = 0x60;
+const INITIATE_UPLOAD: u8 = 0x40;
+const ABORT: u8 = 0x80;
+/// Expedited and size indicated bits of the initiate commands.
+const EXPEDITED: u8 = 0x03;
+
+/// SDO abort code.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct SdoAbort(pub u32);
+
+impl SdoAbort {
+    pub const TIMEOUT: Self = Self(0x0504_0000);
+    pub const COMMAND_INVALID: Self = Self(0x0504_0001);
+    pub const UNSUPPORTED_ACCESS: Self = Self(0x0601_0000);
+    pub const WRITE_ONLY: Self = Self(0x0601_0001);
+    pub const READ_ONLY: Self = Self(0x0601_0002);
+    pub const OBJECT_DOES_NOT_EXIST: Self = Self(0x0602_0000);
+    pub const LENGTH_MISMATCH: Self = Self(0x0607_0010);
+    pub const SUBINDEX_DOES_NOT_EXIST: Self = Self(0x0609_0011);
+    pub const VALUE_OUT_OF_RANGE: Self = Self(0x0609_0030);
+    pub const GENERAL_ERROR: Self = Self(0x0800_0000);
+}
+
+/// Entries of a device's object dictionary, of up to 4 bytes.
+pub trait ObjectDictionary {
+    /// Writes the little-endian value of `index`/`subindex` to `data`, returning
+    /// its length.
+    fn read(&mut self, index: u16, subindex: u8, data: &mut [u8; 4]) -> Result<usize, SdoAbort>;
+
+    /// Stores the little-endian value `data` to `index`/`subindex`.
+    fn write(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoAbort>;
+}
+
+/// SDO server answering expedited transfers from the entries of an
+/// [ObjectDictionary]. Segmented and block transfers are aborted.
+pub struct SdoServer {
+    node_id: u8,
+}
+
+impl SdoServer {
+    pub fn new(node_id: u8) -> Self {
+        check_node_id(node_id);
+
+        Self { node_id }
+    }
+
+    /// Handles a received frame, returning the response to a request addressed
+    /// to this server.
+    pub fn on_frame(
+        &self,
+        frame: &CanFrame,
+        dictionary: &mut impl ObjectDictionary,
+    ) -> Option<CanFrame> {
+        if split_cob_id(frame)? != (SDO_RX, self.node_id) || frame.dlc() < 8 {
+            return None;
+        }
+        let data = frame.data();
+        let (command, index, subindex) = (data[0], u16::from_le_bytes([data[1], data[2]]), data[3]);
+
+        let result = match command & 0xE0 {
+            INITIATE_DOWNLOAD if command & EXPEDITED == EXPEDITED => {
+                let len = 4 - (command >> 2 & 0x3) as usize;
+                dictionary
+                    .write(index, subindex, &data[4..4 + len])
+                    .map(|_| [DOWNLOAD_RESPONSE, 0, 0, 0, 0])
+            }
+            INITIATE_UPLOAD => {
+                let mut value = [0; 4];
+                dictionary.read(index, subindex, &mut value).map(|len| {
+                    let len = len.clamp(1, 4);
+                    let command = INITIATE_UPLOAD | ((4 - len) as u8) << 2 | EXPEDITED;
+                    [command, value[0], value[1], value[2], value[3]]
+                })
+            }
+            ABORT => return None,
+            _ => Err(SdoAbort::COMMAND_INVALID),
+        };
+
+        let tx_id = cob_id(SDO_TX, self.node_id);
+        Some(match result {
+            Ok(response) => sdo_frame(tx_id, response[0], index, subindex, &response[1..]),
+            Err(abort) => sdo_frame(tx_id, ABORT, index, subindex, &abort.0.to_le_bytes()),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum SdoError {
+    /// The server aborted the transfer
+    Abort(SdoAbort),
+    /// The value is longer than 4 bytes, which needs a segmented transfer
+    TooLong,
+    /// The server responded with something else than an expedited transfer
+    UnexpectedResponse,
+    /// The server did not respond in time
+    Timeout,
+    Can(CanError),
+}
+
+/// Blocking SDO client doing expedited transfers.
+///
+/// Frames other than responses to the pending request are dropped, so filter
+/// them out or dedicate the driver to the client.
+pub struct SdoClient<C, D> {
+    can: C,
+    delay: D,
+    timeout_ms: u32,
+}
+
+impl<C, D> SdoClient<C, D>
+where
+    C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    D: DelayNs,
+{
+    /// `timeout_ms` is how long to wait for each response.
+    pub fn new(can: C, delay: D, timeout_ms: u32) -> Self {
+        Self {
+            can,
+            delay,
+            timeout_ms,
+        }
+    }
+
+    pub fn release(self) -> (C, D) {
+        (self.can, self.delay)
+    }
+
+    /// Reads `index`/`subindex` of node `node_id` into `data`, returning its length.
+    pub fn upload(
+        &mut self,
+        node_id: u8,
+        index: u16,
+        subindex: u8,
+        data: &mut [u8; 4],
+    ) -> Result<usize, SdoError> {
+        let response = self.request(node_id, INITIATE_UPLOAD, index, subindex, &[])?;
+        let command = response[0];
+        if command & 0xE0 != INITIATE_UPLOAD || command & EXPEDITED != EXPEDITED {
+            return Err(SdoError::UnexpectedResponse);
+        }
+
+        let len = 4 - (command >> 2 & 0x3) as usize;
+        data[..len].copy_from_slice(&response[4..4 + len]);
+        Ok(len)
+    }
+
+    /// Writes `data`, of up to 4 bytes, to `index`/`subindex` of node `node_id`.
+    pub fn download(
+        &mut self,
+        node_id: u8,
+        index: u16,
+        subindex: u8,
+        data: &[u8],
+    ) -> Result<(), SdoError> {
+        if data.is_empty() || data.len() > 4 {
+            return Err(SdoError::TooLong);
+        }
+
+        let command = INITIATE_DOWNLOAD | ((4 - data.len()) as u8) << 2 | EXPEDITED;
+        let response = self.request(node_id, command, index, subindex, data)?;
+        match response[0] {
+            DOWNLOAD_RESPONSE => Ok(()),
+            _ => Err(SdoError::UnexpectedResponse),
+        }
+    }
+
+    /// Sends a request and returns the data of the matching response, aborts
+    /// turned into errors.
+    fn request(
+        &mut self,
+        node_id: u8,
+        command: u8,
+        index: u16,
+        subindex: u8,
+        data: &[u8],
+    ) -> Result<[u8; 8], SdoError> {
+        check_node_id(node_id);
+        let request = sdo_frame(cob_id(SDO_RX, node_id), command, index, subindex, data);
+        self.poll(|can| can.transmit(&request).map(|_| Some(())))?;
+
+        let response = self.poll(|can| {
+            let frame = can.receive()?;
+            let mut data = [0; 8];
+            data.copy_from_slice(frame.data());
+            let matches = split_cob_id(&frame) == Some((SDO_TX, node_id))
+                && frame.dlc() == 8
+                && u16::from_le_bytes([data[1], data[2]]) == index
+                && data[3] == subindex;
+
+            Ok(matches.then_some(data))
+        })?;
+
+        match response[0] {
+            ABORT => {
+                let code = u32::from_le_bytes([response[4], response[5], response[6], response[7]]);
+                Err(SdoError::Abort(SdoAbort(code)))
+            }
+            _ => Ok(response),
+        }
+    }
+
+    /// Retries `f` every 100 µs until it returns a value or the timeout elapses,
+    /// also when it got an unrelated frame so that traffic doesn't cut the timeout
+    /// short.
+    fn poll<R>(
+        &mut self,
+        mut f: impl FnMut(&mut C) -> nb::Result<Option<R>, CanError>,
+    ) -> Result<R, SdoError> {
+        for _ in 0..=self.timeout_ms.saturating_mul(10) {
+            match f(&mut self.can) {
+                Ok(Some(value)) => return Ok(value),
+                Err(nb::Error::Other(error)) => return Err(SdoError::Can(error)),
+                Ok(None) | Err(nb::Error::WouldBlock) => self.delay.delay_us(100),
+            }
+        }
+
+        Err(SdoError::Timeout)
+    }
+}
+
+/// SDO frame, always 8 bytes long.
+fn sdo_frame(
+    id: embedded_can::StandardId,
+    command: u8,
+    index: u16,
+    subindex: u8,
+    data: &[u8],
+) -> CanFrame {
+    let [index_low, index_high] = index.to_le_bytes();
+    let mut bytes = [command, index_low, index_high, subindex, 0, 0, 0, 0];
+    bytes[4..4 + data.len()].copy_from_slice(data);
+
+    CanFrame::new(id, &bytes).unwrap()
+}
@@ -0,0 +1,189 @@
+//! Host tests of expedited SDO transfers, the client talking to a server through a
+//! fake driver.
+
+use super::*;
+use crate::canopen::cob_id;
+
+const NODE_ID: u8 = 5;
+const INDEX: u16 = 0x2001;
+const TIMEOUT_MS: u32 = 10;
+
+/// One read-write entry at [INDEX] sub-index 0 and one read-only entry at sub-index
+/// 1.
+struct Dictionary {
+    value: [u8; 4],
+    len: usize,
+}
+
+impl ObjectDictionary for Dictionary {
+    fn read(&mut self, index: u16, subindex: u8, data: &mut [u8; 4]) -> Result<usize, SdoAbort> {
+        match (index, subindex) {
+            (INDEX, 0 | 1) => {
+                *data = self.value;
+                Ok(self.len)
+            }
+            (INDEX, _) => Err(SdoAbort::SUBINDEX_DOES_NOT_EXIST),
+            _ => Err(SdoAbort::OBJECT_DOES_NOT_EXIST),
+        }
+    }
+
+    fn write(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoAbort> {
+        match (index, subindex) {
+            (INDEX, 0) => {
+                self.value = [0; 4];
+                self.value[..data.len()].copy_from_slice(data);
+                self.len = data.len();
+                Ok(())
+            }
+            (INDEX, 1) => Err(SdoAbort::READ_ONLY),
+            _ => Err(SdoAbort::OBJECT_DOES_NOT_EXIST),
+        }
+    }
+}
+
+/// Driver delivering requests to a server, or only unrelated frames with `noise`.
+struct Link {
+    server: SdoServer,
+    dictionary: Dictionary,
+    response: Option<CanFrame>,
+    noise: bool,
+    requests: usize,
+}
+
+impl Link {
+    fn new(value: &[u8]) -> Self {
+        let mut dictionary = Dictionary {
+            value: [0; 4],
+            len: 0,
+        };
+        dictionary.write(INDEX, 0, value).unwrap();
+
+        Self {
+            server: SdoServer::new(NODE_ID),
+            dictionary,
+            response: None,
+            noise: false,
+            requests: 0,
+        }
+    }
+}
+
+impl embedded_can::nb::Can for Link {
+    type Frame = CanFrame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &CanFrame) -> nb::Result<Option<CanFrame>, CanError> {
+        self.requests += 1;
+        self.response = self.server.on_frame(frame, &mut self.dictionary);
+
+        Ok(None)
+    }
+
+    fn receive(&mut self) -> nb::Result<CanFrame, CanError> {
+        if self.noise {
+            return Ok(CanFrame::new(cob_id(SDO_TX, NODE_ID + 1), &[0; 8]).unwrap());
+        }
+
+        self.response.take().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+/// Delay adding up the time waited.
+#[derive(Default)]
+struct Elapsed {
+    ns: u64,
+}
+
+impl DelayNs for Elapsed {
+    fn delay_ns(&mut self, ns: u32) {
+        self.ns += ns as u64;
+    }
+}
+
+fn client(link: Link) -> SdoClient<Link, Elapsed> {
+    SdoClient::new(link, Elapsed::default(), TIMEOUT_MS)
+}
+
+/// Request of command `command` sent straight to the server of `link`.
+fn respond(link: &mut Link, command: u8, subindex: u8, data: &[u8]) -> [u8; 8] {
+    let request = sdo_frame(cob_id(SDO_RX, NODE_ID), command, INDEX, subindex, data);
+    let response = link
+        .server
+        .on_frame(&request, &mut link.dictionary)
+        .unwrap();
+    assert_eq!(*response.id(), cob_id(SDO_TX, NODE_ID).into());
+
+    response.data().try_into().unwrap()
+}
+
+#[test]
+fn expedited_upload_sizes() {
+    let value = [0x11, 0x22, 0x33, 0x44];
+    for (len, command) in [(1, 0x4F), (2, 0x4B), (3, 0x47), (4, 0x43)] {
+        let mut link = Link::new(&value[..len]);
+        let mut expected = [command, 0x01, 0x20, 0, 0, 0, 0, 0];
+        expected[4..4 + len].copy_from_slice(&value[..len]);
+        assert_eq!(respond(&mut link, INITIATE_UPLOAD, 0, &[]), expected);
+
+        let mut data = [0; 4];
+        assert_eq!(client(link).upload(NODE_ID, INDEX, 0, &mut data), Ok(len));
+        assert_eq!(data[..len], value[..len]);
+    }
+}
+
+#[test]
+fn expedited_download_sizes() {
+    let value = [0x11, 0x22, 0x33, 0x44];
+    for (len, command) in [(1, 0x2F), (2, 0x2B), (3, 0x27), (4, 0x23)] {
+        let mut link = Link::new(&[0]);
+        let response = respond(&mut link, command, 0, &value[..len]);
+        assert_eq!(response, [DOWNLOAD_RESPONSE, 0x01, 0x20, 0, 0, 0, 0, 0]);
+        assert_eq!(link.dictionary.value[..len], value[..len]);
+
+        let mut client = client(Link::new(&[0]));
+        assert_eq!(client.download(NODE_ID, INDEX, 0, &value[..len]), Ok(()));
+        let (link, _) = client.release();
+        assert_eq!(link.dictionary.len, len);
+        assert_eq!(link.dictionary.value[..len], value[..len]);
+    }
+}
+
+#[test]
+fn abort_frames() {
+    let mut link = Link::new(&[0]);
+    let response = respond(&mut link, INITIATE_DOWNLOAD | EXPEDITED, 1, &[1, 2, 3, 4]);
+    assert_eq!(response, [ABORT, 0x01, 0x20, 1, 0x02, 0x00, 0x01, 0x06]);
+    // Segmented transfers are aborted
+    let response = respond(&mut link, INITIATE_DOWNLOAD, 0, &[8, 0, 0, 0]);
+    assert_eq!(response, [ABORT, 0x01, 0x20, 0, 0x01, 0x00, 0x04, 0x05]);
+
+    let mut client = client(link);
+    assert_eq!(
+        client.download(NODE_ID, INDEX, 1, &[1]),
+        Err(SdoError::Abort(SdoAbort::READ_ONLY))
+    );
+    assert_eq!(
+        client.upload(NODE_ID, INDEX, 2, &mut [0; 4]),
+        Err(SdoError::Abort(SdoAbort::SUBINDEX_DOES_NOT_EXIST))
+    );
+    assert_eq!(
+        client.download(NODE_ID, INDEX, 0, &[0; 5]),
+        Err(SdoError::TooLong)
+    );
+    // Rejected before anything is sent
+    assert_eq!(client.release().0.requests, 2);
+}
+
+#[test]
+fn unrelated_frames_wait_out_the_timeout() {
+    let mut link = Link::new(&[1]);
+    link.noise = true;
+    let mut client = client(link);
+
+    assert_eq!(
+        client.upload(NODE_ID, INDEX, 0, &mut [0; 4]),
+        Err(SdoError::Timeout)
+    );
+    let (_, delay) = client.release();
+    assert!(delay.ns >= TIMEOUT_MS as u64 * 1_000_000);
+}
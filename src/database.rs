@@ -0,0 +1,329 @@
+//! Message database filled at runtime, decoding traffic into named signal values.
+//!
+//! Layouts can be added one by one, or loaded from the `BO_` and `SG_` lines of a
+//! DBC file, e.g. received line by line over a UART.
+
+use embedded_can::{ExtendedId, Id, StandardId};
+
+use crate::frame::CanFrame;
+use crate::signals::{ByteOrder, Signal};
+
+/// Longest message or signal name stored.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Flag of extended identifiers in DBC files.
+const DBC_EXTENDED_FLAG: u32 = 1 << 31;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum DatabaseError {
+    /// No room left for the message or signal
+    Full,
+    /// The name is longer than [MAX_NAME_LEN]
+    NameTooLong,
+    /// The message index is unknown, or no message precedes a DBC signal
+    UnknownMessage,
+    /// The DBC line could not be parsed
+    InvalidLine,
+    /// The message already has a multiplexor
+    DuplicateMultiplexor,
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+struct Name {
+    bytes: [u8; MAX_NAME_LEN],
+    len: u8,
+}
+
+impl Name {
+    fn new(name: &str) -> Result<Self, DatabaseError> {
+        if name.len() > MAX_NAME_LEN {
+            return Err(DatabaseError::NameTooLong);
+        }
+
+        let mut bytes = [0; MAX_NAME_LEN];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(Self {
+            bytes,
+            len: name.len() as u8,
+        })
+    }
+
+    fn as_str(&self) -> &str {
+        // Copied from a `&str` as a whole
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap()
+    }
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+struct Message {
+    id: Id,
+    name: Name,
+    len: u8,
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+struct SignalEntry {
+    message: usize,
+    name: Name,
+    signal: Signal,
+    /// Selects the multiplexed signals of the message present in a payload
+    multiplexor: bool,
+}
+
+/// Layouts of up to `M` messages holding up to `S` signals in total.
+pub struct MessageDatabase<const M: usize, const S: usize> {
+    messages: [Option<Message>; M],
+    signals: [Option<SignalEntry>; S],
+    /// Message the next DBC signal belongs to
+    dbc_message: Option<usize>,
+}
+
+impl<const M: usize, const S: usize> Default for MessageDatabase<M, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const M: usize, const S: usize> MessageDatabase<M, S> {
+    pub const fn new() -> Self {
+        Self {
+            messages: [None; M],
+            signals: [None; S],
+            dbc_message: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Adds message `name` of `len` bytes and returns its index.
+    pub fn add_message(
+        &mut self,
+        id: impl Into<Id>,
+        name: &str,
+        len: u8,
+    ) -> Result<usize, DatabaseError> {
+        let name = Name::new(name)?;
+        let index = self
+            .messages
+            .iter()
+            .position(Option::is_none)
+            .ok_or(DatabaseError::Full)?;
+        self.messages[index] = Some(Message {
+            id: id.into(),
+            name,
+            len,
+        });
+
+        Ok(index)
+    }
+
+    /// Adds signal `name` to the message at `message`. Multiplexed signals are
+    /// only decoded when the message's multiplexor selects them.
+    pub fn add_signal(
+        &mut self,
+        message: usize,
+        name: &str,
+        signal: Signal,
+    ) -> Result<(), DatabaseError> {
+        self.insert_signal(message, name, signal, false)
+    }
+
+    /// Adds signal `name` as the multiplexor of the message at `message`, see
+    /// [Signal::multiplexed]. A message has at most one.
+    pub fn add_multiplexor(
+        &mut self,
+        message: usize,
+        name: &str,
+        signal: Signal,
+    ) -> Result<(), DatabaseError> {
+        if self.multiplexor(message).is_some() {
+            return Err(DatabaseError::DuplicateMultiplexor);
+        }
+
+        self.insert_signal(message, name, signal, true)
+    }
+
+    fn insert_signal(
+        &mut self,
+        message: usize,
+        name: &str,
+        signal: Signal,
+        multiplexor: bool,
+    ) -> Result<(), DatabaseError> {
+        if self.messages.get(message).copied().flatten().is_none() {
+            return Err(DatabaseError::UnknownMessage);
+        }
+
+        let name = Name::new(name)?;
+        let slot = self
+            .signals
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(DatabaseError::Full)?;
+        *slot = Some(SignalEntry {
+            message,
+            name,
+            signal,
+            multiplexor,
+        });
+
+        Ok(())
+    }
+
+    fn multiplexor(&self, message: usize) -> Option<Signal> {
+        self.signals
+            .iter()
+            .flatten()
+            .find(|entry| entry.message == message && entry.multiplexor)
+            .map(|entry| entry.signal)
+    }
+
+    /// Index of the message with identifier `id`.
+    pub fn find(&self, id: impl Into<Id>) -> Option<usize> {
+        let id = id.into();
+        self.messages
+            .iter()
+            .position(|message| message.is_some_and(|message| message.id == id))
+    }
+
+    pub fn message_name(&self, message: usize) -> Option<&str> {
+        self.messages
+            .get(message)?
+            .as_ref()
+            .map(|message| message.name.as_str())
+    }
+
+    /// Payload length of the message in bytes.
+    pub fn message_len(&self, message: usize) -> Option<usize> {
+        self.messages
+            .get(message)?
+            .as_ref()
+            .map(|message| message.len as usize)
+    }
+
+    /// Signal `name` of the message at `message`.
+    pub fn signal(&self, message: usize, name: &str) -> Option<Signal> {
+        self.signals
+            .iter()
+            .flatten()
+            .find(|entry| entry.message == message && entry.name.as_str() == name)
+            .map(|entry| entry.signal)
+    }
+
+    /// Named values of the signals of `frame`, `None` if its message is unknown.
+    /// Signals beyond the frame's length or not selected by the multiplexor are left
+    /// out.
+    pub fn decode<'a>(
+        &'a self,
+        frame: &'a CanFrame,
+    ) -> Option<impl Iterator<Item = (&'a str, f32)> + 'a> {
+        let message = self.find(*frame.id())?;
+        let data = &frame.data()[..frame.dlc()];
+        let multiplexor = self.multiplexor(message);
+
+        Some(
+            self.signals
+                .iter()
+                .flatten()
+                .filter(move |entry| entry.message == message)
+                .filter(move |entry| match (entry.signal.multiplex, multiplexor) {
+                    (None, _) => true,
+                    (Some(_), Some(multiplexor)) => entry.signal.is_present(&multiplexor, data),
+                    (Some(_), None) => false,
+                })
+                .filter_map(move |entry| Some((entry.name.as_str(), entry.signal.decode(data)?))),
+        )
+    }
+
+    /// Loads one line of a DBC file. `BO_` lines add a message, the `SG_` lines
+    /// following it its signals, other lines are ignored.
+    pub fn load_dbc_line(&mut self, line: &str) -> Result<(), DatabaseError> {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("BO_") => {
+                self.dbc_message = None;
+                let raw_id: u32 = parse(tokens.next())?;
+                let name = tokens.next().ok_or(DatabaseError::InvalidLine)?;
+                let len = parse(tokens.next())?;
+
+                let id: Id = match raw_id & DBC_EXTENDED_FLAG != 0 {
+                    true => ExtendedId::new(raw_id & !DBC_EXTENDED_FLAG).map(Id::Extended),
+                    false => StandardId::new(raw_id as u16).map(Id::Standard),
+                }
+                .ok_or(DatabaseError::InvalidLine)?;
+
+                let message = self.add_message(id, name.trim_end_matches(':'), len)?;
+                self.dbc_message = Some(message);
+                Ok(())
+            }
+            Some("SG_") => {
+                let message = self.dbc_message.ok_or(DatabaseError::UnknownMessage)?;
+                let name = tokens.next().ok_or(DatabaseError::InvalidLine)?;
+                let indicator = match tokens.next() {
+                    Some(":") => None,
+                    Some(indicator) if tokens.next() == Some(":") => Some(indicator),
+                    _ => return Err(DatabaseError::InvalidLine),
+                };
+                let signal = parse_dbc_signal(tokens.next(), tokens.next())?;
+
+                // `M` for the multiplexor, `m3` for a signal present when it is 3.
+                // Extended multiplexing, `m3M`, is reduced to the first level.
+                match indicator {
+                    None => self.add_signal(message, name, signal),
+                    Some("M") => self.add_multiplexor(message, name, signal),
+                    Some(indicator) => {
+                        let value = indicator
+                            .strip_prefix('m')
+                            .map(|value| value.trim_end_matches('M'));
+                        let signal = signal.multiplexed(parse(value)?);
+                        self.add_signal(message, name, signal)
+                    }
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn parse<T: core::str::FromStr>(token: Option<&str>) -> Result<T, DatabaseError> {
+    token
+        .and_then(|token| token.parse().ok())
+        .ok_or(DatabaseError::InvalidLine)
+}
+
+/// Decodes the `start|length@order sign` and `(factor,offset)` fields of an `SG_` line.
+fn parse_dbc_signal(layout: Option<&str>, scale: Option<&str>) -> Result<Signal, DatabaseError> {
+    let layout = layout.ok_or(DatabaseError::InvalidLine)?;
+    let (start_bit, rest) = layout.split_once('|').ok_or(DatabaseError::InvalidLine)?;
+    let (length, rest) = rest.split_once('@').ok_or(DatabaseError::InvalidLine)?;
+    let length: u8 = parse(Some(length))?;
+    if length == 0 || length > 64 {
+        return Err(DatabaseError::InvalidLine);
+    }
+
+    let byte_order = match rest.as_bytes().first() {
+        Some(b'1') => ByteOrder::LittleEndian,
+        Some(b'0') => ByteOrder::BigEndian,
+        _ => return Err(DatabaseError::InvalidLine),
+    };
+    let signal = Signal::new(parse(Some(start_bit))?, length, byte_order);
+    let signal = match rest.as_bytes().get(1) {
+        Some(b'+') => signal,
+        Some(b'-') => signal.signed(),
+        _ => return Err(DatabaseError::InvalidLine),
+    };
+
+    let scale = scale
+        .and_then(|scale| scale.strip_prefix('('))
+        .and_then(|scale| scale.strip_suffix(')'))
+        .ok_or(DatabaseError::InvalidLine)?;
+    let (factor, offset) = scale.split_once(',').ok_or(DatabaseError::InvalidLine)?;
+
+    Ok(signal.with_scale(parse(Some(factor))?, parse(Some(offset))?))
+}
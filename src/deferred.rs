@@ -0,0 +1,69 @@
+//! Frames set aside by [crate::Can::receive_id] until the application reads them,
+//! also used to remember recent frames in [crate::RedundantCan].
+
+use crate::frame::CanFrame;
+
+/// Ordered list of up to `N` received frames that did not match the ID waited for.
+pub(crate) struct Deferred<const N: usize> {
+    frames: [Option<CanFrame>; N],
+    len: usize,
+}
+
+impl<const N: usize> Deferred<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            frames: [None; N],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the frame back if the list is full.
+    pub(crate) fn push(&mut self, frame: CanFrame) -> Result<(), CanFrame> {
+        if self.len == N {
+            return Err(frame);
+        }
+
+        self.frames[self.len] = Some(frame);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Removes the oldest frame.
+    pub(crate) fn pop(&mut self) -> Option<CanFrame> {
+        self.remove(0)
+    }
+
+    /// Removes the oldest frame with identifier `id`.
+    pub(crate) fn take(&mut self, id: embedded_can::Id) -> Option<CanFrame> {
+        self.take_matching(|frame| frame.id == id)
+    }
+
+    /// Removes the oldest frame for which `matches` returns true.
+    pub(crate) fn take_matching(
+        &mut self,
+        matches: impl Fn(&CanFrame) -> bool,
+    ) -> Option<CanFrame> {
+        let index = self.frames[..self.len]
+            .iter()
+            .position(|frame| frame.as_ref().is_some_and(&matches))?;
+
+        self.remove(index)
+    }
+
+    fn remove(&mut self, index: usize) -> Option<CanFrame> {
+        if index >= self.len {
+            return None;
+        }
+
+        let frame = self.frames[index].take();
+        self.frames[index..self.len].rotate_left(1);
+        self.len -= 1;
+
+        frame
+    }
+}
@@ -0,0 +1,111 @@
+//! Routing of received frames to handlers by identifier.
+
+use embedded_can::Id;
+
+use crate::frame::CanFrame;
+use crate::gateway::id_matches;
+
+/// Handler of the frames of a route, given the context passed to
+/// [Dispatcher::dispatch].
+pub type FrameHandler<C> = fn(&mut C, &CanFrame);
+
+struct Route<C> {
+    id: Id,
+    mask: u32,
+    handler: FrameHandler<C>,
+}
+
+/// Calls the handler registered for the identifier of each received frame, out of up
+/// to `N` routes.
+///
+/// Routes are tried in registration order and the first match wins, so register
+/// exact identifiers before the ranges covering them. Handlers share the context
+/// `C`, e.g. the application state.
+pub struct Dispatcher<C, const N: usize> {
+    routes: [Option<Route<C>>; N],
+    default: Option<FrameHandler<C>>,
+}
+
+impl<C, const N: usize> Default for Dispatcher<C, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C, const N: usize> Dispatcher<C, N> {
+    pub const fn new() -> Self {
+        Self {
+            routes: [const { None }; N],
+            default: None,
+        }
+    }
+
+    /// Routes the frames with identifier `id` to `handler`.
+    pub fn on(
+        &mut self,
+        id: impl Into<Id>,
+        handler: FrameHandler<C>,
+    ) -> Result<(), DispatcherFull> {
+        self.on_masked(id, u32::MAX, handler)
+    }
+
+    /// Routes the frames whose identifier equals `id` on the bits set in `mask` to
+    /// `handler`, e.g. `0x100` and `0x7F0` for `0x100` to `0x10F`.
+    pub fn on_masked(
+        &mut self,
+        id: impl Into<Id>,
+        mask: u32,
+        handler: FrameHandler<C>,
+    ) -> Result<(), DispatcherFull> {
+        let route = self
+            .routes
+            .iter_mut()
+            .find(|route| route.is_none())
+            .ok_or(DispatcherFull)?;
+        *route = Some(Route {
+            id: id.into(),
+            mask,
+            handler,
+        });
+
+        Ok(())
+    }
+
+    /// Handles the frames no route matches.
+    pub fn otherwise(&mut self, handler: FrameHandler<C>) {
+        self.default = Some(handler);
+    }
+
+    /// Removes all routes and the default handler.
+    pub fn clear(&mut self) {
+        self.routes = [const { None }; N];
+        self.default = None;
+    }
+
+    /// Calls the handler of `frame`, returning whether a route matched. Frames no
+    /// route matches go to the default handler, if any.
+    pub fn dispatch(&self, frame: &CanFrame, context: &mut C) -> bool {
+        let route = self
+            .routes
+            .iter()
+            .flatten()
+            .find(|route| id_matches(route.id, route.mask, *frame.id()));
+
+        match (route, self.default) {
+            (Some(route), _) => {
+                (route.handler)(context, frame);
+                true
+            }
+            (None, Some(default)) => {
+                default(context, frame);
+                false
+            }
+            (None, None) => false,
+        }
+    }
+}
+
+/// Error returned by [Dispatcher::on] when all routes are in use.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct DispatcherFull;
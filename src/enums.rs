@@ -1,5 +1,7 @@
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum CanError {
+/// Kind of a [CanError].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum CanErrorKind {
     /// The peripheral receive buffer was overrun.
     Overrun,
     // MAC sublayer errors
@@ -21,9 +23,12 @@ pub enum CanError {
     BusPassive,
     ///  At least one of error counter has reached the Error_Warning limit of 96.
     BusWarning,
+    /// Error of another controller without a matching kind, see [crate::CanAdapter].
+    Other,
 }
 
-impl core::fmt::Display for CanError {
+#[cfg(not(feature = "minimal"))]
+impl core::fmt::Display for CanErrorKind {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Overrun => write!(f, "The peripheral receive buffer was overrun"),
@@ -44,11 +49,48 @@ impl core::fmt::Display for CanError {
                 f,
                 "A peripheral error counter has reached the Warning threshold"
             ),
+            Self::Other => write!(f, "Another controller error occurred"),
+        }
+    }
+}
+
+impl CanErrorKind {
+    /// Decodes the last error code (`LEC`) field of `ERRSR`.
+    pub(crate) fn from_lec(lec: u8) -> Option<Self> {
+        match lec {
+            0b001 => Some(Self::Stuff),
+            0b010 => Some(Self::Form),
+            0b011 => Some(Self::Acknowledge),
+            0b100 | 0b101 => Some(Self::Bit), // Recessive or dominant bit error
+            0b110 => Some(Self::Crc),
+            _ => None, // No error, or code set by software
+        }
+    }
+
+    /// Non-zero code identifying the kind, e.g. in the binary log.
+    pub(crate) fn code(self) -> u8 {
+        self as u8 + 1
+    }
+
+    /// Inverse of [CanErrorKind::code], `0` meaning no error.
+    pub(crate) fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::Overrun),
+            2 => Some(Self::Bit),
+            3 => Some(Self::Stuff),
+            4 => Some(Self::Crc),
+            5 => Some(Self::Form),
+            6 => Some(Self::Acknowledge),
+            7 => Some(Self::BusOff),
+            8 => Some(Self::BusPassive),
+            9 => Some(Self::BusWarning),
+            10 => Some(Self::Other),
+            _ => None,
         }
     }
 }
 
-impl Into<embedded_can::ErrorKind> for CanError {
+impl Into<embedded_can::ErrorKind> for CanErrorKind {
     fn into(self) -> embedded_can::ErrorKind {
         match self {
             Self::Overrun => embedded_can::ErrorKind::Overrun,
@@ -60,17 +102,320 @@ impl Into<embedded_can::ErrorKind> for CanError {
             Self::BusOff => embedded_can::ErrorKind::Other,
             Self::BusPassive => embedded_can::ErrorKind::Other,
             Self::BusWarning => embedded_can::ErrorKind::Other,
+            Self::Other => embedded_can::ErrorKind::Other,
+        }
+    }
+}
+
+impl From<embedded_can::ErrorKind> for CanErrorKind {
+    fn from(kind: embedded_can::ErrorKind) -> Self {
+        match kind {
+            embedded_can::ErrorKind::Overrun => Self::Overrun,
+            embedded_can::ErrorKind::Bit => Self::Bit,
+            embedded_can::ErrorKind::Stuff => Self::Stuff,
+            embedded_can::ErrorKind::Crc => Self::Crc,
+            embedded_can::ErrorKind::Form => Self::Form,
+            embedded_can::ErrorKind::Acknowledge => Self::Acknowledge,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Error of the controller, with the context it was detected in, so that it can
+/// be diagnosed once propagated through a protocol stack.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(not(feature = "minimal"), derive(Debug))]
+pub struct CanError {
+    pub kind: CanErrorKind,
+    /// Transmit mailbox whose last attempt failed with an error (`TERRx` of
+    /// `TSTATR`), for bus errors detected while transmitting
+    pub mailbox: Option<usize>,
+    /// Receive FIFO whose frame was lost, for [CanErrorKind::Overrun]
+    pub fifo: Option<CanFifo>,
+    /// Error status register (`ERRSR`) when the error was detected: state flags,
+    /// last error code and error counters. `0` if it wasn't read from the controller
+    pub errsr: u32,
+}
+
+/// Bits of `ERRSR` kept by [CanError::pack], the others are always zero.
+const ERRSR_BITS: u32 = 0xFFFF_0077;
+
+impl CanError {
+    /// Error of kind `kind`, without context.
+    pub const fn new(kind: CanErrorKind) -> Self {
+        Self {
+            kind,
+            mailbox: None,
+            fifo: None,
+            errsr: 0,
+        }
+    }
+
+    /// Overrun of the receive FIFO `fifo`, or of its software queue.
+    pub(crate) const fn overrun(fifo: CanFifo) -> Self {
+        Self {
+            fifo: Some(fifo),
+            ..Self::new(CanErrorKind::Overrun)
         }
     }
+
+    /// Transmit error counter, `TEC`, when the error was detected.
+    pub fn tx_errors(&self) -> u8 {
+        (self.errsr >> 16) as u8
+    }
+
+    /// Receive error counter, `REC`, when the error was detected.
+    pub fn rx_errors(&self) -> u8 {
+        (self.errsr >> 24) as u8
+    }
+
+    /// Non-zero word used to store an error in an atomic: the unused bits of `ERRSR`
+    /// hold the kind, mailbox and FIFO.
+    pub(crate) fn pack(self) -> u32 {
+        let mailbox = self.mailbox.map_or(0, |mailbox| mailbox as u32 + 1);
+        let fifo = self.fifo.map_or(0, |fifo| fifo.val() as u32 + 1);
+
+        (self.errsr & ERRSR_BITS) | (self.kind.code() as u32) << 8 | mailbox << 12 | fifo << 14
+    }
+
+    /// Inverse of [CanError::pack], `0` meaning no error.
+    pub(crate) fn unpack(word: u32) -> Option<Self> {
+        let kind = CanErrorKind::from_code((word >> 8) as u8 & 0xF)?;
+        let mailbox = match (word >> 12) & 0b11 {
+            0 => None,
+            mailbox => Some(mailbox as usize - 1),
+        };
+        let fifo = match (word >> 14) & 0b11 {
+            1 => Some(CanFifo::Fifo0),
+            2 => Some(CanFifo::Fifo1),
+            _ => None,
+        };
+
+        Some(Self {
+            kind,
+            mailbox,
+            fifo,
+            errsr: word & ERRSR_BITS,
+        })
+    }
+}
+
+impl From<CanErrorKind> for CanError {
+    fn from(kind: CanErrorKind) -> Self {
+        Self::new(kind)
+    }
 }
 
+impl From<embedded_can::ErrorKind> for CanError {
+    fn from(kind: embedded_can::ErrorKind) -> Self {
+        Self::new(kind.into())
+    }
+}
+
+#[cfg(not(feature = "minimal"))]
+impl core::fmt::Display for CanError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(mailbox) = self.mailbox {
+            write!(f, ", mailbox {mailbox}")?;
+        }
+        if let Some(fifo) = self.fifo {
+            write!(f, ", FIFO {}", fifo.val())?;
+        }
+        if self.errsr != 0 {
+            write!(
+                f,
+                " (ERRSR 0x{:08X}, TEC {}, REC {})",
+                self.errsr,
+                self.tx_errors(),
+                self.rx_errors()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Only the error code and the packed context are printed in `minimal` builds.
+#[cfg(feature = "minimal")]
+impl core::fmt::Display for CanError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "CAN error {} ({:08X})", self.kind.code(), self.pack())
+    }
+}
+
+// Required by embedded_can::Error
+#[cfg(feature = "minimal")]
+impl core::fmt::Debug for CanError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(self, f)
+    }
+}
+
+impl core::error::Error for CanError {}
+
 impl embedded_can::Error for CanError {
     fn kind(&self) -> embedded_can::ErrorKind {
-        (*self).into()
+        self.kind.into()
+    }
+}
+
+/// Bus bitrate, either one of the standard rates or any other in bit/s.
+///
+/// Converts from a bare `u32` in bit/s, or from [crate::hal::time::Hertz] to make the
+/// unit explicit, e.g. `Hertz::khz(500)`.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum Bitrate {
+    /// 125 kbit/s
+    K125,
+    /// 250 kbit/s
+    K250,
+    /// 500 kbit/s
+    K500,
+    /// 1 Mbit/s
+    M1,
+    /// Any other bitrate, in bit/s
+    Custom(u32),
+}
+
+impl Bitrate {
+    /// Bitrate in bit/s
+    pub const fn bps(&self) -> u32 {
+        match self {
+            Bitrate::K125 => 125_000,
+            Bitrate::K250 => 250_000,
+            Bitrate::K500 => 500_000,
+            Bitrate::M1 => 1_000_000,
+            Bitrate::Custom(bps) => *bps,
+        }
+    }
+}
+
+impl From<u32> for Bitrate {
+    fn from(bps: u32) -> Self {
+        match bps {
+            125_000 => Bitrate::K125,
+            250_000 => Bitrate::K250,
+            500_000 => Bitrate::K500,
+            1_000_000 => Bitrate::M1,
+            _ => Bitrate::Custom(bps),
+        }
+    }
+}
+
+#[cfg(feature = "_hal")]
+impl From<crate::hal::time::Hertz> for Bitrate {
+    fn from(rate: crate::hal::time::Hertz) -> Self {
+        Bitrate::from(rate.0)
+    }
+}
+
+/// Bus configuration of [crate::Can::new_with_config].
+pub struct CanConfig {
+    /// Bitrate the bit timing is computed for, `None` when `bit_timing` is given
+    pub bitrate: Option<Bitrate>,
+    /// Target position of the sample point within the bit, in tenths of a percent.
+    /// The closest achievable position is used.
+    pub sample_point_permill: u16,
+    /// Largest accepted deviation of the achieved bitrate from `bitrate`, in parts
+    /// per million. `0` requires the clock to divide down to `bitrate` exactly.
+    pub max_deviation_ppm: u32,
+    /// Time quanta a bit may be shortened or lengthened by to resynchronize, 1 to 4
+    /// and at most the number of quanta after the sample point. Long cables or
+    /// imprecise oscillators may need more than the default of 1.
+    pub sync_jump_width: u8,
+    /// Register values to use as is, ignoring all fields above
+    pub bit_timing: Option<CanBitTiming>,
+    /// Longest wait of [crate::Can::transmit_status] for the frame to be sent, in
+    /// microseconds
+    pub tx_timeout_us: u32,
+    /// Longest wait for the controller to enter or leave initialization or sleep
+    /// mode, in microseconds. Leaving them takes 11 recessive bits, so it times out
+    /// while the bus is held dominant.
+    pub init_timeout_us: u32,
+}
+
+impl CanConfig {
+    /// Default of both timeouts, 50 ms: long enough for a frame with the most stuff
+    /// bits at 10 kbit/s, plus a few arbitration losses
+    pub const DEFAULT_TIMEOUT_US: u32 = 50_000;
+
+    /// Configuration for `bitrate` with the sample point recommended by CiA, 87.5%
+    pub fn new(bitrate: impl Into<Bitrate>) -> Self {
+        Self {
+            bitrate: Some(bitrate.into()),
+            sample_point_permill: crate::timing::CIA_SAMPLE_POINT_PERMILL,
+            max_deviation_ppm: 0,
+            sync_jump_width: 1,
+            bit_timing: None,
+            tx_timeout_us: Self::DEFAULT_TIMEOUT_US,
+            init_timeout_us: Self::DEFAULT_TIMEOUT_US,
+        }
+    }
+
+    /// Configuration with bit timing register values given explicitly, e.g. to match
+    /// an existing C project or a network specification exactly, or computed at
+    /// compile time with [crate::NominalBitTiming::calc].
+    pub fn with_bit_timing(bit_timing: CanBitTiming) -> Self {
+        Self {
+            bitrate: None,
+            sample_point_permill: crate::timing::CIA_SAMPLE_POINT_PERMILL,
+            max_deviation_ppm: 0,
+            sync_jump_width: bit_timing.sjw,
+            bit_timing: Some(bit_timing),
+            tx_timeout_us: Self::DEFAULT_TIMEOUT_US,
+            init_timeout_us: Self::DEFAULT_TIMEOUT_US,
+        }
+    }
+}
+
+/// Raw bit timing, in time quanta, as programmed into `BTIMR` plus one.
+///
+/// The bitrate is `CAN clock / (prescaler * (1 + seg1 + seg2))`.
+#[derive(Clone, Copy)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct CanBitTiming {
+    /// CAN clock divider giving the time quantum, 1 to 1024
+    pub prescaler: u16,
+    /// Time quanta before the sample point, excluding the sync segment, 1 to 16
+    pub seg1: u8,
+    /// Time quanta after the sample point, 1 to 8
+    pub seg2: u8,
+    /// Maximum time quanta a bit may be shortened or lengthened by to resynchronize,
+    /// 1 to 4
+    pub sjw: u8,
+}
+
+impl From<crate::timing::NominalBitTiming> for CanBitTiming {
+    fn from(bit_timing: crate::timing::NominalBitTiming) -> Self {
+        Self {
+            prescaler: bit_timing.prescaler.get(),
+            seg1: bit_timing.seg1.get(),
+            seg2: bit_timing.seg2.get(),
+            sjw: bit_timing.sync_jump_width.get(),
+        }
+    }
+}
+
+impl CanBitTiming {
+    /// Returns `None` if a value is out of the hardware's range.
+    pub(crate) fn to_nominal(self) -> Option<crate::timing::NominalBitTiming> {
+        if self.prescaler > 1024 || self.seg1 > 16 || self.seg2 > 8 || self.sjw > 4 {
+            return None;
+        }
+
+        Some(crate::timing::NominalBitTiming {
+            prescaler: core::num::NonZeroU16::new(self.prescaler)?,
+            seg1: core::num::NonZeroU8::new(self.seg1)?,
+            seg2: core::num::NonZeroU8::new(self.seg2)?,
+            sync_jump_width: core::num::NonZeroU8::new(self.sjw)?,
+        })
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
 pub enum CanMode {
     Normal,
     Silent,
@@ -108,6 +453,8 @@ impl CanMode {
     }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
 pub enum CanFifo {
     Fifo0,
     Fifo1,
@@ -127,8 +474,17 @@ impl CanFifo {
             CanFifo::Fifo1 => true,
         }
     }
+
+    pub(crate) fn other(&self) -> CanFifo {
+        match self {
+            CanFifo::Fifo0 => CanFifo::Fifo1,
+            CanFifo::Fifo1 => CanFifo::Fifo0,
+        }
+    }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
 pub enum CanFilterMode {
     /// Matches the incoming ID to a predefined value after applying a predefined bit mask.
     IdMask,
@@ -147,7 +503,8 @@ impl CanFilterMode {
 
 /// See table 24-1 of the reference manual for more details on filtering and modes.
 pub struct CanFilter {
-    /// Filter bank number, 0-27
+    /// Filter bank number, 0-27. On chips with CAN2, the banks are split between
+    /// both controllers, see [crate::Can::set_filter_split].
     pub bank: usize,
     /// Filter mode, either identifier mask or identifier list
     pub mode: CanFilterMode,
@@ -170,6 +527,23 @@ impl CanFilter {
         }
     }
 
+    /// Filter on bank `bank` accepting the frames whose identifier equals `id` on the
+    /// bits set in `mask`, and of the same format as `id`.
+    pub fn matching(bank: usize, id: impl Into<embedded_can::Id>, mask: u32) -> Self {
+        const IDE: u32 = 1 << 2;
+        let (id_value, id_mask) = match id.into() {
+            embedded_can::Id::Standard(id) => ((id.as_raw() as u32) << 21, (mask & 0x7FF) << 21),
+            embedded_can::Id::Extended(id) => (id.as_raw() << 3 | IDE, (mask & 0x1FFF_FFFF) << 3),
+        };
+
+        Self {
+            bank,
+            mode: CanFilterMode::IdMask,
+            id_value,
+            id_mask: id_mask | IDE,
+        }
+    }
+
     /// Offset in `usize` for bank `n` filter register 1
     pub(crate) fn fr_id_value_reg(&self) -> usize {
         self.bank * 2 + 0
@@ -181,7 +555,58 @@ impl CanFilter {
     }
 }
 
-#[derive(Debug)]
+/// Filter configuration saved by [crate::Can::enter_low_power], to be handed back
+/// to [crate::Can::resume].
+#[must_use]
+pub struct WakeToken {
+    pub(crate) active_filters: u32,
+}
+
+/// Something that happened on the peripheral, see [crate::Can::take_event].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum CanEvent {
+    /// A frame is ready to be read with [crate::Can::receive]
+    FrameReceived,
+    /// Transmit mailbox `n` finished its request, successfully or not
+    TxComplete(usize),
+    /// The peripheral entered the Bus Off state
+    BusOff,
+    /// An error counter reached the warning limit, or the peripheral went Error Passive
+    ErrorWarning,
+    /// Bus activity was detected while the peripheral was sleeping
+    Wakeup,
+    /// A frame was lost because the receive FIFO or software queue was full
+    Overrun,
+}
+
+impl CanEvent {
+    pub(crate) fn bit(&self) -> u32 {
+        match self {
+            CanEvent::FrameReceived => 1 << 0,
+            CanEvent::TxComplete(n) => 1 << (1 + n),
+            CanEvent::BusOff => 1 << 4,
+            CanEvent::ErrorWarning => 1 << 5,
+            CanEvent::Wakeup => 1 << 6,
+            CanEvent::Overrun => 1 << 7,
+        }
+    }
+
+    pub(crate) fn from_bit_index(index: u32) -> Option<Self> {
+        match index {
+            0 => Some(CanEvent::FrameReceived),
+            1..=3 => Some(CanEvent::TxComplete(index as usize - 1)),
+            4 => Some(CanEvent::BusOff),
+            5 => Some(CanEvent::ErrorWarning),
+            6 => Some(CanEvent::Wakeup),
+            7 => Some(CanEvent::Overrun),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
 pub enum TxStatus {
     /// Message was sent correctly
     Sent,
@@ -192,3 +617,241 @@ pub enum TxStatus {
     /// Message wasn't sent correctly due to error
     OtherError,
 }
+
+impl TxStatus {
+    /// Non-zero code used to store a status in an atomic.
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            TxStatus::Sent => 1,
+            TxStatus::TimeoutError => 2,
+            TxStatus::ArbitrationError => 3,
+            TxStatus::OtherError => 4,
+        }
+    }
+
+    /// Inverse of [TxStatus::code], `0` meaning no status.
+    pub(crate) fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(TxStatus::Sent),
+            2 => Some(TxStatus::TimeoutError),
+            3 => Some(TxStatus::ArbitrationError),
+            4 => Some(TxStatus::OtherError),
+            _ => None,
+        }
+    }
+
+    /// Same status of a request of mailbox `mailbox`, as a [TxOutcome].
+    pub(crate) fn outcome(self, mailbox: usize, timestamp: Option<u16>) -> TxOutcome {
+        let kind = match self {
+            TxStatus::Sent => return Ok(TxOk { mailbox, timestamp }),
+            TxStatus::TimeoutError => TxErrorKind::Timeout,
+            TxStatus::ArbitrationError => TxErrorKind::Arbitration,
+            TxStatus::OtherError => TxErrorKind::Other,
+        };
+
+        Err(TxError { mailbox, kind })
+    }
+}
+
+/// Outcome of a transmit request, see [crate::Can::poll_tx_outcome]: the same as a
+/// [TxStatus], with success and failure apart so that failures propagate with `?`.
+pub type TxOutcome = Result<TxOk, TxError>;
+
+/// Frame sent.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct TxOk {
+    /// Transmit mailbox it was sent from
+    pub mailbox: usize,
+    /// Bit-time counter value captured at the start of the frame in time-triggered
+    /// mode, see [crate::Can::tx_timestamp]
+    pub timestamp: Option<u16>,
+}
+
+/// Frame not sent.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct TxError {
+    /// Transmit mailbox it was loaded into
+    pub mailbox: usize,
+    pub kind: TxErrorKind,
+}
+
+/// Why a frame was not sent, see [TxError].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum TxErrorKind {
+    /// Not sent before the transmit timeout, see [TxStatus::TimeoutError]
+    Timeout,
+    /// Lost arbitration in single-shot mode, see [TxStatus::ArbitrationError]
+    Arbitration,
+    /// Failed with a bus error, aborted, or its outcome was overwritten, see
+    /// [TxStatus::OtherError]
+    Other,
+}
+
+#[cfg(not(feature = "minimal"))]
+impl core::fmt::Display for TxError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.kind {
+            TxErrorKind::Timeout => write!(f, "Frame not sent before the timeout")?,
+            TxErrorKind::Arbitration => write!(f, "Frame lost arbitration")?,
+            TxErrorKind::Other => write!(f, "Frame not sent due to an error")?,
+        }
+        write!(f, ", mailbox {}", self.mailbox)
+    }
+}
+
+#[cfg(not(feature = "minimal"))]
+impl core::error::Error for TxError {}
+
+/// Order in which frames waiting in the software transmit queue are sent.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum TxOrder {
+    /// Highest arbitration priority first, all three mailboxes in use. Frames with
+    /// different IDs may be reordered on the bus.
+    Priority,
+    /// Strict submission order, with only one mailbox loaded at a time. Needed by
+    /// protocols such as ISO-TP or bootloaders, at the cost of throughput.
+    Fifo,
+}
+
+/// Number of filter banks, the same on every supported family. They are shared
+/// between CAN1 and CAN2 on chips with both.
+pub(crate) const FILTER_BANKS: usize = 28;
+/// Number of hardware transmit mailboxes, also the same on every supported family.
+pub(crate) const TX_MAILBOXES: usize = 3;
+
+/// Identifies one frame handed to [crate::Can::transmit_tracked].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct TxHandle {
+    pub(crate) mailbox: usize,
+    pub(crate) seq: u32,
+}
+
+impl TxHandle {
+    /// Transmit mailbox the frame was loaded into, 0-2
+    pub fn mailbox(&self) -> usize {
+        self.mailbox
+    }
+}
+
+/// State of a transmit mailbox, decoded from `TSTATR`.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum MailboxState {
+    /// Free, its last request sent or acknowledged
+    Empty,
+    /// Request waiting for the bus or being sent
+    Pending,
+    /// Last attempt lost arbitration: retried while pending, given up on if empty
+    ArbitrationLost,
+    /// Last attempt failed with a bus error: retried while pending, given up on if
+    /// empty
+    Error,
+    /// Request aborted, or being aborted while pending
+    Aborted,
+}
+
+/// Transmit mailboxes, see [crate::Can::tx_mailbox_status].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct TxMailboxStatus {
+    pub mailboxes: [MailboxState; TX_MAILBOXES],
+    /// Mailbox the next frame is loaded into, `None` if all are pending
+    pub next_free: Option<usize>,
+}
+
+/// Completed transmit request, passed to the [crate::Can::on_tx_complete] callback.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct TxCompletion {
+    /// Request that completed, as returned by [crate::Can::transmit_tracked]
+    pub handle: TxHandle,
+    pub status: TxStatus,
+    /// Bit-time counter value captured at the start of the frame in time-triggered
+    /// mode, see [crate::Can::tx_timestamp]
+    pub timestamp: Option<u16>,
+}
+
+impl TxCompletion {
+    /// [TxCompletion::status] as a [TxOutcome].
+    pub fn outcome(&self) -> TxOutcome {
+        self.status.outcome(self.handle.mailbox, self.timestamp)
+    }
+}
+
+/// Fault confinement state of the controller, from the transmit and receive error
+/// counters.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum BusState {
+    /// Taking part in bus communication normally
+    ErrorActive,
+    /// Still communicating, but only signaling errors passively
+    ErrorPassive,
+    /// Disconnected from the bus
+    BusOff,
+}
+
+/// Error state of the controller, see [crate::Can::health].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct BusHealth {
+    pub state: BusState,
+    /// Transmit error counter, `TEC`
+    pub tx_errors: u8,
+    /// Receive error counter, `REC`
+    pub rx_errors: u8,
+}
+
+/// Condition forced with [crate::Can::inject_fault], to exercise error handling
+/// without a faulty bus.
+#[cfg(feature = "fault-injection")]
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum InjectedFault {
+    /// The next transmit request to complete reports [TxStatus::ArbitrationError]
+    ArbitrationLost,
+    /// A received frame is reported lost, as if the receive FIFO was overrun
+    Overrun,
+    /// A bus error with this last error code (`LEC` of `ERRSR`) is reported, from
+    /// `1` (stuff error) to `6` (CRC error)
+    LastErrorCode(u8),
+    /// The peripheral is reported Bus Off until it goes through initialization mode
+    BusOff,
+}
+
+/// Error returned by [crate::CanInterface::add_id_filter] when all filter banks are
+/// in use.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct NoFreeFilter;
+
+/// Error returned by [crate::Can::reconfigure].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum ConfigError {
+    /// The bit timing can't be achieved with the peripheral clock
+    InvalidBitTiming,
+    /// The controller didn't enter or leave initialization mode within
+    /// [CanConfig::init_timeout_us]
+    InitTimeout,
+}
+
+/// Error returned by [crate::Can::request].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum RequestError {
+    /// No response with the expected identifier arrived in time
+    Timeout,
+    Can(CanError),
+}
+
+impl From<CanError> for RequestError {
+    fn from(error: CanError) -> Self {
+        RequestError::Can(error)
+    }
+}
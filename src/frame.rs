@@ -1,11 +1,14 @@
 use embedded_can;
 
-#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
 pub struct CanFrame {
     pub(crate) id: embedded_can::Id,
     pub(crate) dlc: usize,
     pub(crate) data: [u8; 8],
     pub(crate) is_remote: bool,
+    pub(crate) timestamp: Option<u32>,
+    pub(crate) hw_timestamp: Option<u16>,
 }
 
 impl CanFrame {
@@ -19,12 +22,31 @@ impl CanFrame {
 
         Some(CanFrame {
             id: id.into(),
-            dlc: 8,
+            dlc: raw_data.len(),
             data,
             is_remote: false,
+            timestamp: None,
+            hw_timestamp: None,
         })
     }
 
+    /// Copy of a frame of another controller, keeping its length. Lets the protocol
+    /// modules handle frames received by other drivers.
+    pub fn from_frame(frame: &impl embedded_can::Frame) -> Self {
+        let len = frame.data().len().min(8);
+        let mut data = [0; 8];
+        data[..len].copy_from_slice(&frame.data()[..len]);
+
+        Self {
+            id: frame.id(),
+            dlc: frame.dlc().min(8),
+            data,
+            is_remote: frame.is_remote_frame(),
+            timestamp: None,
+            hw_timestamp: None,
+        }
+    }
+
     pub(crate) fn new_from_data_registers(
         id: impl Into<embedded_can::Id>,
         frame_data_unordered: u64,
@@ -41,9 +63,69 @@ impl CanFrame {
             data,
             dlc,
             is_remote: false,
+            timestamp: None,
+            hw_timestamp: None,
+        }
+    }
+
+    /// Identifier read from the `STID:EXID:IDE` bits of a mailbox identifier register.
+    pub(crate) fn id_from_bits(bits: u32) -> embedded_can::Id {
+        match bits & (1 << 2) {
+            0 => embedded_can::StandardId::new((bits >> 21) as u16)
+                .unwrap()
+                .into(),
+            _ => embedded_can::ExtendedId::new(bits >> 3).unwrap().into(),
+        }
+    }
+
+    /// Data bytes as the low and high mailbox data registers hold them, byte 0 in
+    /// the least significant byte of the low register.
+    pub(crate) fn data_registers(&self) -> (u32, u32) {
+        let low = u32::from_le_bytes([self.data[0], self.data[1], self.data[2], self.data[3]]);
+        let high = u32::from_le_bytes([self.data[4], self.data[5], self.data[6], self.data[7]]);
+
+        (low, high)
+    }
+
+    /// Value to compare frames by arbitration priority, lower wins on the bus.
+    ///
+    /// Lays out the arbitration field bits as they appear on the wire: the base ID,
+    /// then RTR (standard) or SRR (extended), then IDE, the extended ID bits and RTR.
+    pub(crate) fn arbitration_key(&self) -> u32 {
+        match self.id {
+            embedded_can::Id::Standard(id) => {
+                ((id.as_raw() as u32) << 21) | ((self.is_remote as u32) << 20)
+            }
+            embedded_can::Id::Extended(id) => {
+                let raw = id.as_raw();
+                ((raw >> 18) << 21)
+                    | (1 << 20)
+                    | (1 << 19)
+                    | ((raw & 0x3FFFF) << 1)
+                    | self.is_remote as u32
+            }
         }
     }
 
+    /// Identifier laid out as in the filter and mailbox identifier registers,
+    /// `STID:EXID:IDE:RTR:0` from msb to lsb.
+    pub(crate) fn filter_bits(&self) -> u32 {
+        let rtr = (self.is_remote as u32) << 1;
+        match self.id {
+            embedded_can::Id::Standard(id) => ((id.as_raw() as u32) << 21) | rtr,
+            embedded_can::Id::Extended(id) => (id.as_raw() << 3) | (1 << 2) | rtr,
+        }
+    }
+
+    /// Whether both frames carry the same identifier and payload, regardless of
+    /// when they were received.
+    pub(crate) fn same_content(&self, other: &CanFrame) -> bool {
+        self.id == other.id
+            && self.is_remote == other.is_remote
+            && self.dlc == other.dlc
+            && self.data[..self.dlc] == other.data[..other.dlc]
+    }
+
     /// Return ID
     pub fn id(&self) -> &embedded_can::Id {
         &self.id
@@ -58,6 +140,18 @@ impl CanFrame {
     pub fn data(&self) -> &[u8] {
         &self.data
     }
+
+    /// Time at which the frame was taken out of the receive FIFO, as returned by
+    /// the source set with [crate::Can::set_time_source]
+    pub fn timestamp(&self) -> Option<u32> {
+        self.timestamp
+    }
+
+    /// Value of the 16-bit CAN bit-time counter captured at the start of frame, in
+    /// time-triggered mode, see [crate::Can::enable_time_triggered_mode]
+    pub fn hardware_timestamp(&self) -> Option<u16> {
+        self.hw_timestamp
+    }
 }
 
 impl embedded_can::Frame for CanFrame {
@@ -65,9 +159,19 @@ impl embedded_can::Frame for CanFrame {
         CanFrame::new(id, raw_data)
     }
 
-    #[allow(unused_variables)]
     fn new_remote(id: impl Into<embedded_can::Id>, dlc: usize) -> Option<Self> {
-        return None; // Not implemented
+        if dlc > 8 {
+            return None;
+        }
+
+        Some(CanFrame {
+            id: id.into(),
+            dlc,
+            data: [0; 8],
+            is_remote: true,
+            timestamp: None,
+            hw_timestamp: None,
+        })
     }
 
     fn is_extended(&self) -> bool {
@@ -78,7 +182,7 @@ impl embedded_can::Frame for CanFrame {
     }
 
     fn is_remote_frame(&self) -> bool {
-        false // Not implemented
+        self.is_remote
     }
 
     fn id(&self) -> embedded_can::Id {
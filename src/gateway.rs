@@ -0,0 +1,310 @@
+//! Frame forwarding between two CAN buses.
+
+use embedded_can::{ExtendedId, Id, StandardId};
+
+#[cfg(feature = "_hal")]
+use crate::can::{Can, Instance};
+use crate::frame::CanFrame;
+#[cfg(feature = "_hal")]
+use crate::txqueue::TxQueue;
+
+/// Two peripherals bridged by forwarding rules, typically CAN1 and CAN2 of a
+/// CH32V305/307.
+///
+/// A frame received on one bus is handled according to the first [GatewayRule]
+/// that matches it for that direction: dropped, or forwarded to the other bus as
+/// is or rewritten, see [GatewayAction]. Frames matching no rule are
+/// dropped. Rules are evaluated in the interrupt handler, in table order. Frames
+/// wait for a free mailbox on the destination bus in a priority queue of up to `N`
+/// frames per direction; when it is full, the frame is dropped and counted, see
+/// [Gateway::dropped].
+///
+/// Forwarding is driven by [Gateway::on_interrupt], to be called from every CAN
+/// interrupt vector of both peripherals.
+#[cfg(feature = "_hal")]
+pub struct Gateway<'d, A: Instance, B: Instance, const N: usize> {
+    first: Can<'d, A>,
+    second: Can<'d, B>,
+    rules: &'d [GatewayRule],
+    queues: [TxQueue<N>; 2],
+    forwarded: u32,
+    dropped: u32,
+}
+
+#[cfg(feature = "_hal")]
+impl<'d, A: Instance, B: Instance, const N: usize> Gateway<'d, A, B, N> {
+    /// Enables the interrupts of both peripherals, see [Can::enable_interrupts].
+    ///
+    /// Panics if interrupts were already enabled for either peripheral.
+    pub fn new(first: Can<'d, A>, second: Can<'d, B>, rules: &'d [GatewayRule]) -> Self {
+        first.enable_interrupts();
+        second.enable_interrupts();
+
+        Self {
+            first,
+            second,
+            rules,
+            queues: [TxQueue::new(), TxQueue::new()],
+            forwarded: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Number of frames queued towards their destination bus, wrapping around.
+    pub fn forwarded(&self) -> u32 {
+        self.forwarded
+    }
+
+    /// Number of frames dropped because the queue towards their destination was full.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Services the interrupts of both peripherals, then forwards the frames
+    /// received since the last call and loads the mailboxes freed meanwhile.
+    ///
+    /// Bus errors are discarded, frames keep being forwarded once the bus recovers.
+    /// Must be the only handler servicing both peripherals.
+    pub fn on_interrupt(&mut self) {
+        // Safety: `&mut self` keeps gateway handlers from preempting each other
+        unsafe {
+            Can::<A>::on_interrupt();
+            Can::<B>::on_interrupt();
+        }
+
+        for bus in [Bus::First, Bus::Second] {
+            while let Some(frame) = self.receive_on(bus) {
+                let Some(frame) = self.route(bus, frame) else {
+                    continue;
+                };
+                match self.queues[bus.other() as usize].push(frame) {
+                    Ok(()) => self.forwarded = self.forwarded.wrapping_add(1),
+                    Err(_) => self.dropped = self.dropped.wrapping_add(1),
+                }
+            }
+        }
+
+        // Safety: the gateway owns both peripherals and is their only handler, and
+        // nothing is queued with `CanTx::write`
+        unsafe {
+            load_mailboxes(&self.first, &mut self.queues[Bus::First as usize]);
+            load_mailboxes(&self.second, &mut self.queues[Bus::Second as usize]);
+        }
+    }
+
+    pub fn release(self) -> (Can<'d, A>, Can<'d, B>) {
+        (self.first, self.second)
+    }
+
+    /// Applies the first rule matching `frame` received on `bus`, if any.
+    fn route(&self, bus: Bus, frame: CanFrame) -> Option<CanFrame> {
+        self.rules
+            .iter()
+            .find(|rule| rule.direction.from(bus) && rule.matches(frame.id))?
+            .apply(frame)
+    }
+
+    fn receive_on(&self, bus: Bus) -> Option<CanFrame> {
+        loop {
+            let received = match bus {
+                Bus::First => self.first.receive(),
+                Bus::Second => self.second.receive(),
+            };
+
+            match received {
+                Ok(frame) => return Some(frame),
+                Err(nb::Error::Other(_)) => continue,
+                Err(nb::Error::WouldBlock) => return None,
+            }
+        }
+    }
+}
+
+/// Moves frames from `queue` into free mailboxes with [Can::transmit_unchecked],
+/// highest priority first.
+///
+/// # Safety
+///
+/// See [Can::transmit_unchecked].
+#[cfg(feature = "_hal")]
+unsafe fn load_mailboxes<T: Instance, const N: usize>(can: &Can<'_, T>, queue: &mut TxQueue<N>) {
+    while let Some(frame) = queue.peek() {
+        if can.transmit_unchecked(frame).is_err() {
+            break;
+        }
+        queue.pop();
+    }
+}
+
+/// One of the two buses of a [Gateway], indexing its queues.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Bus {
+    First,
+    Second,
+}
+
+impl Bus {
+    fn other(self) -> Bus {
+        match self {
+            Bus::First => Bus::Second,
+            Bus::Second => Bus::First,
+        }
+    }
+}
+
+/// Direction in which a [GatewayRule] forwards frames between the two buses of a
+/// [Gateway].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum GatewayDirection {
+    FirstToSecond,
+    SecondToFirst,
+    Both,
+}
+
+impl GatewayDirection {
+    /// Whether frames received on `bus` are forwarded in this direction.
+    pub(crate) fn from(&self, bus: Bus) -> bool {
+        matches!(
+            (self, bus),
+            (GatewayDirection::Both, _)
+                | (GatewayDirection::FirstToSecond, Bus::First)
+                | (GatewayDirection::SecondToFirst, Bus::Second)
+        )
+    }
+}
+
+/// What a [Gateway] does with a frame matching a [GatewayRule].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum GatewayAction {
+    /// Not forwarded, e.g. to exclude part of a range forwarded by a later rule
+    Drop,
+    /// Forwarded unchanged
+    Forward,
+    /// Forwarded with this identifier instead
+    Remap(Id),
+    /// Forwarded with the bits of the raw identifier set in the rule's `id_mask`
+    /// taken from this identifier, and the others kept, e.g. to move a range of
+    /// identifiers by a fixed offset. Must be of the same kind as the rule's `id`.
+    Translate(Id),
+    /// Forwarded with the bits set in `mask` of data byte `index` replaced by those
+    /// of `value`. Remote frames and frames too short to have the byte are forwarded
+    /// unchanged.
+    RewriteByte { index: u8, mask: u8, value: u8 },
+}
+
+/// Forwarding rule of a [Gateway], matching frames by identifier.
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct GatewayRule {
+    pub direction: GatewayDirection,
+    /// Identifier to match, standard and extended identifiers never match each other.
+    pub id: Id,
+    /// Bits of the raw identifier compared with `id`, all set to match `id` only.
+    pub id_mask: u32,
+    pub action: GatewayAction,
+}
+
+impl GatewayRule {
+    /// Rule forwarding the frames with identifier `id` unchanged.
+    pub fn allow(direction: GatewayDirection, id: impl Into<Id>) -> Self {
+        Self {
+            direction,
+            id: id.into(),
+            id_mask: u32::MAX,
+            action: GatewayAction::Forward,
+        }
+    }
+
+    /// Rule dropping the frames with identifier `id`.
+    pub fn deny(direction: GatewayDirection, id: impl Into<Id>) -> Self {
+        Self {
+            action: GatewayAction::Drop,
+            ..Self::allow(direction, id)
+        }
+    }
+
+    /// Matches all identifiers that are equal to `id` on the bits set in `id_mask`.
+    pub fn with_mask(self, id_mask: u32) -> Self {
+        Self { id_mask, ..self }
+    }
+
+    /// Sends forwarded frames with identifier `id` instead.
+    pub fn remap_to(self, id: impl Into<Id>) -> Self {
+        Self {
+            action: GatewayAction::Remap(id.into()),
+            ..self
+        }
+    }
+
+    /// Sends forwarded frames with the masked bits of their identifier taken from
+    /// `id`, see [GatewayAction::Translate].
+    pub fn translate_to(self, id: impl Into<Id>) -> Self {
+        Self {
+            action: GatewayAction::Translate(id.into()),
+            ..self
+        }
+    }
+
+    /// Sends forwarded frames with byte `index` rewritten, see
+    /// [GatewayAction::RewriteByte].
+    pub fn rewrite_byte(self, index: u8, mask: u8, value: u8) -> Self {
+        Self {
+            action: GatewayAction::RewriteByte { index, mask, value },
+            ..self
+        }
+    }
+
+    pub(crate) fn matches(&self, id: Id) -> bool {
+        id_matches(self.id, self.id_mask, id)
+    }
+
+    /// Applies the action to `frame`, returning the frame to forward, if any.
+    pub(crate) fn apply(&self, mut frame: CanFrame) -> Option<CanFrame> {
+        match self.action {
+            GatewayAction::Drop => return None,
+            GatewayAction::Forward => {}
+            GatewayAction::Remap(id) => frame.id = id,
+            GatewayAction::Translate(target) => {
+                frame.id = translate_id(frame.id, target, self.id_mask)?;
+            }
+            GatewayAction::RewriteByte { index, mask, value } => {
+                let index = index as usize;
+                if !frame.is_remote && index < frame.dlc.min(8) {
+                    frame.data[index] = (frame.data[index] & !mask) | (value & mask);
+                }
+            }
+        }
+
+        Some(frame)
+    }
+}
+
+/// `id` with the bits set in `mask` taken from `target`, `None` if they aren't of
+/// the same kind.
+fn translate_id(id: Id, target: Id, mask: u32) -> Option<Id> {
+    let merge = |id: u32, target: u32| (target & mask) | (id & !mask);
+    match (id, target) {
+        (Id::Standard(id), Id::Standard(target)) => {
+            let raw = merge(id.as_raw() as u32, target.as_raw() as u32);
+            StandardId::new(raw as u16).map(Into::into)
+        }
+        (Id::Extended(id), Id::Extended(target)) => {
+            ExtendedId::new(merge(id.as_raw(), target.as_raw())).map(Into::into)
+        }
+        _ => None,
+    }
+}
+
+/// Whether `id` is of the same kind as `expected` and equal to it on the bits set in
+/// `mask`.
+pub(crate) fn id_matches(expected: Id, mask: u32, id: Id) -> bool {
+    match (expected, id) {
+        (Id::Standard(expected), Id::Standard(id)) => {
+            (expected.as_raw() as u32 ^ id.as_raw() as u32) & mask == 0
+        }
+        (Id::Extended(expected), Id::Extended(id)) => (expected.as_raw() ^ id.as_raw()) & mask == 0,
+        _ => false,
+    }
+}
@@ -0,0 +1,311 @@
+//! Message handling of the gs_usb protocol of candleLight adapters, driven by the
+//! Linux `gs_usb` SocketCAN driver.
+//!
+//! The USB stack is left to the application: it enumerates with [USB_VID] and
+//! [USB_PID], forwards the vendor control requests of the interface to
+//! [GsUsb::control_in] and [GsUsb::control_out], the bulk OUT transfers to
+//! [GsUsb::on_host_frame], and sends what [GsUsb::poll] returns on the bulk IN
+//! endpoint. One channel, classic CAN frames without timestamps.
+
+use embedded_can::{ExtendedId, Frame, Id, StandardId};
+
+#[cfg(feature = "_hal")]
+use crate::can::{Can, Instance};
+#[cfg(feature = "_hal")]
+use crate::enums::{CanBitTiming, CanConfig, CanError, CanMode, TxHandle, TX_MAILBOXES};
+use crate::frame::CanFrame;
+
+#[cfg(test)]
+mod tests;
+
+/// Vendor ID of candleLight adapters.
+pub const USB_VID: u16 = 0x1D50;
+/// Product ID of candleLight adapters.
+pub const USB_PID: u16 = 0x606F;
+
+/// Length of a classic frame exchanged on the bulk endpoints.
+pub const HOST_FRAME_LEN: usize = 20;
+
+/// `echo_id` of the frames received from the bus.
+pub const RX_ECHO_ID: u32 = 0xFFFF_FFFF;
+
+const CAN_EFF_FLAG: u32 = 1 << 31;
+const CAN_RTR_FLAG: u32 = 1 << 30;
+const CAN_ERR_FLAG: u32 = 1 << 29;
+
+const MODE_RESET: u32 = 0;
+const MODE_START: u32 = 1;
+const FEATURE_LISTEN_ONLY: u32 = 1 << 0;
+const FEATURE_LOOP_BACK: u32 = 1 << 1;
+
+/// Vendor requests of the host.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum Request {
+    HostFormat = 0,
+    BitTiming = 1,
+    Mode = 2,
+    BusErrors = 3,
+    BitTimingConst = 4,
+    DeviceConfig = 5,
+    Timestamp = 6,
+    Identify = 7,
+}
+
+impl Request {
+    pub fn from_u8(request: u8) -> Option<Self> {
+        match request {
+            0 => Some(Request::HostFormat),
+            1 => Some(Request::BitTiming),
+            2 => Some(Request::Mode),
+            3 => Some(Request::BusErrors),
+            4 => Some(Request::BitTimingConst),
+            5 => Some(Request::DeviceConfig),
+            6 => Some(Request::Timestamp),
+            7 => Some(Request::Identify),
+            _ => None,
+        }
+    }
+}
+
+/// Frame as exchanged with the host, `struct gs_host_frame`.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct HostFrame {
+    /// Identifies a frame sent by the host in its echo, [RX_ECHO_ID] for received frames
+    pub echo_id: u32,
+    /// SocketCAN identifier, with the extended, remote and error flags
+    pub can_id: u32,
+    pub dlc: u8,
+    pub channel: u8,
+    pub flags: u8,
+    pub data: [u8; 8],
+}
+
+impl HostFrame {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HOST_FRAME_LEN {
+            return None;
+        }
+
+        let mut data = [0; 8];
+        data.copy_from_slice(&bytes[12..20]);
+        Some(Self {
+            echo_id: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            can_id: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            dlc: bytes[8],
+            channel: bytes[9],
+            flags: bytes[10],
+            data,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; HOST_FRAME_LEN] {
+        let mut bytes = [0; HOST_FRAME_LEN];
+        bytes[0..4].copy_from_slice(&self.echo_id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.can_id.to_le_bytes());
+        bytes[8] = self.dlc;
+        bytes[9] = self.channel;
+        bytes[10] = self.flags;
+        bytes[12..20].copy_from_slice(&self.data);
+
+        bytes
+    }
+
+    /// Frame received from the bus.
+    pub fn from_can_frame(frame: &CanFrame, echo_id: u32) -> Self {
+        let rtr = match frame.is_remote_frame() {
+            true => CAN_RTR_FLAG,
+            false => 0,
+        };
+        let can_id = match *frame.id() {
+            Id::Standard(id) => id.as_raw() as u32 | rtr,
+            Id::Extended(id) => id.as_raw() | CAN_EFF_FLAG | rtr,
+        };
+        let mut data = [0; 8];
+        let payload = Frame::data(frame);
+        data[..payload.len()].copy_from_slice(payload);
+
+        Self {
+            echo_id,
+            can_id,
+            dlc: frame.dlc() as u8,
+            channel: 0,
+            flags: 0,
+            data,
+        }
+    }
+
+    /// Frame to transmit, `None` for error frames, which are not supported.
+    pub fn to_can_frame(&self) -> Option<CanFrame> {
+        if self.can_id & CAN_ERR_FLAG != 0 || self.dlc > 8 {
+            return None;
+        }
+
+        let id: Id = match self.can_id & CAN_EFF_FLAG != 0 {
+            true => ExtendedId::new(self.can_id & 0x1FFF_FFFF)?.into(),
+            false => StandardId::new((self.can_id & 0x7FF) as u16)?.into(),
+        };
+        match self.can_id & CAN_RTR_FLAG != 0 {
+            true => CanFrame::new_remote(id, self.dlc as usize),
+            false => CanFrame::new(id, &self.data[..self.dlc as usize]),
+        }
+    }
+}
+
+/// gs_usb device with one channel on top of the driver.
+///
+/// Frames are read from the receive FIFO, so interrupts must not be enabled on the
+/// driver.
+#[cfg(feature = "_hal")]
+pub struct GsUsb<'d, T: Instance> {
+    can: Can<'d, T>,
+    bit_timing: CanBitTiming,
+    started: bool,
+    echoes: [Option<(TxHandle, HostFrame)>; TX_MAILBOXES],
+}
+
+#[cfg(feature = "_hal")]
+impl<'d, T: Instance> GsUsb<'d, T> {
+    /// Takes over `can` stopped, until the host starts the channel.
+    pub fn new(can: Can<'d, T>) -> Self {
+        can.sleep();
+
+        Self {
+            bit_timing: can.bit_timing().into(),
+            can,
+            started: false,
+            echoes: [None; TX_MAILBOXES],
+        }
+    }
+
+    pub fn release(self) -> Can<'d, T> {
+        self.can
+    }
+
+    /// Answers a device-to-host vendor request, writing the response to `buf` and
+    /// returning its length. `None` stalls the request.
+    pub fn control_in(&mut self, request: u8, buf: &mut [u8]) -> Option<usize> {
+        let mut response = [0u32; 10];
+        let words = match Request::from_u8(request)? {
+            Request::BitTimingConst => {
+                response = [
+                    FEATURE_LISTEN_ONLY | FEATURE_LOOP_BACK,
+                    T::frequency().0,
+                    1,    // tseg1 min
+                    16,   // tseg1 max
+                    1,    // tseg2 min
+                    8,    // tseg2 max
+                    4,    // sjw max
+                    1,    // brp min
+                    1024, // brp max
+                    1,    // brp increment
+                ];
+                10
+            }
+            Request::DeviceConfig => {
+                // Reserved bytes, channel count minus one, software and hardware versions
+                response[..3].copy_from_slice(&[0, 1, 1]);
+                3
+            }
+            _ => return None,
+        };
+
+        let len = words * 4;
+        let buf = buf.get_mut(..len)?;
+        for (chunk, word) in buf.chunks_mut(4).zip(response) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+
+        Some(len)
+    }
+
+    /// Handles a host-to-device vendor request, returning whether it was accepted.
+    pub fn control_out(&mut self, request: u8, data: &[u8]) -> bool {
+        let word = |index: usize| {
+            data.get(index * 4..index * 4 + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+
+        match Request::from_u8(request) {
+            Some(Request::HostFormat | Request::Identify) => true,
+            Some(Request::BitTiming) => {
+                let (Some(prop_seg), Some(phase_seg1), Some(phase_seg2), Some(sjw), Some(brp)) =
+                    (word(0), word(1), word(2), word(3), word(4))
+                else {
+                    return false;
+                };
+
+                self.bit_timing = CanBitTiming {
+                    prescaler: brp as u16,
+                    seg1: (prop_seg + phase_seg1) as u8,
+                    seg2: phase_seg2 as u8,
+                    sjw: sjw as u8,
+                };
+                true
+            }
+            Some(Request::Mode) => match (word(0), word(1)) {
+                (Some(MODE_RESET), _) => {
+                    self.can.sleep();
+                    self.started = false;
+                    true
+                }
+                (Some(MODE_START), Some(flags)) => {
+                    let mode = match (
+                        flags & FEATURE_LISTEN_ONLY != 0,
+                        flags & FEATURE_LOOP_BACK != 0,
+                    ) {
+                        (false, false) => CanMode::Normal,
+                        (true, false) => CanMode::Silent,
+                        (false, true) => CanMode::Loopback,
+                        (true, true) => CanMode::SilentLoopback,
+                    };
+                    let config = CanConfig::with_bit_timing(self.bit_timing);
+                    self.started = self.can.reconfigure(mode, &config).is_ok();
+                    self.started
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Transmits a frame received on the bulk OUT endpoint, echoed back by
+    /// [GsUsb::poll] once sent. Returns `Err(WouldBlock)` while all mailboxes are
+    /// busy, so keep the transfer pending. Unsupported frames are dropped.
+    pub fn on_host_frame(&mut self, bytes: &[u8]) -> nb::Result<(), CanError> {
+        let Some(host_frame) = HostFrame::parse(bytes) else {
+            return Ok(());
+        };
+        let Some(frame) = host_frame.to_can_frame().filter(|_| self.started) else {
+            return Ok(());
+        };
+
+        let handle = self.can.transmit_tracked(&frame)?;
+        self.echoes[handle.mailbox()] = Some((handle, host_frame));
+
+        Ok(())
+    }
+
+    /// Returns the next frame to send on the bulk IN endpoint: the echo of a frame
+    /// sent, or a frame received from the bus.
+    pub fn poll(&mut self) -> Option<[u8; HOST_FRAME_LEN]> {
+        for echo in &mut self.echoes {
+            if let Some((handle, host_frame)) = *echo {
+                if self.can.poll_tx_result(handle).is_ok() {
+                    *echo = None;
+                    return Some(host_frame.to_bytes());
+                }
+            }
+        }
+
+        match self.started {
+            true => self
+                .can
+                .receive()
+                .ok()
+                .map(|frame| HostFrame::from_can_frame(&frame, RX_ECHO_ID).to_bytes()),
+            false => None,
+        }
+    }
+}
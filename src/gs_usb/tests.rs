@@ -0,0 +1,97 @@
+//! Host tests of the `gs_host_frame` layout, against the Linux `gs_usb` driver.
+
+use super::*;
+
+/// 8-byte data frame with identifier 0x123, echo ID 7.
+const STANDARD: [u8; HOST_FRAME_LEN] = [
+    7, 0, 0, 0, 0x23, 0x01, 0, 0, 8, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8,
+];
+
+#[test]
+fn host_frame_bytes_round_trip() {
+    let host_frame = HostFrame::parse(&STANDARD).unwrap();
+    assert_eq!(
+        host_frame,
+        HostFrame {
+            echo_id: 7,
+            can_id: 0x123,
+            dlc: 8,
+            channel: 0,
+            flags: 0,
+            data: [1, 2, 3, 4, 5, 6, 7, 8],
+        }
+    );
+    assert_eq!(host_frame.to_bytes(), STANDARD);
+
+    assert!(HostFrame::parse(&STANDARD[..HOST_FRAME_LEN - 1]).is_none());
+    // Longer transfers carry a timestamp after the frame
+    assert_eq!(
+        HostFrame::parse(&[STANDARD, [0; 20]].concat()),
+        Some(host_frame)
+    );
+}
+
+#[test]
+fn standard_frame_round_trip() {
+    let host_frame = HostFrame::parse(&STANDARD).unwrap();
+    let frame = host_frame.to_can_frame().unwrap();
+    assert_eq!(*frame.id(), Id::Standard(StandardId::new(0x123).unwrap()));
+    assert_eq!(Frame::data(&frame), [1, 2, 3, 4, 5, 6, 7, 8]);
+
+    assert_eq!(HostFrame::from_can_frame(&frame, 7), host_frame);
+}
+
+#[test]
+fn extended_flag() {
+    let id = ExtendedId::new(0x1ABC_DEF0).unwrap();
+    let frame = CanFrame::new(id, &[0xAA]).unwrap();
+    let host_frame = HostFrame::from_can_frame(&frame, RX_ECHO_ID);
+    assert_eq!(host_frame.can_id, 0x1ABC_DEF0 | CAN_EFF_FLAG);
+    assert_eq!(
+        host_frame.to_bytes()[..12],
+        [0xFF, 0xFF, 0xFF, 0xFF, 0xF0, 0xDE, 0xBC, 0x9A, 1, 0, 0, 0]
+    );
+
+    let frame = host_frame.to_can_frame().unwrap();
+    assert_eq!(*frame.id(), Id::Extended(id));
+    assert_eq!(Frame::data(&frame), [0xAA]);
+}
+
+#[test]
+fn remote_flag() {
+    let id = StandardId::new(0x7FF).unwrap();
+    let frame = CanFrame::new_remote(id, 4).unwrap();
+    let host_frame = HostFrame::from_can_frame(&frame, RX_ECHO_ID);
+    assert_eq!(host_frame.can_id, 0x7FF | CAN_RTR_FLAG);
+    assert_eq!((host_frame.dlc, host_frame.data), (4, [0; 8]));
+
+    let frame = host_frame.to_can_frame().unwrap();
+    assert!(frame.is_remote_frame() && !frame.is_extended());
+    assert_eq!(frame.dlc(), 4);
+
+    let host_frame = HostFrame {
+        can_id: 0x1234_5678 | CAN_EFF_FLAG | CAN_RTR_FLAG,
+        ..host_frame
+    };
+    let frame = host_frame.to_can_frame().unwrap();
+    assert!(frame.is_remote_frame() && frame.is_extended());
+}
+
+#[test]
+fn error_frames_and_long_dlc_rejected() {
+    let host_frame = HostFrame::parse(&STANDARD).unwrap();
+    let error_frame = HostFrame {
+        can_id: 0x004 | CAN_ERR_FLAG,
+        ..host_frame
+    };
+    assert!(error_frame.to_can_frame().is_none());
+
+    for dlc in [9, 15, 0xFF] {
+        assert!(HostFrame { dlc, ..host_frame }.to_can_frame().is_none());
+    }
+    let empty = HostFrame {
+        dlc: 0,
+        ..host_frame
+    };
+    assert_eq!(empty.to_can_frame().unwrap().dlc(), 0);
+}
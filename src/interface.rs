@@ -0,0 +1,20 @@
+//! Controller-independent interface for application and protocol code.
+
+use crate::enums::{BusState, NoFreeFilter};
+
+/// Operations application code needs from a CAN controller, on top of sending and
+/// receiving frames with [embedded_can::nb::Can].
+///
+/// Code written against this trait instead of [crate::Can] can move to another
+/// controller by implementing it there. Filters are described by identifier and
+/// mask only, leaving the allocation of filter banks to the implementation.
+pub trait CanInterface: embedded_can::nb::Can {
+    /// Also accepts the frames whose identifier equals `id` on the bits set in
+    /// `mask`. Standard and extended identifiers never match each other.
+    fn add_id_filter(&mut self, id: embedded_can::Id, mask: u32) -> Result<(), NoFreeFilter>;
+
+    /// Removes all filters, no frames are received until one is added.
+    fn clear_filters(&mut self);
+
+    fn bus_state(&self) -> BusState;
+}
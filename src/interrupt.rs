@@ -0,0 +1,852 @@
+//! Interrupt-context halves of the driver.
+//!
+//! [Can::split_interrupt_resources] hands out one object per CAN interrupt vector
+//! plus the [Can] instance itself, which stays in the application (main) context.
+//! Each object only touches the status bits of its own vector. Received frames,
+//! errors, events and wakers cross from an ISR to the application through
+//! single-producer single-consumer rings and atomics, so the receive path never
+//! needs a critical section or a lock, however high the bus load. Only the software
+//! transmit queue, which both sides reorder, the frames set aside by
+//! [Can::receive_id] and those kept by [Can::watch] are guarded by short critical
+//! sections.
+//! This lets RTIC users hand each object to a hardware task bound to the matching
+//! vector, at whatever priority fits the application.
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::sync::atomic::{
+    AtomicBool, AtomicPtr, AtomicU16, AtomicU32, AtomicU8, AtomicUsize, Ordering,
+};
+
+use crate::can::{self, Can, Instance};
+use crate::deferred::Deferred;
+#[cfg(feature = "fault-injection")]
+use crate::enums::InjectedFault;
+use crate::enums::{
+    CanError, CanErrorKind, CanEvent, CanFifo, TxCompletion, TxHandle, TxOrder, TxStatus,
+    TX_MAILBOXES,
+};
+use crate::frame::CanFrame;
+use crate::pool::{PoolSlot, PooledFrame};
+use crate::registers::Registers;
+use crate::ring::Ring;
+use crate::txqueue::{TxQueue, TX_QUEUE_LEN};
+use crate::waker::AtomicWaker;
+
+/// Number of frames each receive FIFO can buffer in software, plus one.
+pub(crate) const RX_QUEUE_LEN: usize = 8;
+
+/// Identifiers that can be followed with [Can::watch] at the same time.
+pub(crate) const WATCH_SLOTS: usize = 4;
+
+/// Identifier followed, and the last frame received with it.
+type WatchSlot = Option<(embedded_can::Id, Option<CanFrame>)>;
+
+/// Request counters wrap at 29 bits so they fit next to a 3-bit [TxStatus] code.
+const TX_SEQ_MASK: u32 = 0x1FFF_FFFF;
+
+/// Bits of [Faults].
+const FAULT_ARBITRATION_LOST: u8 = 1 << 0;
+const FAULT_BUS_OFF: u8 = 1 << 1;
+
+/// Conditions forced with [Can::inject_fault]. Never set without the
+/// `fault-injection` feature, so the checks compile out.
+struct Faults(AtomicU8);
+
+impl Faults {
+    const fn new() -> Self {
+        Self(AtomicU8::new(0))
+    }
+
+    fn is_set(&self, fault: u8) -> bool {
+        cfg!(feature = "fault-injection") && self.0.load(Ordering::Acquire) & fault != 0
+    }
+
+    fn take(&self, fault: u8) -> bool {
+        cfg!(feature = "fault-injection") && self.0.fetch_and(!fault, Ordering::AcqRel) & fault != 0
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn set(&self, fault: u8) {
+        self.0.fetch_or(fault, Ordering::AcqRel);
+    }
+}
+
+/// Per-instance state shared between interrupt and application context.
+pub struct State {
+    split: AtomicBool,
+    burst: AtomicBool,
+    wfi: AtomicBool,
+    rx_queue: [Ring<CanFrame, RX_QUEUE_LEN>; 2],
+    error: AtomicU32,
+    events: AtomicU32,
+    tx_seq: [AtomicU32; TX_MAILBOXES],
+    tx_result: [AtomicU32; TX_MAILBOXES],
+    tx_time: [AtomicU16; TX_MAILBOXES],
+    /// Time source value when each mailbox's outcome was recorded
+    tx_confirm_time: [AtomicU32; TX_MAILBOXES],
+    tx_time_append: AtomicBool,
+    rx_callback: AtomicPtr<()>,
+    tx_callback: AtomicPtr<()>,
+    error_callback: AtomicPtr<()>,
+    time_source: AtomicPtr<()>,
+    tx_queue: critical_section::Mutex<RefCell<TxQueue<TX_QUEUE_LEN>>>,
+    deferred: critical_section::Mutex<RefCell<Deferred<RX_QUEUE_LEN>>>,
+    has_deferred: AtomicBool,
+    /// Identifiers followed with [Can::watch]
+    watched: critical_section::Mutex<RefCell<[WatchSlot; WATCH_SLOTS]>>,
+    has_watched: AtomicBool,
+    pub(crate) tx_waker: AtomicWaker,
+    pub(crate) rx_waker: AtomicWaker,
+    pub(crate) bus_off_waker: AtomicWaker,
+    pool: AtomicPtr<PoolSlot>,
+    pool_len: AtomicUsize,
+    pool_write: AtomicUsize,
+    pool_read: AtomicUsize,
+    faults: Faults,
+}
+
+impl State {
+    pub(crate) const fn new() -> Self {
+        Self {
+            split: AtomicBool::new(false),
+            burst: AtomicBool::new(false),
+            wfi: AtomicBool::new(false),
+            rx_queue: [Ring::new(), Ring::new()],
+            error: AtomicU32::new(0),
+            events: AtomicU32::new(0),
+            tx_seq: [const { AtomicU32::new(0) }; TX_MAILBOXES],
+            tx_result: [const { AtomicU32::new(0) }; TX_MAILBOXES],
+            tx_time: [const { AtomicU16::new(0) }; TX_MAILBOXES],
+            tx_confirm_time: [const { AtomicU32::new(0) }; TX_MAILBOXES],
+            tx_time_append: AtomicBool::new(false),
+            rx_callback: AtomicPtr::new(core::ptr::null_mut()),
+            tx_callback: AtomicPtr::new(core::ptr::null_mut()),
+            error_callback: AtomicPtr::new(core::ptr::null_mut()),
+            time_source: AtomicPtr::new(core::ptr::null_mut()),
+            tx_queue: critical_section::Mutex::new(RefCell::new(TxQueue::new())),
+            deferred: critical_section::Mutex::new(RefCell::new(Deferred::new())),
+            has_deferred: AtomicBool::new(false),
+            watched: critical_section::Mutex::new(RefCell::new([None; WATCH_SLOTS])),
+            has_watched: AtomicBool::new(false),
+            tx_waker: AtomicWaker::new(),
+            rx_waker: AtomicWaker::new(),
+            bus_off_waker: AtomicWaker::new(),
+            pool: AtomicPtr::new(core::ptr::null_mut()),
+            pool_len: AtomicUsize::new(0),
+            pool_write: AtomicUsize::new(0),
+            pool_read: AtomicUsize::new(0),
+            faults: Faults::new(),
+        }
+    }
+
+    pub(crate) fn is_split(&self) -> bool {
+        self.split.load(Ordering::Acquire)
+    }
+
+    /// Records that an ISR drains the receive FIFOs from now on.
+    pub(crate) fn mark_split(&self) {
+        if self.split.swap(true, Ordering::AcqRel) {
+            fail!("CAN interrupt resources were already split.");
+        }
+    }
+
+    /// Whether frames are spread over both receive FIFOs, see [Can::add_burst_filter].
+    pub(crate) fn is_burst(&self) -> bool {
+        self.burst.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn mark_burst(&self) {
+        self.burst.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn set_wfi(&self, enabled: bool) {
+        self.wfi.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Pops a received frame buffered by the ISR of `fifo`.
+    pub(crate) fn pop_frame(&self, fifo: &CanFifo) -> Option<CanFrame> {
+        self.rx_queue[fifo.val()].pop()
+    }
+
+    /// Sets aside a frame skipped by [Can::receive_id], returning it back if there is
+    /// no room left.
+    pub(crate) fn defer(&self, frame: CanFrame) -> Result<(), CanFrame> {
+        critical_section::with(|cs| {
+            self.deferred.borrow_ref_mut(cs).push(frame)?;
+            self.has_deferred.store(true, Ordering::Release);
+
+            Ok(())
+        })
+    }
+
+    /// Takes the oldest frame set aside, or the oldest with identifier `id`. Only
+    /// enters a critical section when some frame was actually set aside, so plain
+    /// reception stays lock-free.
+    pub(crate) fn take_deferred(&self, id: Option<embedded_can::Id>) -> Option<CanFrame> {
+        if !self.has_deferred.load(Ordering::Acquire) {
+            return None;
+        }
+
+        critical_section::with(|cs| {
+            let mut deferred = self.deferred.borrow_ref_mut(cs);
+            let frame = match id {
+                Some(id) => deferred.take(id),
+                None => deferred.pop(),
+            };
+            self.has_deferred
+                .store(!deferred.is_empty(), Ordering::Release);
+
+            frame
+        })
+    }
+
+    /// Starts following `id`, returning its slot, or `None` if all slots are in use.
+    pub(crate) fn watch(&self, id: embedded_can::Id) -> Option<usize> {
+        critical_section::with(|cs| {
+            let mut watched = self.watched.borrow_ref_mut(cs);
+            let slot = watched.iter().position(Option::is_none)?;
+            watched[slot] = Some((id, None));
+            self.has_watched.store(true, Ordering::Release);
+
+            Some(slot)
+        })
+    }
+
+    pub(crate) fn unwatch(&self, slot: usize) {
+        critical_section::with(|cs| {
+            let mut watched = self.watched.borrow_ref_mut(cs);
+            watched[slot] = None;
+            self.has_watched
+                .store(watched.iter().any(Option::is_some), Ordering::Release);
+        })
+    }
+
+    /// Last frame received with the identifier followed in `slot`.
+    pub(crate) fn watched(&self, slot: usize) -> Option<CanFrame> {
+        critical_section::with(|cs| self.watched.borrow_ref(cs)[slot]?.1)
+    }
+
+    /// ISR side: keeps `frame` as the last one of its identifier if it is followed,
+    /// and returns whether it was. Only enters a critical section when some
+    /// identifier is followed.
+    fn update_watched(&self, frame: &CanFrame) -> bool {
+        if !self.has_watched.load(Ordering::Acquire) {
+            return false;
+        }
+
+        critical_section::with(|cs| {
+            let mut watched = self.watched.borrow_ref_mut(cs);
+            let Some((_, last)) = watched.iter_mut().flatten().find(|(id, _)| *id == frame.id)
+            else {
+                return false;
+            };
+            *last = Some(*frame);
+
+            true
+        })
+    }
+
+    /// Makes the receive interrupt decode frames into `slots` instead of the
+    /// internal queue. Must happen before interrupts are enabled.
+    pub(crate) fn set_pool(&self, slots: &'static [PoolSlot]) {
+        if self.is_split() {
+            fail!("CAN receive pool must be set before enabling interrupts.");
+        }
+
+        self.pool_len.store(slots.len(), Ordering::Relaxed);
+        self.pool_write.store(0, Ordering::Relaxed);
+        self.pool_read.store(0, Ordering::Relaxed);
+        self.pool
+            .store(slots.as_ptr() as *mut PoolSlot, Ordering::Release);
+    }
+
+    fn pool(&self) -> Option<&'static [PoolSlot]> {
+        let ptr = self.pool.load(Ordering::Acquire);
+        if ptr.is_null() {
+            return None;
+        }
+
+        // Safety: only ever stored from a `&'static [PoolSlot]` in `set_pool`
+        Some(unsafe { core::slice::from_raw_parts(ptr, self.pool_len.load(Ordering::Relaxed)) })
+    }
+
+    /// Interrupt side: fills the next slot of the pool ring, if it is free.
+    fn push_pooled(&self, slots: &'static [PoolSlot], frame: CanFrame) -> bool {
+        let index = self.pool_write.load(Ordering::Relaxed);
+        if !slots[index].fill(frame) {
+            return false;
+        }
+
+        self.pool_write
+            .store((index + 1) % slots.len(), Ordering::Relaxed);
+
+        true
+    }
+
+    /// Application side: lends out the next slot of the pool ring, if it is filled.
+    pub(crate) fn pop_pooled(&self) -> Option<PooledFrame> {
+        let slots = self.pool()?;
+        let index = self.pool_read.load(Ordering::Relaxed);
+        let frame = slots[index].take()?;
+        self.pool_read
+            .store((index + 1) % slots.len(), Ordering::Relaxed);
+
+        Some(frame)
+    }
+
+    /// Keeps the first error raised until the application takes it.
+    fn set_error(&self, error: CanError) {
+        let _ = self
+            .error
+            .compare_exchange(0, error.pack(), Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    pub(crate) fn take_error(&self) -> Option<CanError> {
+        CanError::unpack(self.error.swap(0, Ordering::AcqRel))
+    }
+
+    fn raise_event(&self, event: CanEvent) {
+        self.events.fetch_or(event.bit(), Ordering::AcqRel);
+
+        #[cfg(feature = "rtt")]
+        crate::rtt::emit_event(event);
+    }
+
+    /// Takes the pending event with the lowest bit index. Events of the same kind
+    /// raised before this is called are coalesced into one.
+    pub(crate) fn take_event(&self) -> Option<CanEvent> {
+        let events = self.events.load(Ordering::Acquire);
+        if events == 0 {
+            return None;
+        }
+
+        let index = events.trailing_zeros();
+        self.events.fetch_and(!(1 << index), Ordering::AcqRel);
+
+        CanEvent::from_bit_index(index)
+    }
+
+    /// Starts tracking a new request in mailbox `mailbox_num`, returning its handle.
+    pub(crate) fn next_tx_handle(&self, mailbox_num: usize) -> TxHandle {
+        let seq = self.tx_seq[mailbox_num].fetch_add(1, Ordering::AcqRel) + 1;
+        TxHandle {
+            mailbox: mailbox_num,
+            seq: seq & TX_SEQ_MASK,
+        }
+    }
+
+    /// Handle of the request currently tracked in mailbox `mailbox_num`.
+    fn current_tx_handle(&self, mailbox_num: usize) -> TxHandle {
+        TxHandle {
+            mailbox: mailbox_num,
+            seq: self.tx_seq[mailbox_num].load(Ordering::Acquire) & TX_SEQ_MASK,
+        }
+    }
+
+    /// Records the outcome of the request currently tracked in mailbox `mailbox_num`.
+    fn set_tx_result(&self, mailbox_num: usize, status: TxStatus) {
+        let seq = self.tx_seq[mailbox_num].load(Ordering::Acquire) & TX_SEQ_MASK;
+        self.tx_result[mailbox_num].store((seq << 3) | status.code() as u32, Ordering::Release);
+    }
+
+    /// Returns the outcome recorded for `handle`. `Err(())` means that the mailbox has
+    /// been reused since and the outcome was overwritten.
+    pub(crate) fn tx_result(&self, handle: TxHandle) -> Result<Option<TxStatus>, ()> {
+        let result = self.tx_result[handle.mailbox].load(Ordering::Acquire);
+        if result >> 3 == handle.seq {
+            return Ok(TxStatus::from_code((result & 0b111) as u8));
+        }
+        if self.tx_seq[handle.mailbox].load(Ordering::Acquire) & TX_SEQ_MASK == handle.seq {
+            return Ok(None); // Still pending
+        }
+
+        Err(())
+    }
+
+    /// Records the timer value captured when the request in `mailbox_num` started,
+    /// before its outcome is recorded.
+    fn set_tx_time(&self, mailbox_num: usize, time: u16) {
+        self.tx_time[mailbox_num].store(time, Ordering::Release);
+    }
+
+    /// Returns the timer value captured for `handle`, once its outcome is known and
+    /// as long as the mailbox hasn't been reused.
+    pub(crate) fn tx_time(&self, handle: TxHandle) -> Option<u16> {
+        self.tx_result(handle).ok()??;
+        let time = self.tx_time[handle.mailbox].load(Ordering::Acquire);
+        self.tx_result(handle).ok()??; // Still the same request after reading
+
+        Some(time)
+    }
+
+    /// Records the time source value when the outcome of `mailbox_num` is noticed,
+    /// before it is recorded.
+    fn set_tx_confirm_time(&self, mailbox_num: usize, time: u32) {
+        self.tx_confirm_time[mailbox_num].store(time, Ordering::Release);
+    }
+
+    /// Returns the time source value recorded for `handle`, once its outcome is known
+    /// and as long as the mailbox hasn't been reused.
+    pub(crate) fn tx_confirm_time(&self, handle: TxHandle) -> Option<u32> {
+        self.tx_result(handle).ok()??;
+        let time = self.tx_confirm_time[handle.mailbox].load(Ordering::Acquire);
+        self.tx_result(handle).ok()??; // Still the same request after reading
+
+        Some(time)
+    }
+
+    pub(crate) fn set_tx_time_append(&self, enabled: bool) {
+        self.tx_time_append.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn tx_time_append(&self) -> bool {
+        self.tx_time_append.load(Ordering::Relaxed)
+    }
+
+    /// Whether the peripheral is forced Bus Off, see [Can::inject_fault].
+    pub(crate) fn forced_bus_off(&self) -> bool {
+        self.faults.is_set(FAULT_BUS_OFF)
+    }
+
+    /// Ends a forced Bus Off, once the peripheral went through initialization mode.
+    pub(crate) fn clear_forced_bus_off(&self) {
+        self.faults.take(FAULT_BUS_OFF);
+    }
+
+    pub(crate) fn set_rx_callback(&self, callback: Option<fn(&CanFrame)>) {
+        let ptr = callback.map_or(core::ptr::null_mut(), |f| f as *mut ());
+        self.rx_callback.store(ptr, Ordering::Release);
+    }
+
+    pub(crate) fn set_tx_callback(&self, callback: Option<fn(TxCompletion)>) {
+        let ptr = callback.map_or(core::ptr::null_mut(), |f| f as *mut ());
+        self.tx_callback.store(ptr, Ordering::Release);
+    }
+
+    pub(crate) fn set_error_callback(&self, callback: Option<fn(CanError)>) {
+        let ptr = callback.map_or(core::ptr::null_mut(), |f| f as *mut ());
+        self.error_callback.store(ptr, Ordering::Release);
+    }
+
+    pub(crate) fn set_time_source(&self, source: Option<fn() -> u32>) {
+        let ptr = source.map_or(core::ptr::null_mut(), |f| f as *mut ());
+        self.time_source.store(ptr, Ordering::Release);
+    }
+
+    pub(crate) fn has_time_source(&self) -> bool {
+        !self.time_source.load(Ordering::Acquire).is_null()
+    }
+
+    /// Samples the time source, if one is set.
+    pub(crate) fn now(&self) -> Option<u32> {
+        let ptr = self.time_source.load(Ordering::Acquire);
+        // Safety: only ever stored from a `fn() -> u32` in `set_time_source`
+        (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), fn() -> u32>(ptr) }())
+    }
+
+    fn rx_callback(&self) -> Option<fn(&CanFrame)> {
+        let ptr = self.rx_callback.load(Ordering::Acquire);
+        // Safety: only ever stored from a `fn(&CanFrame)` in `set_rx_callback`
+        (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), fn(&CanFrame)>(ptr) })
+    }
+
+    fn tx_callback(&self) -> Option<fn(TxCompletion)> {
+        let ptr = self.tx_callback.load(Ordering::Acquire);
+        // Safety: only ever stored from a `fn(TxCompletion)` in `set_tx_callback`
+        (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), fn(TxCompletion)>(ptr) })
+    }
+
+    fn error_callback(&self) -> Option<fn(CanError)> {
+        let ptr = self.error_callback.load(Ordering::Acquire);
+        // Safety: only ever stored from a `fn(CanError)` in `set_error_callback`
+        (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), fn(CanError)>(ptr) })
+    }
+}
+
+/// Objects returned by [Can::split_interrupt_resources].
+pub struct InterruptResources<'d, T: Instance> {
+    /// Main-context handle. Received frames and bus errors are read from the
+    /// software queues the ISRs fill.
+    pub can: Can<'d, T>,
+    /// Owned by the transmit interrupt, [Instance::TxInterrupt].
+    pub tx: TxIsr<T>,
+    /// Owned by the FIFO 0 interrupt, [Instance::Rx0Interrupt].
+    pub rx0: Rx0Isr<T>,
+    /// Owned by the FIFO 1 interrupt, [Instance::Rx1Interrupt].
+    pub rx1: Rx1Isr<T>,
+    /// Owned by the status change & error interrupt, [Instance::SceInterrupt].
+    pub sce: SceIsr<T>,
+}
+
+/// Transmit interrupt half, records the outcome of completed mailbox requests.
+pub struct TxIsr<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> TxIsr<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn on_interrupt(&mut self) {
+        service_tx::<T>();
+    }
+}
+
+/// FIFO 0 interrupt half, moves received frames into the software queue.
+pub struct Rx0Isr<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> Rx0Isr<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn on_interrupt(&mut self) {
+        drain_fifo::<T>(&CanFifo::Fifo0);
+    }
+}
+
+/// FIFO 1 interrupt half, moves received frames into the software queue.
+pub struct Rx1Isr<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> Rx1Isr<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn on_interrupt(&mut self) {
+        drain_fifo::<T>(&CanFifo::Fifo1);
+    }
+}
+
+/// Status change & error interrupt half, records bus errors for the application.
+pub struct SceIsr<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> SceIsr<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn on_interrupt(&mut self) {
+        service_sce::<T>();
+    }
+}
+
+fn service_tx<T: Instance>() {
+    let regs = Registers(T::regs());
+    let state = T::state();
+    let mut time_triggered = None;
+
+    for mailbox_num in 0..TX_MAILBOXES {
+        if let Some(mut status) = regs.take_tx_completed(mailbox_num) {
+            if state.faults.take(FAULT_ARBITRATION_LOST) {
+                status = TxStatus::ArbitrationError;
+            }
+            let sof_time = regs.tx_timestamp(mailbox_num);
+            state.set_tx_time(mailbox_num, sof_time);
+            if let Some(time) = state.now() {
+                state.set_tx_confirm_time(mailbox_num, time);
+            }
+            state.set_tx_result(mailbox_num, status);
+            state.raise_event(CanEvent::TxComplete(mailbox_num));
+            if let Some(callback) = state.tx_callback() {
+                // Read once, only if a request completed and a callback is set
+                let time_triggered =
+                    *time_triggered.get_or_insert_with(|| regs.time_triggered_mode());
+                callback(TxCompletion {
+                    handle: state.current_tx_handle(mailbox_num),
+                    status,
+                    timestamp: time_triggered.then_some(sof_time),
+                });
+            }
+        }
+    }
+
+    critical_section::with(|cs| pump_tx_queue::<T>(&mut state.tx_queue.borrow_ref_mut(cs)));
+    state.tx_waker.wake();
+}
+
+/// Whether a mailbox may be loaded now under `order`. In [TxOrder::Fifo] mode,
+/// only one mailbox is in use at a time so frames can't overtake each other.
+fn can_load_mailbox<T: Instance>(order: TxOrder) -> bool {
+    match order {
+        TxOrder::Priority => Registers(T::regs()).find_free_mailbox().is_some(),
+        TxOrder::Fifo => Registers(T::regs()).all_mailboxes_empty(),
+    }
+}
+
+/// Loads queued frames into free mailboxes, in the queue's order.
+fn pump_tx_queue<T: Instance>(queue: &mut TxQueue<TX_QUEUE_LEN>) {
+    while can_load_mailbox::<T>(queue.order()) {
+        let frame = match queue.pop() {
+            Some(frame) => frame,
+            None => break,
+        };
+        let _ = can::transmit_frame::<T>(&frame);
+    }
+}
+
+/// Adds `frame` to the software transmit queue and loads mailboxes if any are free.
+/// Returns the frame back if the queue is full.
+#[cfg(feature = "async")]
+pub(crate) fn enqueue_frame<T: Instance>(frame: CanFrame) -> Result<(), CanFrame> {
+    critical_section::with(|cs| {
+        let mut queue = T::state().tx_queue.borrow_ref_mut(cs);
+        let result = match queue.push(frame) {
+            Ok(()) => Ok(()),
+            Err(frame) => {
+                pump_tx_queue::<T>(&mut queue);
+                queue.push(frame)
+            }
+        };
+        pump_tx_queue::<T>(&mut queue);
+
+        result
+    })
+}
+
+fn service_sce<T: Instance>() {
+    let regs = Registers(T::regs());
+    let state = T::state();
+
+    if regs.take_wakeup() {
+        regs.request_wakeup(); // Leave sleep mode to resume servicing traffic
+        state.raise_event(CanEvent::Wakeup);
+    }
+
+    if let Some(error) = regs.take_error() {
+        report_error::<T>(error);
+    }
+}
+
+/// Raises the events matching a bus error and hands it to the application.
+fn report_error<T: Instance>(error: CanError) {
+    let state = T::state();
+
+    match error.kind {
+        CanErrorKind::BusOff => {
+            state.raise_event(CanEvent::BusOff);
+            state.bus_off_waker.wake();
+        }
+        CanErrorKind::BusPassive | CanErrorKind::BusWarning => {
+            state.raise_event(CanEvent::ErrorWarning)
+        }
+        _ => {}
+    }
+    state.set_error(error);
+    if let Some(callback) = state.error_callback() {
+        callback(error);
+    }
+    state.rx_waker.wake();
+}
+
+/// Forces `fault` as if the peripheral had reported it, see [Can::inject_fault].
+#[cfg(feature = "fault-injection")]
+pub(crate) fn inject_fault<T: Instance>(fault: InjectedFault) {
+    let state = T::state();
+
+    match fault {
+        InjectedFault::ArbitrationLost => state.faults.set(FAULT_ARBITRATION_LOST),
+        InjectedFault::Overrun => {
+            state.set_error(CanErrorKind::Overrun.into());
+            state.raise_event(CanEvent::Overrun);
+            state.rx_waker.wake();
+        }
+        InjectedFault::LastErrorCode(lec) => match CanErrorKind::from_lec(lec) {
+            Some(kind) => report_error::<T>(kind.into()),
+            None => fail!("CAN last error code must be between 1 and 6."),
+        },
+        InjectedFault::BusOff => {
+            state.faults.set(FAULT_BUS_OFF);
+            report_error::<T>(CanErrorKind::BusOff.into());
+        }
+    }
+}
+
+/// Moves every frame pending in `fifo` to the software queue. Runs on both the
+/// message pending and the FIFO full interrupt, so a burst that fills all three
+/// hardware mailboxes before the handler gets to run is still drained at once.
+///
+/// The FIFO status is read once per batch rather than once per frame: all frames
+/// pending at that point are read back to back, then the status is read again to
+/// pick up frames received in the meantime.
+fn drain_fifo<T: Instance>(fifo: &CanFifo) {
+    let regs = Registers(T::regs());
+    let state = T::state();
+    let hw_timestamp = regs.time_triggered_mode();
+
+    loop {
+        let status = regs.fifo_status(fifo);
+        if status.overrun {
+            state.set_error(CanError::overrun(*fifo));
+            state.raise_event(CanEvent::Overrun);
+        }
+        if status.pending == 0 {
+            regs.clear_fifo_flags(fifo, &status);
+            break;
+        }
+
+        let timestamp = state.now();
+        for _ in 0..status.pending {
+            let mut frame = regs.read_frame_fifo_batched(fifo, hw_timestamp);
+            frame.timestamp = timestamp;
+            dispatch_frame::<T>(fifo, frame);
+        }
+        regs.clear_fifo_flags(fifo, &status); // Acknowledge after draining so it can fire again
+    }
+
+    state.rx_waker.wake();
+}
+
+/// Hands a received frame to the callback, or the pool or queue of `fifo`.
+fn dispatch_frame<T: Instance>(fifo: &CanFifo, frame: CanFrame) {
+    let state = T::state();
+
+    if state.update_watched(&frame) {
+        return;
+    }
+
+    if let Some(callback) = state.rx_callback() {
+        callback(&frame);
+        return;
+    }
+    if let Some(slots) = state.pool() {
+        if !state.push_pooled(slots, frame) {
+            state.set_error(CanError::overrun(*fifo));
+            state.raise_event(CanEvent::Overrun);
+        }
+    } else if state.rx_queue[fifo.val()].push(frame).is_err() {
+        state.set_error(CanError::overrun(*fifo));
+        state.raise_event(CanEvent::Overrun);
+    }
+    state.raise_event(CanEvent::FrameReceived);
+}
+
+/// Loads `frame` straight into a free mailbox. Frames already waiting in the
+/// software queue go first, unless `bypass_queue` is set and the queue is in
+/// [TxOrder::Priority] mode.
+pub(crate) fn transmit_direct<T: Instance>(
+    frame: &CanFrame,
+    bypass_queue: bool,
+) -> nb::Result<TxHandle, CanError> {
+    critical_section::with(|cs| {
+        let queue = T::state().tx_queue.borrow_ref(cs);
+        let bypass_queue = bypass_queue && queue.order() == TxOrder::Priority;
+        if !bypass_queue && !queue.is_empty() {
+            return Err(nb::Error::WouldBlock);
+        }
+        if !can_load_mailbox::<T>(queue.order()) {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        can::transmit_frame::<T>(frame)
+    })
+}
+
+pub(crate) fn set_tx_order<T: Instance>(order: TxOrder) {
+    critical_section::with(|cs| T::state().tx_queue.borrow_ref_mut(cs).set_order(order));
+}
+
+/// Whether every queued frame has been sent and all mailboxes are empty.
+#[cfg(feature = "async")]
+pub(crate) fn tx_idle<T: Instance>() -> bool {
+    critical_section::with(|cs| {
+        T::state().tx_queue.borrow_ref(cs).is_empty() && Registers(T::regs()).all_mailboxes_empty()
+    })
+}
+
+/// Calls `poll` until it returns a value, sleeping with `wfi` between calls when
+/// enabled with [Can::set_wfi_wait] and interrupts are serviced, spinning otherwise.
+///
+/// `poll` and `wfi` run with interrupts masked, so an interrupt firing right after
+/// `poll` still wakes the core up and is serviced before the next call.
+pub(crate) fn wait_until<T: Instance, R>(mut poll: impl FnMut() -> Option<R>) -> R {
+    let state = T::state();
+    loop {
+        if !(state.is_split() && state.wfi.load(Ordering::Relaxed)) {
+            if let Some(value) = poll() {
+                return value;
+            }
+            core::hint::spin_loop();
+            continue;
+        }
+
+        let value = critical_section::with(|_| {
+            let value = poll();
+            if value.is_none() {
+                unsafe { riscv::asm::wfi() }; // Wakes on a pending interrupt even while masked
+            }
+            value
+        });
+        if let Some(value) = value {
+            return value;
+        }
+    }
+}
+
+/// Services every CAN interrupt source regardless of which vector fired.
+///
+/// Both FIFOs are drained until neither has a frame pending, so frames that land
+/// in one FIFO while the other is being emptied are picked up on the same pass.
+pub(crate) fn service_all<T: Instance>() {
+    let regs = Registers(T::regs());
+
+    service_tx::<T>();
+    loop {
+        drain_fifo::<T>(&CanFifo::Fifo0);
+        drain_fifo::<T>(&CanFifo::Fifo1);
+        if !regs.fifo_has_messages_pending(&CanFifo::Fifo0)
+            && !regs.fifo_has_messages_pending(&CanFifo::Fifo1)
+        {
+            break;
+        }
+    }
+    if Registers(T::regs()).status_change_pending() {
+        service_sce::<T>();
+    }
+}
+
+/// Collects events from the status registers when no ISR is servicing them. Frames
+/// stay in the hardware FIFO until they are read with [Can::receive].
+pub(crate) fn poll_events<T: Instance>(fifo: &CanFifo) {
+    let regs = Registers(T::regs());
+    let state = T::state();
+
+    service_tx::<T>();
+    if regs.status_change_pending() {
+        service_sce::<T>(); // Persistent bus states are reported once, when entered
+    }
+
+    let fifos = [*fifo, fifo.other()];
+    let fifos = if state.is_burst() {
+        &fifos[..]
+    } else {
+        &fifos[..1]
+    };
+    for fifo in fifos {
+        if regs.take_fifo_overrun(fifo) {
+            state.raise_event(CanEvent::Overrun);
+        }
+        if regs.fifo_has_messages_pending(fifo) {
+            state.raise_event(CanEvent::FrameReceived);
+        }
+
+        regs.take_fifo_full(fifo); // Acknowledge after draining so it can fire again
+    }
+    state.rx_waker.wake();
+}
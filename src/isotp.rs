@@ -0,0 +1,567 @@
+//! ISO-TP (ISO 15765-2) transport, carrying messages of up to 4095 bytes over
+//! classic CAN frames.
+//!
+//! [Sender] and [Receiver] implement the segmentation protocol without doing any
+//! I/O, so they can be driven from any context. [IsoTp] drives them over any
+//! [embedded_can::nb::Can] with blocking waits, [AsyncIsoTp] over the async halves
+//! of [crate::Can].
+//!
+//! Frames are always padded to 8 bytes.
+
+#[cfg(all(feature = "async", feature = "_hal"))]
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_can::Id;
+use embedded_hal::delay::DelayNs;
+
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::asynch::{CanRx, CanTx};
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::can::Instance;
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::enums::TxErrorKind;
+use crate::enums::{CanError, TxError};
+use crate::frame::CanFrame;
+
+/// Longest message ISO-TP can carry over classic CAN.
+pub const MAX_MESSAGE_LEN: usize = 4095;
+
+const SINGLE: u8 = 0x0;
+const FIRST: u8 = 0x1;
+const CONSECUTIVE: u8 = 0x2;
+const FLOW_CONTROL: u8 = 0x3;
+
+/// Addressing and flow control parameters of one ISO-TP channel.
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct IsoTpConfig {
+    /// Identifier of the frames sent to the peer
+    pub tx_id: Id,
+    /// Identifier of the frames received from the peer
+    pub rx_id: Id,
+    /// Consecutive frames the peer may send before waiting for the next flow
+    /// control frame, 0 for no limit
+    pub block_size: u8,
+    /// Minimum separation time between consecutive frames requested from the peer,
+    /// in the raw STmin encoding: 0-127 ms, or 0xF1-0xF9 for 100-900 µs
+    pub st_min: u8,
+    /// Value of the unused bytes of a frame
+    pub padding: u8,
+    /// Time to wait for the next frame from the peer or for a free mailbox, in ms
+    pub timeout_ms: u32,
+}
+
+impl IsoTpConfig {
+    /// No block size limit, no separation time, 0xCC padding and a 1 s timeout.
+    pub fn new(tx_id: impl Into<Id>, rx_id: impl Into<Id>) -> Self {
+        Self {
+            tx_id: tx_id.into(),
+            rx_id: rx_id.into(),
+            block_size: 0,
+            st_min: 0,
+            padding: 0xCC,
+            timeout_ms: 1000,
+        }
+    }
+
+    /// Flow control frame sent to the peer with `status`.
+    pub fn flow_control(&self, status: FlowStatus) -> CanFrame {
+        self.frame(&[
+            FLOW_CONTROL << 4 | status as u8,
+            self.block_size,
+            self.st_min,
+        ])
+    }
+
+    fn frame(&self, bytes: &[u8]) -> CanFrame {
+        let mut data = [self.padding; 8];
+        data[..bytes.len()].copy_from_slice(bytes);
+
+        CanFrame::new(self.tx_id, &data).unwrap()
+    }
+}
+
+/// Flow status of a flow control frame.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum FlowStatus {
+    /// Continue to send
+    ContinueToSend = 0,
+    /// Wait for the next flow control frame
+    Wait = 1,
+    /// The message is too long for the receiver
+    Overflow = 2,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum IsoTpError {
+    /// The message is longer than [MAX_MESSAGE_LEN]
+    TooLong,
+    /// The message received is longer than the buffer
+    BufferTooSmall,
+    /// The peer reported the message is too long for it
+    Overflow,
+    /// A frame was not the one expected at this point of the transfer
+    UnexpectedFrame,
+    /// A consecutive frame was lost
+    WrongSequenceNumber,
+    /// The peer or the bus did not respond in time
+    Timeout,
+    /// A frame was not sent, e.g. it lost arbitration in single-shot mode
+    Tx(TxError),
+    Can(CanError),
+}
+
+impl From<CanError> for IsoTpError {
+    fn from(error: CanError) -> Self {
+        IsoTpError::Can(error)
+    }
+}
+
+/// Next action for the user of a [Sender].
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum SendStep {
+    /// Transmit this frame, then wait [Sender::separation_time_us] before calling
+    /// [Sender::next_step] again.
+    Send(CanFrame),
+    /// Wait for a flow control frame from the peer and hand it to
+    /// [Sender::on_flow_control].
+    AwaitFlowControl,
+    Done,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+enum SenderState {
+    Start,
+    AwaitFlowControl,
+    Consecutive,
+    Done,
+}
+
+/// Splits one message into frames.
+pub struct Sender<'a> {
+    config: IsoTpConfig,
+    data: &'a [u8],
+    offset: usize,
+    seq: u8,
+    block_left: Option<u8>,
+    st_min_us: u32,
+    state: SenderState,
+}
+
+impl<'a> Sender<'a> {
+    pub fn new(config: IsoTpConfig, data: &'a [u8]) -> Result<Self, IsoTpError> {
+        if data.len() > MAX_MESSAGE_LEN {
+            return Err(IsoTpError::TooLong);
+        }
+
+        Ok(Self {
+            config,
+            data,
+            offset: 0,
+            seq: 1,
+            block_left: None,
+            st_min_us: 0,
+            state: SenderState::Start,
+        })
+    }
+
+    pub fn next_step(&mut self) -> SendStep {
+        match self.state {
+            SenderState::Start if self.data.len() <= 7 => {
+                self.state = SenderState::Done;
+                let mut bytes = [0; 8];
+                bytes[0] = SINGLE << 4 | self.data.len() as u8;
+                bytes[1..=self.data.len()].copy_from_slice(self.data);
+
+                SendStep::Send(self.config.frame(&bytes[..=self.data.len()]))
+            }
+            SenderState::Start => {
+                self.state = SenderState::AwaitFlowControl;
+                self.offset = 6;
+                let len = self.data.len();
+                let mut bytes = [FIRST << 4 | (len >> 8) as u8, len as u8, 0, 0, 0, 0, 0, 0];
+                bytes[2..].copy_from_slice(&self.data[..6]);
+
+                SendStep::Send(self.config.frame(&bytes))
+            }
+            SenderState::AwaitFlowControl => SendStep::AwaitFlowControl,
+            SenderState::Consecutive => {
+                let end = (self.offset + 7).min(self.data.len());
+                let mut bytes = [0; 8];
+                bytes[0] = CONSECUTIVE << 4 | self.seq;
+                bytes[1..=end - self.offset].copy_from_slice(&self.data[self.offset..end]);
+                let frame = self.config.frame(&bytes[..=end - self.offset]);
+
+                self.offset = end;
+                self.seq = (self.seq + 1) & 0xF;
+                if let Some(left) = self.block_left.as_mut() {
+                    *left -= 1;
+                }
+                if self.offset == self.data.len() {
+                    self.state = SenderState::Done;
+                } else if self.block_left == Some(0) {
+                    self.state = SenderState::AwaitFlowControl;
+                }
+
+                SendStep::Send(frame)
+            }
+            SenderState::Done => SendStep::Done,
+        }
+    }
+
+    /// Handles a flow control frame received while [SendStep::AwaitFlowControl].
+    pub fn on_flow_control(&mut self, frame: &CanFrame) -> Result<(), IsoTpError> {
+        let data = &frame.data()[..frame.dlc()];
+        if self.state != SenderState::AwaitFlowControl
+            || data.len() < 3
+            || data[0] >> 4 != FLOW_CONTROL
+        {
+            return Err(IsoTpError::UnexpectedFrame);
+        }
+
+        match data[0] & 0xF {
+            0 => {
+                self.block_left = if data[1] == 0 { None } else { Some(data[1]) };
+                self.st_min_us = st_min_us(data[2]);
+                self.state = SenderState::Consecutive;
+                Ok(())
+            }
+            1 => Ok(()),
+            2 => Err(IsoTpError::Overflow),
+            _ => Err(IsoTpError::UnexpectedFrame),
+        }
+    }
+
+    /// Separation time between consecutive frames requested by the peer, `0` when
+    /// no consecutive frame follows: once the message is sent or while awaiting a
+    /// flow control frame.
+    pub fn separation_time_us(&self) -> u32 {
+        match self.state {
+            SenderState::Consecutive => self.st_min_us,
+            _ => 0,
+        }
+    }
+}
+
+/// Decodes a raw STmin value, reserved values standing for the longest time.
+fn st_min_us(st_min: u8) -> u32 {
+    match st_min {
+        0..=0x7F => st_min as u32 * 1000,
+        0xF1..=0xF9 => (st_min - 0xF0) as u32 * 100,
+        _ => 127_000,
+    }
+}
+
+/// Progress of a [Receiver].
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum ReceiveStep {
+    /// Keep feeding the frames from the peer.
+    Pending,
+    /// Transmit this flow control frame, then keep feeding the frames from the peer.
+    FlowControl(CanFrame),
+    /// A message of this length is in the buffer.
+    Complete(usize),
+}
+
+/// Reassembles one message from frames, into a buffer of the user.
+pub struct Receiver<'b> {
+    config: IsoTpConfig,
+    buf: &'b mut [u8],
+    len: usize,
+    received: usize,
+    seq: u8,
+    block_left: u8,
+}
+
+impl<'b> Receiver<'b> {
+    pub fn new(config: IsoTpConfig, buf: &'b mut [u8]) -> Self {
+        Self {
+            config,
+            buf,
+            len: 0,
+            received: 0,
+            seq: 0,
+            block_left: 0,
+        }
+    }
+
+    /// Handles a frame from the peer. After an error, the receiver waits for the
+    /// start of a new message.
+    ///
+    /// On [IsoTpError::BufferTooSmall], the peer should be told with a
+    /// [FlowStatus::Overflow] flow control frame, see [IsoTpConfig::flow_control].
+    pub fn on_frame(&mut self, frame: &CanFrame) -> Result<ReceiveStep, IsoTpError> {
+        let result = self.handle(&frame.data()[..frame.dlc()]);
+        if result.is_err() {
+            self.len = 0;
+        }
+
+        result
+    }
+
+    fn handle(&mut self, data: &[u8]) -> Result<ReceiveStep, IsoTpError> {
+        let Some(&pci) = data.first() else {
+            return Err(IsoTpError::UnexpectedFrame);
+        };
+
+        match pci >> 4 {
+            SINGLE => {
+                let len = (pci & 0xF) as usize;
+                if len == 0 || len > 7 || len >= data.len() {
+                    return Err(IsoTpError::UnexpectedFrame);
+                }
+                if len > self.buf.len() {
+                    return Err(IsoTpError::BufferTooSmall);
+                }
+
+                self.buf[..len].copy_from_slice(&data[1..=len]);
+                self.len = 0;
+                Ok(ReceiveStep::Complete(len))
+            }
+            FIRST => {
+                if data.len() < 8 {
+                    return Err(IsoTpError::UnexpectedFrame);
+                }
+                let len = ((pci & 0xF) as usize) << 8 | data[1] as usize;
+                if len < 8 {
+                    return Err(IsoTpError::UnexpectedFrame);
+                }
+                if len > self.buf.len() {
+                    return Err(IsoTpError::BufferTooSmall);
+                }
+
+                self.buf[..6].copy_from_slice(&data[2..8]);
+                self.len = len;
+                self.received = 6;
+                self.seq = 1;
+                self.block_left = self.config.block_size;
+                Ok(ReceiveStep::FlowControl(
+                    self.config.flow_control(FlowStatus::ContinueToSend),
+                ))
+            }
+            CONSECUTIVE if self.len != 0 => {
+                if pci & 0xF != self.seq {
+                    return Err(IsoTpError::WrongSequenceNumber);
+                }
+
+                let count = (self.len - self.received).min(7).min(data.len() - 1);
+                self.buf[self.received..self.received + count].copy_from_slice(&data[1..=count]);
+                self.received += count;
+                self.seq = (self.seq + 1) & 0xF;
+
+                if self.received == self.len {
+                    let len = self.len;
+                    self.len = 0;
+                    return Ok(ReceiveStep::Complete(len));
+                }
+                if self.config.block_size != 0 {
+                    self.block_left -= 1;
+                    if self.block_left == 0 {
+                        self.block_left = self.config.block_size;
+                        return Ok(ReceiveStep::FlowControl(
+                            self.config.flow_control(FlowStatus::ContinueToSend),
+                        ));
+                    }
+                }
+
+                Ok(ReceiveStep::Pending)
+            }
+            _ => Err(IsoTpError::UnexpectedFrame),
+        }
+    }
+}
+
+/// Blocking ISO-TP channel over a CAN driver.
+///
+/// Frames with identifiers other than [IsoTpConfig::rx_id] received while waiting
+/// are dropped, so filter them out or dedicate the driver to the channel.
+///
+/// Consecutive frames are handed to the driver as soon as it takes them, so the
+/// driver must send frames in submission order: with [crate::Can], select
+/// [crate::TxOrder::Fifo] with [crate::Can::set_tx_order] before sending, or
+/// frames of the same identifier may leave the three mailboxes out of order.
+pub struct IsoTp<C, D> {
+    can: C,
+    delay: D,
+    config: IsoTpConfig,
+}
+
+impl<C, D> IsoTp<C, D>
+where
+    C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    D: DelayNs,
+{
+    pub fn new(can: C, delay: D, config: IsoTpConfig) -> Self {
+        Self { can, delay, config }
+    }
+
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    pub fn release(self) -> (C, D) {
+        (self.can, self.delay)
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> Result<(), IsoTpError> {
+        let mut sender = Sender::new(self.config, data)?;
+        loop {
+            match sender.next_step() {
+                SendStep::Send(frame) => {
+                    self.transmit(&frame)?;
+                    self.delay.delay_us(sender.separation_time_us());
+                }
+                SendStep::AwaitFlowControl => {
+                    let frame = self.receive_frame()?;
+                    sender.on_flow_control(&frame)?;
+                }
+                SendStep::Done => return Ok(()),
+            }
+        }
+    }
+
+    /// Waits for a message from the peer, returning its length.
+    pub fn receive(&mut self, buf: &mut [u8]) -> Result<usize, IsoTpError> {
+        let config = self.config;
+        let mut receiver = Receiver::new(config, buf);
+        loop {
+            let frame = self.receive_frame()?;
+            match receiver.on_frame(&frame) {
+                Ok(ReceiveStep::Pending) => {}
+                Ok(ReceiveStep::FlowControl(frame)) => self.transmit(&frame)?,
+                Ok(ReceiveStep::Complete(len)) => return Ok(len),
+                Err(IsoTpError::BufferTooSmall) => {
+                    self.transmit(&config.flow_control(FlowStatus::Overflow))?;
+                    return Err(IsoTpError::BufferTooSmall);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn transmit(&mut self, frame: &CanFrame) -> Result<(), IsoTpError> {
+        self.poll(|can| can.transmit(frame).map(|_| ()))
+    }
+
+    fn receive_frame(&mut self) -> Result<CanFrame, IsoTpError> {
+        let rx_id = self.config.rx_id;
+        self.poll(|can| match can.receive() {
+            Ok(frame) if *frame.id() == rx_id => Ok(frame),
+            Ok(_) => Err(nb::Error::WouldBlock),
+            Err(error) => Err(error),
+        })
+    }
+
+    /// Retries `f` every 100 µs until it stops blocking or the timeout elapses.
+    fn poll<R>(
+        &mut self,
+        mut f: impl FnMut(&mut C) -> nb::Result<R, CanError>,
+    ) -> Result<R, IsoTpError> {
+        for _ in 0..=self.config.timeout_ms.saturating_mul(10) {
+            match f(&mut self.can) {
+                Ok(value) => return Ok(value),
+                Err(nb::Error::Other(error)) => return Err(error.into()),
+                Err(nb::Error::WouldBlock) => self.delay.delay_us(100),
+            }
+        }
+
+        Err(IsoTpError::Timeout)
+    }
+}
+
+/// ISO-TP channel over the async halves of [crate::Can].
+///
+/// Frames with other identifiers than [IsoTpConfig::rx_id] are set aside for
+/// [CanRx::read], see [CanRx::receive_id].
+///
+/// Each frame is waited for until it has left its mailbox before the next one is
+/// loaded, so consecutive frames stay in order whatever the [crate::TxOrder].
+#[cfg(all(feature = "async", feature = "_hal"))]
+pub struct AsyncIsoTp<'a, T: Instance> {
+    tx: CanTx<'a, T>,
+    rx: CanRx<'a, T>,
+    config: IsoTpConfig,
+}
+
+#[cfg(all(feature = "async", feature = "_hal"))]
+impl<'a, T: Instance> AsyncIsoTp<'a, T> {
+    pub fn new(tx: CanTx<'a, T>, rx: CanRx<'a, T>, config: IsoTpConfig) -> Self {
+        Self { tx, rx, config }
+    }
+
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    pub fn release(self) -> (CanTx<'a, T>, CanRx<'a, T>) {
+        (self.tx, self.rx)
+    }
+
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), IsoTpError> {
+        let mut sender = Sender::new(self.config, data)?;
+        loop {
+            match sender.next_step() {
+                SendStep::Send(frame) => {
+                    self.transmit(&frame).await?;
+                    Timer::after_micros(sender.separation_time_us() as u64).await;
+                }
+                SendStep::AwaitFlowControl => {
+                    let frame = self.receive_frame().await?;
+                    sender.on_flow_control(&frame)?;
+                }
+                SendStep::Done => return Ok(()),
+            }
+        }
+    }
+
+    /// Waits for a message from the peer, returning its length.
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<usize, IsoTpError> {
+        let config = self.config;
+        let mut receiver = Receiver::new(config, buf);
+        loop {
+            let frame = self.receive_frame().await?;
+            match receiver.on_frame(&frame) {
+                Ok(ReceiveStep::Pending) => {}
+                Ok(ReceiveStep::FlowControl(frame)) => self.transmit(&frame).await?,
+                Ok(ReceiveStep::Complete(len)) => return Ok(len),
+                Err(IsoTpError::BufferTooSmall) => {
+                    self.transmit(&config.flow_control(FlowStatus::Overflow))
+                        .await?;
+                    return Err(IsoTpError::BufferTooSmall);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    async fn transmit(&mut self, frame: &CanFrame) -> Result<(), IsoTpError> {
+        let timeout = self.timeout();
+        let sent = async {
+            let handle = self.tx.write_tracked(frame).await;
+            self.tx.flush(handle).await.outcome(handle.mailbox(), None)
+        };
+
+        match with_timeout(timeout, sent).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(error)) if error.kind == TxErrorKind::Timeout => Err(IsoTpError::Timeout),
+            Ok(Err(error)) => Err(IsoTpError::Tx(error)),
+            Err(_) => Err(IsoTpError::Timeout),
+        }
+    }
+
+    async fn receive_frame(&mut self) -> Result<CanFrame, IsoTpError> {
+        let timeout = self.timeout();
+        match with_timeout(timeout, self.rx.receive_id(self.config.rx_id)).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(IsoTpError::Timeout),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.config.timeout_ms as u64)
+    }
+}
@@ -0,0 +1,687 @@
+//! SAE J1939 addressing: identifiers, requests, address claiming and the
+//! multi-packet transport protocol.
+//!
+//! Like [crate::isotp], the protocol state machines do no I/O: they are handed the
+//! received frames and return the frames to transmit.
+
+use embedded_can::{ExtendedId, Id};
+
+use crate::frame::CanFrame;
+
+#[cfg(test)]
+mod tests;
+
+/// Request PGN, asking a node to send a parameter group.
+pub const PGN_REQUEST: u32 = 0xEA00;
+/// Address claimed PGN, announcing the NAME behind a source address.
+pub const PGN_ADDRESS_CLAIMED: u32 = 0xEE00;
+/// Transport protocol connection management PGN (TP.CM).
+pub const PGN_TP_CM: u32 = 0xEC00;
+/// Transport protocol data transfer PGN (TP.DT).
+pub const PGN_TP_DT: u32 = 0xEB00;
+/// Destination address of broadcast messages.
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+/// Source address of a node that could not claim an address.
+pub const NULL_ADDRESS: u8 = 0xFE;
+
+/// Priority of requests, address claims and single frame messages.
+const DEFAULT_PRIORITY: u8 = 6;
+/// Time others have to contest an address claim, in ms.
+const CLAIM_TIMEOUT_MS: u32 = 250;
+/// Priority of the transport protocol messages.
+const TP_PRIORITY: u8 = 7;
+
+/// Longest message the transport protocol can carry, in 255 packets of 7 bytes.
+pub const MAX_TRANSPORT_LEN: usize = 1785;
+/// Time to wait between the packets of a broadcast, in ms (50 to 200).
+pub const BAM_PACKET_INTERVAL_MS: u32 = 50;
+
+const TP_RTS: u8 = 16;
+const TP_CTS: u8 = 17;
+const TP_END_OF_MESSAGE_ACK: u8 = 19;
+const TP_BAM: u8 = 32;
+const TP_ABORT: u8 = 255;
+/// Abort reason of a message too long for the receiver's resources.
+const ABORT_RESOURCES: u8 = 2;
+
+/// Fields of a J1939 29-bit identifier.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct J1939Id {
+    /// 0 (highest) to 7
+    pub priority: u8,
+    /// Parameter group number, with the destination byte cleared for PDU1 groups
+    pub pgn: u32,
+    pub source: u8,
+    /// Destination address of PDU1 groups (PF below 240), `None` for PDU2 groups
+    pub destination: Option<u8>,
+}
+
+impl J1939Id {
+    /// Identifier of a broadcast message. PDU1 groups are sent to [GLOBAL_ADDRESS].
+    pub fn new(priority: u8, pgn: u32, source: u8) -> Self {
+        let destination = is_pdu1(pgn).then_some(GLOBAL_ADDRESS);
+
+        Self {
+            priority,
+            pgn: normalize_pgn(pgn),
+            source,
+            destination,
+        }
+    }
+
+    /// Addresses a PDU1 group to `destination`, PDU2 groups are always broadcast.
+    pub fn with_destination(self, destination: u8) -> Self {
+        Self {
+            destination: self.destination.map(|_| destination),
+            ..self
+        }
+    }
+
+    pub fn from_id(id: ExtendedId) -> Self {
+        let raw = id.as_raw();
+        let pgn = (raw >> 8) & 0x3FFFF;
+        let destination = is_pdu1(pgn).then_some(pgn as u8);
+
+        Self {
+            priority: (raw >> 26) as u8,
+            pgn: normalize_pgn(pgn),
+            source: raw as u8,
+            destination,
+        }
+    }
+
+    pub fn to_id(&self) -> ExtendedId {
+        let pgn = normalize_pgn(self.pgn) | self.destination.unwrap_or(0) as u32;
+        let raw = ((self.priority & 0x7) as u32) << 26 | pgn << 8 | self.source as u32;
+
+        ExtendedId::new(raw).unwrap()
+    }
+
+    /// Decodes the identifier of `frame`, if extended.
+    pub fn of(frame: &CanFrame) -> Option<Self> {
+        match *frame.id() {
+            Id::Extended(id) => Some(Self::from_id(id)),
+            Id::Standard(_) => None,
+        }
+    }
+
+    /// Whether a node at `address` should process a message with this identifier.
+    pub fn is_for(&self, address: u8) -> bool {
+        matches!(self.destination, None | Some(GLOBAL_ADDRESS)) || self.destination == Some(address)
+    }
+}
+
+fn is_pdu1(pgn: u32) -> bool {
+    ((pgn >> 8) as u8) < 240
+}
+
+/// Clears the destination byte of PDU1 groups.
+fn normalize_pgn(pgn: u32) -> u32 {
+    let pgn = pgn & 0x3FFFF;
+    match is_pdu1(pgn) {
+        true => pgn & !0xFF,
+        false => pgn,
+    }
+}
+
+/// Request for parameter group `pgn`, sent from `source` to `destination`.
+pub fn request_frame(pgn: u32, source: u8, destination: u8) -> CanFrame {
+    let id = J1939Id::new(DEFAULT_PRIORITY, PGN_REQUEST, source).with_destination(destination);
+
+    CanFrame::new(id.to_id(), &pgn.to_le_bytes()[..3]).unwrap()
+}
+
+/// Decodes a request, returning the requested PGN and the request's identifier.
+pub fn parse_request(frame: &CanFrame) -> Option<(u32, J1939Id)> {
+    let id = J1939Id::of(frame)?;
+    if id.pgn != PGN_REQUEST || frame.dlc() < 3 {
+        return None;
+    }
+
+    let data = frame.data();
+    Some((u32::from_le_bytes([data[0], data[1], data[2], 0]), id))
+}
+
+/// 64-bit NAME identifying a node, lower values winning address contention.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct Name(pub u64);
+
+impl Name {
+    /// Whether the node can pick another address when it loses its preferred one.
+    pub fn is_arbitrary_address_capable(&self) -> bool {
+        self.0 >> 63 != 0
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+enum ClaimState {
+    Idle,
+    Claiming { since_ms: u32 },
+    Claimed,
+    CannotClaim,
+}
+
+/// Address claim procedure of J1939-81, without doing any I/O.
+///
+/// Start it with [AddressClaimer::start], then hand it every received frame with
+/// [AddressClaimer::on_frame] and call [AddressClaimer::poll] regularly,
+/// transmitting the frames they return. The address is usable once
+/// [AddressClaimer::address] returns it.
+///
+/// Arbitrary address capable nodes that lose their address move on to the next
+/// one in the 128-247 dynamic range.
+pub struct AddressClaimer {
+    name: Name,
+    candidate: u8,
+    state: ClaimState,
+}
+
+impl AddressClaimer {
+    pub fn new(name: Name, preferred_address: u8) -> Self {
+        Self {
+            name,
+            candidate: preferred_address,
+            state: ClaimState::Idle,
+        }
+    }
+
+    pub fn name(&self) -> Name {
+        self.name
+    }
+
+    /// Address claimed, once no other node contested it.
+    pub fn address(&self) -> Option<u8> {
+        match self.state {
+            ClaimState::Claimed => Some(self.candidate),
+            _ => None,
+        }
+    }
+
+    /// Whether no address was left to claim.
+    pub fn cannot_claim(&self) -> bool {
+        self.state == ClaimState::CannotClaim
+    }
+
+    /// Claims the preferred address, returning the address claimed frame to send.
+    pub fn start(&mut self, now_ms: u32) -> CanFrame {
+        self.state = ClaimState::Claiming { since_ms: now_ms };
+        self.claim_frame()
+    }
+
+    /// Completes the claim once the contention time has elapsed.
+    pub fn poll(&mut self, now_ms: u32) {
+        if let ClaimState::Claiming { since_ms } = self.state {
+            if now_ms.wrapping_sub(since_ms) >= CLAIM_TIMEOUT_MS {
+                self.state = ClaimState::Claimed;
+            }
+        }
+    }
+
+    /// Handles a received frame, returning the frame to send in response, if any.
+    pub fn on_frame(&mut self, frame: &CanFrame, now_ms: u32) -> Option<CanFrame> {
+        if self.state == ClaimState::Idle {
+            return None;
+        }
+
+        if let Some((pgn, id)) = parse_request(frame) {
+            let address = self.address().unwrap_or(GLOBAL_ADDRESS);
+            if pgn == PGN_ADDRESS_CLAIMED && id.is_for(address) {
+                return Some(self.claim_frame());
+            }
+            return None;
+        }
+
+        let id = J1939Id::of(frame)?;
+        if id.pgn != PGN_ADDRESS_CLAIMED || id.source != self.candidate || frame.dlc() < 8 {
+            return None;
+        }
+        if self.state == ClaimState::CannotClaim {
+            return None;
+        }
+
+        let mut name = [0; 8];
+        name.copy_from_slice(&frame.data()[..8]);
+        let other = Name(u64::from_le_bytes(name));
+        if self.name < other {
+            // Our NAME wins, defend the address
+            return Some(self.claim_frame());
+        }
+
+        match self.next_candidate() {
+            Some(address) => {
+                self.candidate = address;
+                self.state = ClaimState::Claiming { since_ms: now_ms };
+            }
+            None => self.state = ClaimState::CannotClaim,
+        }
+
+        Some(self.claim_frame())
+    }
+
+    fn next_candidate(&self) -> Option<u8> {
+        if !self.name.is_arbitrary_address_capable() {
+            return None;
+        }
+
+        match self.candidate {
+            128..=246 => Some(self.candidate + 1),
+            247.. => None,
+            _ => Some(128),
+        }
+    }
+
+    /// Address claimed frame, or cannot claim frame once no address is left.
+    fn claim_frame(&self) -> CanFrame {
+        let source = match self.state {
+            ClaimState::CannotClaim => NULL_ADDRESS,
+            _ => self.candidate,
+        };
+        let id = J1939Id::new(DEFAULT_PRIORITY, PGN_ADDRESS_CLAIMED, source);
+
+        CanFrame::new(id.to_id(), &self.name.0.to_le_bytes()).unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum TransportError {
+    /// The message is longer than [MAX_TRANSPORT_LEN]
+    TooLong,
+    /// A data packet was lost
+    WrongSequenceNumber,
+    /// The peer aborted the transfer with this reason code
+    Aborted(u8),
+}
+
+/// Next action for the user of a [TransportSender].
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum TransportStep {
+    /// Transmit this frame, then call [TransportSender::next_step] again, after
+    /// [BAM_PACKET_INTERVAL_MS] between the packets of a broadcast.
+    Send(CanFrame),
+    /// Wait for a frame from the destination and hand it to
+    /// [TransportSender::on_frame].
+    AwaitResponse,
+    Done,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+enum SenderState {
+    Start,
+    Data,
+    AwaitClearToSend,
+    AwaitAck,
+    Done,
+}
+
+/// Sends one parameter group, with the transport protocol when longer than 8 bytes.
+///
+/// Broadcasts are announced with TP.BAM, messages to a single destination use a
+/// TP.CM connection paced by the destination.
+///
+/// Data packets must reach the bus in the order they are returned: transmit each
+/// once the previous one has left its mailbox, or select [crate::TxOrder::Fifo]
+/// with [crate::Can::set_tx_order].
+pub struct TransportSender<'a> {
+    pgn: u32,
+    source: u8,
+    destination: u8,
+    data: &'a [u8],
+    next_packet: u16,
+    window_end: u16,
+    state: SenderState,
+}
+
+impl<'a> TransportSender<'a> {
+    /// Sends `data` from `source` to `destination`, [GLOBAL_ADDRESS] for a broadcast.
+    pub fn new(
+        pgn: u32,
+        source: u8,
+        destination: u8,
+        data: &'a [u8],
+    ) -> Result<Self, TransportError> {
+        if data.len() > MAX_TRANSPORT_LEN {
+            return Err(TransportError::TooLong);
+        }
+
+        Ok(Self {
+            pgn: normalize_pgn(pgn),
+            source,
+            destination,
+            data,
+            next_packet: 1,
+            window_end: 0,
+            state: SenderState::Start,
+        })
+    }
+
+    fn packets(&self) -> u16 {
+        self.data.len().div_ceil(7) as u16
+    }
+
+    fn is_broadcast(&self) -> bool {
+        self.destination == GLOBAL_ADDRESS
+    }
+
+    pub fn next_step(&mut self) -> TransportStep {
+        let len = self.data.len() as u16;
+        match self.state {
+            SenderState::Start if self.data.len() <= 8 => {
+                self.state = SenderState::Done;
+                let id = J1939Id::new(DEFAULT_PRIORITY, self.pgn, self.source)
+                    .with_destination(self.destination);
+
+                TransportStep::Send(CanFrame::new(id.to_id(), self.data).unwrap())
+            }
+            SenderState::Start if self.is_broadcast() => {
+                self.state = SenderState::Data;
+                self.window_end = self.packets();
+
+                TransportStep::Send(self.control_frame([
+                    TP_BAM,
+                    len as u8,
+                    (len >> 8) as u8,
+                    self.packets() as u8,
+                    0xFF,
+                ]))
+            }
+            SenderState::Start => {
+                self.state = SenderState::AwaitClearToSend;
+
+                TransportStep::Send(self.control_frame([
+                    TP_RTS,
+                    len as u8,
+                    (len >> 8) as u8,
+                    self.packets() as u8,
+                    0xFF,
+                ]))
+            }
+            SenderState::Data => {
+                let start = (self.next_packet as usize - 1) * 7;
+                let end = (start + 7).min(self.data.len());
+                let mut bytes = [0xFF; 8];
+                bytes[0] = self.next_packet as u8;
+                bytes[1..=end - start].copy_from_slice(&self.data[start..end]);
+                let frame = tp_frame(PGN_TP_DT, self.source, self.destination, bytes);
+
+                self.next_packet += 1;
+                if self.next_packet > self.window_end {
+                    self.state = match (self.is_broadcast(), self.next_packet > self.packets()) {
+                        (true, _) => SenderState::Done,
+                        (false, true) => SenderState::AwaitAck,
+                        (false, false) => SenderState::AwaitClearToSend,
+                    };
+                }
+
+                TransportStep::Send(frame)
+            }
+            SenderState::AwaitClearToSend | SenderState::AwaitAck => TransportStep::AwaitResponse,
+            SenderState::Done => TransportStep::Done,
+        }
+    }
+
+    /// Handles a frame received while [TransportStep::AwaitResponse], ignoring the
+    /// frames of other transfers.
+    pub fn on_frame(&mut self, frame: &CanFrame) -> Result<(), TransportError> {
+        let Some(id) = J1939Id::of(frame) else {
+            return Ok(());
+        };
+        let data = frame.data();
+        if id.pgn != PGN_TP_CM
+            || id.source != self.destination
+            || id.destination != Some(self.source)
+            || frame.dlc() < 8
+            || control_pgn(data) != self.pgn
+        {
+            return Ok(());
+        }
+
+        match (data[0], self.state) {
+            (TP_ABORT, _) => {
+                self.state = SenderState::Done;
+                return Err(TransportError::Aborted(data[1]));
+            }
+            // A clear to send of no packets holds the connection open
+            (TP_CTS, SenderState::AwaitClearToSend) if data[1] != 0 => {
+                self.next_packet = (data[2] as u16).clamp(1, self.packets());
+                self.window_end = (self.next_packet + data[1] as u16 - 1).min(self.packets());
+                self.state = SenderState::Data;
+            }
+            (TP_END_OF_MESSAGE_ACK, SenderState::AwaitAck) => self.state = SenderState::Done,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn control_frame(&self, bytes: [u8; 5]) -> CanFrame {
+        control_frame(self.pgn, self.source, self.destination, bytes)
+    }
+}
+
+/// Progress of a [TransportReceiver].
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum TransportReceive {
+    /// Keep feeding the received frames.
+    Pending,
+    /// Transmit this frame, then keep feeding the received frames.
+    Reply(CanFrame),
+    /// A message of parameter group `pgn` from `source` is in
+    /// [TransportReceiver::data]. Transmit `ack`, if any, to acknowledge it.
+    Complete {
+        pgn: u32,
+        source: u8,
+        ack: Option<CanFrame>,
+    },
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+struct Session {
+    pgn: u32,
+    source: u8,
+    broadcast: bool,
+    len: usize,
+    packets: u8,
+    next_seq: u16,
+    window: u8,
+    window_left: u8,
+}
+
+/// Reassembles transport protocol messages of up to `N` bytes sent to `address`
+/// or broadcast.
+///
+/// One message is reassembled at a time, a new announcement or request to send
+/// replacing the current one.
+pub struct TransportReceiver<const N: usize> {
+    address: u8,
+    buf: [u8; N],
+    len: usize,
+    session: Option<Session>,
+}
+
+impl<const N: usize> TransportReceiver<N> {
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            buf: [0; N],
+            len: 0,
+            session: None,
+        }
+    }
+
+    /// Changes the address of the node, e.g. once claimed.
+    pub fn set_address(&mut self, address: u8) {
+        self.address = address;
+        self.session = None;
+    }
+
+    /// Last message completed.
+    pub fn data(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Handles a received frame, ignoring the frames of other parameter groups.
+    pub fn on_frame(&mut self, frame: &CanFrame) -> Result<TransportReceive, TransportError> {
+        let Some(id) = J1939Id::of(frame) else {
+            return Ok(TransportReceive::Pending);
+        };
+        if frame.dlc() < 8 {
+            return Ok(TransportReceive::Pending);
+        }
+
+        match id.pgn {
+            PGN_TP_CM => self.on_control(id, frame.data()),
+            PGN_TP_DT => self.on_data(id, frame.data()),
+            _ => Ok(TransportReceive::Pending),
+        }
+    }
+
+    fn on_control(&mut self, id: J1939Id, data: &[u8]) -> Result<TransportReceive, TransportError> {
+        let pgn = control_pgn(data);
+        let len = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let packets = data[3];
+        let valid = len > 8 && len <= MAX_TRANSPORT_LEN && packets as usize == len.div_ceil(7);
+
+        match data[0] {
+            TP_BAM if id.destination == Some(GLOBAL_ADDRESS) && valid && len <= N => {
+                self.session = Some(Session {
+                    pgn,
+                    source: id.source,
+                    broadcast: true,
+                    len,
+                    packets,
+                    next_seq: 1,
+                    window: packets,
+                    window_left: packets,
+                });
+                Ok(TransportReceive::Pending)
+            }
+            TP_RTS if id.destination == Some(self.address) => {
+                if !valid || len > N {
+                    let bytes = [TP_ABORT, ABORT_RESOURCES, 0xFF, 0xFF, 0xFF];
+                    let reply = control_frame(pgn, self.address, id.source, bytes);
+                    return Ok(TransportReceive::Reply(reply));
+                }
+
+                let window = data[4].clamp(1, packets);
+                self.session = Some(Session {
+                    pgn,
+                    source: id.source,
+                    broadcast: false,
+                    len,
+                    packets,
+                    next_seq: 1,
+                    window,
+                    window_left: window,
+                });
+                let bytes = [TP_CTS, window, 1, 0xFF, 0xFF];
+                Ok(TransportReceive::Reply(control_frame(
+                    pgn,
+                    self.address,
+                    id.source,
+                    bytes,
+                )))
+            }
+            TP_ABORT if self.is_session(&id) && self.session.is_some_and(|s| s.pgn == pgn) => {
+                self.session = None;
+                Err(TransportError::Aborted(data[1]))
+            }
+            _ => Ok(TransportReceive::Pending),
+        }
+    }
+
+    fn on_data(&mut self, id: J1939Id, data: &[u8]) -> Result<TransportReceive, TransportError> {
+        if !self.is_session(&id) {
+            return Ok(TransportReceive::Pending);
+        }
+        let Some(session) = self.session.as_mut() else {
+            return Ok(TransportReceive::Pending);
+        };
+        if data[0] as u16 != session.next_seq {
+            self.session = None;
+            return Err(TransportError::WrongSequenceNumber);
+        }
+
+        let start = (session.next_seq as usize - 1) * 7;
+        let count = (session.len - start).min(7);
+        self.buf[start..start + count].copy_from_slice(&data[1..=count]);
+        session.next_seq += 1;
+        session.window_left -= 1;
+        let session = *session;
+
+        if session.next_seq > session.packets as u16 {
+            self.session = None;
+            self.len = session.len;
+            let ack = (!session.broadcast).then(|| {
+                let len = session.len as u16;
+                let bytes = [
+                    TP_END_OF_MESSAGE_ACK,
+                    len as u8,
+                    (len >> 8) as u8,
+                    session.packets,
+                    0xFF,
+                ];
+                control_frame(session.pgn, self.address, session.source, bytes)
+            });
+
+            return Ok(TransportReceive::Complete {
+                pgn: session.pgn,
+                source: session.source,
+                ack,
+            });
+        }
+        if !session.broadcast && session.window_left == 0 {
+            let left = session.packets as u16 - session.next_seq + 1;
+            let window = session.window.min(left as u8);
+            if let Some(current) = self.session.as_mut() {
+                current.window_left = window;
+            }
+            let bytes = [TP_CTS, window, session.next_seq as u8, 0xFF, 0xFF];
+            let reply = control_frame(session.pgn, self.address, session.source, bytes);
+            return Ok(TransportReceive::Reply(reply));
+        }
+
+        Ok(TransportReceive::Pending)
+    }
+
+    /// Whether `id` belongs to the transfer being reassembled.
+    fn is_session(&self, id: &J1939Id) -> bool {
+        self.session.is_some_and(|session| {
+            let destination = match session.broadcast {
+                true => GLOBAL_ADDRESS,
+                false => self.address,
+            };
+            id.source == session.source && id.destination == Some(destination)
+        })
+    }
+}
+
+/// PGN carried in the last 3 bytes of a TP.CM frame.
+fn control_pgn(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[5], data[6], data[7], 0])
+}
+
+fn control_frame(pgn: u32, source: u8, destination: u8, bytes: [u8; 5]) -> CanFrame {
+    let [pgn_low, pgn_mid, pgn_high, _] = pgn.to_le_bytes();
+    let [control, a, b, c, d] = bytes;
+
+    tp_frame(
+        PGN_TP_CM,
+        source,
+        destination,
+        [control, a, b, c, d, pgn_low, pgn_mid, pgn_high],
+    )
+}
+
+fn tp_frame(pgn: u32, source: u8, destination: u8, bytes: [u8; 8]) -> CanFrame {
+    let id = J1939Id::new(TP_PRIORITY, pgn, source).with_destination(destination);
+
+    CanFrame::new(id.to_id(), &bytes).unwrap()
+}
@@ -0,0 +1,100 @@
+//! Host tests of the transport protocol, the sender and receiver exchanging frames
+//! directly.
+
+use super::*;
+
+const PGN: u32 = 0xFEF1;
+const SENDER: u8 = 0x20;
+const RECEIVER: u8 = 0x30;
+
+/// Payload of `len` bytes that differ from one packet to the next.
+fn payload(len: usize) -> [u8; MAX_TRANSPORT_LEN] {
+    let mut data = [0; MAX_TRANSPORT_LEN];
+    for (i, byte) in data[..len].iter_mut().enumerate() {
+        *byte = (i * 7 + i / 7) as u8;
+    }
+
+    data
+}
+
+/// Runs `sender` against `receiver` until the transfer completes, returning the
+/// number of data packets sent.
+fn transfer<const N: usize>(
+    sender: &mut TransportSender,
+    receiver: &mut TransportReceiver<N>,
+) -> Result<usize, TransportError> {
+    let mut packets = 0;
+    loop {
+        match sender.next_step() {
+            TransportStep::Send(frame) => {
+                packets += (J1939Id::of(&frame).unwrap().pgn == PGN_TP_DT) as usize;
+                match receiver.on_frame(&frame)? {
+                    TransportReceive::Pending => {}
+                    TransportReceive::Reply(reply) => sender.on_frame(&reply)?,
+                    TransportReceive::Complete { pgn, source, ack } => {
+                        assert_eq!((pgn, source), (PGN, SENDER));
+                        if let Some(ack) = ack {
+                            sender.on_frame(&ack)?;
+                        }
+                    }
+                }
+            }
+            TransportStep::AwaitResponse => panic!("no response to wait for"),
+            TransportStep::Done => return Ok(packets),
+        }
+    }
+}
+
+#[test]
+fn broadcast_of_255_packets() {
+    let data = payload(MAX_TRANSPORT_LEN);
+    let mut sender = TransportSender::new(PGN, SENDER, GLOBAL_ADDRESS, &data).unwrap();
+    let mut receiver = TransportReceiver::<MAX_TRANSPORT_LEN>::new(RECEIVER);
+
+    assert_eq!(transfer(&mut sender, &mut receiver), Ok(255));
+    assert_eq!(receiver.data(), &data[..]);
+}
+
+#[test]
+fn connection_of_255_packets() {
+    for len in [MAX_TRANSPORT_LEN - 6, MAX_TRANSPORT_LEN] {
+        let data = payload(len);
+        let mut sender = TransportSender::new(PGN, SENDER, RECEIVER, &data[..len]).unwrap();
+        let mut receiver = TransportReceiver::<MAX_TRANSPORT_LEN>::new(RECEIVER);
+
+        assert_eq!(transfer(&mut sender, &mut receiver), Ok(255));
+        assert_eq!(receiver.data(), &data[..len]);
+    }
+}
+
+#[test]
+fn connection_of_a_few_packets() {
+    let data = payload(100);
+    let mut sender = TransportSender::new(PGN, SENDER, RECEIVER, &data[..100]).unwrap();
+    let mut receiver = TransportReceiver::<256>::new(RECEIVER);
+
+    assert_eq!(transfer(&mut sender, &mut receiver), Ok(15));
+    assert_eq!(receiver.data(), &data[..100]);
+}
+
+#[test]
+fn lost_packet_reported() {
+    let data = payload(20);
+    let mut sender = TransportSender::new(PGN, SENDER, GLOBAL_ADDRESS, &data[..20]).unwrap();
+    let mut receiver = TransportReceiver::<256>::new(RECEIVER);
+
+    for _ in 0..2 {
+        let TransportStep::Send(frame) = sender.next_step() else {
+            panic!("announcement and first packet expected");
+        };
+        receiver.on_frame(&frame).unwrap();
+    }
+    sender.next_step(); // Second packet lost
+    let TransportStep::Send(frame) = sender.next_step() else {
+        panic!("third packet expected");
+    };
+    assert_eq!(
+        receiver.on_frame(&frame).err(),
+        Some(TransportError::WrongSequenceNumber)
+    );
+}
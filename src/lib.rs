@@ -1,17 +1,137 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+// Without a chip, the helpers of the driver are left unused
+#![cfg_attr(not(feature = "_hal"), allow(dead_code))]
 
+#[cfg(all(target_arch = "riscv32", not(feature = "_hal")))]
+compile_error!("Select the chip with one of the part number features, e.g. `ch32v203c8t6`.");
+
+/// Panics with `$msg`, or without a message with the `minimal` feature, which leaves
+/// the string out of flash.
+macro_rules! fail {
+    ($msg:literal) => {{
+        #[cfg(not(feature = "minimal"))]
+        panic!($msg);
+        #[cfg(feature = "minimal")]
+        panic!();
+    }};
+}
+
+mod adapter;
+#[cfg(all(feature = "async", feature = "_hal"))]
+mod asynch;
+#[cfg(feature = "binlog")]
+pub mod binlog;
+#[cfg(feature = "bootloader")]
+pub mod bootloader;
+#[cfg(all(feature = "async", feature = "_hal"))]
+mod busoff;
+#[cfg(all(feature = "bxcan", feature = "_hal"))]
+pub mod bxcan;
+#[cfg(feature = "_hal")]
 mod can;
+#[cfg(feature = "candump")]
+pub mod candump;
+#[cfg(feature = "canopen")]
+pub mod canopen;
+#[cfg(feature = "signals")]
+pub mod database;
+mod deferred;
+mod dispatcher;
 mod enums;
 mod frame;
+mod gateway;
+#[cfg(feature = "gs-usb")]
+pub mod gs_usb;
+mod interface;
+#[cfg(feature = "_hal")]
+mod interrupt;
+#[cfg(feature = "isotp")]
+pub mod isotp;
+#[cfg(feature = "j1939")]
+pub mod j1939;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "nmea2000")]
+pub mod nmea2000;
+#[cfg(feature = "obd2")]
+pub mod obd2;
+mod pool;
+mod ratelimit;
+mod recorder;
+#[cfg(feature = "_hal")]
+mod redundant;
+#[cfg(feature = "_hal")]
 mod registers;
-mod util;
+mod ring;
+#[cfg(all(feature = "rtt", feature = "_hal"))]
+pub mod rtt;
+mod scheduler;
+#[cfg(feature = "secoc")]
+pub mod secoc;
+#[cfg(feature = "signals")]
+pub mod signals;
+#[cfg(feature = "mock")]
+pub mod sim;
+#[cfg(feature = "slcan")]
+pub mod slcan;
+mod sniffer;
+mod softfilter;
+#[cfg(feature = "_hal")]
+mod systick;
+#[cfg(feature = "time-sync")]
+pub mod timesync;
+pub mod timing;
+mod transceiver;
+mod txqueue;
+#[cfg(feature = "uds")]
+pub mod uds;
+mod waker;
+mod watchdog;
+#[cfg(feature = "xcp")]
+pub mod xcp;
 
-pub use can::Can;
-pub use embedded_can::StandardId;
-pub use enums::{CanError, CanFifo, CanFilter, CanFilterMode, CanMode, TxStatus};
+pub use adapter::CanAdapter;
+#[cfg(all(feature = "async", feature = "_hal"))]
+pub use asynch::{CanRx, CanTx};
+#[cfg(all(feature = "async", feature = "_hal"))]
+pub use busoff::BusOffSupervisor;
+#[cfg(feature = "_hal")]
+pub use can::{Can, Instance, Watch, WatchFull};
+pub use dispatcher::{Dispatcher, DispatcherFull, FrameHandler};
+pub use embedded_can;
+pub use embedded_can::{ExtendedId, Id, StandardId};
+#[cfg(feature = "fault-injection")]
+pub use enums::InjectedFault;
+pub use enums::{
+    Bitrate, BusHealth, BusState, CanBitTiming, CanConfig, CanError, CanErrorKind, CanEvent,
+    CanFifo, CanFilter, CanFilterMode, CanMode, ConfigError, MailboxState, NoFreeFilter,
+    RequestError, TxCompletion, TxError, TxErrorKind, TxHandle, TxMailboxStatus, TxOk, TxOrder,
+    TxOutcome, TxStatus, WakeToken,
+};
 pub use frame::CanFrame;
+#[cfg(feature = "_hal")]
+pub use gateway::Gateway;
+pub use gateway::{GatewayAction, GatewayDirection, GatewayRule};
+pub use interface::CanInterface;
+#[cfg(feature = "_hal")]
+pub use interrupt::{InterruptResources, Rx0Isr, Rx1Isr, SceIsr, TxIsr};
 pub use nb;
+pub use pool::{FramePool, PoolSlot, PooledFrame};
+pub use ratelimit::{RateLimiter, RateLimiterFull};
+pub use recorder::{Recorder, Replay};
+#[cfg(feature = "_hal")]
+pub use redundant::{RedundancyMode, RedundantBus, RedundantCan};
+pub use scheduler::{Scheduler, SchedulerFull};
+pub use sniffer::{Sniffer, SnifferChange};
+pub use softfilter::{SoftFilter, SoftFilterFull};
+#[cfg(feature = "_hal")]
+pub use systick::{systick_micros, systick_millis};
+pub use timing::NominalBitTiming;
+pub use transceiver::{CanTransceiver, GpioTransceiver};
+pub use txqueue::TxQueue;
+pub use watchdog::{NodeWatchdog, WatchdogEvent, WatchdogFull};
 
+#[cfg(feature = "_hal")]
 pub use ch32_hal as hal;
+#[cfg(feature = "_hal")]
 use hal::pac;
@@ -0,0 +1,194 @@
+//! In-memory bus for developing application and protocol code without hardware.
+//!
+//! Nodes implement [CanInterface], so code written against it runs unchanged on
+//! [crate::Can]. Like the other hardware-independent modules, it builds for the
+//! host without a chip feature.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+use crate::deferred::Deferred;
+use crate::enums::{
+    BusState, CanError, CanErrorKind, CanFilter, CanFilterMode, NoFreeFilter, FILTER_BANKS,
+};
+use crate::frame::CanFrame;
+use crate::interface::CanInterface;
+
+/// Filter banks of a simulated node, matched in software like the hardware does.
+#[derive(Copy, Clone)]
+pub(crate) struct Filters {
+    /// `(mode, id_value, id_mask)` of each bank, as in [CanFilter]
+    banks: [Option<(CanFilterMode, u32, u32)>; FILTER_BANKS],
+}
+
+impl Filters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            banks: [None; FILTER_BANKS],
+        }
+    }
+
+    pub(crate) fn accepts(&self, frame: &CanFrame) -> bool {
+        let bits = frame.filter_bits();
+        self.banks
+            .iter()
+            .flatten()
+            .any(|(mode, value, mask)| match mode {
+                CanFilterMode::IdMask => (bits ^ value) & mask == 0,
+                CanFilterMode::IdList => bits == *value || bits == *mask,
+            })
+    }
+
+    pub(crate) fn set(&mut self, filter: CanFilter) {
+        if filter.bank >= FILTER_BANKS {
+            fail!("CAN filter bank is out of range.");
+        }
+
+        self.banks[filter.bank] = Some((filter.mode, filter.id_value, filter.id_mask));
+    }
+
+    pub(crate) fn free_bank(&self) -> Option<usize> {
+        self.banks.iter().position(Option::is_none)
+    }
+}
+
+struct Node<const DEPTH: usize> {
+    attached: bool,
+    rx: Deferred<DEPTH>,
+    overrun: bool,
+    filters: Filters,
+}
+
+impl<const DEPTH: usize> Node<DEPTH> {
+    const fn new() -> Self {
+        Self {
+            attached: false,
+            rx: Deferred::new(),
+            overrun: false,
+            filters: Filters::new(),
+        }
+    }
+}
+
+/// Bus shared by up to `NODES` [MockCan] nodes, each buffering up to `DEPTH`
+/// received frames.
+///
+/// A frame sent by a node is received by every other node whose filters accept
+/// it, like on a real bus. Nodes without filters receive nothing, as with the
+/// hardware. When a node's buffer is full, further frames are dropped and its next
+/// receive returns [CanErrorKind::Overrun].
+pub struct MockBus<const NODES: usize, const DEPTH: usize> {
+    nodes: Mutex<RefCell<[Node<DEPTH>; NODES]>>,
+}
+
+impl<const NODES: usize, const DEPTH: usize> MockBus<NODES, DEPTH> {
+    pub const fn new() -> Self {
+        Self {
+            nodes: Mutex::new(RefCell::new([const { Node::new() }; NODES])),
+        }
+    }
+
+    /// Attaches a new node, or returns `None` if all `NODES` are attached.
+    pub fn node(&self) -> Option<MockCan<'_, NODES, DEPTH>> {
+        critical_section::with(|cs| {
+            let mut nodes = self.nodes.borrow_ref_mut(cs);
+            let index = nodes.iter().position(|node| !node.attached)?;
+            nodes[index].attached = true;
+
+            Some(MockCan { bus: self, index })
+        })
+    }
+}
+
+impl<const NODES: usize, const DEPTH: usize> Default for MockBus<NODES, DEPTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Node of a [MockBus], with the same transmit, receive and filter methods as
+/// [crate::Can].
+pub struct MockCan<'a, const NODES: usize, const DEPTH: usize> {
+    bus: &'a MockBus<NODES, DEPTH>,
+    index: usize,
+}
+
+impl<'a, const NODES: usize, const DEPTH: usize> MockCan<'a, NODES, DEPTH> {
+    /// Delivers `frame` to the other nodes right away, so transmission never blocks.
+    pub fn transmit(&self, frame: &CanFrame) -> nb::Result<Option<CanFrame>, CanError> {
+        critical_section::with(|cs| {
+            let mut nodes = self.bus.nodes.borrow_ref_mut(cs);
+            for (index, node) in nodes.iter_mut().enumerate() {
+                if index == self.index || !node.attached || !node.filters.accepts(frame) {
+                    continue;
+                }
+                if node.rx.push(*frame).is_err() {
+                    node.overrun = true;
+                }
+            }
+        });
+
+        Ok(None)
+    }
+
+    pub fn receive(&self) -> nb::Result<CanFrame, CanError> {
+        self.with_node(|node| {
+            if node.overrun {
+                node.overrun = false;
+                return Err(nb::Error::Other(CanErrorKind::Overrun.into()));
+            }
+
+            node.rx.pop().ok_or(nb::Error::WouldBlock)
+        })
+    }
+
+    pub fn add_filter(&self, filter: CanFilter) {
+        self.with_node(|node| node.filters.set(filter));
+    }
+
+    fn with_node<R>(&self, f: impl FnOnce(&mut Node<DEPTH>) -> R) -> R {
+        critical_section::with(|cs| f(&mut self.bus.nodes.borrow_ref_mut(cs)[self.index]))
+    }
+}
+
+impl<'a, const NODES: usize, const DEPTH: usize> Drop for MockCan<'a, NODES, DEPTH> {
+    fn drop(&mut self) {
+        self.with_node(|node| *node = Node::new());
+    }
+}
+
+impl<'a, const NODES: usize, const DEPTH: usize> embedded_can::nb::Can
+    for MockCan<'a, NODES, DEPTH>
+{
+    type Frame = CanFrame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        MockCan::transmit(self, frame)
+    }
+
+    fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+        MockCan::receive(self)
+    }
+}
+
+impl<'a, const NODES: usize, const DEPTH: usize> CanInterface for MockCan<'a, NODES, DEPTH> {
+    fn add_id_filter(&mut self, id: embedded_can::Id, mask: u32) -> Result<(), NoFreeFilter> {
+        let bank = self
+            .with_node(|node| node.filters.free_bank())
+            .ok_or(NoFreeFilter)?;
+        self.add_filter(CanFilter::matching(bank, id, mask));
+
+        Ok(())
+    }
+
+    fn clear_filters(&mut self) {
+        self.with_node(|node| node.filters = Filters::new());
+    }
+
+    /// Always [BusState::ErrorActive], bus errors are not simulated.
+    fn bus_state(&self) -> BusState {
+        BusState::ErrorActive
+    }
+}
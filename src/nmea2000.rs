@@ -0,0 +1,221 @@
+//! NMEA 2000 fast-packet transport and common PGNs, on top of [crate::j1939]
+//! addressing.
+//!
+//! Fast-packet messages carry up to 223 bytes in 32 frames: the first holds a
+//! sequence counter, the message length and 6 bytes, then each following frame
+//! 7 bytes.
+
+use crate::frame::CanFrame;
+use crate::j1939::J1939Id;
+
+#[cfg(test)]
+mod tests;
+
+/// Longest message a fast packet can carry.
+pub const MAX_FAST_PACKET_LEN: usize = 223;
+
+const PADDING: u8 = 0xFF;
+
+/// Common parameter groups.
+pub mod pgn {
+    pub const ISO_ACKNOWLEDGEMENT: u32 = 59392;
+    pub const SYSTEM_TIME: u32 = 126992;
+    pub const PRODUCT_INFORMATION: u32 = 126996;
+    pub const HEADING: u32 = 127250;
+    pub const RATE_OF_TURN: u32 = 127251;
+    pub const ATTITUDE: u32 = 127257;
+    pub const ENGINE_PARAMETERS_RAPID: u32 = 127488;
+    pub const FLUID_LEVEL: u32 = 127505;
+    pub const BATTERY_STATUS: u32 = 127508;
+    pub const SPEED: u32 = 128259;
+    pub const WATER_DEPTH: u32 = 128267;
+    pub const POSITION_RAPID: u32 = 129025;
+    pub const COG_SOG_RAPID: u32 = 129026;
+    pub const GNSS_POSITION: u32 = 129029;
+    pub const WIND_DATA: u32 = 130306;
+    pub const ENVIRONMENTAL_PARAMETERS: u32 = 130311;
+    pub const TEMPERATURE: u32 = 130312;
+}
+
+/// Error returned when a message is longer than [MAX_FAST_PACKET_LEN].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct TooLong;
+
+/// Splits messages into fast-packet frames, counting sequences for one PGN.
+#[derive(Default)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct FastPacketSender {
+    seq: u8,
+}
+
+impl FastPacketSender {
+    pub fn new() -> Self {
+        Self { seq: 0 }
+    }
+
+    /// Frames of message `data` sent with identifier `id`, to transmit in order:
+    /// each once the previous one has left its mailbox, or with
+    /// [crate::TxOrder::Fifo] selected by [crate::Can::set_tx_order].
+    pub fn send<'a>(
+        &mut self,
+        id: J1939Id,
+        data: &'a [u8],
+    ) -> Result<FastPacketFrames<'a>, TooLong> {
+        if data.len() > MAX_FAST_PACKET_LEN {
+            return Err(TooLong);
+        }
+
+        let seq = self.seq;
+        self.seq = (self.seq + 1) & 0x7;
+
+        Ok(FastPacketFrames {
+            id,
+            seq,
+            index: 0,
+            data,
+        })
+    }
+}
+
+/// Iterator over the frames of one fast-packet message.
+pub struct FastPacketFrames<'a> {
+    id: J1939Id,
+    seq: u8,
+    index: u8,
+    data: &'a [u8],
+}
+
+impl Iterator for FastPacketFrames<'_> {
+    type Item = CanFrame;
+
+    fn next(&mut self) -> Option<CanFrame> {
+        let mut bytes = [PADDING; 8];
+        bytes[0] = self.seq << 5 | self.index;
+
+        let (start, payload) = match self.index {
+            0 => {
+                bytes[1] = self.data.len() as u8;
+                (0, &mut bytes[2..])
+            }
+            index => (6 + (index as usize - 1) * 7, &mut bytes[1..]),
+        };
+        if self.index != 0 && start >= self.data.len() {
+            return None;
+        }
+
+        let end = (start + payload.len()).min(self.data.len());
+        payload[..end - start].copy_from_slice(&self.data[start..end]);
+        self.index += 1;
+
+        Some(CanFrame::new(self.id.to_id(), &bytes).unwrap())
+    }
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+struct Session {
+    active: bool,
+    pgn: u32,
+    source: u8,
+    seq: u8,
+    next_index: u8,
+    len: usize,
+    received: usize,
+    buf: [u8; MAX_FAST_PACKET_LEN],
+}
+
+/// Reassembles fast-packet messages, up to `S` of them from different sources or
+/// PGNs at a time.
+///
+/// Only hand it the frames of fast-packet PGNs, single frame PGNs look the same.
+/// A message missing a frame is dropped.
+pub struct FastPacketReceiver<const S: usize> {
+    sessions: [Session; S],
+}
+
+impl<const S: usize> Default for FastPacketReceiver<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const S: usize> FastPacketReceiver<S> {
+    pub fn new() -> Self {
+        Self {
+            sessions: [Session {
+                active: false,
+                pgn: 0,
+                source: 0,
+                seq: 0,
+                next_index: 0,
+                len: 0,
+                received: 0,
+                buf: [0; MAX_FAST_PACKET_LEN],
+            }; S],
+        }
+    }
+
+    /// Handles a received frame, returning the message it completed.
+    pub fn on_frame(&mut self, frame: &CanFrame) -> Option<(J1939Id, &[u8])> {
+        let id = J1939Id::of(frame)?;
+        if frame.dlc() < 8 {
+            return None;
+        }
+        let data = frame.data();
+        let (seq, index) = (data[0] >> 5, data[0] & 0x1F);
+        let key = |session: &Session| {
+            session.active && session.pgn == id.pgn && session.source == id.source
+        };
+
+        let session = match index {
+            0 => {
+                let len = data[1] as usize;
+                if len > MAX_FAST_PACKET_LEN {
+                    return None;
+                }
+                let position = self
+                    .sessions
+                    .iter()
+                    .position(key)
+                    .or_else(|| self.sessions.iter().position(|session| !session.active))?;
+
+                let session = &mut self.sessions[position];
+                let count = len.min(6);
+                session.buf[..count].copy_from_slice(&data[2..2 + count]);
+                *session = Session {
+                    active: true,
+                    pgn: id.pgn,
+                    source: id.source,
+                    seq,
+                    next_index: 1,
+                    len,
+                    received: count,
+                    buf: session.buf,
+                };
+                session
+            }
+            _ => {
+                let session = self.sessions.iter_mut().find(|session| key(session))?;
+                if session.seq != seq || session.next_index != index {
+                    session.active = false;
+                    return None;
+                }
+
+                let count = (session.len - session.received).min(7);
+                session.buf[session.received..session.received + count]
+                    .copy_from_slice(&data[1..=count]);
+                session.received += count;
+                session.next_index += 1;
+                session
+            }
+        };
+
+        if session.received < session.len {
+            return None;
+        }
+
+        session.active = false;
+        Some((id, &session.buf[..session.len]))
+    }
+}
@@ -0,0 +1,151 @@
+//! Host tests of the fast-packet transport, the sender's frames handed straight to
+//! the receiver.
+
+use super::*;
+
+fn id(source: u8) -> J1939Id {
+    J1939Id::new(2, pgn::PRODUCT_INFORMATION, source)
+}
+
+/// Message of `len` bytes that differ from one frame to the next.
+fn payload(len: usize) -> [u8; MAX_FAST_PACKET_LEN] {
+    let mut data = [0; MAX_FAST_PACKET_LEN];
+    for (i, byte) in data[..len].iter_mut().enumerate() {
+        *byte = (i * 7 + i / 7) as u8;
+    }
+
+    data
+}
+
+#[test]
+fn split_into_frames() {
+    let data = payload(MAX_FAST_PACKET_LEN);
+    let frames: Vec<_> = FastPacketSender::new()
+        .send(id(1), &data)
+        .unwrap()
+        .collect();
+    assert_eq!(frames.len(), 32);
+
+    assert_eq!(frames[0].data()[..2], [0x00, 223]);
+    assert_eq!(frames[0].data()[2..], data[..6]);
+    assert_eq!(frames[1].data()[0], 1);
+    assert_eq!(frames[1].data()[1..], data[6..13]);
+    // 223 = 6 + 31 * 7, the last frame is full
+    assert_eq!(frames[31].data()[0], 31);
+    assert_eq!(frames[31].data()[1..], data[216..]);
+    assert!(frames.iter().all(|frame| frame.dlc() == 8));
+    assert!(frames.iter().all(|frame| J1939Id::of(frame) == Some(id(1))));
+}
+
+#[test]
+fn short_messages_padded() {
+    let mut sender = FastPacketSender::new();
+    let data = payload(8);
+
+    let frames: Vec<_> = sender.send(id(1), &data[..3]).unwrap().collect();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(
+        frames[0].data(),
+        [0x00, 3, data[0], data[1], data[2], 0xFF, 0xFF, 0xFF]
+    );
+
+    let frames: Vec<_> = sender.send(id(1), &data[..6]).unwrap().collect();
+    assert_eq!(frames.len(), 1);
+
+    let frames: Vec<_> = sender.send(id(1), &data[..8]).unwrap().collect();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(
+        frames[1].data(),
+        [0x41, data[6], data[7], 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+    );
+
+    assert!(sender.send(id(1), &[0; MAX_FAST_PACKET_LEN + 1]).is_err());
+}
+
+#[test]
+fn sequence_counter_wraps_around() {
+    let mut sender = FastPacketSender::new();
+    let seqs: Vec<_> = (0..10)
+        .map(|_| sender.send(id(1), &[0; 10]).unwrap().next().unwrap().data()[0] >> 5)
+        .collect();
+
+    assert_eq!(seqs, [0, 1, 2, 3, 4, 5, 6, 7, 0, 1]);
+}
+
+#[test]
+fn reassembled_message_lengths() {
+    let mut sender = FastPacketSender::new();
+    let mut receiver = FastPacketReceiver::<1>::new();
+
+    for len in [1, 6, 7, 13, 14, 100, MAX_FAST_PACKET_LEN] {
+        let data = payload(len);
+        let frames: Vec<_> = sender.send(id(1), &data[..len]).unwrap().collect();
+
+        let (last, first) = frames.split_last().unwrap();
+        assert!(first.iter().all(|frame| receiver.on_frame(frame).is_none()));
+        let (message_id, message) = receiver.on_frame(last).unwrap();
+        assert_eq!(message_id, id(1));
+        assert_eq!(message, &data[..len]);
+    }
+}
+
+#[test]
+fn interleaved_sources() {
+    let data = payload(20);
+    let a: Vec<_> = FastPacketSender::new()
+        .send(id(1), &data[..20])
+        .unwrap()
+        .collect();
+    let b: Vec<_> = FastPacketSender::new()
+        .send(id(2), &data[..13])
+        .unwrap()
+        .collect();
+    let mut receiver = FastPacketReceiver::<2>::new();
+
+    assert!(receiver.on_frame(&a[0]).is_none());
+    assert!(receiver.on_frame(&b[0]).is_none());
+    assert!(receiver.on_frame(&a[1]).is_none());
+    assert_eq!(receiver.on_frame(&b[1]).unwrap().1, &data[..13]);
+    assert_eq!(receiver.on_frame(&a[2]).unwrap().1, &data[..20]);
+}
+
+#[test]
+fn message_missing_a_frame_dropped() {
+    let data = payload(30);
+    let mut sender = FastPacketSender::new();
+    let mut receiver = FastPacketReceiver::<1>::new();
+
+    let frames: Vec<_> = sender.send(id(1), &data[..30]).unwrap().collect();
+    assert!(receiver.on_frame(&frames[0]).is_none());
+    assert!(receiver.on_frame(&frames[2]).is_none());
+    assert!(frames[3..]
+        .iter()
+        .all(|frame| receiver.on_frame(frame).is_none()));
+
+    // A new first frame starts over, replacing an unfinished message
+    let retry: Vec<_> = sender.send(id(1), &data[..30]).unwrap().collect();
+    assert!(receiver.on_frame(&frames[0]).is_none());
+    assert!(retry[..4]
+        .iter()
+        .all(|frame| receiver.on_frame(frame).is_none()));
+    assert_eq!(receiver.on_frame(&retry[4]).unwrap().1, &data[..30]);
+}
+
+#[test]
+fn sessions_in_use_ignore_new_sources() {
+    let data = payload(10);
+    let a: Vec<_> = FastPacketSender::new()
+        .send(id(1), &data[..10])
+        .unwrap()
+        .collect();
+    let b: Vec<_> = FastPacketSender::new()
+        .send(id(2), &data[..10])
+        .unwrap()
+        .collect();
+    let mut receiver = FastPacketReceiver::<1>::new();
+
+    assert!(receiver.on_frame(&a[0]).is_none());
+    assert!(receiver.on_frame(&b[0]).is_none());
+    assert!(receiver.on_frame(&b[1]).is_none());
+    assert!(receiver.on_frame(&a[1]).is_some());
+}
@@ -0,0 +1,204 @@
+//! OBD-II (SAE J1979) requests over the 11-bit addressing of ISO 15765-4.
+
+use embedded_can::StandardId;
+use embedded_hal::delay::DelayNs;
+
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+
+/// Identifier of requests addressed to all emission-related ECUs.
+pub const FUNCTIONAL_REQUEST_ID: u16 = 0x7DF;
+/// Identifier of the responses from the first ECU, the others following up to 0x7EF.
+pub const FIRST_RESPONSE_ID: u16 = 0x7E8;
+
+/// Current data service.
+pub const SHOW_CURRENT_DATA: u8 = 0x01;
+
+/// Commonly supported PIDs of [SHOW_CURRENT_DATA].
+pub mod pid {
+    pub const SUPPORTED_PIDS_01_20: u8 = 0x00;
+    pub const ENGINE_LOAD: u8 = 0x04;
+    pub const COOLANT_TEMPERATURE: u8 = 0x05;
+    pub const ENGINE_RPM: u8 = 0x0C;
+    pub const VEHICLE_SPEED: u8 = 0x0D;
+    pub const INTAKE_AIR_TEMPERATURE: u8 = 0x0F;
+    pub const THROTTLE_POSITION: u8 = 0x11;
+}
+
+const PADDING: u8 = 0xCC;
+
+/// Identifier of requests addressed to ECU `ecu` (0-7) only.
+pub fn physical_request_id(ecu: u8) -> StandardId {
+    StandardId::new(FIRST_RESPONSE_ID - 8 + (ecu & 0x7) as u16).unwrap()
+}
+
+/// Identifier of the responses from ECU `ecu` (0-7).
+pub fn response_id(ecu: u8) -> StandardId {
+    StandardId::new(FIRST_RESPONSE_ID + (ecu & 0x7) as u16).unwrap()
+}
+
+/// Functional request for `pid` of service `mode`.
+pub fn request_frame(mode: u8, pid: u8) -> CanFrame {
+    let id = StandardId::new(FUNCTIONAL_REQUEST_ID).unwrap();
+
+    CanFrame::new(
+        id,
+        &[2, mode, pid, PADDING, PADDING, PADDING, PADDING, PADDING],
+    )
+    .unwrap()
+}
+
+/// Positive single-frame response to a PID request.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct Obd2Response {
+    /// Responding ECU, 0-7
+    pub ecu: u8,
+    /// Service of the request
+    pub mode: u8,
+    pub pid: u8,
+    data: [u8; 4],
+    len: usize,
+}
+
+impl Obd2Response {
+    /// Decodes `frame`, returning `None` if it is not a response to a PID request.
+    pub fn parse(frame: &CanFrame) -> Option<Self> {
+        let embedded_can::Id::Standard(id) = *frame.id() else {
+            return None;
+        };
+        let ecu = id.as_raw().checked_sub(FIRST_RESPONSE_ID)?;
+        let bytes = &frame.data()[..frame.dlc()];
+        if ecu > 7 || bytes.len() < 3 {
+            return None;
+        }
+
+        let len = bytes[0] as usize;
+        if !(2..=bytes.len() - 1).contains(&len) || bytes[1] < 0x40 {
+            return None;
+        }
+
+        let mut data = [0; 4];
+        let value = &bytes[3..1 + len];
+        data[..value.len().min(4)].copy_from_slice(&value[..value.len().min(4)]);
+
+        Some(Self {
+            ecu: ecu as u8,
+            mode: bytes[1] - 0x40,
+            pid: bytes[2],
+            data,
+            len: value.len().min(4),
+        })
+    }
+
+    /// Data bytes A, B, C and D, as many as sent.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    fn byte(&self, pid: u8, index: usize) -> Option<u8> {
+        match self.mode == SHOW_CURRENT_DATA && self.pid == pid {
+            true => self.data().get(index).copied(),
+            false => None,
+        }
+    }
+
+    /// Engine speed in rpm, for [pid::ENGINE_RPM].
+    pub fn engine_rpm(&self) -> Option<f32> {
+        let a = self.byte(pid::ENGINE_RPM, 0)? as f32;
+        let b = self.byte(pid::ENGINE_RPM, 1)? as f32;
+
+        Some((a * 256.0 + b) / 4.0)
+    }
+
+    /// Vehicle speed in km/h, for [pid::VEHICLE_SPEED].
+    pub fn vehicle_speed_kmh(&self) -> Option<u8> {
+        self.byte(pid::VEHICLE_SPEED, 0)
+    }
+
+    /// Engine coolant temperature in °C, for [pid::COOLANT_TEMPERATURE].
+    pub fn coolant_temperature_c(&self) -> Option<i16> {
+        Some(self.byte(pid::COOLANT_TEMPERATURE, 0)? as i16 - 40)
+    }
+
+    /// Intake air temperature in °C, for [pid::INTAKE_AIR_TEMPERATURE].
+    pub fn intake_air_temperature_c(&self) -> Option<i16> {
+        Some(self.byte(pid::INTAKE_AIR_TEMPERATURE, 0)? as i16 - 40)
+    }
+
+    /// Calculated engine load in %, for [pid::ENGINE_LOAD].
+    pub fn engine_load_percent(&self) -> Option<f32> {
+        Some(self.byte(pid::ENGINE_LOAD, 0)? as f32 * 100.0 / 255.0)
+    }
+
+    /// Absolute throttle position in %, for [pid::THROTTLE_POSITION].
+    pub fn throttle_position_percent(&self) -> Option<f32> {
+        Some(self.byte(pid::THROTTLE_POSITION, 0)? as f32 * 100.0 / 255.0)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum Obd2Error {
+    /// No ECU responded in time
+    Timeout,
+    Can(CanError),
+}
+
+/// Blocking OBD-II client sending functional requests.
+///
+/// Frames other than responses to the pending request are dropped, so filter
+/// them out or dedicate the driver to the client.
+pub struct Obd2<C, D> {
+    can: C,
+    delay: D,
+    timeout_ms: u32,
+}
+
+impl<C, D> Obd2<C, D>
+where
+    C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    D: DelayNs,
+{
+    /// `timeout_ms` is how long to wait for the first response, 50 ms (P2) or more.
+    pub fn new(can: C, delay: D, timeout_ms: u32) -> Self {
+        Self {
+            can,
+            delay,
+            timeout_ms,
+        }
+    }
+
+    pub fn release(self) -> (C, D) {
+        (self.can, self.delay)
+    }
+
+    /// Requests `pid` of service `mode` and returns the first response.
+    pub fn query(&mut self, mode: u8, pid: u8) -> Result<Obd2Response, Obd2Error> {
+        let request = request_frame(mode, pid);
+        self.poll(|can| can.transmit(&request).map(|_| Some(())))?;
+
+        self.poll(|can| {
+            let frame = can.receive()?;
+            Ok(Obd2Response::parse(&frame)
+                .filter(|response| response.mode == mode && response.pid == pid))
+        })
+    }
+
+    /// Retries `f` every 100 µs until it returns a value or the timeout elapses.
+    fn poll<R>(
+        &mut self,
+        mut f: impl FnMut(&mut C) -> nb::Result<Option<R>, CanError>,
+    ) -> Result<R, Obd2Error> {
+        for _ in 0..=self.timeout_ms.saturating_mul(10) {
+            match f(&mut self.can) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(nb::Error::Other(error)) => return Err(Obd2Error::Can(error)),
+                Err(nb::Error::WouldBlock) => self.delay.delay_us(100),
+            }
+        }
+
+        Err(Obd2Error::Timeout)
+    }
+}
@@ -0,0 +1,102 @@
+//! Application-provided frame storage filled directly by the receive interrupt.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::frame::CanFrame;
+
+const FREE: u8 = 0;
+const FILLED: u8 = 1;
+const LENT: u8 = 2;
+
+/// One frame slot of a [FramePool].
+pub struct PoolSlot {
+    state: AtomicU8,
+    frame: UnsafeCell<MaybeUninit<CanFrame>>,
+}
+
+// Safety: `frame` is only written by the ISR while FREE and only read while LENT.
+unsafe impl Sync for PoolSlot {}
+
+impl PoolSlot {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(FREE),
+            frame: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Interrupt side: stores `frame` if the slot is free.
+    pub(crate) fn fill(&self, frame: CanFrame) -> bool {
+        if self.state.load(Ordering::Acquire) != FREE {
+            return false;
+        }
+
+        unsafe { (*self.frame.get()).write(frame) };
+        self.state.store(FILLED, Ordering::Release);
+
+        true
+    }
+
+    /// Application side: lends the frame out if the slot is filled.
+    pub(crate) fn take(&'static self) -> Option<PooledFrame> {
+        self.state
+            .compare_exchange(FILLED, LENT, Ordering::AcqRel, Ordering::Acquire)
+            .ok()?;
+
+        Some(PooledFrame { slot: self })
+    }
+}
+
+/// Pool of `N` frame slots the receive interrupt decodes frames into, handed to
+/// [crate::Can::set_rx_pool].
+///
+/// Slots are filled and lent out in a ring, so frames come out in reception order
+/// without being copied into an intermediate queue. A slot is reused once the
+/// [PooledFrame] lent from it is dropped; if the next slot in the ring is still
+/// lent when a frame arrives, that frame is dropped and counted as an overrun.
+pub struct FramePool<const N: usize> {
+    slots: [PoolSlot; N],
+}
+
+impl<const N: usize> FramePool<N> {
+    /// Fails to build for an empty pool, which couldn't store any frame.
+    pub const fn new() -> Self {
+        const { assert!(N > 0, "A frame pool needs at least one slot") };
+
+        Self {
+            slots: [const { PoolSlot::new() }; N],
+        }
+    }
+
+    pub(crate) fn slots(&'static self) -> &'static [PoolSlot] {
+        &self.slots
+    }
+}
+
+impl<const N: usize> Default for FramePool<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Received frame borrowed from a [FramePool] slot, released when dropped.
+pub struct PooledFrame {
+    slot: &'static PoolSlot,
+}
+
+impl Deref for PooledFrame {
+    type Target = CanFrame;
+
+    fn deref(&self) -> &CanFrame {
+        unsafe { (*self.slot.frame.get()).assume_init_ref() }
+    }
+}
+
+impl Drop for PooledFrame {
+    fn drop(&mut self) {
+        self.slot.state.store(FREE, Ordering::Release);
+    }
+}
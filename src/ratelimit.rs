@@ -0,0 +1,188 @@
+//! Token-bucket limits on the transmit rate, globally and per identifier.
+
+use embedded_can::Id;
+
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+
+#[cfg(test)]
+mod tests;
+
+/// Tokens a frame costs. Buckets are refilled by `frames_per_second` tokens every
+/// millisecond, so sub-frame rates need no fractions.
+const FRAME_COST: u32 = 1000;
+
+#[derive(Copy, Clone)]
+struct Bucket {
+    frames_per_second: u32,
+    capacity: u32,
+    tokens: u32,
+    /// Time of the last refill, set on the first frame
+    last_ms: Option<u32>,
+}
+
+impl Bucket {
+    /// Starts full, so a burst is allowed right away.
+    fn new(frames_per_second: u32, burst: u32) -> Self {
+        let capacity = burst.max(1).saturating_mul(FRAME_COST);
+        Self {
+            frames_per_second,
+            capacity,
+            tokens: capacity,
+            last_ms: None,
+        }
+    }
+
+    fn refill(&mut self, now_ms: u32) {
+        let last_ms = *self.last_ms.get_or_insert(now_ms);
+        let elapsed_ms = now_ms.wrapping_sub(last_ms);
+        let added = elapsed_ms.saturating_mul(self.frames_per_second);
+        self.tokens = self.tokens.saturating_add(added).min(self.capacity);
+        self.last_ms = Some(now_ms);
+    }
+
+    fn has_token(&self) -> bool {
+        self.tokens >= FRAME_COST
+    }
+
+    fn take(&mut self) {
+        self.tokens -= FRAME_COST;
+    }
+}
+
+/// Limits the transmit rate, so that a misbehaving task can't flood the bus and
+/// starve the other nodes, e.g. on a gateway forwarding whatever it receives.
+///
+/// Each limit is a token bucket: frames are let through at `frames_per_second` on
+/// average, in bursts of up to `burst` frames. A frame must fit both the global
+/// limit, if set with [RateLimiter::limit_all], and the limit of its identifier,
+/// if one of the `N` slots set with [RateLimiter::limit_id] matches it.
+///
+/// Send frames through [RateLimiter::transmit], or check them with
+/// [RateLimiter::allow] before sending them another way.
+pub struct RateLimiter<const N: usize> {
+    global: Option<Bucket>,
+    ids: [Option<(Id, Bucket)>; N],
+    limited: u32,
+}
+
+impl<const N: usize> Default for RateLimiter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RateLimiter<N> {
+    pub const fn new() -> Self {
+        Self {
+            global: None,
+            ids: [None; N],
+            limited: 0,
+        }
+    }
+
+    /// Limits all frames to `frames_per_second`, in bursts of up to `burst`.
+    pub fn limit_all(&mut self, frames_per_second: u32, burst: u32) {
+        self.global = Some(Bucket::new(frames_per_second, burst));
+    }
+
+    /// Removes the global limit.
+    pub fn unlimit_all(&mut self) {
+        self.global = None;
+    }
+
+    /// Limits frames with identifier `id` to `frames_per_second`, in bursts of up to
+    /// `burst`, and returns the slot of the limit.
+    pub fn limit_id(
+        &mut self,
+        id: impl Into<Id>,
+        frames_per_second: u32,
+        burst: u32,
+    ) -> Result<usize, RateLimiterFull> {
+        let slot = self
+            .ids
+            .iter()
+            .position(Option::is_none)
+            .ok_or(RateLimiterFull)?;
+        self.ids[slot] = Some((id.into(), Bucket::new(frames_per_second, burst)));
+
+        Ok(slot)
+    }
+
+    pub fn unlimit_id(&mut self, slot: usize) {
+        self.ids[slot] = None;
+    }
+
+    /// Frames refused since the limiter was created.
+    pub fn limited(&self) -> u32 {
+        self.limited
+    }
+
+    /// Whether `frame` may be sent at `now_ms`, using up one frame of each limit
+    /// it is subject to if so.
+    pub fn allow(&mut self, frame: &CanFrame, now_ms: u32) -> bool {
+        if !self.check(frame, now_ms) {
+            self.limited = self.limited.wrapping_add(1);
+            return false;
+        }
+
+        self.take(frame);
+        true
+    }
+
+    /// Transmits `frame` on `can` if the limits allow it at `now_ms`, returning
+    /// `Err(WouldBlock)` otherwise. A frame the controller doesn't accept uses up
+    /// nothing.
+    pub fn transmit<C>(
+        &mut self,
+        can: &mut C,
+        frame: &CanFrame,
+        now_ms: u32,
+    ) -> nb::Result<Option<CanFrame>, CanError>
+    where
+        C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    {
+        if !self.check(frame, now_ms) {
+            self.limited = self.limited.wrapping_add(1);
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let replaced = can.transmit(frame)?;
+        self.take(frame);
+
+        Ok(replaced)
+    }
+
+    /// Refills the buckets `frame` is subject to and returns whether all have a
+    /// token left.
+    fn check(&mut self, frame: &CanFrame, now_ms: u32) -> bool {
+        let mut allowed = true;
+        for bucket in self.buckets(frame) {
+            bucket.refill(now_ms);
+            allowed &= bucket.has_token();
+        }
+
+        allowed
+    }
+
+    fn take(&mut self, frame: &CanFrame) {
+        self.buckets(frame).for_each(Bucket::take);
+    }
+
+    fn buckets<'a>(&'a mut self, frame: &CanFrame) -> impl Iterator<Item = &'a mut Bucket> {
+        let id = *frame.id();
+        let per_id = self
+            .ids
+            .iter_mut()
+            .flatten()
+            .filter(move |(limited_id, _)| *limited_id == id)
+            .map(|(_, bucket)| bucket);
+
+        self.global.iter_mut().chain(per_id)
+    }
+}
+
+/// Error returned by [RateLimiter::limit_id] when all slots are in use.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct RateLimiterFull;
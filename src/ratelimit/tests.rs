@@ -0,0 +1,102 @@
+//! Host tests of the token buckets, driven with explicit timestamps.
+
+use super::*;
+use embedded_can::StandardId;
+
+fn frame(id: u16) -> CanFrame {
+    CanFrame::new(StandardId::new(id).unwrap(), &[]).unwrap()
+}
+
+/// Number of frames with identifier `id` let through at `now_ms` out of `count`.
+fn allowed<const N: usize>(
+    limiter: &mut RateLimiter<N>,
+    id: u16,
+    now_ms: u32,
+    count: usize,
+) -> usize {
+    (0..count)
+        .filter(|_| limiter.allow(&frame(id), now_ms))
+        .count()
+}
+
+#[test]
+fn burst_then_refill_rate() {
+    let mut limiter = RateLimiter::<0>::new();
+    limiter.limit_all(100, 3);
+
+    assert_eq!(allowed(&mut limiter, 0x100, 0, 5), 3);
+    assert_eq!(limiter.limited(), 2);
+    // One frame every 10 ms
+    assert_eq!(allowed(&mut limiter, 0x100, 9, 1), 0);
+    assert_eq!(allowed(&mut limiter, 0x100, 10, 2), 1);
+    assert_eq!(allowed(&mut limiter, 0x100, 30, 3), 2);
+    // Never more than the burst, however long the bus was idle
+    assert_eq!(allowed(&mut limiter, 0x100, 60_000, 5), 3);
+
+    limiter.unlimit_all();
+    assert_eq!(allowed(&mut limiter, 0x100, 60_000, 5), 5);
+}
+
+#[test]
+fn one_frame_per_second() {
+    let mut limiter = RateLimiter::<1>::new();
+    limiter
+        .limit_id(StandardId::new(0x100).unwrap(), 1, 1)
+        .unwrap();
+
+    assert_eq!(allowed(&mut limiter, 0x100, 0, 2), 1);
+    // Refilled a thousandth of a frame every millisecond
+    assert_eq!(allowed(&mut limiter, 0x100, 999, 1), 0);
+    assert_eq!(allowed(&mut limiter, 0x100, 1_000, 1), 1);
+}
+
+#[test]
+fn per_id_limits() {
+    let mut limiter = RateLimiter::<2>::new();
+    let slot = limiter
+        .limit_id(StandardId::new(0x100).unwrap(), 10, 1)
+        .unwrap();
+    limiter
+        .limit_id(StandardId::new(0x200).unwrap(), 10, 2)
+        .unwrap();
+    assert_eq!(
+        limiter.limit_id(StandardId::new(0x300).unwrap(), 10, 1),
+        Err(RateLimiterFull)
+    );
+
+    assert_eq!(allowed(&mut limiter, 0x100, 0, 3), 1);
+    assert_eq!(allowed(&mut limiter, 0x200, 0, 3), 2);
+    assert_eq!(allowed(&mut limiter, 0x300, 0, 3), 3);
+    assert_eq!(allowed(&mut limiter, 0x100, 100, 3), 1);
+
+    limiter.unlimit_id(slot);
+    assert_eq!(allowed(&mut limiter, 0x100, 100, 3), 3);
+    assert_eq!(
+        limiter.limit_id(StandardId::new(0x300).unwrap(), 10, 1),
+        Ok(slot)
+    );
+}
+
+#[test]
+fn refused_frame_uses_up_no_limit() {
+    let mut limiter = RateLimiter::<1>::new();
+    limiter.limit_all(100, 2);
+    limiter
+        .limit_id(StandardId::new(0x100).unwrap(), 100, 1)
+        .unwrap();
+
+    // The second frame is refused by its own limit, leaving a global token
+    assert_eq!(allowed(&mut limiter, 0x100, 0, 2), 1);
+    assert_eq!(allowed(&mut limiter, 0x200, 0, 2), 1);
+    assert_eq!(limiter.limited(), 2);
+}
+
+#[test]
+fn time_wrap_around() {
+    let mut limiter = RateLimiter::<0>::new();
+    limiter.limit_all(100, 1);
+
+    assert_eq!(allowed(&mut limiter, 0x100, u32::MAX - 4, 2), 1);
+    assert_eq!(allowed(&mut limiter, 0x100, 4, 1), 0);
+    assert_eq!(allowed(&mut limiter, 0x100, 5, 1), 1);
+}
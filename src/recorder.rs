@@ -0,0 +1,173 @@
+//! Capture of timestamped traffic and its retransmission with the same timing.
+
+#[cfg(all(feature = "async", feature = "_hal"))]
+use embassy_time::Timer;
+
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::asynch::CanTx;
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::can::Instance;
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::systick::systick_millis as now_ms;
+
+/// Last `N` frames recorded with their time, the oldest overwritten once full.
+pub struct Recorder<const N: usize> {
+    frames: [Option<(u32, CanFrame)>; N],
+    /// Slot of the oldest frame
+    start: usize,
+    len: usize,
+    overwritten: u32,
+    paused: bool,
+}
+
+impl<const N: usize> Default for Recorder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Recorder<N> {
+    pub const fn new() -> Self {
+        Self {
+            frames: [None; N],
+            start: 0,
+            len: 0,
+            overwritten: 0,
+            paused: false,
+        }
+    }
+
+    /// Records `frame` seen at `time_ms`, e.g. its [CanFrame::timestamp] with a
+    /// millisecond time source or the SysTick time in milliseconds. Ignored while
+    /// paused.
+    pub fn record(&mut self, frame: &CanFrame, time_ms: u32) {
+        if self.paused || N == 0 {
+            return;
+        }
+
+        let slot = (self.start + self.len) % N;
+        self.frames[slot] = Some((time_ms, *frame));
+        match self.len < N {
+            true => self.len += 1,
+            false => {
+                self.start = (self.start + 1) % N;
+                self.overwritten += 1;
+            }
+        }
+    }
+
+    /// Stops recording, e.g. to replay what was captured.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.overwritten = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Frames dropped to make room since the last clear.
+    pub fn overwritten(&self) -> u32 {
+        self.overwritten
+    }
+
+    /// Recorded frames with their time, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &CanFrame)> + '_ {
+        (0..self.len).map(move |index| {
+            let (time_ms, frame) = self.frames[(self.start + index) % N].as_ref().unwrap();
+            (*time_ms, frame)
+        })
+    }
+
+    fn get(&self, index: usize) -> Option<(u32, &CanFrame)> {
+        self.iter().nth(index)
+    }
+
+    /// Replays the recording, see [Replay].
+    pub fn replay(&self) -> Replay<'_, N> {
+        Replay {
+            recorder: self,
+            next: 0,
+            start_ms: None,
+        }
+    }
+}
+
+/// Retransmission of a recording, each frame sent as long after the first one as
+/// it was recorded. Driven from a timer tick with [Replay::poll] or from an embassy
+/// task with [Replay::run].
+///
+/// A frame that can't be sent on time delays the rest of the replay by as much, so
+/// the order and spacing of frames are kept.
+pub struct Replay<'a, const N: usize> {
+    recorder: &'a Recorder<N>,
+    next: usize,
+    /// Time the first frame would have been recorded at on the replay's clock
+    start_ms: Option<u32>,
+}
+
+impl<const N: usize> Replay<'_, N> {
+    /// Whether all frames were sent.
+    pub fn is_done(&self) -> bool {
+        self.next >= self.recorder.len()
+    }
+
+    /// Transmits the frames due at `now_ms` and returns whether the replay is
+    /// done. Call it from a timer tick, the first call sending the first frame.
+    pub fn poll<C>(&mut self, can: &mut C, now_ms: u32) -> bool
+    where
+        C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    {
+        while let Some((time_ms, frame)) = self.recorder.get(self.next) {
+            let first_ms = self.recorder.get(0).unwrap().0;
+            let start_ms = *self.start_ms.get_or_insert(now_ms);
+            let offset_ms = time_ms.wrapping_sub(first_ms);
+            let elapsed_ms = now_ms.wrapping_sub(start_ms);
+            if elapsed_ms < offset_ms {
+                break;
+            }
+
+            if can.transmit(frame).is_err() {
+                // Shift the rest of the replay by the time lost
+                self.start_ms = Some(now_ms.wrapping_sub(offset_ms));
+                break;
+            }
+            self.next += 1;
+        }
+
+        self.is_done()
+    }
+
+    /// Sends the whole recording, waiting between frames. Call it from a task owning
+    /// the transmit half.
+    #[cfg(all(feature = "async", feature = "_hal"))]
+    pub async fn run<T: Instance>(&mut self, tx: &mut CanTx<'_, T>) {
+        while let Some((time_ms, frame)) = self.recorder.get(self.next) {
+            let first_ms = self.recorder.get(0).unwrap().0;
+            let start_ms = *self.start_ms.get_or_insert(now_ms());
+            let offset_ms = time_ms.wrapping_sub(first_ms);
+            let elapsed_ms = now_ms().wrapping_sub(start_ms);
+            if elapsed_ms < offset_ms {
+                Timer::after_millis((offset_ms - elapsed_ms) as u64).await;
+            }
+
+            tx.write(frame).await;
+            self.next += 1;
+        }
+    }
+}
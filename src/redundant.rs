@@ -0,0 +1,168 @@
+//! Redundant operation over two CAN buses carrying the same traffic.
+
+use crate::can::{Can, Instance};
+use crate::deferred::Deferred;
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+use crate::registers::Registers;
+
+/// Number of recently received frames remembered per bus to detect duplicates.
+const RECENT_LEN: usize = 8;
+
+/// Two peripherals attached to redundant buses, used as a single one.
+///
+/// Frames are sent on both buses or only on the active one, see [RedundancyMode].
+/// The active bus switches over to the other one as soon as it goes Error Passive
+/// or Bus Off while the other one is still healthy.
+///
+/// Frames are received from both buses. A frame that arrives on one bus with the
+/// same identifier and payload as a recent frame from the other bus is taken as
+/// its redundant copy and dropped.
+pub struct RedundantCan<'d, A: Instance, B: Instance> {
+    first: Can<'d, A>,
+    second: Can<'d, B>,
+    mode: RedundancyMode,
+    active: RedundantBus,
+    recent: [Deferred<RECENT_LEN>; 2],
+}
+
+impl<'d, A: Instance, B: Instance> RedundantCan<'d, A, B> {
+    pub fn new(first: Can<'d, A>, second: Can<'d, B>, mode: RedundancyMode) -> Self {
+        Self {
+            first,
+            second,
+            mode,
+            active: RedundantBus::First,
+            recent: [Deferred::new(), Deferred::new()],
+        }
+    }
+
+    pub fn active_bus(&self) -> RedundantBus {
+        self.active
+    }
+
+    pub fn release(self) -> (Can<'d, A>, Can<'d, B>) {
+        (self.first, self.second)
+    }
+
+    /// Sends `frame` on the active bus and, in [RedundancyMode::Both], on the other
+    /// one if it is healthy.
+    ///
+    /// Returns `Err(WouldBlock)` if the active bus has no free mailbox. The copy
+    /// for the other bus is sent on a best-effort basis, as it is only a backup.
+    pub fn transmit(&mut self, frame: &CanFrame) -> nb::Result<(), CanError> {
+        self.update_active();
+
+        self.transmit_on(self.active, frame)?;
+        if self.mode == RedundancyMode::Both && self.is_healthy(self.active.other()) {
+            let _ = self.transmit_on(self.active.other(), frame);
+        }
+
+        Ok(())
+    }
+
+    /// Returns a frame received on either bus, dropping redundant copies.
+    ///
+    /// Bus errors are only returned for the active bus.
+    pub fn receive(&mut self) -> nb::Result<CanFrame, CanError> {
+        self.update_active();
+
+        for bus in [self.active, self.active.other()] {
+            loop {
+                let frame = match self.receive_on(bus) {
+                    Ok(frame) => frame,
+                    Err(nb::Error::Other(error)) if bus == self.active => {
+                        return Err(nb::Error::Other(error))
+                    }
+                    Err(_) => break,
+                };
+
+                if self.is_duplicate(bus, &frame) {
+                    continue;
+                }
+
+                return Ok(frame);
+            }
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Whether `frame` just received on `bus` is the copy of a recent frame from
+    /// the other bus, remembering it otherwise.
+    fn is_duplicate(&mut self, bus: RedundantBus, frame: &CanFrame) -> bool {
+        let other = &mut self.recent[bus.other() as usize];
+        if other
+            .take_matching(|recent| recent.same_content(frame))
+            .is_some()
+        {
+            return true;
+        }
+
+        let recent = &mut self.recent[bus as usize];
+        if let Err(frame) = recent.push(*frame) {
+            recent.pop(); // Forget the oldest frame, its copy is not coming anymore
+            let _ = recent.push(frame);
+        }
+
+        false
+    }
+
+    fn update_active(&mut self) {
+        if !self.is_healthy(self.active) && self.is_healthy(self.active.other()) {
+            self.active = self.active.other();
+        }
+    }
+
+    fn is_healthy(&self, bus: RedundantBus) -> bool {
+        match bus {
+            RedundantBus::First => {
+                !Registers(A::regs()).is_degraded() && !A::state().forced_bus_off()
+            }
+            RedundantBus::Second => {
+                !Registers(B::regs()).is_degraded() && !B::state().forced_bus_off()
+            }
+        }
+    }
+
+    fn transmit_on(&self, bus: RedundantBus, frame: &CanFrame) -> nb::Result<(), CanError> {
+        match bus {
+            RedundantBus::First => self.first.transmit(frame).map(|_| ()),
+            RedundantBus::Second => self.second.transmit(frame).map(|_| ()),
+        }
+    }
+
+    fn receive_on(&self, bus: RedundantBus) -> nb::Result<CanFrame, CanError> {
+        match bus {
+            RedundantBus::First => self.first.receive(),
+            RedundantBus::Second => self.second.receive(),
+        }
+    }
+}
+
+/// One of the two buses of a [RedundantCan].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum RedundantBus {
+    First,
+    Second,
+}
+
+impl RedundantBus {
+    pub(crate) fn other(&self) -> RedundantBus {
+        match self {
+            RedundantBus::First => RedundantBus::Second,
+            RedundantBus::Second => RedundantBus::First,
+        }
+    }
+}
+
+/// Where a [RedundantCan] sends frames.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum RedundancyMode {
+    /// On both buses, as long as they are healthy
+    Both,
+    /// On the active bus only, switching over when it fails
+    ActiveOnly,
+}
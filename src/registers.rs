@@ -1,35 +1,195 @@
-const CAN_TX_TIMEOUT: u32 = 0xFFF;
+use crate::pac::can::regs;
 
-pub(crate) struct Registers(pub crate::pac::can::Can);
+#[cfg(test)]
+mod tests;
 
-impl Registers {
-    pub fn enter_init_mode(&self) {
+/// Bound on a wait for the hardware to acknowledge a request: `polls` checks, with
+/// `pause` called between two.
+#[derive(Copy, Clone)]
+pub(crate) struct Timeout {
+    pub polls: u32,
+    pub pause: fn(),
+}
+
+impl Timeout {
+    /// Checks `done` until it returns true, or returns false once out of polls.
+    pub fn wait(self, mut done: impl FnMut() -> bool) -> bool {
+        for _ in 0..self.polls {
+            if done() {
+                return true;
+            }
+            (self.pause)();
+        }
+
+        done()
+    }
+}
+
+/// Typed accesses to one register, as provided by the PAC's `Reg`.
+pub(crate) trait Register<T: Copy + Default> {
+    fn read(&self) -> T;
+    fn write_value(&self, value: T);
+
+    /// Writes the value set by `f` on top of the reset value 0.
+    #[inline(always)]
+    fn write<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut value = T::default();
+        let result = f(&mut value);
+        self.write_value(value);
+        result
+    }
+
+    #[inline(always)]
+    fn modify<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut value = self.read();
+        let result = f(&mut value);
+        self.write_value(value);
+        result
+    }
+}
+
+impl<T: Copy + Default> Register<T> for crate::pac::common::Reg<T, crate::pac::common::RW> {
+    #[inline(always)]
+    fn read(&self) -> T {
+        crate::pac::common::Reg::read(self)
+    }
+
+    #[inline(always)]
+    fn write_value(&self, value: T) {
+        crate::pac::common::Reg::write_value(self, value)
+    }
+}
+
+macro_rules! register_block {
+    ($($name:ident($($n:ident)?): $value:ident),* $(,)?) => {
+        /// The accessors of the PAC's CAN register block used by the driver.
+        /// Implemented by the register block itself on target, and by a recording
+        /// mock in unit tests.
+        pub(crate) trait RegisterBlock: Copy {
+            $(fn $name(self $(, $n: usize)?) -> impl Register<regs::$value>;)*
+        }
+
+        impl RegisterBlock for crate::pac::can::Can {
+            $(
+                #[inline(always)]
+                fn $name(self $(, $n: usize)?) -> impl Register<regs::$value> {
+                    crate::pac::can::Can::$name(self $(, $n)?)
+                }
+            )*
+        }
+    };
+}
+
+register_block!(
+    ctlr(): Ctlr,
+    statr(): Statr,
+    tstatr(): Tstatr,
+    rfifo(n): Rfifo,
+    intenr(): Intenr,
+    errsr(): Errsr,
+    btimr(): Btimr,
+    txmir(n): Txmir,
+    txmdtr(n): Txmdtr,
+    txmdlr(n): Txmdlr,
+    txmdhr(n): Txmdhr,
+    rxmir(n): Rxmir,
+    rxmdtr(n): Rxmdtr,
+    rxmdlr(n): Rxmdlr,
+    rxmdhr(n): Rxmdhr,
+    fctlr(): Fctlr,
+    fmcfgr(): Fmcfgr,
+    fscfgr(): Fscfgr,
+    fafifor(): Fafifor,
+    fwr(): Fwr,
+    fr(n): Fr,
+);
+
+/// Snapshot of a receive FIFO's status register.
+pub(crate) struct FifoStatus {
+    /// Frames waiting in the FIFO, 0 to 3
+    pub pending: usize,
+    pub full: bool,
+    pub overrun: bool,
+}
+
+pub(crate) struct Registers<B = crate::pac::can::Can>(pub B);
+
+impl<B: RegisterBlock> Registers<B> {
+    /// Returns whether init mode was acknowledged within `timeout`.
+    pub fn enter_init_mode(&self, timeout: Timeout) -> bool {
         self.0.ctlr().modify(|w| {
             w.set_sleep(false); // Wake up
             w.set_inrq(true); // Request enter init mode
         });
 
         // Wait until CAN is in init mode
-        loop {
-            if self.0.statr().read().inak() {
-                break;
-            }
-        }
+        timeout.wait(|| self.0.statr().read().inak())
     }
 
-    pub fn leave_init_mode(&self) {
+    /// Returns whether leaving init mode was acknowledged within `timeout`, which
+    /// takes 11 recessive bits on the bus.
+    pub fn leave_init_mode(&self, timeout: Timeout) -> bool {
         self.0.ctlr().modify(|w| w.set_inrq(false)); // Request exit init mode
 
         // Wait until CAN is no longer in init mode
-        loop {
-            if !self.0.statr().read().inak() {
-                break;
-            }
+        timeout.wait(|| !self.0.statr().read().inak())
+    }
+
+    /// Applies `bt` and `mode`, going through init mode. Returns whether both mode
+    /// changes were acknowledged within `timeout` each.
+    pub fn configure(
+        &self,
+        bt: crate::timing::NominalBitTiming,
+        mode: crate::CanMode,
+        timeout: Timeout,
+    ) -> bool {
+        if !self.enter_init_mode(timeout) {
+            return false;
         }
+        self.set_bit_timing_and_mode(bt, mode);
+        self.leave_init_mode(timeout)
+    }
+
+    /// Must be called in init mode.
+    pub fn set_time_triggered_mode(&self, enabled: bool) {
+        self.0.ctlr().modify(|w| w.set_ttcm(enabled)); // Capture timer value on SOF in mailboxes
+    }
+
+    pub fn time_triggered_mode(&self) -> bool {
+        self.0.ctlr().read().ttcm()
+    }
+
+    /// Returns whether sleep mode was acknowledged within `timeout`, once the frame
+    /// on the bus, if any, is completed.
+    pub fn enter_sleep_mode(&self, timeout: Timeout) -> bool {
+        self.0.ctlr().modify(|w| {
+            w.set_inrq(false); // Leave init mode request, if any
+            w.set_sleep(true); // Request enter sleep mode
+        });
+
+        // Wait until CAN is in sleep mode
+        timeout.wait(|| self.0.statr().read().slak())
+    }
+
+    /// Returns whether leaving sleep mode was acknowledged within `timeout`, which
+    /// takes 11 recessive bits on the bus.
+    pub fn leave_sleep_mode(&self, timeout: Timeout) -> bool {
+        self.0.ctlr().modify(|w| w.set_sleep(false)); // Request exit sleep mode
+
+        // Wait until CAN has synchronized to the bus again
+        timeout.wait(|| !self.0.statr().read().slak())
     }
 
-    pub fn set_bit_timing_and_mode(&self, bt: crate::util::NominalBitTiming, mode: crate::CanMode) {
-        let prescaler = u16::from(bt.prescaler) & 0x1FF;
+    pub fn is_sleeping(&self) -> bool {
+        self.0.statr().read().slak()
+    }
+
+    pub fn set_bit_timing_and_mode(
+        &self,
+        bt: crate::timing::NominalBitTiming,
+        mode: crate::CanMode,
+    ) {
+        let prescaler = u16::from(bt.prescaler) & 0x7FF;
         let seg1 = u8::from(bt.seg1);
         let seg2 = u8::from(bt.seg2) & 0x7F;
         let sync_jump_width = u8::from(bt.sync_jump_width) & 0x7F;
@@ -49,10 +209,10 @@ impl Registers {
         self.0.fscfgr().modify(|w| w.set_fsc(filter.bank, true)); // Set filter scale config to single 32-bit (16-bit not implemented)
         self.0
             .fr(filter.fr_id_value_reg())
-            .write_value(crate::pac::can::regs::Fr(filter.id_value)); // Set filter's id value to match/mask
+            .write_value(regs::Fr(filter.id_value)); // Set filter's id value to match/mask
         self.0
             .fr(filter.fr_id_mask_reg())
-            .write_value(crate::pac::can::regs::Fr(filter.id_mask)); // Set filter's id bits to mask
+            .write_value(regs::Fr(filter.id_mask)); // Set filter's id bits to mask
         self.0
             .fmcfgr()
             .modify(|w| w.set_fbm(filter.bank, filter.mode.val_bool())); // Set new filter's operating mode
@@ -63,6 +223,33 @@ impl Registers {
         self.0.fctlr().modify(|w| w.set_finit(false)); // Exit filter init mode
     }
 
+    /// Bit `n` is set if filter bank `n` is active.
+    pub fn active_filters(&self) -> u32 {
+        self.0.fwr().read().0
+    }
+
+    /// Activates exactly `banks` among the filter banks in `mask`, others are left as they are.
+    pub fn set_active_filters(&self, banks: u32, mask: u32) {
+        self.0.fctlr().modify(|w| w.set_finit(true)); // Enable filter init mode
+        self.0
+            .fwr()
+            .modify(|w| w.0 = (w.0 & !mask) | (banks & mask)); // Activate exactly the given filter banks
+        self.0.fctlr().modify(|w| w.set_finit(false)); // Exit filter init mode
+    }
+
+    /// First filter bank assigned to CAN2, on CAN1's register block.
+    #[cfg(feature = "_can2")]
+    pub fn can2_start_bank(&self) -> u8 {
+        self.0.fctlr().read().can2sb()
+    }
+
+    #[cfg(feature = "_can2")]
+    pub fn set_can2_start_bank(&self, bank: u8) {
+        self.0.fctlr().modify(|w| w.set_finit(true)); // Enable filter init mode
+        self.0.fctlr().modify(|w| w.set_can2sb(bank)); // Banks from `bank` on belong to CAN2
+        self.0.fctlr().modify(|w| w.set_finit(false)); // Exit filter init mode
+    }
+
     pub fn find_free_mailbox(&self) -> Option<usize> {
         let tstatr = self.0.tstatr().read();
         if tstatr.tme(0) {
@@ -77,73 +264,324 @@ impl Registers {
         return None;
     }
 
-    pub fn write_frame_mailbox(&self, mailbox_num: usize, frame: &crate::CanFrame) {
-        let tx_data_high: u32 = ((frame.data[7] as u32) << 24)
-            | ((frame.data[6] as u32) << 16)
-            | ((frame.data[5] as u32) << 8)
-            | frame.data[4] as u32;
-        let tx_data_low: u32 = ((frame.data[3] as u32) << 24)
-            | ((frame.data[2] as u32) << 16)
-            | ((frame.data[1] as u32) << 8)
-            | frame.data[0] as u32;
+    pub fn all_mailboxes_empty(&self) -> bool {
+        let tstatr = self.0.tstatr().read();
+        tstatr.tme(0) && tstatr.tme(1) && tstatr.tme(2)
+    }
+
+    /// With `append_time` set in time-triggered mode, the hardware overwrites data
+    /// bytes 6 and 7 with the timer value at start of frame.
+    pub fn write_frame_mailbox(
+        &self,
+        mailbox_num: usize,
+        frame: &crate::CanFrame,
+        append_time: bool,
+    ) {
+        let (tx_data_low, tx_data_high) = frame.data_registers();
 
-        self.0.txmdtr(mailbox_num).modify(|w| w.set_dlc(8)); // Set message length in bytes
+        self.0.txmdtr(mailbox_num).modify(|w| {
+            w.set_dlc(frame.dlc as u8); // Set message length in bytes
+            w.set_tgt(append_time); // Transmit global time in the last two data bytes
+        });
         self.0
             .txmdhr(mailbox_num)
-            .write_value(crate::pac::can::regs::Txmdhr(tx_data_high));
+            .write_value(regs::Txmdhr(tx_data_high));
         self.0
             .txmdlr(mailbox_num)
-            .write_value(crate::pac::can::regs::Txmdlr(tx_data_low));
-        self.0
-            .txmir(mailbox_num)
-            .write_value(crate::pac::can::regs::Txmir(0x0)); // Clear CAN TXMIR register
+            .write_value(regs::Txmdlr(tx_data_low));
+        self.0.txmir(mailbox_num).write_value(regs::Txmir(0x0)); // Clear CAN TXMIR register
         self.0.txmir(mailbox_num).modify(|w| {
-            w.set_stid(match frame.id {
-                embedded_can::Id::Standard(id) => id.as_raw(),
-                embedded_can::Id::Extended(_) => unimplemented!(),
-            }); // Using CAN Standard ID for message
+            w.0 |= frame.filter_bits(); // Standard or extended ID, with IDE set for extended
             w.set_txrq(true); // Initiate mailbox transfer request
         });
     }
 
-    pub fn transmit_status(&self, mailbox_num: usize) -> crate::TxStatus {
-        let mut wait_status: u32 = 0;
-        while !self.0.tstatr().read().txok(mailbox_num) && wait_status < CAN_TX_TIMEOUT {
-            wait_status += 1;
+    /// Same as [Registers::write_frame_mailbox], writing every register whole instead
+    /// of clearing and modifying them: four writes and no read.
+    #[inline(always)]
+    pub fn write_frame_mailbox_unchecked(
+        &self,
+        mailbox_num: usize,
+        frame: &crate::CanFrame,
+        append_time: bool,
+    ) {
+        let (tx_data_low, tx_data_high) = frame.data_registers();
+
+        self.0.txmdtr(mailbox_num).write(|w| {
+            w.set_dlc(frame.dlc as u8); // Set message length in bytes
+            w.set_tgt(append_time); // Transmit global time in the last two data bytes
+        });
+        self.0
+            .txmdhr(mailbox_num)
+            .write_value(regs::Txmdhr(tx_data_high));
+        self.0
+            .txmdlr(mailbox_num)
+            .write_value(regs::Txmdlr(tx_data_low));
+        self.0.txmir(mailbox_num).write(|w| {
+            w.0 = frame.filter_bits(); // Standard or extended ID, with IDE set for extended
+            w.set_txrq(true); // Initiate mailbox transfer request
+        });
+    }
+
+    pub fn fifo_has_messages_pending(&self, fifo: &crate::CanFifo) -> bool {
+        self.0.rfifo(fifo.val()).read().fmp() != 0
+    }
+
+    /// Frames pending in `fifo` and its full and overrun flags, from a single read.
+    pub fn fifo_status(&self, fifo: &crate::CanFifo) -> FifoStatus {
+        let rfifo = self.0.rfifo(fifo.val()).read();
+        FifoStatus {
+            pending: rfifo.fmp() as usize,
+            full: rfifo.full(),
+            overrun: rfifo.fovr(),
         }
-        if wait_status == CAN_TX_TIMEOUT {
-            return crate::TxStatus::TimeoutError;
+    }
+
+    /// Acknowledges the full and overrun flags set in `status` with a single write.
+    pub fn clear_fifo_flags(&self, fifo: &crate::CanFifo, status: &FifoStatus) {
+        if !status.full && !status.overrun {
+            return;
         }
 
-        let tx_result = self.0.tstatr().read();
-        if tx_result.txok(mailbox_num) {
-            return crate::TxStatus::Sent;
+        self.0.rfifo(fifo.val()).write(|w| {
+            w.set_full(status.full); // Clear FIFO full flag
+            w.set_fovr(status.overrun); // Clear FIFO overrun flag
+        });
+    }
+
+    pub fn read_frame_fifo(&self, fifo: &crate::CanFifo) -> crate::frame::CanFrame {
+        self.read_frame_fifo_batched(fifo, self.time_triggered_mode())
+    }
+
+    /// Reads and releases the frame at the output of `fifo`, with the time-triggered
+    /// mode read once by the caller for the whole batch.
+    pub fn read_frame_fifo_batched(
+        &self,
+        fifo: &crate::CanFifo,
+        hw_timestamp: bool,
+    ) -> crate::frame::CanFrame {
+        // Each register is read once, the frame is decoded from the copies
+        let rxmir = self.0.rxmir(fifo.val()).read();
+        let rxmdtr = self.0.rxmdtr(fifo.val()).read();
+        let dlc = (rxmdtr.dlc() as usize).min(8); // DLC 9 to 15 also mean 8 bytes
+        let remote = rxmir.rtr(); // Remote frames carry a DLC but no data
+        let rxmdlr = match (remote, dlc) {
+            (true, _) | (_, 0) => 0, // No data bytes to read
+            _ => self.0.rxmdlr(fifo.val()).read().0,
+        };
+        let rxmdhr = match (remote, dlc) {
+            (true, _) | (_, 0..=4) => 0, // Data bytes 4 to 7 unused
+            _ => self.0.rxmdhr(fifo.val()).read().0,
+        };
+
+        let id = crate::frame::CanFrame::id_from_bits(rxmir.0);
+        let frame_data_unordered = (rxmdhr as u64) << 32 | rxmdlr as u64;
+        let mut frame =
+            crate::frame::CanFrame::new_from_data_registers(id, frame_data_unordered, dlc);
+        frame.is_remote = remote;
+        if hw_timestamp {
+            frame.hw_timestamp = Some(rxmdtr.time()); // Timer value at SOF
         }
-        if tx_result.alst(mailbox_num) {
-            return crate::TxStatus::ArbitrationError;
+
+        self.0.rfifo(fifo.val()).write(|w| w.set_rfom(true)); // Release FIFO output mailbox
+
+        frame
+    }
+
+    pub fn enable_interrupts(&self, fifo: &crate::CanFifo) {
+        self.0.intenr().modify(|w| {
+            w.set_tmeie(true); // Transmit mailbox empty
+            w.set_fmpie(fifo.val(), true); // FIFO message pending
+            w.set_ffie(fifo.val(), true); // FIFO full
+            w.set_fovie(fifo.val(), true); // FIFO overrun
+            w.set_ewgie(true); // Error warning
+            w.set_epvie(true); // Error passive
+            w.set_bofie(true); // Bus-off
+            w.set_lecie(true); // Last error code
+            w.set_errie(true); // Error interrupt
+        });
+    }
+
+    /// Lets error conditions latch the error interrupt flag, so that polling tells a
+    /// new error from a persistent bus state. Nothing is raised while the CAN
+    /// vectors are masked in the PFIC.
+    pub fn enable_error_flags(&self) {
+        self.0.intenr().modify(|w| {
+            w.set_ewgie(true); // Error warning
+            w.set_epvie(true); // Error passive
+            w.set_bofie(true); // Bus-off
+            w.set_lecie(true); // Last error code
+            w.set_errie(true); // Error interrupt
+        });
+    }
+
+    /// Returns the result of mailbox `mailbox_num`'s last request and acknowledges it,
+    /// or `None` if no request has completed since.
+    pub fn take_tx_completed(&self, mailbox_num: usize) -> Option<crate::TxStatus> {
+        let tstatr = self.0.tstatr().read();
+        if !tstatr.rqcp(mailbox_num) {
+            return None;
         }
-        if tx_result.terr(mailbox_num) {
-            return crate::TxStatus::OtherError;
+
+        let status = if tstatr.txok(mailbox_num) {
+            crate::TxStatus::Sent
+        } else if tstatr.alst(mailbox_num) {
+            crate::TxStatus::ArbitrationError
+        } else {
+            crate::TxStatus::OtherError
+        };
+        self.0.tstatr().write(|w| w.set_rqcp(mailbox_num, true)); // Clear RQCP, TXOK, ALST & TERR
+
+        Some(status)
+    }
+
+    /// Decodes the state of every transmit mailbox, without acknowledging anything.
+    pub fn tx_mailbox_status(&self) -> crate::TxMailboxStatus {
+        use crate::MailboxState;
+
+        let tstatr = self.0.tstatr().read();
+        let mailboxes = core::array::from_fn(|mailbox_num| {
+            let pending = !tstatr.tme(mailbox_num);
+            // ALST and TERR describe the last attempt, until the completion is acknowledged
+            let attempted = pending || tstatr.rqcp(mailbox_num);
+            if pending && tstatr.abrq(mailbox_num) {
+                MailboxState::Aborted
+            } else if attempted && tstatr.terr(mailbox_num) {
+                MailboxState::Error
+            } else if attempted && tstatr.alst(mailbox_num) {
+                MailboxState::ArbitrationLost
+            } else if pending {
+                MailboxState::Pending
+            } else if tstatr.rqcp(mailbox_num) && !tstatr.txok(mailbox_num) {
+                MailboxState::Aborted // Completed without being sent nor failing
+            } else {
+                MailboxState::Empty
+            }
+        });
+        let next_free = (0..crate::enums::TX_MAILBOXES)
+            .any(|mailbox_num| tstatr.tme(mailbox_num))
+            .then_some(tstatr.code() as usize);
+
+        crate::TxMailboxStatus {
+            mailboxes,
+            next_free,
         }
+    }
 
-        crate::TxStatus::OtherError
+    /// Timer value captured at the start of the last frame sent from `mailbox_num`,
+    /// in time-triggered mode.
+    pub fn tx_timestamp(&self, mailbox_num: usize) -> u16 {
+        self.0.txmdtr(mailbox_num).read().time()
     }
 
-    pub fn fifo_has_messages_pending(&self, fifo: &crate::CanFifo) -> bool {
-        self.0.rfifo(fifo.val()).read().fmp() != 0
+    pub fn take_fifo_full(&self, fifo: &crate::CanFifo) -> bool {
+        if !self.0.rfifo(fifo.val()).read().full() {
+            return false;
+        }
+
+        self.0.rfifo(fifo.val()).write(|w| w.set_full(true)); // Clear FIFO full flag
+
+        true
     }
 
-    pub fn read_frame_fifo(&self, fifo: &crate::CanFifo) -> crate::frame::CanFrame {
-        let dlc = self.0.rxmdtr(fifo.val()).read().dlc() as usize;
-        let raw_id = self.0.rxmir(fifo.val()).read().stid();
+    pub fn take_fifo_overrun(&self, fifo: &crate::CanFifo) -> bool {
+        if !self.0.rfifo(fifo.val()).read().fovr() {
+            return false;
+        }
 
-        let id = embedded_can::StandardId::new(raw_id).unwrap();
+        self.0.rfifo(fifo.val()).write(|w| w.set_fovr(true)); // Clear FIFO overrun flag
 
-        let frame_data_unordered: u64 = ((self.0.rxmdhr(fifo.val()).read().0 as u64) << 32)
-            | self.0.rxmdlr(fifo.val()).read().0 as u64;
+        true
+    }
 
-        let frame = crate::frame::CanFrame::new_from_data_registers(id, frame_data_unordered, dlc);
+    /// Whether the status change & error interrupt has a flag waiting to be serviced.
+    pub fn status_change_pending(&self) -> bool {
+        let statr = self.0.statr().read();
+        statr.erri() || statr.wkui()
+    }
 
-        frame
+    /// Decodes the current error state and acknowledges the error interrupt.
+    pub fn take_error(&self) -> Option<crate::CanError> {
+        let errsr = self.0.errsr().read();
+        self.0.errsr().modify(|w| w.set_lec(0b111)); // Set LEC to an unused code to detect the next error
+        self.0.statr().write(|w| w.set_erri(true)); // Clear error interrupt flag
+
+        let lec_error = crate::CanErrorKind::from_lec(errsr.lec());
+        let kind = if errsr.boff() {
+            crate::CanErrorKind::BusOff
+        } else if let Some(kind) = lec_error {
+            kind
+        } else if errsr.epvf() {
+            crate::CanErrorKind::BusPassive
+        } else if errsr.ewgf() {
+            crate::CanErrorKind::BusWarning
+        } else {
+            return None;
+        };
+        let mailbox = match lec_error {
+            Some(_) => {
+                let tstatr = self.0.tstatr().read();
+                (0..crate::enums::TX_MAILBOXES).find(|&mailbox_num| tstatr.terr(mailbox_num))
+            }
+            None => None,
+        };
+
+        Some(crate::CanError {
+            kind,
+            mailbox,
+            fifo: None,
+            errsr: errsr.0,
+        })
+    }
+
+    /// Whether the peripheral is Error Passive or Bus Off.
+    pub fn is_degraded(&self) -> bool {
+        let errsr = self.0.errsr().read();
+        errsr.epvf() || errsr.boff()
+    }
+
+    pub fn bus_state(&self) -> crate::BusState {
+        let errsr = self.0.errsr().read();
+        if errsr.boff() {
+            crate::BusState::BusOff
+        } else if errsr.epvf() {
+            crate::BusState::ErrorPassive
+        } else {
+            crate::BusState::ErrorActive
+        }
+    }
+
+    /// Transmit and receive error counters.
+    pub fn error_counters(&self) -> (u8, u8) {
+        let errsr = self.0.errsr().read();
+        (errsr.tec(), errsr.rec())
+    }
+
+    #[cfg(feature = "async")]
+    pub fn is_bus_off(&self) -> bool {
+        self.0.errsr().read().boff()
+    }
+
+    pub fn take_wakeup(&self) -> bool {
+        if !self.0.statr().read().wkui() {
+            return false;
+        }
+
+        self.0.statr().write(|w| w.set_wkui(true)); // Clear wake-up interrupt flag
+
+        true
+    }
+
+    pub fn set_automatic_wakeup(&self, enabled: bool) {
+        self.0.ctlr().modify(|w| w.set_awum(enabled)); // Leave sleep mode on bus activity
+    }
+
+    pub fn set_wakeup_interrupt(&self, enabled: bool) {
+        self.0.intenr().modify(|w| w.set_wkuie(enabled)); // Wake-up interrupt
+    }
+
+    /// Clears the sleep request so the peripheral synchronizes to the bus again.
+    pub fn request_wakeup(&self) {
+        self.0.ctlr().modify(|w| w.set_sleep(false));
     }
 }
@@ -0,0 +1,637 @@
+//! Host tests of the register sequences, against a mock recording every access.
+
+extern crate std;
+
+use core::cell::{Cell, RefCell};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
+use std::vec::Vec;
+
+use std::collections::HashMap;
+
+use super::{Register, RegisterBlock, Registers, Timeout};
+use crate::pac::can::regs;
+use crate::timing::{calc_can_timings_with_sample_point, CIA_SAMPLE_POINT_PERMILL};
+use crate::{CanFifo, CanFilter, CanFrame, CanMode};
+use Name::*;
+
+/// Register of the block, with its index for the arrayed ones.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub(crate) enum Name {
+    Ctlr,
+    Statr,
+    Tstatr,
+    Rfifo(usize),
+    Intenr,
+    Errsr,
+    Btimr,
+    Txmir(usize),
+    Txmdtr(usize),
+    Txmdlr(usize),
+    Txmdhr(usize),
+    Rxmir(usize),
+    Rxmdtr(usize),
+    Rxmdlr(usize),
+    Rxmdhr(usize),
+    Fctlr,
+    Fmcfgr,
+    Fscfgr,
+    Fafifor,
+    Fwr,
+    Fr(usize),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Access {
+    Read(Name, u32),
+    Write(Name, u32),
+}
+
+/// Register block in memory, logging accesses. Mode requests are acknowledged at
+/// once in STATR, like the hardware eventually does, unless stalled.
+pub(crate) struct MockRegisters {
+    values: RefCell<HashMap<Name, u32>>,
+    log: RefCell<Vec<Access>>,
+    stalled: Cell<bool>,
+}
+
+impl MockRegisters {
+    /// Reset state: sleeping, all mailboxes empty.
+    pub(crate) fn new() -> Self {
+        let mock = Self {
+            values: RefCell::new(HashMap::new()),
+            log: RefCell::new(Vec::new()),
+            stalled: Cell::new(false),
+        };
+        mock.set(Ctlr, 0x0001_0002);
+        mock.set(Statr, 0x0000_0C02);
+        mock.set(Tstatr, 0x1C00_0000);
+
+        mock
+    }
+
+    /// Sets a register as the hardware would, without logging.
+    pub(crate) fn set(&self, name: Name, value: u32) {
+        self.values.borrow_mut().insert(name, value);
+    }
+
+    pub(crate) fn get(&self, name: Name) -> u32 {
+        self.values.borrow().get(&name).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn take_log(&self) -> Vec<Access> {
+        self.log.take()
+    }
+
+    /// Writes only, in order.
+    pub(crate) fn take_writes(&self) -> Vec<(Name, u32)> {
+        self.take_log()
+            .into_iter()
+            .filter_map(|access| match access {
+                Access::Write(name, value) => Some((name, value)),
+                Access::Read(..) => None,
+            })
+            .collect()
+    }
+
+    fn read(&self, name: Name) -> u32 {
+        let value = self.get(name);
+        self.log.borrow_mut().push(Access::Read(name, value));
+        value
+    }
+
+    fn write(&self, name: Name, value: u32) {
+        self.log.borrow_mut().push(Access::Write(name, value));
+        self.set(name, value);
+
+        if name == Ctlr && !self.stalled.get() {
+            let inrq = value & 1 != 0;
+            let sleep = value & 2 != 0;
+            let statr = self.get(Statr) & !0b11;
+            self.set(Statr, statr | inrq as u32 | ((sleep && !inrq) as u32) << 1);
+        }
+    }
+}
+
+/// Register value types of the PAC, converted from and to their raw bits.
+trait Bits: Copy + Default {
+    fn from_bits(bits: u32) -> Self;
+    fn to_bits(self) -> u32;
+}
+
+/// One register of a [MockRegisters].
+struct MockReg<'a, T> {
+    mock: &'a MockRegisters,
+    name: Name,
+    _value: PhantomData<T>,
+}
+
+impl<T: Bits> Register<T> for MockReg<'_, T> {
+    fn read(&self) -> T {
+        T::from_bits(self.mock.read(self.name))
+    }
+
+    fn write_value(&self, value: T) {
+        self.mock.write(self.name, value.to_bits());
+    }
+}
+
+macro_rules! mock_block {
+    ($($name:ident($($n:ident)?): $value:ident),* $(,)?) => {
+        $(
+            impl Bits for regs::$value {
+                fn from_bits(bits: u32) -> Self {
+                    regs::$value(bits)
+                }
+
+                fn to_bits(self) -> u32 {
+                    self.0
+                }
+            }
+        )*
+
+        impl<'a> RegisterBlock for &'a MockRegisters {
+            $(
+                fn $name(self $(, $n: usize)?) -> impl Register<regs::$value> {
+                    MockReg::<'a, regs::$value> {
+                        mock: self,
+                        name: $value$(($n))?,
+                        _value: PhantomData,
+                    }
+                }
+            )*
+        }
+    };
+}
+
+mock_block!(
+    ctlr(): Ctlr,
+    statr(): Statr,
+    tstatr(): Tstatr,
+    rfifo(n): Rfifo,
+    intenr(): Intenr,
+    errsr(): Errsr,
+    btimr(): Btimr,
+    txmir(n): Txmir,
+    txmdtr(n): Txmdtr,
+    txmdlr(n): Txmdlr,
+    txmdhr(n): Txmdhr,
+    rxmir(n): Rxmir,
+    rxmdtr(n): Rxmdtr,
+    rxmdlr(n): Rxmdlr,
+    rxmdhr(n): Rxmdhr,
+    fctlr(): Fctlr,
+    fmcfgr(): Fmcfgr,
+    fscfgr(): Fscfgr,
+    fafifor(): Fafifor,
+    fwr(): Fwr,
+    fr(n): Fr,
+);
+
+/// Gives up at the first check, enough for the mock acknowledging at once.
+const NO_WAIT: Timeout = Timeout {
+    polls: 0,
+    pause: || {},
+};
+
+#[test]
+fn init_mode_waits_for_acknowledge() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+
+    assert!(regs.enter_init_mode(NO_WAIT));
+    assert_eq!(mock.get(Ctlr) & 0b11, 0b01);
+    assert_eq!(mock.get(Statr) & 0b11, 0b01);
+
+    assert!(regs.leave_init_mode(NO_WAIT));
+    assert_eq!(mock.get(Ctlr) & 0b11, 0b00);
+    assert_eq!(mock.get(Statr) & 0b11, 0b00);
+}
+
+#[test]
+fn init_mode_times_out() {
+    static PAUSES: AtomicU32 = AtomicU32::new(0);
+    let timeout = Timeout {
+        polls: 5,
+        pause: || {
+            PAUSES.fetch_add(1, Ordering::Relaxed);
+        },
+    };
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    assert!(regs.enter_init_mode(timeout));
+    assert_eq!(PAUSES.load(Ordering::Relaxed), 0); // Acknowledged at the first check
+
+    mock.stalled.set(true); // Like with the bus held dominant
+    assert!(!regs.leave_init_mode(timeout));
+    assert_eq!(PAUSES.load(Ordering::Relaxed), 5);
+    assert!(!regs.configure(bit_timing_500k_at_96mhz(), CanMode::Normal, NO_WAIT));
+}
+
+#[test]
+fn sleep_mode_round_trip() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+
+    assert!(regs.leave_sleep_mode(NO_WAIT));
+    assert!(!regs.is_sleeping());
+    assert!(regs.enter_sleep_mode(NO_WAIT));
+    assert!(regs.is_sleeping());
+}
+
+#[test]
+fn sleep_mode_times_out() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    assert!(regs.enter_sleep_mode(NO_WAIT));
+
+    mock.stalled.set(true); // Like with the bus held dominant
+    assert!(!regs.leave_sleep_mode(NO_WAIT));
+    assert!(regs.is_sleeping());
+    assert_eq!(mock.get(Ctlr) & 0b10, 0); // Request left pending
+}
+
+#[test]
+fn standard_frame_written_to_mailbox() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    let id = embedded_can::StandardId::new(0x123).unwrap();
+    let frame = CanFrame::new(id, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+    regs.write_frame_mailbox(0, &frame, false);
+
+    assert_eq!(mock.get(Txmdtr(0)) & 0xF, 8);
+    assert_eq!(mock.get(Txmdlr(0)), 0x0403_0201);
+    assert_eq!(mock.get(Txmdhr(0)), 0x0807_0605);
+    assert_eq!(mock.get(Txmir(0)), 0x123 << 21 | 1); // TXRQ last
+    assert_eq!(
+        mock.take_writes().last(),
+        Some(&(Txmir(0), 0x123 << 21 | 1))
+    );
+}
+
+#[test]
+fn extended_frame_written_to_mailbox() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    let id = embedded_can::ExtendedId::new(0x1ABC_DEF0).unwrap();
+    let frame = CanFrame::new(id, &[]).unwrap();
+
+    regs.write_frame_mailbox(0, &frame, false);
+
+    assert_eq!(mock.get(Txmir(0)), 0x1ABC_DEF0 << 3 | 1 << 2 | 1);
+    assert_eq!(mock.get(Txmdtr(0)) & 0xF, 0);
+}
+
+#[test]
+fn short_frame_written_with_its_length() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    let id = embedded_can::StandardId::new(0x701).unwrap();
+    let frame = CanFrame::new(id, &[0x05]).unwrap();
+
+    regs.write_frame_mailbox(0, &frame, false);
+    assert_eq!(mock.get(Txmdtr(0)) & 0xF, 1);
+
+    regs.write_frame_mailbox_unchecked(1, &frame, false);
+    assert_eq!(mock.get(Txmdtr(1)) & 0xF, 1);
+}
+
+#[test]
+fn unchecked_mailbox_write_skips_reads() {
+    let id = embedded_can::ExtendedId::new(0x1ABC_DEF0).unwrap();
+    let frame = CanFrame::new(id, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+    let count = |log: &[Access]| {
+        let reads = log.iter().filter(|a| matches!(a, Access::Read(..))).count();
+        (reads, log.len() - reads)
+    };
+
+    let checked = MockRegisters::new();
+    Registers(&checked).write_frame_mailbox(1, &frame, false);
+    let unchecked = MockRegisters::new();
+    Registers(&unchecked).write_frame_mailbox_unchecked(1, &frame, false);
+
+    assert_eq!(count(&checked.take_log()), (2, 5));
+    assert_eq!(count(&unchecked.take_log()), (0, 4));
+    for name in [Txmir(1), Txmdtr(1), Txmdlr(1), Txmdhr(1)] {
+        assert_eq!(unchecked.get(name), checked.get(name));
+    }
+}
+
+#[test]
+fn frames_read_from_fifo() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    mock.set(Rfifo(0), 1);
+    mock.set(Rxmir(0), 0x1ABC_DEF0 << 3 | 1 << 2);
+    mock.set(Rxmdtr(0), 3);
+    mock.set(Rxmdlr(0), 0x0033_2211);
+    mock.set(Rxmdhr(0), 0);
+
+    assert!(regs.fifo_has_messages_pending(&CanFifo::Fifo0));
+    let frame = regs.read_frame_fifo(&CanFifo::Fifo0);
+
+    let id = embedded_can::ExtendedId::new(0x1ABC_DEF0).unwrap();
+    assert_eq!(*frame.id(), embedded_can::Id::Extended(id));
+    assert_eq!(&frame.data()[..frame.dlc()], &[0x11, 0x22, 0x33]);
+    assert_eq!(mock.take_writes(), [(Rfifo(0), 1 << 5)]); // RFOM releases the mailbox
+}
+
+#[test]
+fn remote_frames_written_and_read_with_rtr() {
+    use embedded_can::Frame;
+
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    let id = embedded_can::StandardId::new(0x123).unwrap();
+    let frame = CanFrame::new_remote(id, 4).unwrap();
+
+    regs.write_frame_mailbox(0, &frame, false);
+    assert_eq!(mock.get(Txmir(0)), 0x123 << 21 | 1 << 1 | 1);
+    assert_eq!(mock.get(Txmdtr(0)) & 0xF, 4);
+
+    mock.set(Rxmir(0), 0x123 << 21 | 1 << 1);
+    mock.set(Rxmdtr(0), 4);
+    mock.take_log();
+    let received = regs.read_frame_fifo(&CanFifo::Fifo0);
+    assert!(received.is_remote_frame());
+    assert_eq!(Frame::dlc(&received), 4);
+    assert!(Frame::data(&received).is_empty());
+    assert!(!mock
+        .take_log()
+        .iter()
+        .any(|access| matches!(access, Access::Read(Rxmdlr(0) | Rxmdhr(0), _))));
+}
+
+#[test]
+fn fifo_registers_read_once_per_frame() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    let count_reads = |log: &[Access], name: Name| {
+        log.iter()
+            .filter(|access| matches!(access, Access::Read(n, _) if *n == name))
+            .count()
+    };
+    mock.set(Rxmir(0), 0x123 << 21);
+    mock.set(Rxmdlr(0), 0x4433_2211);
+    mock.set(Rxmdhr(0), 0x8877_6655);
+
+    mock.set(Rxmdtr(0), 0x1234 << 16 | 8);
+    let frame = regs.read_frame_fifo_batched(&CanFifo::Fifo0, true);
+    let log = mock.take_log();
+    assert_eq!(
+        frame.data(),
+        &[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]
+    );
+    assert_eq!(frame.hardware_timestamp(), Some(0x1234));
+    for name in [Rxmir(0), Rxmdtr(0), Rxmdlr(0), Rxmdhr(0)] {
+        assert_eq!(count_reads(&log, name), 1);
+    }
+
+    mock.set(Rxmdtr(0), 2);
+    let frame = regs.read_frame_fifo_batched(&CanFifo::Fifo0, false);
+    let log = mock.take_log();
+    assert_eq!(&frame.data()[..frame.dlc()], &[0x11, 0x22]);
+    assert_eq!(count_reads(&log, Rxmdhr(0)), 0); // Only the low data register holds bytes
+
+    mock.set(Rxmdtr(0), 0);
+    regs.read_frame_fifo_batched(&CanFifo::Fifo0, false);
+    let log = mock.take_log();
+    assert_eq!(
+        count_reads(&log, Rxmdlr(0)) + count_reads(&log, Rxmdhr(0)),
+        0
+    );
+}
+
+#[test]
+fn fifo_batch_reads_status_once() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    mock.set(Rfifo(0), 3 | 1 << 3 | 1 << 4); // Three frames pending, full and overrun
+
+    let status = regs.fifo_status(&CanFifo::Fifo0);
+    for _ in 0..status.pending {
+        regs.read_frame_fifo_batched(&CanFifo::Fifo0, false);
+    }
+    regs.clear_fifo_flags(&CanFifo::Fifo0, &status);
+
+    let log = mock.take_log();
+    let status_reads = log
+        .iter()
+        .filter(|access| matches!(access, Access::Read(Rfifo(0), _)))
+        .count();
+    let writes: Vec<_> = log
+        .iter()
+        .filter_map(|access| match *access {
+            Access::Write(name, value) => Some((name, value)),
+            Access::Read(..) => None,
+        })
+        .collect();
+    assert_eq!(status_reads, 1);
+    assert_eq!(
+        writes,
+        [
+            (Rfifo(0), 1 << 5), // RFOM, once per frame
+            (Rfifo(0), 1 << 5),
+            (Rfifo(0), 1 << 5),
+            (Rfifo(0), 1 << 3 | 1 << 4), // Full and overrun acknowledged at once
+        ]
+    );
+}
+
+#[test]
+fn filter_programmed_in_init_mode() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    let id = embedded_can::StandardId::new(0x100).unwrap();
+    let filter = CanFilter::matching(3, id, 0x7FF);
+
+    regs.add_filter(filter, &CanFifo::Fifo1);
+
+    let writes = mock.take_writes();
+    assert_eq!(writes.first(), Some(&(Fctlr, 1)));
+    assert_eq!(writes.last(), Some(&(Fctlr, 0)));
+    assert_eq!(mock.get(Fr(6)), 0x100 << 21);
+    assert_eq!(mock.get(Fr(7)), 0x7FF << 21 | 1 << 2); // IDE always compared
+    assert_eq!(mock.get(Fwr), 1 << 3);
+    assert_eq!(mock.get(Fscfgr), 1 << 3);
+    assert_eq!(mock.get(Fmcfgr), 0);
+    assert_eq!(mock.get(Fafifor), 1 << 3);
+}
+
+#[test]
+fn completed_mailbox_acknowledged() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    assert_eq!(regs.take_tx_completed(1), None);
+
+    mock.set(Tstatr, 0x1C00_0000 | 0b11 << 8); // RQCP1 and TXOK1
+    assert_eq!(regs.take_tx_completed(1), Some(crate::TxStatus::Sent));
+    assert_eq!(mock.take_writes(), [(Tstatr, 1 << 8)]);
+}
+
+#[test]
+fn bus_off_takes_precedence() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    mock.set(Errsr, 0b111); // Bus off, error passive and warning
+
+    let error = regs.take_error().unwrap();
+    assert_eq!(error.kind, crate::CanErrorKind::BusOff);
+    assert_eq!(error.errsr, 0b111);
+    assert_eq!(regs.bus_state(), crate::BusState::BusOff);
+}
+
+#[test]
+fn tx_mailbox_status_decodes_tstatr() {
+    use crate::MailboxState;
+
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    // Mailbox 0 pending after losing arbitration, 1 pending after an error, 2 empty
+    // after an unacknowledged abort, next free mailbox 2
+    mock.set(
+        Tstatr,
+        (1 << 28) | (2 << 24) | (1 << 2) | (1 << (3 + 8)) | (1 << 16),
+    );
+
+    let status = regs.tx_mailbox_status();
+    assert_eq!(
+        status.mailboxes,
+        [
+            MailboxState::ArbitrationLost,
+            MailboxState::Error,
+            MailboxState::Aborted
+        ]
+    );
+    assert_eq!(status.next_free, Some(2));
+
+    mock.set(Tstatr, (0b111 << 26) | (1 << 1) | 1); // All empty, mailbox 0 sent
+    let status = regs.tx_mailbox_status();
+    assert_eq!(status.mailboxes, [MailboxState::Empty; 3]);
+}
+
+#[test]
+fn bus_error_keeps_context() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    mock.set(Errsr, (3 << 24) | (40 << 16) | (0b011 << 4)); // REC 3, TEC 40, acknowledge error
+    mock.set(Tstatr, 1 << (3 + 8)); // Transmission error of mailbox 1
+
+    let error = regs.take_error().unwrap();
+    assert_eq!(error.kind, crate::CanErrorKind::Acknowledge);
+    assert_eq!(error.mailbox, Some(1));
+    assert_eq!((error.tx_errors(), error.rx_errors()), (40, 3));
+    assert_eq!(crate::CanError::unpack(error.pack()), Some(error));
+
+    let overrun = crate::CanError::overrun(CanFifo::Fifo1);
+    assert_eq!(crate::CanError::unpack(overrun.pack()), Some(overrun));
+}
+
+// Golden sequences: every register write of known configurations, in order. A change
+// here is a change of what the hardware sees, so it has to be deliberate.
+
+/// Mock with the reset values of the registers the golden sequences modify.
+fn reset_mock() -> MockRegisters {
+    let mock = MockRegisters::new();
+    mock.set(Btimr, 0x0123_0000);
+    mock.set(Fctlr, 0x2A1C_0E01);
+
+    mock
+}
+
+/// 500 kbit/s from a 96 MHz clock: 12 × 16 quanta, sampling at 87.5%.
+fn bit_timing_500k_at_96mhz() -> crate::NominalBitTiming {
+    calc_can_timings_with_sample_point(96_000_000, 500_000, CIA_SAMPLE_POINT_PERMILL).unwrap()
+}
+
+#[test]
+fn golden_500k_at_96mhz_normal_mode() {
+    let mock = reset_mock();
+    let regs = Registers(&mock);
+
+    regs.configure(bit_timing_500k_at_96mhz(), CanMode::Normal, NO_WAIT);
+
+    assert_eq!(
+        mock.take_writes(),
+        [
+            (Ctlr, 0x0001_0001),  // Leave sleep, request init mode
+            (Btimr, 0x001C_000B), // BRP 11, TS1 12, TS2 1, SJW 0, neither loopback nor silent
+            (Ctlr, 0x0001_0000),  // Leave init mode
+        ]
+    );
+}
+
+#[test]
+fn golden_mode_bits() {
+    for (mode, bits) in [
+        (CanMode::Normal, 0b00 << 30),
+        (CanMode::Silent, 0b10 << 30),
+        (CanMode::Loopback, 0b01 << 30),
+        (CanMode::SilentLoopback, 0b11 << 30),
+    ] {
+        let mock = reset_mock();
+        Registers(&mock).configure(bit_timing_500k_at_96mhz(), mode, NO_WAIT);
+
+        assert_eq!(mock.get(Btimr), 0x001C_000B | bits, "{mode:?}");
+    }
+}
+
+#[test]
+fn golden_accept_all_filter() {
+    let mock = reset_mock();
+    let regs = Registers(&mock);
+
+    regs.add_filter(CanFilter::accept_all(), &CanFifo::Fifo0);
+
+    assert_eq!(
+        mock.take_writes(),
+        [
+            (Fctlr, 0x2A1C_0E01), // Filter init mode, already set out of reset
+            (Fwr, 1),             // Bank 0 active
+            (Fscfgr, 1),          // Bank 0 single 32-bit
+            (Fr(0), 0),           // Any identifier
+            (Fr(1), 0),           // No bit compared
+            (Fmcfgr, 0),          // Bank 0 in mask mode
+            (Fafifor, 0),         // Bank 0 to FIFO 0
+            (Fwr, 1),             // Bank 0 active
+            (Fctlr, 0x2A1C_0E00), // Leave filter init mode, CAN2 from bank 14
+        ]
+    );
+}
+
+#[test]
+fn golden_interrupts_fifo0() {
+    let mock = reset_mock();
+    let regs = Registers(&mock);
+
+    regs.enable_interrupts(&CanFifo::Fifo0);
+
+    // TMEIE, FMPIE0, FFIE0, FOVIE0, EWGIE, EPVIE, BOFIE, LECIE, ERRIE
+    assert_eq!(mock.take_writes(), [(Intenr, 0x0000_8F0F)]);
+}
+
+#[test]
+fn golden_error_flags() {
+    let mock = reset_mock();
+    let regs = Registers(&mock);
+
+    regs.enable_error_flags();
+
+    // EWGIE, EPVIE, BOFIE, LECIE, ERRIE
+    assert_eq!(mock.take_writes(), [(Intenr, 0x0000_8F00)]);
+}
+
+#[test]
+fn persistent_error_state_not_pending_once_taken() {
+    let mock = MockRegisters::new();
+    let regs = Registers(&mock);
+    mock.set(Errsr, 1 << 2 | 0b111 << 4); // Bus-off, no new error code
+    mock.set(Statr, 1 << 2); // ERRI
+
+    assert!(regs.status_change_pending());
+    assert!(regs.take_error().is_some());
+    assert_eq!(mock.take_writes().last(), Some(&(Statr, 1 << 2))); // ERRI written to clear it
+    mock.set(Statr, 0); // Cleared by the hardware, while still bus-off
+    assert!(!regs.status_change_pending());
+}
@@ -0,0 +1,58 @@
+//! Single-producer single-consumer ring buffer used to hand data from interrupt
+//! context to the application without critical sections.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Fixed-capacity queue holding up to `N - 1` elements.
+///
+/// Exactly one context may call [Ring::push] and exactly one context may call
+/// [Ring::pop]. The producer only writes `head` and the consumer only writes `tail`,
+/// so each side observes a consistent view with acquire/release ordering alone.
+pub(crate) struct Ring<T: Copy, const N: usize> {
+    buf: [UnsafeCell<MaybeUninit<T>>; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// Safety: slots are only accessed by the side that currently owns them, as
+// established by the `head`/`tail` handshake.
+unsafe impl<T: Copy + Send, const N: usize> Sync for Ring<T, N> {}
+
+impl<T: Copy, const N: usize> Ring<T, N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            buf: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Producer side. Returns the element back if the queue is full.
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % N;
+        if next == self.tail.load(Ordering::Acquire) {
+            return Err(value);
+        }
+
+        unsafe { (*self.buf[head].get()).write(value) };
+        self.head.store(next, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Consumer side.
+    pub(crate) fn pop(&self) -> Option<T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = unsafe { (*self.buf[tail].get()).assume_init() };
+        self.tail.store((tail + 1) % N, Ordering::Release);
+
+        Some(value)
+    }
+}
@@ -0,0 +1,99 @@
+//! Instrumentation over RTT (Real-Time Transfer), to observe the bus through a
+//! debug probe such as a WCH-Link, e.g. with probe-rs, without using a UART.
+//!
+//! Once a channel is set with [set_event_channel], the driver writes a line for
+//! every event raised by the interrupt handler of any controller, e.g.
+//! `can: tx-complete 1`. The application writes its health telemetry to another
+//! channel with [write_health], e.g. `can: error-passive tec=128 rec=0`.
+//!
+//! Lines are dropped rather than blocking when the probe doesn't read them fast
+//! enough, in the default mode of the channels.
+
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use critical_section::Mutex;
+use rtt_target::UpChannel;
+
+use crate::{BusHealth, BusState, CanEvent};
+
+/// Longest line written.
+const MAX_LINE_LEN: usize = 48;
+
+static EVENTS: Mutex<RefCell<Option<UpChannel>>> = Mutex::new(RefCell::new(None));
+
+/// Writes the driver events to `channel` from now on.
+pub fn set_event_channel(channel: UpChannel) {
+    critical_section::with(|cs| EVENTS.borrow_ref_mut(cs).replace(channel));
+}
+
+/// Stops writing the driver events, and returns the channel they were written to.
+pub fn take_event_channel() -> Option<UpChannel> {
+    critical_section::with(|cs| EVENTS.borrow_ref_mut(cs).take())
+}
+
+/// Writes `health`, e.g. from [crate::Can::health], to `channel`.
+pub fn write_health(channel: &mut UpChannel, health: &BusHealth) {
+    let mut line = Line::new();
+    let state = match health.state {
+        BusState::ErrorActive => "error-active",
+        BusState::ErrorPassive => "error-passive",
+        BusState::BusOff => "bus-off",
+    };
+    let _ = writeln!(
+        line,
+        "can: {state} tec={} rec={}",
+        health.tx_errors, health.rx_errors
+    );
+
+    channel.write(line.as_bytes());
+}
+
+pub(crate) fn emit_event(event: CanEvent) {
+    critical_section::with(|cs| {
+        let mut channel = EVENTS.borrow_ref_mut(cs);
+        let Some(channel) = channel.as_mut() else {
+            return;
+        };
+
+        let mut line = Line::new();
+        let _ = match event {
+            CanEvent::FrameReceived => writeln!(line, "can: rx"),
+            CanEvent::TxComplete(mailbox) => writeln!(line, "can: tx-complete {mailbox}"),
+            CanEvent::BusOff => writeln!(line, "can: bus-off"),
+            CanEvent::ErrorWarning => writeln!(line, "can: error-warning"),
+            CanEvent::Wakeup => writeln!(line, "can: wakeup"),
+            CanEvent::Overrun => writeln!(line, "can: overrun"),
+        };
+
+        channel.write(line.as_bytes());
+    });
+}
+
+/// Line formatted on the stack, truncated at [MAX_LINE_LEN].
+struct Line {
+    bytes: [u8; MAX_LINE_LEN],
+    len: usize,
+}
+
+impl Line {
+    fn new() -> Self {
+        Self {
+            bytes: [0; MAX_LINE_LEN],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let len = s.len().min(MAX_LINE_LEN - self.len);
+        self.bytes[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! Cyclic transmission of frames at fixed periods.
+
+#[cfg(all(feature = "async", feature = "_hal"))]
+use embassy_time::Timer;
+
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::asynch::CanTx;
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::can::Instance;
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+#[cfg(all(feature = "async", feature = "_hal"))]
+use crate::systick::systick_millis as now_ms;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+struct Entry {
+    frame: CanFrame,
+    period_ms: u32,
+    offset_ms: u32,
+    /// Time the next transmission is due, set on the first poll
+    due_ms: Option<u32>,
+    missed: u32,
+}
+
+/// Sends up to `N` frames each at its own period, from a timer tick with
+/// [Scheduler::poll] or from an embassy task with [Scheduler::run].
+///
+/// A transmission that can't take place before the next one is due counts as a
+/// missed deadline and is skipped, so a late frame never goes out twice in a row.
+pub struct Scheduler<const N: usize> {
+    entries: [Option<Entry>; N],
+}
+
+impl<const N: usize> Default for Scheduler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Scheduler<N> {
+    pub const fn new() -> Self {
+        Self { entries: [None; N] }
+    }
+
+    /// Sends `frame` every `period_ms`, the first time `offset_ms` after the first
+    /// poll, and returns its slot. Offsets spread frames of the same period over
+    /// time, relieving the mailboxes.
+    pub fn add(
+        &mut self,
+        frame: CanFrame,
+        period_ms: u32,
+        offset_ms: u32,
+    ) -> Result<usize, SchedulerFull> {
+        if period_ms == 0 {
+            fail!("Cyclic frames need a period of at least 1 ms.");
+        }
+
+        let slot = self
+            .entries
+            .iter()
+            .position(Option::is_none)
+            .ok_or(SchedulerFull)?;
+        self.entries[slot] = Some(Entry {
+            frame,
+            period_ms,
+            offset_ms,
+            due_ms: None,
+            missed: 0,
+        });
+
+        Ok(slot)
+    }
+
+    pub fn remove(&mut self, slot: usize) {
+        self.entries[slot] = None;
+    }
+
+    /// Replaces the frame sent in `slot`, e.g. with updated signal values, keeping
+    /// its schedule.
+    pub fn set_frame(&mut self, slot: usize, frame: CanFrame) {
+        if let Some(entry) = self.entries[slot].as_mut() {
+            entry.frame = frame;
+        }
+    }
+
+    /// Deadlines missed by `slot` so far.
+    pub fn missed_deadlines(&self, slot: usize) -> u32 {
+        self.entries[slot].map_or(0, |entry| entry.missed)
+    }
+
+    /// Transmits the frames due at `now_ms` and returns the number of deadlines
+    /// missed since the previous call. Call it from a timer tick, at least as often
+    /// as the shortest period.
+    pub fn poll<C>(&mut self, can: &mut C, now_ms: u32) -> u32
+    where
+        C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    {
+        let mut missed = 0;
+        for entry in self.entries.iter_mut().flatten() {
+            let due_ms = *entry
+                .due_ms
+                .get_or_insert(now_ms.wrapping_add(entry.offset_ms));
+            if !is_reached(now_ms, due_ms) {
+                continue;
+            }
+
+            let sent = can.transmit(&entry.frame).is_ok();
+            missed += entry.advance(now_ms, sent);
+        }
+
+        missed
+    }
+
+    /// Waits for the next frame due, transmits all frames due and returns the number
+    /// of deadlines missed. Call it in a loop from a task owning the transmit half.
+    #[cfg(all(feature = "async", feature = "_hal"))]
+    pub async fn run<T: Instance>(&mut self, tx: &mut CanTx<'_, T>) -> u32 {
+        let start_ms = now_ms();
+        let next_ms = self
+            .entries
+            .iter_mut()
+            .flatten()
+            .map(|entry| {
+                *entry
+                    .due_ms
+                    .get_or_insert(start_ms.wrapping_add(entry.offset_ms))
+            })
+            .min_by_key(|due_ms| due_ms.wrapping_sub(start_ms) as i32);
+        let Some(next_ms) = next_ms else {
+            return 0;
+        };
+
+        if !is_reached(start_ms, next_ms) {
+            Timer::after_millis(next_ms.wrapping_sub(start_ms) as u64).await;
+        }
+
+        let mut missed = 0;
+        for entry in self.entries.iter_mut().flatten() {
+            if is_reached(now_ms(), entry.due_ms.unwrap()) {
+                tx.write(&entry.frame).await;
+                missed += entry.advance(now_ms(), true);
+            }
+        }
+
+        missed
+    }
+}
+
+/// Error returned by [Scheduler::add] when all slots are in use.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct SchedulerFull;
+
+impl Entry {
+    /// Moves the deadline on after a transmission attempt, returning the deadlines
+    /// missed on the way.
+    fn advance(&mut self, now_ms: u32, sent: bool) -> u32 {
+        let due_ms = self.due_ms.unwrap();
+        let late_periods = now_ms.wrapping_sub(due_ms) / self.period_ms;
+        if !sent && late_periods == 0 {
+            // Retried on the next poll until the next deadline
+            return 0;
+        }
+
+        self.due_ms = Some(due_ms.wrapping_add((late_periods + sent as u32) * self.period_ms));
+        self.missed += late_periods;
+        late_periods
+    }
+}
+
+/// Whether `due_ms` has been reached at `now_ms`, across wrap-arounds.
+fn is_reached(now_ms: u32, due_ms: u32) -> bool {
+    now_ms.wrapping_sub(due_ms) as i32 >= 0
+}
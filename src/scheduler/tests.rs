@@ -0,0 +1,125 @@
+//! Host tests of the deadlines of cyclic frames, driven with explicit timestamps.
+
+use super::*;
+use embedded_can::StandardId;
+
+fn frame(id: u16) -> CanFrame {
+    CanFrame::new(StandardId::new(id).unwrap(), &[]).unwrap()
+}
+
+fn entry(period_ms: u32, due_ms: u32) -> Entry {
+    Entry {
+        frame: frame(0x100),
+        period_ms,
+        offset_ms: 0,
+        due_ms: Some(due_ms),
+        missed: 0,
+    }
+}
+
+/// Driver recording the identifiers sent, refusing frames while `busy`.
+#[derive(Default)]
+struct Bus {
+    sent: Vec<u16>,
+    busy: bool,
+}
+
+impl embedded_can::nb::Can for Bus {
+    type Frame = CanFrame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &CanFrame) -> nb::Result<Option<CanFrame>, CanError> {
+        if self.busy {
+            return Err(nb::Error::WouldBlock);
+        }
+        match frame.id() {
+            embedded_can::Id::Standard(id) => self.sent.push(id.as_raw()),
+            embedded_can::Id::Extended(_) => unreachable!(),
+        }
+
+        Ok(None)
+    }
+
+    fn receive(&mut self) -> nb::Result<CanFrame, CanError> {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+#[test]
+fn sent_frame_moves_to_next_period() {
+    let mut entry = entry(10, 100);
+    assert_eq!(entry.advance(100, true), 0);
+    assert_eq!(entry.due_ms, Some(110));
+
+    // Late within the period: the schedule doesn't drift
+    assert_eq!(entry.advance(119, true), 0);
+    assert_eq!(entry.due_ms, Some(120));
+    assert_eq!(entry.missed, 0);
+}
+
+#[test]
+fn late_frame_skips_missed_periods() {
+    let mut entry = entry(10, 100);
+    assert_eq!(entry.advance(125, true), 2);
+    assert_eq!(entry.due_ms, Some(130));
+    assert_eq!(entry.missed, 2);
+}
+
+#[test]
+fn unsent_frame_retried_until_next_deadline() {
+    let mut entry = entry(10, 100);
+    assert_eq!(entry.advance(105, false), 0);
+    assert_eq!(entry.due_ms, Some(100));
+
+    // Still not sent past the next deadline: it is missed, retried in this period
+    assert_eq!(entry.advance(121, false), 2);
+    assert_eq!(entry.due_ms, Some(120));
+    assert_eq!(entry.advance(122, true), 0);
+    assert_eq!(entry.due_ms, Some(130));
+    assert_eq!(entry.missed, 2);
+}
+
+#[test]
+fn deadlines_across_time_wrap_around() {
+    let mut entry = entry(10, u32::MAX - 5);
+    assert_eq!(entry.advance(4, true), 1);
+    assert_eq!(entry.due_ms, Some(14));
+    assert!(!is_reached(13, 14) && is_reached(14, 14));
+}
+
+#[test]
+fn periods_and_offsets() {
+    let mut scheduler = Scheduler::<2>::new();
+    let mut bus = Bus::default();
+    scheduler.add(frame(0x100), 10, 0).unwrap();
+    scheduler.add(frame(0x200), 20, 5).unwrap();
+    assert_eq!(scheduler.add(frame(0x300), 10, 0), Err(SchedulerFull));
+
+    for now_ms in 1000..1040 {
+        assert_eq!(scheduler.poll(&mut bus, now_ms), 0);
+    }
+    assert_eq!(bus.sent, [0x100, 0x200, 0x100, 0x100, 0x200, 0x100]);
+}
+
+#[test]
+fn busy_bus_misses_deadlines() {
+    let mut scheduler = Scheduler::<1>::new();
+    let mut bus = Bus::default();
+    let slot = scheduler.add(frame(0x100), 10, 0).unwrap();
+
+    assert_eq!(scheduler.poll(&mut bus, 0), 0);
+    bus.busy = true;
+    assert_eq!(scheduler.poll(&mut bus, 10), 0);
+    assert_eq!(scheduler.poll(&mut bus, 25), 1);
+    bus.busy = false;
+    assert_eq!(scheduler.poll(&mut bus, 26), 0);
+    assert_eq!(scheduler.poll(&mut bus, 29), 0);
+    assert_eq!(scheduler.poll(&mut bus, 30), 0);
+
+    assert_eq!(bus.sent.len(), 3);
+    assert_eq!(scheduler.missed_deadlines(slot), 1);
+
+    scheduler.remove(slot);
+    assert_eq!(scheduler.poll(&mut bus, 40), 0);
+    assert_eq!(bus.sent.len(), 3);
+}
@@ -0,0 +1,315 @@
+//! Frame authentication in the style of AUTOSAR SecOC: each payload is followed by
+//! the low bytes of a freshness counter and a truncated AES-128 CMAC, so receivers
+//! reject forged and replayed frames.
+//!
+//! The MAC covers the identifier (4 bytes, big-endian), the data and the full
+//! 32-bit counter (big-endian). Keys come from a [KeyStore], e.g. a table in flash
+//! or a secure element.
+
+use embedded_can::Id;
+
+use crate::frame::CanFrame;
+
+#[cfg(test)]
+mod tests;
+
+/// Source of the 128-bit keys, by key ID.
+pub trait KeyStore {
+    fn key(&mut self, key_id: u8) -> Option<[u8; 16]>;
+}
+
+/// A single key, used whatever the key ID.
+impl KeyStore for [u8; 16] {
+    fn key(&mut self, _key_id: u8) -> Option<[u8; 16]> {
+        Some(*self)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum SecOcError {
+    /// The data doesn't have the length of the channel
+    InvalidLength,
+    /// The key store has no key for the channel
+    UnknownKey,
+    /// The frame has another identifier or is too short
+    UnexpectedFrame,
+    /// The MAC doesn't match, the frame is forged, corrupted or replayed
+    Unauthentic,
+    /// The full counter sent didn't advance, the frame is replayed
+    Replayed,
+    /// The counter reached its maximum, ask for a new key
+    CounterExhausted,
+}
+
+/// Authenticated data of one identifier, in both directions.
+///
+/// Payloads are `data_len` bytes, followed by `freshness_len` bytes of the counter
+/// and `mac_len` bytes of the MAC, 8 bytes at most. Receivers accept a frame whose
+/// counter lies in the `2^(8 * freshness_len) - 1` values following the last one
+/// accepted, so too short a truncation fails after as many frames lost.
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct SecOcChannel {
+    id: Id,
+    key_id: u8,
+    data_len: usize,
+    freshness_len: usize,
+    mac_len: usize,
+    tx_counter: u32,
+    /// Last counter received
+    rx_counter: u32,
+}
+
+impl SecOcChannel {
+    /// Panics if the payload is longer than 8 bytes, or if `freshness_len` is above 4
+    /// or `mac_len` is 0.
+    pub fn new(
+        id: impl Into<Id>,
+        key_id: u8,
+        data_len: usize,
+        freshness_len: usize,
+        mac_len: usize,
+    ) -> Self {
+        if freshness_len > 4 || mac_len == 0 || data_len + freshness_len + mac_len > 8 {
+            fail!("Authenticated payloads must fit in 8 bytes.");
+        }
+
+        Self {
+            id: id.into(),
+            key_id,
+            data_len,
+            freshness_len,
+            mac_len,
+            tx_counter: 0,
+            rx_counter: 0,
+        }
+    }
+
+    /// Restores the last counters sent and received, e.g. persisted across resets,
+    /// so they never go back. Both start at 0.
+    pub fn set_counters(&mut self, tx_counter: u32, rx_counter: u32) {
+        self.tx_counter = tx_counter;
+        self.rx_counter = rx_counter;
+    }
+
+    pub fn counters(&self) -> (u32, u32) {
+        (self.tx_counter, self.rx_counter)
+    }
+
+    /// Frame carrying `data` with the next counter and its MAC.
+    pub fn secure(
+        &mut self,
+        keys: &mut impl KeyStore,
+        data: &[u8],
+    ) -> Result<CanFrame, SecOcError> {
+        if data.len() != self.data_len {
+            return Err(SecOcError::InvalidLength);
+        }
+        let key = keys.key(self.key_id).ok_or(SecOcError::UnknownKey)?;
+        let counter = self
+            .tx_counter
+            .checked_add(1)
+            .ok_or(SecOcError::CounterExhausted)?;
+
+        let mut payload = [0; 8];
+        payload[..self.data_len].copy_from_slice(data);
+        let freshness = &counter.to_be_bytes()[4 - self.freshness_len..];
+        payload[self.data_len..][..self.freshness_len].copy_from_slice(freshness);
+        let mac = self.mac(&key, data, counter);
+        payload[self.data_len + self.freshness_len..][..self.mac_len]
+            .copy_from_slice(&mac[..self.mac_len]);
+
+        self.tx_counter = counter;
+        Ok(CanFrame::new(self.id, &payload[..self.payload_len()]).unwrap())
+    }
+
+    /// Checks a received frame, returning its data if authentic and fresh.
+    pub fn verify<'f>(
+        &mut self,
+        keys: &mut impl KeyStore,
+        frame: &'f CanFrame,
+    ) -> Result<&'f [u8], SecOcError> {
+        if *frame.id() != self.id || frame.dlc() < self.payload_len() {
+            return Err(SecOcError::UnexpectedFrame);
+        }
+        let key = keys.key(self.key_id).ok_or(SecOcError::UnknownKey)?;
+
+        let (data, auth) = frame.data().split_at(self.data_len);
+        let (freshness, mac) = auth.split_at(self.freshness_len);
+        let counter = self
+            .reconstruct_counter(freshness)
+            .ok_or(SecOcError::Replayed)?;
+
+        // Compared in full so the time taken doesn't tell how many bytes match
+        let expected = self.mac(&key, data, counter);
+        let difference = expected[..self.mac_len]
+            .iter()
+            .zip(&mac[..self.mac_len])
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        if difference != 0 {
+            return Err(SecOcError::Unauthentic);
+        }
+
+        self.rx_counter = counter;
+        Ok(data)
+    }
+
+    fn payload_len(&self) -> usize {
+        self.data_len + self.freshness_len + self.mac_len
+    }
+
+    /// Smallest counter after the last one received ending with `freshness`.
+    fn reconstruct_counter(&self, freshness: &[u8]) -> Option<u32> {
+        let low = freshness
+            .iter()
+            .fold(0u64, |low, &byte| low << 8 | byte as u64);
+        let window = 1u64 << (8 * self.freshness_len);
+        let last = self.rx_counter as u64;
+
+        let mut counter = (last & !(window - 1)) | low;
+        if counter <= last {
+            counter += window;
+        }
+        u32::try_from(counter).ok()
+    }
+
+    fn mac(&self, key: &[u8; 16], data: &[u8], counter: u32) -> [u8; 16] {
+        let raw_id = match self.id {
+            Id::Standard(id) => id.as_raw() as u32,
+            Id::Extended(id) => id.as_raw(),
+        };
+
+        let mut message = [0; 16];
+        message[..4].copy_from_slice(&raw_id.to_be_bytes());
+        message[4..4 + data.len()].copy_from_slice(data);
+        message[4 + data.len()..8 + data.len()].copy_from_slice(&counter.to_be_bytes());
+
+        cmac(key, &message[..8 + data.len()])
+    }
+}
+
+/// AES-128 CMAC (RFC 4493) of `message`.
+pub fn cmac(key: &[u8; 16], message: &[u8]) -> [u8; 16] {
+    let round_keys = expand_key(key);
+    let double = |block: [u8; 16]| {
+        let value = u128::from_be_bytes(block);
+        let carry = (value >> 127) as u8;
+        ((value << 1) ^ (0x87 * carry as u128)).to_be_bytes()
+    };
+    let mut l = [0; 16];
+    encrypt(&round_keys, &mut l);
+    let k1 = double(l);
+    let k2 = double(k1);
+
+    let blocks = message.len().div_ceil(16).max(1);
+    let mut state = [0; 16];
+    for index in 0..blocks {
+        let chunk = &message[index * 16..message.len().min(index * 16 + 16)];
+        let mut block = [0; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+
+        if index == blocks - 1 {
+            let subkey = match chunk.len() {
+                16 => k1,
+                len => {
+                    block[len] = 0x80;
+                    k2
+                }
+            };
+            block
+                .iter_mut()
+                .zip(subkey)
+                .for_each(|(byte, key)| *byte ^= key);
+        }
+
+        state
+            .iter_mut()
+            .zip(block)
+            .for_each(|(byte, block)| *byte ^= block);
+        encrypt(&round_keys, &mut state);
+    }
+
+    state
+}
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+/// Round keys of AES-128.
+fn expand_key(key: &[u8; 16]) -> [[u8; 16]; 11] {
+    let mut round_keys = [[0; 16]; 11];
+    round_keys[0] = *key;
+    let mut rcon = 1u8;
+    for round in 1..11 {
+        let previous = round_keys[round - 1];
+        let mut word = [previous[13], previous[14], previous[15], previous[12]];
+        word.iter_mut()
+            .for_each(|byte| *byte = SBOX[*byte as usize]);
+        word[0] ^= rcon;
+        rcon = xtime(rcon);
+
+        for column in 0..4 {
+            for row in 0..4 {
+                word[row] ^= previous[column * 4 + row];
+                round_keys[round][column * 4 + row] = word[row];
+            }
+        }
+    }
+
+    round_keys
+}
+
+/// Encrypts one block in place with AES-128.
+fn encrypt(round_keys: &[[u8; 16]; 11], block: &mut [u8; 16]) {
+    let add_round_key = |block: &mut [u8; 16], round: usize| {
+        block
+            .iter_mut()
+            .zip(round_keys[round])
+            .for_each(|(byte, key)| *byte ^= key);
+    };
+
+    add_round_key(block, 0);
+    for round in 1..11 {
+        // Substitute bytes and shift rows, columns being 4 consecutive bytes
+        let state = *block;
+        for column in 0..4 {
+            for row in 0..4 {
+                block[column * 4 + row] = SBOX[state[(column + row) % 4 * 4 + row] as usize];
+            }
+        }
+
+        if round < 10 {
+            for column in block.chunks_mut(4) {
+                let [a, b, c, d] = [column[0], column[1], column[2], column[3]];
+                let all = a ^ b ^ c ^ d;
+                column[0] ^= all ^ xtime(a ^ b);
+                column[1] ^= all ^ xtime(b ^ c);
+                column[2] ^= all ^ xtime(c ^ d);
+                column[3] ^= all ^ xtime(d ^ a);
+            }
+        }
+        add_round_key(block, round);
+    }
+}
+
+/// Multiplication by x in GF(2^8).
+fn xtime(byte: u8) -> u8 {
+    byte << 1 ^ (0x1B * (byte >> 7))
+}
@@ -0,0 +1,192 @@
+//! Host tests of the CMAC against RFC 4493 and of the freshness checks.
+
+use super::*;
+
+use crate::embedded_can::StandardId;
+
+/// Key of the RFC 4493 examples.
+const RFC_KEY: [u8; 16] = hex("2b7e151628aed2a6abf7158809cf4f3c");
+/// Message of the RFC 4493 examples, truncated to 0, 16, 40 and 64 bytes.
+const RFC_MESSAGE: [u8; 64] = hex(concat!(
+    "6bc1bee22e409f96e93d7e117393172a",
+    "ae2d8a571e03ac9c9eb76fac45af8e51",
+    "30c81c46a35ce411e5fbc1191a0a52ef",
+    "f69f2445df4f9b17ad2b417be66c3710",
+));
+
+const fn hex<const N: usize>(digits: &str) -> [u8; N] {
+    const fn digit(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            _ => c - b'a' + 10,
+        }
+    }
+
+    let digits = digits.as_bytes();
+    let mut bytes = [0; N];
+    let mut i = 0;
+    while i < N {
+        bytes[i] = digit(digits[2 * i]) << 4 | digit(digits[2 * i + 1]);
+        i += 1;
+    }
+    bytes
+}
+
+#[test]
+fn aes_of_zero_block() {
+    let mut block = [0; 16];
+    encrypt(&expand_key(&RFC_KEY), &mut block);
+
+    assert_eq!(block, hex::<16>("7df76b0c1ab899b33e42f047b91b546f"));
+}
+
+#[test]
+fn cmac_rfc4493_empty_message() {
+    assert_eq!(
+        cmac(&RFC_KEY, &[]),
+        hex::<16>("bb1d6929e95937287fa37d129b756746")
+    );
+}
+
+#[test]
+fn cmac_rfc4493_one_block() {
+    assert_eq!(
+        cmac(&RFC_KEY, &RFC_MESSAGE[..16]),
+        hex::<16>("070a16b46b4d4144f79bdd9dd04a287c")
+    );
+}
+
+#[test]
+fn cmac_rfc4493_partial_block() {
+    assert_eq!(
+        cmac(&RFC_KEY, &RFC_MESSAGE[..40]),
+        hex::<16>("dfa66747de9ae63030ca32611497c827")
+    );
+}
+
+#[test]
+fn cmac_rfc4493_four_blocks() {
+    assert_eq!(
+        cmac(&RFC_KEY, &RFC_MESSAGE),
+        hex::<16>("51f0bebf7e3b9d92fc49741779363cfe")
+    );
+}
+
+/// Sender and receiver of 4 data bytes with 1 byte of counter and 3 of MAC.
+fn channels() -> (SecOcChannel, SecOcChannel) {
+    let id = StandardId::new(0x123).unwrap();
+    (
+        SecOcChannel::new(id, 0, 4, 1, 3),
+        SecOcChannel::new(id, 0, 4, 1, 3),
+    )
+}
+
+#[test]
+fn authentic_frame_accepted_once() {
+    let mut key = RFC_KEY;
+    let (mut sender, mut receiver) = channels();
+
+    let frame = sender.secure(&mut key, &[1, 2, 3, 4]).unwrap();
+    assert_eq!(frame.dlc(), 8);
+    assert_eq!(frame.data()[4], 1); // Low byte of the counter
+    assert_eq!(receiver.verify(&mut key, &frame), Ok(&[1, 2, 3, 4][..]));
+    assert_eq!(receiver.counters(), (0, 1));
+
+    // Replayed: the same low byte now stands for counter 257, whose MAC differs
+    assert_eq!(
+        receiver.verify(&mut key, &frame),
+        Err(SecOcError::Unauthentic)
+    );
+    assert_eq!(receiver.counters(), (0, 1));
+}
+
+#[test]
+fn forged_frame_rejected() {
+    let mut key = RFC_KEY;
+    let (mut sender, mut receiver) = channels();
+    let frame = sender.secure(&mut key, &[1, 2, 3, 4]).unwrap();
+
+    let mut data = [0; 8];
+    data.copy_from_slice(frame.data());
+    data[0] ^= 1;
+    let forged = CanFrame::new(*frame.id(), &data).unwrap();
+    assert_eq!(
+        receiver.verify(&mut key, &forged),
+        Err(SecOcError::Unauthentic)
+    );
+
+    let mut other_key = [0; 16];
+    assert_eq!(
+        receiver.verify(&mut other_key, &frame),
+        Err(SecOcError::Unauthentic)
+    );
+    assert_eq!(receiver.verify(&mut key, &frame), Ok(&[1, 2, 3, 4][..]));
+}
+
+#[test]
+fn counter_reconstructed_across_low_byte_wrap() {
+    let mut key = RFC_KEY;
+    let (mut sender, mut receiver) = channels();
+    sender.set_counters(0x1FD, 0);
+    receiver.set_counters(0, 0x1FD);
+
+    let frame = sender.secure(&mut key, &[5; 4]).unwrap(); // 0x1FE
+    assert!(receiver.verify(&mut key, &frame).is_ok());
+    sender.secure(&mut key, &[6; 4]).unwrap(); // 0x1FF lost
+    let frame = sender.secure(&mut key, &[7; 4]).unwrap(); // 0x200
+
+    assert_eq!(frame.data()[4], 0x00);
+    assert_eq!(receiver.verify(&mut key, &frame), Ok(&[7; 4][..]));
+    assert_eq!(receiver.counters(), (0, 0x200));
+}
+
+#[test]
+fn counter_reconstruction_window() {
+    let (_, mut receiver) = channels();
+    receiver.set_counters(0, 0x1_2345);
+
+    assert_eq!(receiver.reconstruct_counter(&[0x46]), Some(0x1_2346));
+    assert_eq!(receiver.reconstruct_counter(&[0x45]), Some(0x1_2445)); // Not the last one again
+    assert_eq!(receiver.reconstruct_counter(&[0x00]), Some(0x1_2400));
+
+    receiver.set_counters(0, u32::MAX - 1);
+    assert_eq!(receiver.reconstruct_counter(&[0xFF]), Some(u32::MAX));
+    assert_eq!(receiver.reconstruct_counter(&[0xFE]), None); // Past the counter range
+}
+
+#[test]
+fn exhausted_counter_reported() {
+    let mut key = RFC_KEY;
+    let (mut sender, mut receiver) = channels();
+    sender.set_counters(u32::MAX - 1, 0);
+    receiver.set_counters(0, u32::MAX - 1);
+
+    let frame = sender.secure(&mut key, &[0; 4]).unwrap();
+    assert!(receiver.verify(&mut key, &frame).is_ok());
+    assert_eq!(
+        sender.secure(&mut key, &[0; 4]).err(),
+        Some(SecOcError::CounterExhausted)
+    );
+    assert_eq!(receiver.verify(&mut key, &frame), Err(SecOcError::Replayed));
+}
+
+#[test]
+fn other_frames_rejected() {
+    let mut key = RFC_KEY;
+    let (mut sender, mut receiver) = channels();
+
+    assert_eq!(
+        sender.secure(&mut key, &[1, 2, 3]).err(),
+        Some(SecOcError::InvalidLength)
+    );
+    let short = CanFrame::new(StandardId::new(0x123).unwrap(), &[0; 7]).unwrap();
+    assert_eq!(
+        receiver.verify(&mut key, &short),
+        Err(SecOcError::UnexpectedFrame)
+    );
+    let other = CanFrame::new(StandardId::new(0x124).unwrap(), &[0; 8]).unwrap();
+    assert_eq!(
+        receiver.verify(&mut key, &other),
+        Err(SecOcError::UnexpectedFrame)
+    );
+}
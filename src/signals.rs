@@ -0,0 +1,276 @@
+//! Bit-level signals of frame payloads, laid out as in DBC files.
+
+use crate::frame::CanFrame;
+
+#[cfg(test)]
+mod tests;
+
+/// Order of the bytes of a signal spanning several bytes.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum ByteOrder {
+    /// Intel: least significant byte first, start bit is the least significant bit
+    LittleEndian,
+    /// Motorola: most significant byte first, start bit is the most significant bit
+    BigEndian,
+}
+
+/// Position and scaling of a signal within a payload of up to 8 bytes.
+///
+/// Bit `n` of the payload is bit `n % 8` of byte `n / 8`, as numbered by DBC
+/// files. The physical value is `raw * factor + offset`.
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct Signal {
+    pub start_bit: u8,
+    /// Number of bits, 1 to 64
+    pub length: u8,
+    pub byte_order: ByteOrder,
+    /// Raw value in two's complement
+    pub signed: bool,
+    pub factor: f32,
+    pub offset: f32,
+    /// Raw value of the message's multiplexor for which the signal is present,
+    /// `None` if always present
+    pub multiplex: Option<u64>,
+}
+
+impl Signal {
+    /// Unsigned signal with a factor of 1 and no offset.
+    pub const fn new(start_bit: u8, length: u8, byte_order: ByteOrder) -> Self {
+        if length == 0 || length > 64 {
+            fail!("Signals must be 1 to 64 bits long.");
+        }
+
+        Self {
+            start_bit,
+            length,
+            byte_order,
+            signed: false,
+            factor: 1.0,
+            offset: 0.0,
+            multiplex: None,
+        }
+    }
+
+    pub const fn signed(self) -> Self {
+        Self {
+            signed: true,
+            ..self
+        }
+    }
+
+    pub const fn with_scale(self, factor: f32, offset: f32) -> Self {
+        Self {
+            factor,
+            offset,
+            ..self
+        }
+    }
+
+    /// Signal only present when the multiplexor of the message, the signal selecting
+    /// which signals a payload carries, has the raw value `value`.
+    pub const fn multiplexed(self, value: u64) -> Self {
+        Self {
+            multiplex: Some(value),
+            ..self
+        }
+    }
+
+    /// Whether the signal is present in `data` according to its `multiplexor`.
+    pub fn is_present(&self, multiplexor: &Signal, data: &[u8]) -> bool {
+        match self.multiplex {
+            Some(value) => multiplexor.raw(data) == Some(value as i64),
+            None => true,
+        }
+    }
+
+    /// Physical value like [Signal::decode], or `None` if the signal is not present
+    /// according to `multiplexor`.
+    pub fn decode_multiplexed(&self, multiplexor: &Signal, data: &[u8]) -> Option<f32> {
+        match self.is_present(multiplexor, data) {
+            true => self.decode(data),
+            false => None,
+        }
+    }
+
+    /// Payload bit holding bit `index` of the raw value, 0 being its least
+    /// significant bit.
+    fn payload_bit(&self, index: u8) -> usize {
+        match self.byte_order {
+            ByteOrder::LittleEndian => self.start_bit as usize + index as usize,
+            ByteOrder::BigEndian => {
+                // Walk from the most significant bit, which is `start_bit`, going
+                // down each byte then on to the next one
+                let mut position = self.start_bit as usize;
+                for _ in 0..self.length - 1 - index {
+                    position = match position % 8 {
+                        0 => position + 15,
+                        _ => position - 1,
+                    };
+                }
+                position
+            }
+        }
+    }
+
+    /// Whether the signal lies within a payload of `len` bytes.
+    fn fits(&self, len: usize) -> bool {
+        (0..self.length).all(|index| self.payload_bit(index) < len * 8)
+    }
+
+    /// Raw value, sign-extended if signed, or `None` if `data` is too short.
+    pub fn raw(&self, data: &[u8]) -> Option<i64> {
+        if !self.fits(data.len()) {
+            return None;
+        }
+
+        let raw = (0..self.length).fold(0u64, |raw, index| {
+            let bit = self.payload_bit(index);
+            raw | ((data[bit / 8] >> (bit % 8)) as u64 & 1) << index
+        });
+
+        let unused = 64 - self.length as u32;
+        Some(match self.signed {
+            true => ((raw << unused) as i64) >> unused,
+            false => raw as i64,
+        })
+    }
+
+    /// Writes the raw value, truncated to the signal length. Panics if `data` is too
+    /// short.
+    pub fn set_raw(&self, data: &mut [u8], raw: i64) {
+        if !self.fits(data.len()) {
+            fail!("Signal doesn't fit in the payload.");
+        }
+
+        for index in 0..self.length {
+            let bit = self.payload_bit(index);
+            let mask = 1 << (bit % 8);
+            match (raw >> index) & 1 {
+                0 => data[bit / 8] &= !mask,
+                _ => data[bit / 8] |= mask,
+            }
+        }
+    }
+
+    /// Physical value, or `None` if `data` is too short.
+    pub fn decode(&self, data: &[u8]) -> Option<f32> {
+        Some(self.raw(data)? as f32 * self.factor + self.offset)
+    }
+
+    /// Physical value of a received frame, see [Signal::decode].
+    pub fn decode_frame(&self, frame: &CanFrame) -> Option<f32> {
+        self.decode(&frame.data()[..frame.dlc()])
+    }
+
+    /// Writes the physical value, rounded to the closest raw value and saturated to
+    /// the signal range. Panics if `data` is too short.
+    pub fn encode(&self, data: &mut [u8], value: f32) {
+        let scaled = (value - self.offset) / self.factor;
+        let rounded = match scaled >= 0.0 {
+            true => scaled + 0.5,
+            false => scaled - 0.5,
+        };
+
+        let unused = 64 - self.length as u32;
+        let (min, max) = match self.signed {
+            true => (i64::MIN >> unused, i64::MAX >> unused),
+            false => (0, (u64::MAX >> unused).min(i64::MAX as u64) as i64),
+        };
+        self.set_raw(data, (rounded as i64).clamp(min, max));
+    }
+}
+
+/// Generates message structs holding the physical values of their signals, with
+/// `encode` to a frame and `decode` from one.
+///
+/// Each message gives its identifier, `Standard` or `Extended`, and its length in
+/// bytes, then its fields with the [Signal] carrying them, in a constant expression.
+/// Multiplexed signals are decoded whatever the multiplexor, which stays up to the
+/// application.
+///
+/// ```ignore
+/// use ch32_can_rs::can_messages;
+/// use ch32_can_rs::signals::{ByteOrder, Signal};
+///
+/// can_messages! {
+///     /// Engine speed and coolant temperature
+///     #[derive(Debug, Copy, Clone)]
+///     pub struct EngineStatus = Standard(0x100), 8 {
+///         /// rpm
+///         pub speed: Signal::new(0, 16, ByteOrder::LittleEndian).with_scale(0.25, 0.0),
+///         /// °C
+///         pub coolant: Signal::new(16, 8, ByteOrder::LittleEndian).with_scale(1.0, -40.0),
+///     }
+/// }
+///
+/// let frame = EngineStatus { speed: 1500.0, coolant: 90.0 }.encode();
+/// let status = EngineStatus::decode(&frame).unwrap();
+/// ```
+#[macro_export]
+macro_rules! can_messages {
+    ($(
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident = $kind:ident($id:literal), $len:literal {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident : $signal:expr
+            ),* $(,)?
+        }
+    )*) => {$(
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: f32,
+            )*
+        }
+
+        impl $name {
+            pub const ID: $crate::embedded_can::Id = $crate::can_messages!(@id $kind $id);
+            /// Payload length in bytes
+            pub const LEN: usize = $len;
+
+            pub fn encode(&self) -> $crate::CanFrame {
+                let mut data = [0; $len];
+                $({
+                    const SIGNAL: $crate::signals::Signal = $signal;
+                    SIGNAL.encode(&mut data, self.$field);
+                })*
+
+                $crate::CanFrame::new(Self::ID, &data).unwrap()
+            }
+
+            /// Returns `None` if `frame` has another identifier or is too short.
+            pub fn decode(frame: &$crate::CanFrame) -> Option<Self> {
+                if *frame.id() != Self::ID || frame.dlc() < $len {
+                    return None;
+                }
+                let data = &frame.data()[..$len];
+
+                Some(Self {
+                    $($field: {
+                        const SIGNAL: $crate::signals::Signal = $signal;
+                        SIGNAL.decode(data)?
+                    },)*
+                })
+            }
+        }
+    )*};
+
+    // Only evaluated in `const ID`, so the messages are compile errors and never
+    // reach flash, with or without the `minimal` feature
+    (@id Standard $id:literal) => {
+        match $crate::embedded_can::StandardId::new($id) {
+            Some(id) => $crate::embedded_can::Id::Standard(id),
+            None => panic!("Standard identifiers are 11 bits long."),
+        }
+    };
+    (@id Extended $id:literal) => {
+        match $crate::embedded_can::ExtendedId::new($id) {
+            Some(id) => $crate::embedded_can::Id::Extended(id),
+            None => panic!("Extended identifiers are 29 bits long."),
+        }
+    };
+}
@@ -0,0 +1,159 @@
+//! Host tests of the signal layouts, against payloads laid out as by DBC tools.
+
+use super::*;
+
+use crate::embedded_can::StandardId;
+
+#[test]
+fn motorola_bits_walk_down_then_to_next_byte() {
+    // SG_ Speed : 3|12@0+, the 4 low bits of byte 0 then all of byte 1
+    let signal = Signal::new(3, 12, ByteOrder::BigEndian);
+
+    assert_eq!(signal.payload_bit(11), 3); // Most significant bit is the start bit
+    assert_eq!(signal.payload_bit(8), 0);
+    assert_eq!(signal.payload_bit(7), 15);
+    assert_eq!(signal.payload_bit(0), 8);
+}
+
+#[test]
+fn motorola_signal_across_bytes() {
+    // SG_ Speed : 3|12@0+
+    let signal = Signal::new(3, 12, ByteOrder::BigEndian);
+    let mut data = [0; 8];
+
+    signal.set_raw(&mut data, 0xABC);
+    assert_eq!(data[..2], [0x0A, 0xBC]);
+    assert_eq!(signal.raw(&data), Some(0xABC));
+
+    // SG_ Angle : 12|10@0+, bits 4 to 0 of byte 1 then bits 7 to 3 of byte 2
+    let signal = Signal::new(12, 10, ByteOrder::BigEndian);
+    let mut data = [0; 8];
+
+    signal.set_raw(&mut data, 0x3FF);
+    assert_eq!(data[..3], [0x00, 0x1F, 0xF8]);
+    signal.set_raw(&mut data, 0x201);
+    assert_eq!(data[..3], [0x00, 0x10, 0x08]);
+    assert_eq!(signal.raw(&data), Some(0x201));
+}
+
+#[test]
+fn motorola_signal_ending_past_payload() {
+    // SG_ Counter : 7|16@0+ needs two bytes
+    let signal = Signal::new(7, 16, ByteOrder::BigEndian);
+
+    assert_eq!(signal.raw(&[0x12, 0x34]), Some(0x1234));
+    assert_eq!(signal.raw(&[0x12]), None);
+}
+
+#[test]
+fn intel_signal_across_bytes() {
+    // SG_ Torque : 4|12@1-
+    let signal = Signal::new(4, 12, ByteOrder::LittleEndian).signed();
+    let mut data = [0; 8];
+
+    signal.set_raw(&mut data, 0xABC);
+    assert_eq!(data[..2], [0xC0, 0xAB]);
+    assert_eq!(signal.raw(&data), Some(0xABC - 0x1000)); // Sign-extended
+}
+
+#[test]
+fn signed_64_bit_signal() {
+    let signal = Signal::new(0, 64, ByteOrder::LittleEndian).signed();
+    let mut data = [0; 8];
+
+    signal.encode(&mut data, -1.0);
+    assert_eq!(data, [0xFF; 8]);
+    assert_eq!(signal.raw(&data), Some(-1));
+
+    signal.encode(&mut data, 1e30);
+    assert_eq!(signal.raw(&data), Some(i64::MAX));
+    signal.encode(&mut data, -1e30);
+    assert_eq!(signal.raw(&data), Some(i64::MIN));
+    assert_eq!(data, [0, 0, 0, 0, 0, 0, 0, 0x80]);
+
+    let signal = Signal::new(7, 64, ByteOrder::BigEndian).signed();
+    signal.set_raw(&mut data, 0x0102_0304_0506_0708);
+    assert_eq!(data, [1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn encode_saturates_to_signal_range() {
+    let unsigned = Signal::new(8, 8, ByteOrder::LittleEndian);
+    let signed = Signal::new(0, 12, ByteOrder::LittleEndian).signed();
+    let mut data = [0; 2];
+
+    unsigned.encode(&mut data, 300.0);
+    assert_eq!(unsigned.raw(&data), Some(255));
+    unsigned.encode(&mut data, -5.0);
+    assert_eq!(unsigned.raw(&data), Some(0));
+
+    signed.encode(&mut data, -5000.0);
+    assert_eq!(signed.raw(&data), Some(-2048));
+    signed.encode(&mut data, 5000.0);
+    assert_eq!(signed.raw(&data), Some(2047));
+    assert_eq!(data, [0xFF, 0x07]); // Neighbouring signal left alone
+}
+
+#[test]
+fn encode_rounds_scaled_values() {
+    // SG_ EngineSpeed : 24|16@1+ (0.125,0) "rpm"
+    let speed = Signal::new(24, 16, ByteOrder::LittleEndian).with_scale(0.125, 0.0);
+    // SG_ Coolant : 0|8@1+ (1,-40) "degC"
+    let coolant = Signal::new(0, 8, ByteOrder::LittleEndian).with_scale(1.0, -40.0);
+    let mut data = [0; 8];
+
+    speed.encode(&mut data, 1500.06);
+    coolant.encode(&mut data, -40.4);
+    assert_eq!(data[..5], [0x00, 0x00, 0x00, 0xE0, 0x2E]);
+    assert_eq!(speed.decode(&data), Some(1500.0));
+    assert_eq!(coolant.decode(&data), Some(-40.0));
+}
+
+#[test]
+fn multiplexed_signals_follow_multiplexor() {
+    // SG_ Page M : 0|8@1+, SG_ Voltage m1 : 8|16@1+, SG_ Current m2 : 8|16@1-
+    let page = Signal::new(0, 8, ByteOrder::LittleEndian);
+    let voltage = Signal::new(8, 16, ByteOrder::LittleEndian)
+        .with_scale(0.01, 0.0)
+        .multiplexed(1);
+    let current = Signal::new(8, 16, ByteOrder::LittleEndian)
+        .signed()
+        .with_scale(0.1, 0.0)
+        .multiplexed(2);
+
+    let data = [1, 0xD0, 0x07];
+    assert!(voltage.is_present(&page, &data));
+    assert!(!current.is_present(&page, &data));
+    assert_eq!(voltage.decode_multiplexed(&page, &data), Some(20.0));
+    assert_eq!(current.decode_multiplexed(&page, &data), None);
+
+    let data = [2, 0x9C, 0xFF];
+    assert_eq!(voltage.decode_multiplexed(&page, &data), None);
+    assert_eq!(current.decode_multiplexed(&page, &data), Some(-10.0));
+    assert!(page.is_present(&page, &data)); // Not multiplexed itself
+}
+
+crate::can_messages! {
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    struct EngineStatus = Standard(0x0CF), 4 {
+        speed: Signal::new(7, 16, ByteOrder::BigEndian).with_scale(0.25, 0.0),
+        coolant: Signal::new(16, 8, ByteOrder::LittleEndian).with_scale(1.0, -40.0),
+    }
+}
+
+#[test]
+fn generated_message_round_trip() {
+    let status = EngineStatus {
+        speed: 1500.0,
+        coolant: 90.0,
+    };
+
+    let frame = status.encode();
+    assert_eq!(*frame.id(), StandardId::new(0x0CF).unwrap().into());
+    assert_eq!(frame.dlc(), EngineStatus::LEN);
+    assert_eq!(frame.data()[..4], [0x17, 0x70, 130, 0]);
+    assert_eq!(EngineStatus::decode(&frame), Some(status));
+
+    let short = CanFrame::new(EngineStatus::ID, &[0x17, 0x70]).unwrap();
+    assert_eq!(EngineStatus::decode(&short), None);
+}
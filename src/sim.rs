@@ -0,0 +1,385 @@
+//! Simulated bus with arbitration and bus errors, to test protocol code end to end
+//! on the host.
+//!
+//! Unlike [crate::mock::MockBus], frames wait in the transmit mailboxes of their
+//! node until the bus is stepped, then go out one at a time by arbitration priority,
+//! each acknowledged by the other nodes or failing. Error counters follow ISO
+//! 11898-1, so injected errors drive nodes through error passive to bus-off as on a
+//! real bus.
+//!
+//! Protocol halves are stepped in turn from a single thread, e.g. transmitting
+//! from the [crate::isotp::Sender] of one node, [SimBus::run], then feeding the
+//! [crate::isotp::Receiver] of another with what it received.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+use crate::deferred::Deferred;
+use crate::enums::{BusState, CanError, CanErrorKind, CanFilter, NoFreeFilter, TX_MAILBOXES};
+use crate::frame::CanFrame;
+use crate::interface::CanInterface;
+use crate::mock::Filters;
+
+#[cfg(all(test, feature = "isotp", feature = "j1939", feature = "canopen"))]
+mod tests;
+
+/// Error counter level from which a node is error passive.
+const ERROR_PASSIVE_LIMIT: u16 = 128;
+/// Transmit error counter level past which a node goes bus-off.
+const BUS_OFF_LIMIT: u16 = 255;
+
+struct SimNode<const DEPTH: usize> {
+    attached: bool,
+    tx: [Option<CanFrame>; TX_MAILBOXES],
+    rx: Deferred<DEPTH>,
+    overrun: bool,
+    /// Bus error reported by the next receive
+    error: Option<CanErrorKind>,
+    filters: Filters,
+    tec: u16,
+    rec: u16,
+    bus_off: bool,
+}
+
+impl<const DEPTH: usize> SimNode<DEPTH> {
+    const fn new() -> Self {
+        Self {
+            attached: false,
+            tx: [None; TX_MAILBOXES],
+            rx: Deferred::new(),
+            overrun: false,
+            error: None,
+            filters: Filters::new(),
+            tec: 0,
+            rec: 0,
+            bus_off: false,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.attached && !self.bus_off
+    }
+
+    /// Mailbox holding the pending frame with the highest priority.
+    fn next_mailbox(&self) -> Option<usize> {
+        (0..TX_MAILBOXES)
+            .filter(|&mailbox| self.tx[mailbox].is_some())
+            .min_by_key(|&mailbox| self.tx[mailbox].unwrap().arbitration_key())
+    }
+
+    fn bus_state(&self) -> BusState {
+        if self.bus_off {
+            BusState::BusOff
+        } else if self.tec >= ERROR_PASSIVE_LIMIT || self.rec >= ERROR_PASSIVE_LIMIT {
+            BusState::ErrorPassive
+        } else {
+            BusState::ErrorActive
+        }
+    }
+
+    fn transmit_failed(&mut self, error: CanErrorKind) {
+        // An error passive transmitter not acknowledged is alone on the bus, and
+        // keeps its counter to go on retrying
+        if error != CanErrorKind::Acknowledge || self.tec < ERROR_PASSIVE_LIMIT {
+            self.tec += 8;
+        }
+        if self.tec > BUS_OFF_LIMIT {
+            self.bus_off = true;
+            self.tx = [None; TX_MAILBOXES];
+            self.error = Some(CanErrorKind::BusOff);
+        } else {
+            self.error = Some(error);
+        }
+    }
+
+    fn receive_failed(&mut self, error: CanErrorKind) {
+        self.rec = (self.rec + 1).min(ERROR_PASSIVE_LIMIT + 8);
+        self.error = Some(error);
+    }
+
+    fn received(&mut self, frame: &CanFrame) {
+        self.rec = match self.rec {
+            0 => 0,
+            1..ERROR_PASSIVE_LIMIT => self.rec - 1,
+            _ => 120, // Back to error active, from 119 to 127 by the standard
+        };
+        if self.filters.accepts(frame) && self.rx.push(*frame).is_err() {
+            self.overrun = true;
+        }
+    }
+}
+
+struct SimState<const NODES: usize, const DEPTH: usize> {
+    nodes: [SimNode<DEPTH>; NODES],
+    /// Error the next frames fail with, and how many are left to fail
+    injected: Option<(CanErrorKind, u32)>,
+}
+
+/// One frame on a [SimBus], as returned by [SimBus::step].
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct Transfer {
+    /// Index of the sending node, see [SimCan::index]
+    pub node: usize,
+    pub frame: CanFrame,
+    /// On error the frame stays pending and is sent again on the next step, unless
+    /// its node went bus-off.
+    pub result: Result<(), CanErrorKind>,
+}
+
+/// Bus shared by up to `NODES` [SimCan] nodes, each buffering up to `DEPTH`
+/// received frames.
+pub struct SimBus<const NODES: usize, const DEPTH: usize> {
+    state: Mutex<RefCell<SimState<NODES, DEPTH>>>,
+}
+
+impl<const NODES: usize, const DEPTH: usize> SimBus<NODES, DEPTH> {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(SimState {
+                nodes: [const { SimNode::new() }; NODES],
+                injected: None,
+            })),
+        }
+    }
+
+    /// Attaches a new node, or returns `None` if all `NODES` are attached.
+    pub fn node(&self) -> Option<SimCan<'_, NODES, DEPTH>> {
+        self.with_state(|state| {
+            let index = state.nodes.iter().position(|node| !node.attached)?;
+            state.nodes[index].attached = true;
+
+            Some(SimCan { bus: self, index })
+        })
+    }
+
+    /// Makes the next `frames` transfers fail with `error`, e.g. [CanErrorKind::Stuff].
+    ///
+    /// [CanErrorKind::Acknowledge] only affects the sender, the other errors are also
+    /// counted by every node receiving.
+    pub fn inject_error(&self, error: CanErrorKind, frames: u32) {
+        self.with_state(|state| state.injected = (frames > 0).then_some((error, frames)));
+    }
+
+    /// Sends the pending frame winning arbitration among all nodes, or returns
+    /// `None` if no node has a frame pending.
+    ///
+    /// Frames fail with [CanErrorKind::Acknowledge] when no other node is on the bus to
+    /// acknowledge them, and with [CanErrorKind::Bit] when two nodes send different
+    /// frames with the same identifier.
+    pub fn step(&self) -> Option<Transfer> {
+        self.with_state(|state| {
+            let (node, mailbox, frame) = state
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, node)| node.is_active())
+                .filter_map(|(index, node)| {
+                    let mailbox = node.next_mailbox()?;
+                    Some((index, mailbox, node.tx[mailbox].unwrap()))
+                })
+                .min_by_key(|(_, _, frame)| frame.arbitration_key())?;
+
+            let collision = state.nodes.iter().enumerate().find_map(|(index, other)| {
+                let pending = other.tx[other.next_mailbox()?].unwrap();
+                let collides = index != node
+                    && other.is_active()
+                    && pending.arbitration_key() == frame.arbitration_key()
+                    && !pending.same_content(&frame);
+                collides.then_some(index)
+            });
+            let receivers = state
+                .nodes
+                .iter()
+                .enumerate()
+                .any(|(index, other)| index != node && other.is_active());
+
+            let error = match state.injected {
+                Some((error, left)) => {
+                    state.injected = (left > 1).then_some((error, left - 1));
+                    Some(error)
+                }
+                None if collision.is_some() => Some(CanErrorKind::Bit),
+                None if !receivers => Some(CanErrorKind::Acknowledge),
+                None => None,
+            };
+
+            let result = match error {
+                Some(error) => {
+                    state.nodes[node].transmit_failed(error);
+                    if let Some(other) = collision {
+                        state.nodes[other].transmit_failed(error);
+                    }
+                    if error != CanErrorKind::Acknowledge {
+                        for (index, other) in state.nodes.iter_mut().enumerate() {
+                            if index != node && Some(index) != collision && other.is_active() {
+                                other.receive_failed(error);
+                            }
+                        }
+                    }
+                    Err(error)
+                }
+                None => {
+                    let sender = &mut state.nodes[node];
+                    sender.tx[mailbox] = None;
+                    sender.tec = sender.tec.saturating_sub(1);
+                    for (index, other) in state.nodes.iter_mut().enumerate() {
+                        if index != node && other.is_active() {
+                            other.received(&frame);
+                        }
+                    }
+                    Ok(())
+                }
+            };
+
+            Some(Transfer {
+                node,
+                frame,
+                result,
+            })
+        })
+    }
+
+    /// Steps the bus until no frame is pending or `max_transfers` were attempted,
+    /// returning the number of transfers attempted.
+    pub fn run(&self, max_transfers: usize) -> usize {
+        (0..max_transfers)
+            .take_while(|_| self.step().is_some())
+            .count()
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut SimState<NODES, DEPTH>) -> R) -> R {
+        critical_section::with(|cs| f(&mut self.state.borrow_ref_mut(cs)))
+    }
+}
+
+impl<const NODES: usize, const DEPTH: usize> Default for SimBus<NODES, DEPTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Node of a [SimBus], with the same transmit, receive and filter methods as
+/// [crate::Can].
+pub struct SimCan<'a, const NODES: usize, const DEPTH: usize> {
+    bus: &'a SimBus<NODES, DEPTH>,
+    index: usize,
+}
+
+impl<'a, const NODES: usize, const DEPTH: usize> SimCan<'a, NODES, DEPTH> {
+    /// Index of the node on the bus, as in [Transfer::node].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Puts a frame in a free transmit mailbox, to be sent on the next steps of the
+    /// bus.
+    ///
+    /// If all mailboxes are full, replaces the pending frame with the lowest
+    /// priority if it is lower than that of `frame`, and returns it.
+    pub fn transmit(&self, frame: &CanFrame) -> nb::Result<Option<CanFrame>, CanError> {
+        self.with_node(|node| {
+            if node.bus_off {
+                return Err(nb::Error::Other(CanErrorKind::BusOff.into()));
+            }
+            if let Some(mailbox) = node.tx.iter().position(Option::is_none) {
+                node.tx[mailbox] = Some(*frame);
+                return Ok(None);
+            }
+
+            let lowest = (0..TX_MAILBOXES)
+                .max_by_key(|&mailbox| node.tx[mailbox].unwrap().arbitration_key())
+                .unwrap();
+            if node.tx[lowest].unwrap().arbitration_key() <= frame.arbitration_key() {
+                return Err(nb::Error::WouldBlock);
+            }
+
+            Ok(node.tx[lowest].replace(*frame))
+        })
+    }
+
+    /// Returns a received frame if available. A bus error or overrun seen since the
+    /// last call is returned first, once.
+    pub fn receive(&self) -> nb::Result<CanFrame, CanError> {
+        self.with_node(|node| {
+            if let Some(error) = node.error.take() {
+                return Err(nb::Error::Other(error.into()));
+            }
+            if node.overrun {
+                node.overrun = false;
+                return Err(nb::Error::Other(CanErrorKind::Overrun.into()));
+            }
+
+            node.rx.pop().ok_or(nb::Error::WouldBlock)
+        })
+    }
+
+    pub fn add_filter(&self, filter: CanFilter) {
+        self.with_node(|node| node.filters.set(filter));
+    }
+
+    /// Transmit and receive error counters.
+    pub fn error_counters(&self) -> (u16, u16) {
+        self.with_node(|node| (node.tec, node.rec))
+    }
+
+    /// Whether all transmit mailboxes are empty.
+    pub fn is_idle(&self) -> bool {
+        self.with_node(|node| node.tx.iter().all(Option::is_none))
+    }
+
+    /// Clears the error counters and leaves bus-off, as the controller does after
+    /// reinitialization once the bus has been idle long enough.
+    pub fn recover(&self) {
+        self.with_node(|node| {
+            node.tec = 0;
+            node.rec = 0;
+            node.bus_off = false;
+        });
+    }
+
+    fn with_node<R>(&self, f: impl FnOnce(&mut SimNode<DEPTH>) -> R) -> R {
+        self.bus.with_state(|state| f(&mut state.nodes[self.index]))
+    }
+}
+
+impl<'a, const NODES: usize, const DEPTH: usize> Drop for SimCan<'a, NODES, DEPTH> {
+    fn drop(&mut self) {
+        self.with_node(|node| *node = SimNode::new());
+    }
+}
+
+impl<'a, const NODES: usize, const DEPTH: usize> embedded_can::nb::Can
+    for SimCan<'a, NODES, DEPTH>
+{
+    type Frame = CanFrame;
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        SimCan::transmit(self, frame)
+    }
+
+    fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+        SimCan::receive(self)
+    }
+}
+
+impl<'a, const NODES: usize, const DEPTH: usize> CanInterface for SimCan<'a, NODES, DEPTH> {
+    fn add_id_filter(&mut self, id: embedded_can::Id, mask: u32) -> Result<(), NoFreeFilter> {
+        let bank = self
+            .with_node(|node| node.filters.free_bank())
+            .ok_or(NoFreeFilter)?;
+        self.add_filter(CanFilter::matching(bank, id, mask));
+
+        Ok(())
+    }
+
+    fn clear_filters(&mut self) {
+        self.with_node(|node| node.filters = Filters::new());
+    }
+
+    fn bus_state(&self) -> BusState {
+        self.with_node(|node| node.bus_state())
+    }
+}
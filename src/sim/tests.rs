@@ -0,0 +1,199 @@
+//! Host tests of the simulated bus, with protocol code on its nodes.
+
+use super::*;
+use crate::canopen::{HeartbeatConsumer, NmtCommand, NmtEvent, NmtSlave, NmtState};
+use crate::isotp::{IsoTpConfig, ReceiveStep, Receiver, SendStep, Sender};
+use crate::j1939::{
+    TransportReceive, TransportReceiver, TransportSender, TransportStep, MAX_TRANSPORT_LEN,
+};
+use embedded_can::StandardId;
+
+type Bus = SimBus<3, 8>;
+
+/// Attaches a node receiving every frame.
+fn attach(bus: &Bus) -> SimCan<'_, 3, 8> {
+    let node = bus.node().unwrap();
+    node.add_filter(CanFilter::accept_all());
+
+    node
+}
+
+/// Payload of `len` bytes that differ from one frame to the next.
+fn payload<const N: usize>(len: usize) -> [u8; N] {
+    let mut data = [0; N];
+    for (i, byte) in data[..len].iter_mut().enumerate() {
+        *byte = (i * 7 + i / 7) as u8;
+    }
+
+    data
+}
+
+/// Sends one ISO-TP message from `a` to `b`, returning the length received.
+fn isotp_transfer(
+    bus: &Bus,
+    a: &SimCan<'_, 3, 8>,
+    b: &SimCan<'_, 3, 8>,
+    data: &[u8],
+    buf: &mut [u8],
+) -> usize {
+    let tx = StandardId::new(0x7E0).unwrap();
+    let rx = StandardId::new(0x7E8).unwrap();
+    let mut sender = Sender::new(IsoTpConfig::new(tx, rx), data).unwrap();
+    let mut receiver = Receiver::new(
+        IsoTpConfig {
+            block_size: 4,
+            ..IsoTpConfig::new(rx, tx)
+        },
+        buf,
+    );
+
+    let mut received = None;
+    loop {
+        match sender.next_step() {
+            SendStep::Send(frame) => assert!(a.transmit(&frame).unwrap().is_none()),
+            SendStep::AwaitFlowControl => {}
+            SendStep::Done => return received.unwrap(),
+        }
+        bus.run(16);
+        while let Ok(frame) = b.receive() {
+            match receiver.on_frame(&frame).unwrap() {
+                ReceiveStep::Pending => {}
+                ReceiveStep::FlowControl(reply) => assert!(b.transmit(&reply).unwrap().is_none()),
+                ReceiveStep::Complete(len) => received = Some(len),
+            }
+        }
+        bus.run(16);
+        while let Ok(frame) = a.receive() {
+            sender.on_flow_control(&frame).unwrap();
+        }
+    }
+}
+
+#[test]
+fn isotp_message_in_blocks() {
+    let bus = Bus::new();
+    let (a, b) = (attach(&bus), attach(&bus));
+    let data = payload::<100>(100);
+    let mut buf = [0; 128];
+
+    assert_eq!(isotp_transfer(&bus, &a, &b, &data, &mut buf), 100);
+    assert_eq!(buf[..100], data);
+    assert!(a.is_idle() && b.is_idle());
+    assert_eq!(a.error_counters(), (0, 0));
+}
+
+#[test]
+fn j1939_connection_transfer() {
+    const PGN: u32 = 0xFEF1;
+
+    let bus = Bus::new();
+    let (a, b) = (attach(&bus), attach(&bus));
+    let data = payload::<MAX_TRANSPORT_LEN>(100);
+    let mut sender = TransportSender::new(PGN, 0x20, 0x30, &data[..100]).unwrap();
+    let mut receiver = TransportReceiver::<128>::new(0x30);
+
+    let mut complete = false;
+    loop {
+        match sender.next_step() {
+            TransportStep::Send(frame) => assert!(a.transmit(&frame).unwrap().is_none()),
+            TransportStep::AwaitResponse => {}
+            TransportStep::Done => break,
+        }
+        bus.run(16);
+        while let Ok(frame) = b.receive() {
+            match receiver.on_frame(&frame).unwrap() {
+                TransportReceive::Pending => {}
+                TransportReceive::Reply(reply) => assert!(b.transmit(&reply).unwrap().is_none()),
+                TransportReceive::Complete { pgn, source, ack } => {
+                    assert_eq!((pgn, source), (PGN, 0x20));
+                    assert!(b.transmit(&ack.unwrap()).unwrap().is_none());
+                    complete = true;
+                }
+            }
+        }
+        bus.run(16);
+        while let Ok(frame) = a.receive() {
+            sender.on_frame(&frame).unwrap();
+        }
+    }
+
+    assert!(complete);
+    assert_eq!(receiver.data(), &data[..100]);
+}
+
+#[test]
+fn nmt_start_and_heartbeat_timeout() {
+    let bus = Bus::new();
+    let (slave_node, master) = (attach(&bus), attach(&bus));
+    let mut slave = NmtSlave::new(5, 100);
+    let mut consumer = HeartbeatConsumer::new([(5, 250)]);
+
+    assert!(slave_node.transmit(&slave.boot_up(0)).unwrap().is_none());
+    assert!(master
+        .transmit(&NmtCommand::Start.frame(5))
+        .unwrap()
+        .is_none());
+    assert_eq!(bus.run(16), 2);
+
+    let boot_up = master.receive().unwrap();
+    assert_eq!(
+        consumer.on_frame(&boot_up, 0),
+        Some((5, NmtState::Initialising))
+    );
+    let command = slave_node.receive().unwrap();
+    assert_eq!(
+        slave.on_frame(&command),
+        Some(NmtEvent::StateChanged(NmtState::Operational))
+    );
+
+    assert!(slave.poll(50).is_none());
+    assert!(slave_node
+        .transmit(&slave.poll(100).unwrap())
+        .unwrap()
+        .is_none());
+    bus.run(16);
+    let heartbeat = master.receive().unwrap();
+    assert_eq!(
+        consumer.on_frame(&heartbeat, 100),
+        Some((5, NmtState::Operational))
+    );
+
+    // The slave node leaves the bus, its heartbeat stops
+    drop(slave_node);
+    assert_eq!(consumer.poll(350), None);
+    assert_eq!(consumer.poll(351), Some(5));
+    assert_eq!(consumer.state(5), None);
+}
+
+#[test]
+fn injected_errors_retried() {
+    let bus = Bus::new();
+    let (a, b) = (attach(&bus), attach(&bus));
+    let mut slave = NmtSlave::new(5, 100);
+    let mut consumer = HeartbeatConsumer::new([(5, 250)]);
+
+    bus.inject_error(CanErrorKind::Stuff, 2);
+    assert!(a.transmit(&slave.boot_up(0)).unwrap().is_none());
+    for _ in 0..2 {
+        assert_eq!(bus.step().unwrap().result, Err(CanErrorKind::Stuff));
+    }
+    let transfer = bus.step().unwrap();
+    assert_eq!((transfer.node, transfer.result), (a.index(), Ok(())));
+    assert!(bus.step().is_none());
+
+    // Counted by both nodes, the transfer succeeding taking one back
+    assert_eq!(a.error_counters(), (15, 0));
+    assert_eq!(b.error_counters(), (0, 1));
+
+    // The error is reported once before the frame
+    assert!(matches!(
+        b.receive(),
+        Err(nb::Error::Other(error)) if error.kind == CanErrorKind::Stuff
+    ));
+    let boot_up = b.receive().unwrap();
+    assert_eq!(
+        consumer.on_frame(&boot_up, 0),
+        Some((5, NmtState::Initialising))
+    );
+    assert!(matches!(b.receive(), Err(nb::Error::WouldBlock)));
+}
@@ -0,0 +1,277 @@
+//! SLCAN (Lawicel) ASCII protocol, turning a board with a UART into a serial CAN
+//! adapter for `slcand` and the tools built on it.
+//!
+//! [parse] and [write_frame] handle the protocol alone, [SlcanBridge] ties it to the
+//! driver over any [embedded_hal_nb::serial] UART.
+
+use embedded_can::{ExtendedId, Frame, Id, StandardId};
+#[cfg(feature = "_hal")]
+use embedded_hal_nb::serial::{Read, Write};
+
+#[cfg(feature = "_hal")]
+use crate::can::{Can, Instance};
+use crate::enums::{Bitrate, CanMode};
+#[cfg(feature = "_hal")]
+use crate::enums::{BusState, CanConfig};
+use crate::frame::CanFrame;
+
+#[cfg(test)]
+mod tests;
+
+/// Longest line exchanged, an extended frame with 8 bytes and a timestamp.
+pub const MAX_LINE_LEN: usize = 31;
+
+const OK: u8 = b'\r';
+const ERROR: u8 = 0x07;
+
+/// Bitrates of the `S0` to `S8` commands.
+const BITRATES: [u32; 9] = [
+    10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000,
+];
+
+/// Command sent by the host.
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum Command {
+    /// `O` joins the bus, `L` joins it without acknowledging nor transmitting
+    Open(CanMode),
+    /// `C` leaves the bus
+    Close,
+    /// `Sn` selects one of the standard bitrates
+    SetBitrate(Bitrate),
+    /// `tiiil...` or `Tiiiiiiiil...` sends a frame, `riiil` or `Riiiiiiiil` a remote
+    /// frame
+    Transmit(CanFrame),
+    /// `V` asks for the hardware and software versions
+    Version,
+    /// `N` asks for the serial number
+    SerialNumber,
+    /// `F` asks for the status flags
+    Status,
+    /// `Zn` appends a timestamp to the received frames, or not
+    Timestamps(bool),
+}
+
+/// Decodes a command line, without its trailing carriage return.
+pub fn parse(line: &[u8]) -> Option<Command> {
+    let (&command, args) = line.split_first()?;
+
+    match (command, args) {
+        (b'O', []) => Some(Command::Open(CanMode::Normal)),
+        (b'L', []) => Some(Command::Open(CanMode::Silent)),
+        (b'C', []) => Some(Command::Close),
+        (b'S', &[n]) => {
+            let bps = *BITRATES.get(n.checked_sub(b'0')? as usize)?;
+            Some(Command::SetBitrate(Bitrate::from(bps)))
+        }
+        (b't', _) => parse_frame(args, 3, false).map(Command::Transmit),
+        (b'T', _) => parse_frame(args, 8, false).map(Command::Transmit),
+        (b'r', _) => parse_frame(args, 3, true).map(Command::Transmit),
+        (b'R', _) => parse_frame(args, 8, true).map(Command::Transmit),
+        (b'V', []) => Some(Command::Version),
+        (b'N', []) => Some(Command::SerialNumber),
+        (b'F', []) => Some(Command::Status),
+        (b'Z', [b'0']) => Some(Command::Timestamps(false)),
+        (b'Z', [b'1']) => Some(Command::Timestamps(true)),
+        _ => None,
+    }
+}
+
+fn parse_frame(args: &[u8], id_digits: usize, remote: bool) -> Option<CanFrame> {
+    let raw_id = parse_hex(args.get(..id_digits)?)?;
+    let id: Id = match id_digits {
+        3 => StandardId::new(raw_id as u16)?.into(),
+        _ => ExtendedId::new(raw_id)?.into(),
+    };
+
+    let dlc = parse_hex(args.get(id_digits..=id_digits)?)? as usize;
+    let hex = &args[id_digits + 1..];
+    if remote {
+        return match hex.is_empty() {
+            true => CanFrame::new_remote(id, dlc),
+            false => None,
+        };
+    }
+    if dlc > 8 || hex.len() != dlc * 2 {
+        return None;
+    }
+
+    let mut data = [0; 8];
+    for (byte, digits) in data.iter_mut().zip(hex.chunks(2)) {
+        *byte = parse_hex(digits)? as u8;
+    }
+
+    CanFrame::new(id, &data[..dlc])
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u32> {
+    digits.iter().try_fold(0, |value, &digit| {
+        Some(value << 4 | (digit as char).to_digit(16)?)
+    })
+}
+
+/// Encodes a received frame into `line`, carriage return included, and returns its
+/// length. `timestamp_ms` is appended modulo 60 s when given.
+pub fn write_frame(
+    frame: &CanFrame,
+    timestamp_ms: Option<u32>,
+    line: &mut [u8; MAX_LINE_LEN],
+) -> usize {
+    let mut len = 1;
+    let mut push_hex = |value: u32, digits: usize| {
+        for shift in (0..digits).rev() {
+            line[len] = b"0123456789ABCDEF"[(value >> (shift * 4)) as usize & 0xF];
+            len += 1;
+        }
+    };
+
+    match *frame.id() {
+        Id::Standard(id) => push_hex(id.as_raw() as u32, 3),
+        Id::Extended(id) => push_hex(id.as_raw(), 8),
+    }
+    push_hex(frame.dlc() as u32, 1);
+    for &byte in Frame::data(frame) {
+        push_hex(byte as u32, 2);
+    }
+    if let Some(timestamp) = timestamp_ms {
+        push_hex(timestamp % 60_000, 4);
+    }
+
+    line[0] = match (frame.id(), frame.is_remote_frame()) {
+        (Id::Standard(_), false) => b't',
+        (Id::Extended(_), false) => b'T',
+        (Id::Standard(_), true) => b'r',
+        (Id::Extended(_), true) => b'R',
+    };
+    line[len] = OK;
+    len + 1
+}
+
+/// SLCAN adapter bridging a UART to the driver.
+///
+/// Frames are forwarded from the receive FIFO while the channel is open, so
+/// interrupts must not be enabled on the driver. Timestamps are those of the
+/// driver's time source, see [Can::set_time_source], expected to count ms.
+#[cfg(feature = "_hal")]
+pub struct SlcanBridge<'d, T: Instance, S> {
+    can: Can<'d, T>,
+    serial: S,
+    line: [u8; MAX_LINE_LEN],
+    line_len: usize,
+    bitrate: Bitrate,
+    open: Option<CanMode>,
+    timestamps: bool,
+}
+
+#[cfg(feature = "_hal")]
+impl<'d, T: Instance, S: Read + Write> SlcanBridge<'d, T, S> {
+    /// Takes over `can` closed, its bitrate used until the host selects another one.
+    pub fn new(can: Can<'d, T>, serial: S, bitrate: impl Into<Bitrate>) -> Self {
+        can.sleep();
+
+        Self {
+            can,
+            serial,
+            line: [0; MAX_LINE_LEN],
+            line_len: 0,
+            bitrate: bitrate.into(),
+            open: None,
+            timestamps: false,
+        }
+    }
+
+    pub fn release(self) -> (Can<'d, T>, S) {
+        (self.can, self.serial)
+    }
+
+    /// Handles the commands received from the host and forwards the received frames
+    /// to it. Call it in a loop.
+    pub fn poll(&mut self) -> Result<(), S::Error> {
+        loop {
+            let byte = match self.serial.read() {
+                Ok(byte) => byte,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(error)) => return Err(error),
+            };
+
+            match byte {
+                b'\r' => {
+                    let line = self.line;
+                    let len = core::mem::take(&mut self.line_len);
+                    self.handle(line.get(..len).and_then(parse))?;
+                }
+                _ if self.line_len < MAX_LINE_LEN => {
+                    self.line[self.line_len] = byte;
+                    self.line_len += 1;
+                }
+                // Too long for any command, answered with an error at its end
+                _ => self.line_len = MAX_LINE_LEN + 1,
+            }
+        }
+
+        if self.open.is_some() {
+            while let Ok(frame) = self.can.receive() {
+                let timestamp = self.timestamps.then(|| frame.timestamp().unwrap_or(0));
+                let mut line = [0; MAX_LINE_LEN];
+                let len = write_frame(&frame, timestamp, &mut line);
+                self.write(&line[..len])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, command: Option<Command>) -> Result<(), S::Error> {
+        match (command, self.open) {
+            (Some(Command::Open(mode)), None) => {
+                match self.can.reconfigure(mode, &CanConfig::new(self.bitrate)) {
+                    Ok(()) => {
+                        self.open = Some(mode);
+                        self.write(&[OK])
+                    }
+                    Err(_) => self.write(&[ERROR]),
+                }
+            }
+            (Some(Command::Close), Some(_)) => {
+                self.can.sleep();
+                self.open = None;
+                self.write(&[OK])
+            }
+            (Some(Command::SetBitrate(bitrate)), None) => {
+                self.bitrate = bitrate;
+                self.write(&[OK])
+            }
+            (Some(Command::Transmit(frame)), Some(CanMode::Normal)) => {
+                let response: &[u8] = match (frame.id(), self.can.transmit(&frame)) {
+                    (Id::Standard(_), Ok(_)) => b"z\r",
+                    (Id::Extended(_), Ok(_)) => b"Z\r",
+                    (_, Err(_)) => &[ERROR],
+                };
+                self.write(response)
+            }
+            (Some(Command::Version), _) => self.write(b"V1013\r"),
+            (Some(Command::SerialNumber), _) => self.write(b"NCH32\r"),
+            (Some(Command::Status), _) => {
+                let flags = match self.can.bus_state() {
+                    BusState::ErrorActive => b'0',
+                    BusState::ErrorPassive => b'2',
+                    BusState::BusOff => b'8',
+                };
+                self.write(&[b'F', flags, b'0', OK])
+            }
+            (Some(Command::Timestamps(enabled)), None) => {
+                self.timestamps = enabled;
+                self.write(&[OK])
+            }
+            _ => self.write(&[ERROR]),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), S::Error> {
+        for &byte in bytes {
+            nb::block!(self.serial.write(byte))?;
+        }
+
+        Ok(())
+    }
+}
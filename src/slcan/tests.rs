@@ -0,0 +1,103 @@
+//! Host tests of the SLCAN lines, against the format of `slcand` and can-utils.
+
+use super::*;
+
+/// Frame sent by the host with `line`.
+fn transmitted(line: &[u8]) -> Option<CanFrame> {
+    match parse(line)? {
+        Command::Transmit(frame) => Some(frame),
+        command => panic!("not a transmit command: {command:?}"),
+    }
+}
+
+/// Line `frame` is encoded into, without its carriage return.
+fn encoded(frame: &CanFrame, timestamp_ms: Option<u32>) -> ([u8; MAX_LINE_LEN], usize) {
+    let mut line = [0; MAX_LINE_LEN];
+    let len = write_frame(frame, timestamp_ms, &mut line);
+    assert_eq!(line[len - 1], b'\r');
+
+    (line, len - 1)
+}
+
+#[test]
+fn frame_lines_round_trip() {
+    for line in [
+        &b"t1232AABB"[..],
+        b"t7FF0",
+        b"T1FFFFFFF81122334455667788",
+        b"r1238",
+        b"R000000010",
+    ] {
+        let frame = transmitted(line).unwrap();
+        let (encoded, len) = encoded(&frame, None);
+        assert_eq!(&encoded[..len], line);
+        assert!(transmitted(&encoded[..len]).unwrap().same_content(&frame));
+    }
+
+    let frame = transmitted(b"t1232AABB").unwrap();
+    assert_eq!(*frame.id(), Id::Standard(StandardId::new(0x123).unwrap()));
+    assert_eq!(Frame::data(&frame), [0xAA, 0xBB]);
+    let frame = transmitted(b"r1238").unwrap();
+    assert!(frame.is_remote_frame() && frame.dlc() == 8);
+    assert!(Frame::data(&frame).is_empty());
+}
+
+#[test]
+fn invalid_frame_lines_rejected() {
+    // Data length code above 8
+    assert!(parse(b"t1239112233445566778899").is_none());
+    assert!(parse(b"r1239").is_none());
+    // Identifier out of range
+    assert!(parse(b"t8000").is_none());
+    assert!(parse(b"T200000000").is_none());
+    // Payload not matching the data length code
+    assert!(parse(b"t1232AA").is_none());
+    assert!(parse(b"t1231AABB").is_none());
+    assert!(parse(b"r1231AA").is_none());
+    assert!(parse(b"t12G0").is_none());
+    assert!(parse(b"t12").is_none());
+}
+
+#[test]
+fn longest_line() {
+    let frame = transmitted(b"T1FFFFFFF81122334455667788").unwrap();
+    let mut line = [0; MAX_LINE_LEN];
+
+    assert_eq!(write_frame(&frame, Some(59_999), &mut line), MAX_LINE_LEN);
+    assert_eq!(line, *b"T1FFFFFFF81122334455667788EA5F\r");
+}
+
+#[test]
+fn timestamp_modulo_60_s() {
+    let frame = transmitted(b"t1000").unwrap();
+
+    for (timestamp_ms, digits) in [
+        (0, b"0000"),
+        (59_999, b"EA5F"),
+        (60_000, b"0000"),
+        (60_001, b"0001"),
+        (u32::MAX, b"B8BF"),
+    ] {
+        let (line, len) = encoded(&frame, Some(timestamp_ms));
+        assert_eq!(len, 9);
+        assert_eq!(&line[5..9], digits);
+    }
+}
+
+#[test]
+fn commands() {
+    assert!(matches!(parse(b"O"), Some(Command::Open(CanMode::Normal))));
+    assert!(matches!(parse(b"L"), Some(Command::Open(CanMode::Silent))));
+    assert!(matches!(
+        parse(b"S6"),
+        Some(Command::SetBitrate(Bitrate::K500))
+    ));
+    assert!(matches!(
+        parse(b"S7"),
+        Some(Command::SetBitrate(Bitrate::Custom(800_000)))
+    ));
+    assert!(matches!(parse(b"Z1"), Some(Command::Timestamps(true))));
+    assert!(parse(b"S9").is_none());
+    assert!(parse(b"O1").is_none());
+    assert!(parse(b"").is_none());
+}
@@ -0,0 +1,146 @@
+//! Traffic view of a cansniffer: the latest payload of every identifier, and only
+//! the frames that change it, to keep a log of periodic traffic small.
+
+use embedded_can::Id;
+
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+
+/// Keeps the latest payload of up to `N` identifiers, the first tracked
+/// forgotten first, and reports the frames that differ from it.
+///
+/// Feed it every received frame with [Sniffer::observe], or receive through
+/// [Sniffer::receive].
+pub struct Sniffer<const N: usize> {
+    latest: [Option<CanFrame>; N],
+    /// Slot replaced when no slot is free
+    next_slot: usize,
+    unchanged_count: u32,
+}
+
+impl<const N: usize> Default for Sniffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Sniffer<N> {
+    /// Sniffer that hasn't seen any identifier yet.
+    pub const fn new() -> Self {
+        Self {
+            latest: [None; N],
+            next_slot: 0,
+            unchanged_count: 0,
+        }
+    }
+
+    /// Latest frame of `id`, if it is tracked.
+    pub fn latest(&self, id: impl Into<Id>) -> Option<&CanFrame> {
+        let id = id.into();
+        self.latest.iter().flatten().find(|frame| *frame.id() == id)
+    }
+
+    /// Identifiers tracked.
+    pub fn tracked(&self) -> usize {
+        self.latest.iter().flatten().count()
+    }
+
+    /// Forgets every identifier, so that their next frames are reported in full.
+    pub fn clear(&mut self) {
+        self.latest = [None; N];
+        self.next_slot = 0;
+    }
+
+    /// Frames not reported as they didn't change anything, wrapping around.
+    pub fn unchanged(&self) -> u32 {
+        self.unchanged_count
+    }
+
+    /// Records `frame`, and returns what it changed if anything.
+    ///
+    /// The first frame of an identifier has all its bytes marked changed. A change
+    /// of length marks the bytes only one of the frames has.
+    pub fn observe(&mut self, frame: &CanFrame) -> Option<SnifferChange> {
+        let slot = self
+            .latest
+            .iter()
+            .position(|latest| latest.is_some_and(|latest| latest.id() == frame.id()));
+        let Some(slot) = slot else {
+            if N > 0 {
+                let slot = match self.latest.iter().position(Option::is_none) {
+                    Some(slot) => slot,
+                    None => {
+                        let slot = self.next_slot;
+                        self.next_slot = (slot + 1) % N;
+                        slot
+                    }
+                };
+                self.latest[slot] = Some(*frame);
+            }
+
+            return Some(SnifferChange {
+                frame: *frame,
+                changed: length_mask(payload_len(frame)),
+                new_id: true,
+            });
+        };
+
+        let latest = self.latest[slot].unwrap();
+        let mut changed = length_mask(payload_len(&latest)) ^ length_mask(payload_len(frame));
+        let common = payload_len(&latest).min(payload_len(frame));
+        for index in 0..common {
+            if latest.data[index] != frame.data[index] {
+                changed |= 1 << index;
+            }
+        }
+        if changed == 0 && latest.is_remote == frame.is_remote {
+            self.unchanged_count = self.unchanged_count.wrapping_add(1);
+            return None;
+        }
+
+        self.latest[slot] = Some(*frame);
+        Some(SnifferChange {
+            frame: *frame,
+            changed,
+            new_id: false,
+        })
+    }
+
+    /// Returns the next received frame changing its identifier's payload, skipping
+    /// the others.
+    pub fn receive<C>(&mut self, can: &mut C) -> nb::Result<SnifferChange, CanError>
+    where
+        C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    {
+        loop {
+            let frame = can.receive()?;
+            if let Some(change) = self.observe(&frame) {
+                return Ok(change);
+            }
+        }
+    }
+}
+
+/// Frame changing its identifier's payload, reported by [Sniffer::observe].
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct SnifferChange {
+    pub frame: CanFrame,
+    /// Bit `n` is set if data byte `n` changed
+    pub changed: u8,
+    /// Whether it is the first frame seen with this identifier
+    pub new_id: bool,
+}
+
+/// Data bytes carried, none for remote frames.
+fn payload_len(frame: &CanFrame) -> usize {
+    match frame.is_remote {
+        true => 0,
+        false => frame.dlc,
+    }
+}
+
+/// Bits of the first `len` bytes.
+fn length_mask(len: usize) -> u8 {
+    (0xFFu16 >> (8 - len)) as u8
+}
@@ -0,0 +1,161 @@
+//! Software receive filtering, after hardware acceptance: blocked identifiers and
+//! suppression of repeated frames.
+
+use embedded_can::Id;
+
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+
+/// Last frame let through for an identifier, for debouncing.
+#[derive(Copy, Clone)]
+struct Seen {
+    frame: CanFrame,
+    time_ms: u32,
+}
+
+/// Drops received frames the application doesn't want to process, beyond what the
+/// hardware filter banks can express.
+///
+/// Up to `B` identifiers can be blocked with [SoftFilter::block]. With
+/// [SoftFilter::debounce], a frame with the same identifier and payload as the last
+/// one let through for that identifier is dropped if it comes within the window,
+/// e.g. for nodes repeating an unchanged value faster than it needs processing.
+/// The last frame of up to `D` identifiers is remembered, the oldest forgotten
+/// first.
+///
+/// Feed it every received frame with [SoftFilter::accept], or receive through
+/// [SoftFilter::receive].
+pub struct SoftFilter<const B: usize, const D: usize> {
+    blocked: [Option<Id>; B],
+    window_ms: Option<u32>,
+    seen: [Option<Seen>; D],
+    /// Slot replaced when no slot is free
+    next_seen: usize,
+    blocked_count: u32,
+    suppressed_count: u32,
+}
+
+impl<const B: usize, const D: usize> Default for SoftFilter<B, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const B: usize, const D: usize> SoftFilter<B, D> {
+    /// Lets every frame through until identifiers are blocked or debouncing is enabled.
+    pub const fn new() -> Self {
+        Self {
+            blocked: [None; B],
+            window_ms: None,
+            seen: [None; D],
+            next_seen: 0,
+            blocked_count: 0,
+            suppressed_count: 0,
+        }
+    }
+
+    /// Drops frames with identifier `id`, and returns its slot.
+    pub fn block(&mut self, id: impl Into<Id>) -> Result<usize, SoftFilterFull> {
+        let slot = self
+            .blocked
+            .iter()
+            .position(Option::is_none)
+            .ok_or(SoftFilterFull)?;
+        self.blocked[slot] = Some(id.into());
+
+        Ok(slot)
+    }
+
+    pub fn unblock(&mut self, slot: usize) {
+        self.blocked[slot] = None;
+    }
+
+    /// Drops repeated frames coming within `window_ms` of the last one let through.
+    pub fn debounce(&mut self, window_ms: u32) {
+        self.window_ms = Some(window_ms);
+    }
+
+    /// Lets repeated frames through again, and forgets the frames seen.
+    pub fn disable_debounce(&mut self) {
+        self.window_ms = None;
+        self.seen = [None; D];
+    }
+
+    /// Frames dropped for a blocked identifier, wrapping around.
+    pub fn blocked(&self) -> u32 {
+        self.blocked_count
+    }
+
+    /// Frames dropped as repeated, wrapping around.
+    pub fn suppressed(&self) -> u32 {
+        self.suppressed_count
+    }
+
+    /// Whether `frame`, received at `now_ms`, should be processed.
+    pub fn accept(&mut self, frame: &CanFrame, now_ms: u32) -> bool {
+        if self.blocked.iter().flatten().any(|id| id == frame.id()) {
+            self.blocked_count = self.blocked_count.wrapping_add(1);
+            return false;
+        }
+        let Some(window_ms) = self.window_ms else {
+            return true;
+        };
+
+        let seen = Seen {
+            frame: *frame,
+            time_ms: now_ms,
+        };
+        let slot = self
+            .seen
+            .iter()
+            .position(|slot| slot.is_some_and(|last| last.frame.id() == frame.id()));
+        match slot {
+            Some(slot) => {
+                let last = self.seen[slot].unwrap();
+                let repeated = same_payload(&last.frame, frame)
+                    && now_ms.wrapping_sub(last.time_ms) < window_ms;
+                if repeated {
+                    self.suppressed_count = self.suppressed_count.wrapping_add(1);
+                    return false;
+                }
+                self.seen[slot] = Some(seen);
+            }
+            None if D > 0 => {
+                let slot = match self.seen.iter().position(Option::is_none) {
+                    Some(slot) => slot,
+                    None => {
+                        let slot = self.next_seen;
+                        self.next_seen = (slot + 1) % D;
+                        slot
+                    }
+                };
+                self.seen[slot] = Some(seen);
+            }
+            None => {}
+        }
+
+        true
+    }
+
+    /// Returns the next received frame that passes the filter, dropping the others.
+    pub fn receive<C>(&mut self, can: &mut C, now_ms: u32) -> nb::Result<CanFrame, CanError>
+    where
+        C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    {
+        loop {
+            let frame = can.receive()?;
+            if self.accept(&frame, now_ms) {
+                return Ok(frame);
+            }
+        }
+    }
+}
+
+/// Error returned by [SoftFilter::block] when all slots are in use.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct SoftFilterFull;
+
+fn same_payload(a: &CanFrame, b: &CanFrame) -> bool {
+    a.is_remote == b.is_remote && a.dlc == b.dlc && a.data == b.data
+}
@@ -0,0 +1,18 @@
+//! Time base of the HAL's SysTick time driver, shared by the driver's timestamps,
+//! the recorder and the scheduler.
+//!
+//! The values wrap around, about every 71 minutes in microseconds and every 49 days
+//! in milliseconds, so compare them with `wrapping_sub`.
+
+use embassy_time::Instant;
+
+/// Microseconds since the time driver started, see [crate::Can::enable_systick_timestamps].
+pub fn systick_micros() -> u32 {
+    Instant::now().as_micros() as u32
+}
+
+/// Milliseconds since the time driver started, e.g. for [crate::Recorder::record]
+/// and [crate::Scheduler::poll].
+pub fn systick_millis() -> u32 {
+    Instant::now().as_millis() as u32
+}
@@ -0,0 +1,124 @@
+//! Time synchronization of nodes to a master clock, with a reference frame and a
+//! follow-up frame, after the two-step scheme of AUTOSAR CanTSyn.
+//!
+//! The master sends a sync frame, then a follow-up frame carrying the time the sync
+//! frame was sent at on its clock. Each slave notes the time it received the sync
+//! frame at on its own clock, and on the follow-up learns the offset between both
+//! clocks. Both times are taken around the end of the same frame, when the transmit
+//! and receive interrupts run, so the frame's duration cancels out: timestamp with
+//! the same interrupt-driven time source on all nodes, e.g.
+//! `Can::enable_systick_timestamps`.
+//!
+//! Times are counted in microseconds and wrap around.
+
+use embedded_can::Id;
+
+use crate::frame::CanFrame;
+
+/// First data byte of a sync frame.
+pub const SYNC: u8 = 0x10;
+/// First data byte of a follow-up frame.
+pub const FOLLOW_UP: u8 = 0x18;
+
+/// Master side, sending the reference time.
+///
+/// For every synchronization, send [TimeSyncMaster::sync_frame] with
+/// `Can::transmit_tracked`, wait for it to be sent, then send
+/// [TimeSyncMaster::follow_up_frame] with the time of its transmit confirmation,
+/// from `Can::tx_confirmed_at`.
+pub struct TimeSyncMaster {
+    id: Id,
+    sequence: u8,
+}
+
+impl TimeSyncMaster {
+    /// Master sending its frames with identifier `id`.
+    pub const fn new(id: Id) -> Self {
+        Self { id, sequence: 0 }
+    }
+
+    /// Sync frame starting the next synchronization.
+    pub fn sync_frame(&mut self) -> CanFrame {
+        self.sequence = self.sequence.wrapping_add(1);
+
+        CanFrame::new(self.id, &[SYNC, self.sequence, 0, 0]).unwrap()
+    }
+
+    /// Follow-up of the last sync frame, sent at `sent_at_us` on the master clock.
+    pub fn follow_up_frame(&self, sent_at_us: u32) -> CanFrame {
+        let [t0, t1, t2, t3] = sent_at_us.to_be_bytes();
+
+        CanFrame::new(self.id, &[FOLLOW_UP, self.sequence, 0, 0, t0, t1, t2, t3]).unwrap()
+    }
+}
+
+/// Slave side, following the master clock.
+///
+/// Feed it every frame received with the master's identifier with
+/// [TimeSyncSlave::on_frame], then convert local times with
+/// [TimeSyncSlave::master_time].
+pub struct TimeSyncSlave {
+    id: Id,
+    /// Sequence counter and local reception time of the last sync frame
+    pending: Option<(u8, u32)>,
+    /// Master time minus local time, wrapping
+    offset_us: Option<u32>,
+}
+
+impl TimeSyncSlave {
+    /// Slave of the master sending with identifier `id`.
+    pub fn new(id: impl Into<Id>) -> Self {
+        Self {
+            id: id.into(),
+            pending: None,
+            offset_us: None,
+        }
+    }
+
+    /// Handles `frame`, received at `received_at_us` on the local clock, e.g. its
+    /// [CanFrame::timestamp]. Returns whether it completed a synchronization.
+    ///
+    /// A follow-up is only used if it matches the last sync frame; a lost frame
+    /// skips that synchronization and keeps the previous offset.
+    pub fn on_frame(&mut self, frame: &CanFrame, received_at_us: u32) -> bool {
+        if *frame.id() != self.id || frame.dlc() < 2 {
+            return false;
+        }
+
+        let data = frame.data();
+        match data[0] {
+            SYNC => {
+                self.pending = Some((data[1], received_at_us));
+                false
+            }
+            FOLLOW_UP if frame.dlc() == 8 => {
+                let Some((sequence, sync_received_us)) = self.pending.take() else {
+                    return false;
+                };
+                if sequence != data[1] {
+                    return false;
+                }
+
+                let sent_at_us = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+                self.offset_us = Some(sent_at_us.wrapping_sub(sync_received_us));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a synchronization has completed since creation.
+    pub fn is_synchronized(&self) -> bool {
+        self.offset_us.is_some()
+    }
+
+    /// Master clock from the local time `local_us`, once synchronized.
+    pub fn master_time(&self, local_us: u32) -> Option<u32> {
+        Some(local_us.wrapping_add(self.offset_us?))
+    }
+
+    /// Master time minus local time, as a signed difference, once synchronized.
+    pub fn offset_us(&self) -> Option<i32> {
+        Some(self.offset_us? as i32)
+    }
+}
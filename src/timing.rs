@@ -0,0 +1,270 @@
+//! Bit timing calculation, adapted from embassy-stm32.
+//!
+//! Everything here is plain integer math with no access to the peripheral, and the
+//! solvers are `const fn`, so timings can be computed and inspected in constants,
+//! build scripts or host tools. Inputs are the frequency of the clock feeding the
+//! peripheral (APB1) and the bitrate, both in Hz; outputs are [NominalBitTiming]
+//! values in time quanta, as programmed into the `BTIMR` register plus one.
+
+use core::num::{NonZeroU16, NonZeroU8};
+
+#[cfg(test)]
+mod tests;
+
+/// Bit timing in time quanta, as computed by the functions of this module.
+#[derive(Clone, Copy)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct NominalBitTiming {
+    /// Value by which the oscillator frequency is divided for generating the bit time quanta. The bit
+    /// time is built up from a multiple of this quanta. Valid values are 1 to 1024.
+    pub prescaler: NonZeroU16,
+    /// Time quanta before the sample point, excluding the sync segment. Valid values are 1 to 16.
+    pub seg1: NonZeroU8,
+    /// Time quanta after the sample point. Valid values are 1 to 8.
+    pub seg2: NonZeroU8,
+    /// Valid values are 1 to 4.
+    pub sync_jump_width: NonZeroU8,
+}
+
+/// Sample point recommended by CiA 301 for all bitrates, in tenths of a percent.
+pub const CIA_SAMPLE_POINT_PERMILL: u16 = 875;
+
+impl NominalBitTiming {
+    /// Position of the sample point within the bit, in tenths of a percent.
+    pub const fn sample_point_permill(&self) -> u16 {
+        let seg1 = self.seg1.get() as u32;
+        let quanta = 1 + seg1 + self.seg2.get() as u32;
+
+        ((1 + seg1) * 1000 / quanta) as u16
+    }
+
+    /// Bitrate in bit/s achieved with a peripheral clock of `periph_clock` Hz,
+    /// rounded down.
+    pub const fn bitrate(&self, periph_clock: u32) -> u32 {
+        periph_clock / (self.prescaler.get() as u32 * self.quanta_per_bit())
+    }
+
+    /// Deviation of the achieved bitrate from `can_bitrate`, in parts per million.
+    pub const fn deviation_ppm(&self, periph_clock: u32, can_bitrate: u32) -> u32 {
+        deviation_ppm(
+            periph_clock,
+            can_bitrate,
+            self.prescaler.get() as u32 * self.quanta_per_bit(),
+        )
+    }
+
+    /// Replaces the sync jump width, 1 to 4 time quanta and at most `seg2`.
+    pub const fn with_sync_jump_width(self, sync_jump_width: u8) -> Option<Self> {
+        if sync_jump_width > 4 || sync_jump_width > self.seg2.get() {
+            return None;
+        }
+
+        match NonZeroU8::new(sync_jump_width) {
+            Some(sync_jump_width) => Some(Self {
+                sync_jump_width,
+                ..self
+            }),
+            None => None,
+        }
+    }
+
+    /// Settings from a table of interoperable timings following the CiA sample point
+    /// recommendations, for CAN clocks of 8, 16, 36, 48, 72, 96 and 144 MHz and the
+    /// standard bitrates of [crate::Bitrate]. Returns `None` for other combinations.
+    pub const fn recommended(periph_clock: u32, can_bitrate: u32) -> Option<Self> {
+        let mut i = 0;
+        while i < RECOMMENDED_TIMINGS.len() {
+            let (clock, bitrate, prescaler, seg1, seg2) = RECOMMENDED_TIMINGS[i];
+            if clock == periph_clock && bitrate == can_bitrate {
+                return match (
+                    NonZeroU16::new(prescaler),
+                    NonZeroU8::new(seg1),
+                    NonZeroU8::new(seg2),
+                ) {
+                    (Some(prescaler), Some(seg1), Some(seg2)) => Some(Self {
+                        prescaler,
+                        seg1,
+                        seg2,
+                        sync_jump_width: NonZeroU8::MIN,
+                    }),
+                    _ => None,
+                };
+            }
+            i += 1;
+        }
+
+        None
+    }
+
+    const fn quanta_per_bit(&self) -> u32 {
+        1 + self.seg1.get() as u32 + self.seg2.get() as u32
+    }
+
+    /// Same as [calc_can_timings_with_sample_point] with the sample point recommended
+    /// by CiA, panicking if there is no solution. Meant for constants, where a clock
+    /// rate and bitrate that don't fit together fail the build:
+    ///
+    /// ```ignore
+    /// const BIT_TIMING: NominalBitTiming = NominalBitTiming::calc(96_000_000, 500_000);
+    /// ```
+    pub const fn calc(periph_clock: u32, can_bitrate: u32) -> Self {
+        match calc_can_timings_with_sample_point(
+            periph_clock,
+            can_bitrate,
+            CIA_SAMPLE_POINT_PERMILL,
+        ) {
+            Some(bit_timing) => bit_timing,
+            None => fail!(
+                "Bit timing parameters weren't satisfied for CAN clock rate and desired bitrate."
+            ),
+        }
+    }
+}
+
+/// Recommended settings as `(clock, bitrate, prescaler, seg1, seg2)`, all with a
+/// sample point at 87.5% (88.9% for 1 Mbit/s from 36 MHz) and 8 to 18 quanta per bit.
+const RECOMMENDED_TIMINGS: [(u32, u32, u16, u8, u8); 28] = [
+    (8_000_000, 125_000, 4, 13, 2),
+    (8_000_000, 250_000, 2, 13, 2),
+    (8_000_000, 500_000, 1, 13, 2),
+    (8_000_000, 1_000_000, 1, 6, 1),
+    (16_000_000, 125_000, 8, 13, 2),
+    (16_000_000, 250_000, 4, 13, 2),
+    (16_000_000, 500_000, 2, 13, 2),
+    (16_000_000, 1_000_000, 1, 13, 2),
+    (36_000_000, 125_000, 18, 13, 2),
+    (36_000_000, 250_000, 9, 13, 2),
+    (36_000_000, 500_000, 9, 6, 1),
+    (36_000_000, 1_000_000, 2, 15, 2),
+    (48_000_000, 125_000, 24, 13, 2),
+    (48_000_000, 250_000, 12, 13, 2),
+    (48_000_000, 500_000, 6, 13, 2),
+    (48_000_000, 1_000_000, 3, 13, 2),
+    (72_000_000, 125_000, 36, 13, 2),
+    (72_000_000, 250_000, 18, 13, 2),
+    (72_000_000, 500_000, 9, 13, 2),
+    (72_000_000, 1_000_000, 9, 6, 1),
+    (96_000_000, 125_000, 48, 13, 2),
+    (96_000_000, 250_000, 24, 13, 2),
+    (96_000_000, 500_000, 12, 13, 2),
+    (96_000_000, 1_000_000, 6, 13, 2),
+    (144_000_000, 125_000, 72, 13, 2),
+    (144_000_000, 250_000, 36, 13, 2),
+    (144_000_000, 500_000, 18, 13, 2),
+    (144_000_000, 1_000_000, 9, 13, 2),
+];
+
+/// Calculate nominal CAN bit timing based on CAN bitrate and periphial clock frequency,
+/// placing the sample point as close as possible to `sample_point_permill` (e.g. 875 for
+/// 87.5%). The achieved value is given by [NominalBitTiming::sample_point_permill].
+pub const fn calc_can_timings_with_sample_point(
+    periph_clock: u32,
+    can_bitrate: u32,
+    sample_point_permill: u16,
+) -> Option<NominalBitTiming> {
+    calc_can_timings_within(periph_clock, can_bitrate, sample_point_permill, 0)
+}
+
+/// Same as [calc_can_timings_with_sample_point], also accepting timings whose bitrate
+/// deviates from `can_bitrate` by up to `max_deviation_ppm` parts per million when
+/// the clock can't be divided down exactly. The closest bitrate is preferred over
+/// the closest sample point. The achieved bitrate is given by [NominalBitTiming::bitrate].
+pub const fn calc_can_timings_within(
+    periph_clock: u32,
+    can_bitrate: u32,
+    sample_point_permill: u16,
+    max_deviation_ppm: u32,
+) -> Option<NominalBitTiming> {
+    const BS1_MAX: u32 = 16;
+    const BS2_MAX: u32 = 8;
+    const PRESCALER_MAX: u32 = 1024;
+    const MIN_QUANTA_PER_BIT: u32 = 8;
+
+    if can_bitrate < 1000 {
+        return None;
+    }
+
+    // Ref. "Automatic Baudrate Detection in CANopen Networks", U. Koppe, MicroControl GmbH & Co. KG
+    //      CAN in Automation, 2003
+    //
+    // According to the source, optimal quanta per bit are:
+    //   Bitrate        Optimal Maximum
+    //   1000 kbps      8       10
+    //   500  kbps      16      17
+    //   250  kbps      16      17
+    //   125  kbps      16      17
+    let max_quanta_per_bit: u32 = if can_bitrate >= 1_000_000 { 10 } else { 17 };
+
+    // Computing (prescaler * BS):
+    //   BITRATE = 1 / (PRESCALER * (1 / PCLK) * (1 + BS1 + BS2))       -- See the Reference Manual
+    //   BITRATE = PCLK / (PRESCALER * (1 + BS1 + BS2))                 -- Simplified
+    // let:
+    //   BS = 1 + BS1 + BS2                                             -- Number of time quanta per bit
+    // ==>
+    //   PRESCALER = PCLK / (BITRATE * BS), rounded to the nearest integer
+    //
+    // Every split of every quanta count is tried, from the highest number of quanta per bit
+    // down, keeping the first split with the smallest bitrate deviation, then the sample
+    // point closest to the target.
+    //   Sample point location = (1 + bs1) / (1 + bs1 + bs2)
+    let target = sample_point_permill as u32;
+    let mut best = (u32::MAX, u32::MAX); // (deviation, sample point error)
+    let (mut prescaler, mut bs1, mut bs2) = (0, 0, 0);
+    let mut quanta = max_quanta_per_bit;
+    while quanta >= MIN_QUANTA_PER_BIT {
+        let quanta_rate = can_bitrate as u64 * quanta as u64;
+        let candidate_prescaler = ((periph_clock as u64 + quanta_rate / 2) / quanta_rate) as u32;
+        let deviation = deviation_ppm(periph_clock, can_bitrate, candidate_prescaler * quanta);
+        if candidate_prescaler >= 1
+            && candidate_prescaler <= PRESCALER_MAX
+            && deviation <= max_deviation_ppm
+        {
+            let mut candidate_bs2 = 1;
+            while candidate_bs2 <= BS2_MAX && candidate_bs2 + 2 <= quanta {
+                let candidate_bs1 = quanta - 1 - candidate_bs2;
+                let sample_point = (1 + candidate_bs1) * 1000 / quanta;
+                let error = sample_point.abs_diff(target);
+                let better = deviation < best.0 || (deviation == best.0 && error < best.1);
+                if candidate_bs1 <= BS1_MAX && better {
+                    best = (deviation, error);
+                    (prescaler, bs1, bs2) = (candidate_prescaler, candidate_bs1, candidate_bs2);
+                }
+                candidate_bs2 += 1;
+            }
+        }
+        quanta -= 1;
+    }
+
+    // One is recommended by DS-015, CANOpen, and DeviceNet
+    let sync_jump_width = NonZeroU8::MIN;
+
+    // `?` isn't usable in const fn
+    match (
+        NonZeroU8::new(bs1 as u8),
+        NonZeroU8::new(bs2 as u8),
+        NonZeroU16::new(prescaler as u16),
+    ) {
+        (Some(seg1), Some(seg2), Some(prescaler)) => Some(NominalBitTiming {
+            sync_jump_width,
+            prescaler,
+            seg1,
+            seg2,
+        }),
+        _ => None, // No solution
+    }
+}
+
+/// Deviation between `can_bitrate` and `periph_clock / divider`, in parts per million.
+const fn deviation_ppm(periph_clock: u32, can_bitrate: u32, divider: u32) -> u32 {
+    let nominal = can_bitrate as u64 * divider as u64;
+    if nominal == 0 {
+        return u32::MAX;
+    }
+    let ppm = (periph_clock as u64).abs_diff(nominal) * 1_000_000 / nominal;
+
+    if ppm > u32::MAX as u64 {
+        u32::MAX
+    } else {
+        ppm as u32
+    }
+}
@@ -0,0 +1,84 @@
+//! Host tests of the bit timing solvers.
+
+use super::*;
+
+/// `(prescaler, seg1, seg2)` of `timing`.
+fn segments(timing: NominalBitTiming) -> (u16, u8, u8) {
+    (timing.prescaler.get(), timing.seg1.get(), timing.seg2.get())
+}
+
+#[test]
+fn exact_timing_at_96mhz() {
+    let timing = calc_can_timings_within(96_000_000, 500_000, CIA_SAMPLE_POINT_PERMILL, 0).unwrap();
+
+    assert_eq!(segments(timing), (12, 13, 2));
+    assert_eq!(timing.sync_jump_width.get(), 1);
+    assert_eq!(timing.bitrate(96_000_000), 500_000);
+    assert_eq!(timing.deviation_ppm(96_000_000, 500_000), 0);
+    assert_eq!(timing.sample_point_permill(), 875);
+}
+
+#[test]
+fn solver_agrees_with_recommended_table() {
+    for (clock, bitrate, ..) in RECOMMENDED_TIMINGS {
+        let recommended = NominalBitTiming::recommended(clock, bitrate).unwrap();
+        let solved =
+            calc_can_timings_with_sample_point(clock, bitrate, CIA_SAMPLE_POINT_PERMILL).unwrap();
+
+        assert_eq!(recommended.bitrate(clock), bitrate);
+        assert_eq!(
+            recommended.sample_point_permill(),
+            solved.sample_point_permill()
+        );
+        let sample_point = recommended.sample_point_permill();
+        assert!(sample_point.abs_diff(CIA_SAMPLE_POINT_PERMILL) <= 14); // 88.9% at most
+    }
+}
+
+#[test]
+fn recommended_only_for_listed_clocks() {
+    assert!(NominalBitTiming::recommended(96_000_000, 500_000).is_some());
+    assert!(NominalBitTiming::recommended(96_000_000, 800_000).is_none());
+    assert!(NominalBitTiming::recommended(100_000_000, 500_000).is_none());
+}
+
+#[test]
+fn unreachable_bitrates_rejected() {
+    // Below 1 kbit/s
+    assert!(calc_can_timings_within(96_000_000, 999, CIA_SAMPLE_POINT_PERMILL, u32::MAX).is_none());
+    // More than 1024 prescaler steps per quantum
+    assert!(calc_can_timings_within(144_000_000, 5_000, CIA_SAMPLE_POINT_PERMILL, 0).is_none());
+    // Fewer than 8 quanta per bit
+    assert!(calc_can_timings_within(4_000_000, 1_000_000, CIA_SAMPLE_POINT_PERMILL, 0).is_none());
+    // Not an exact divider of the clock
+    assert!(calc_can_timings_within(13_000_000, 1_000_000, CIA_SAMPLE_POINT_PERMILL, 0).is_none());
+}
+
+#[test]
+fn deviation_accepted_within_bound() {
+    let timing =
+        calc_can_timings_within(13_000_000, 1_000_000, CIA_SAMPLE_POINT_PERMILL, 200_000).unwrap();
+
+    assert_eq!(segments(timing), (2, 6, 1));
+    assert_eq!(timing.bitrate(13_000_000), 812_500);
+    assert_eq!(timing.deviation_ppm(13_000_000, 1_000_000), 187_500);
+    assert!(
+        calc_can_timings_within(13_000_000, 1_000_000, CIA_SAMPLE_POINT_PERMILL, 187_499).is_none()
+    );
+}
+
+#[test]
+fn sync_jump_width_bounded_by_seg2() {
+    let timing = NominalBitTiming::calc(96_000_000, 500_000);
+
+    assert_eq!(
+        timing
+            .with_sync_jump_width(2)
+            .unwrap()
+            .sync_jump_width
+            .get(),
+        2
+    );
+    assert!(timing.with_sync_jump_width(3).is_none()); // Longer than seg2
+    assert!(timing.with_sync_jump_width(0).is_none());
+}
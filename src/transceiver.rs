@@ -0,0 +1,84 @@
+//! CAN transceiver control, for the sleep and wake-up flows of [crate::Can].
+
+use embedded_hal::digital::{OutputPin, PinState};
+
+/// Bus transceiver whose operating mode can be switched by the application.
+///
+/// Implement it for transceivers controlled over SPI, I2C or anything else;
+/// [GpioTransceiver] covers the common single standby/enable pin case.
+pub trait CanTransceiver {
+    type Error;
+
+    /// Puts the transceiver in normal mode, able to drive the bus.
+    fn enable(&mut self) -> Result<(), Self::Error>;
+
+    /// Puts the transceiver in its low-power standby mode.
+    fn standby(&mut self) -> Result<(), Self::Error>;
+
+    /// Time in microseconds the transceiver needs after [CanTransceiver::enable]
+    /// before the bus can be used.
+    fn wake_delay_us(&self) -> u32 {
+        0
+    }
+}
+
+/// No transceiver control, for boards where it is hard-wired to normal mode.
+impl CanTransceiver for () {
+    type Error = core::convert::Infallible;
+
+    fn enable(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn standby(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Transceiver with a single pin selecting between normal and standby mode, like
+/// the `S` (TJA1050), `STB` (TJA1042) or `EN` (SN65HVD) pin.
+pub struct GpioTransceiver<P: OutputPin> {
+    pin: P,
+    standby_state: PinState,
+    wake_delay_us: u32,
+}
+
+impl<P: OutputPin> GpioTransceiver<P> {
+    /// Transceiver put in standby mode by driving `pin` high.
+    pub fn new(pin: P, wake_delay_us: u32) -> Self {
+        Self {
+            pin,
+            standby_state: PinState::High,
+            wake_delay_us,
+        }
+    }
+
+    /// Transceiver put in standby mode by driving `pin` low.
+    pub fn new_active_high(pin: P, wake_delay_us: u32) -> Self {
+        Self {
+            pin,
+            standby_state: PinState::Low,
+            wake_delay_us,
+        }
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+impl<P: OutputPin> CanTransceiver for GpioTransceiver<P> {
+    type Error = P::Error;
+
+    fn enable(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_state(!self.standby_state)
+    }
+
+    fn standby(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_state(self.standby_state)
+    }
+
+    fn wake_delay_us(&self) -> u32 {
+        self.wake_delay_us
+    }
+}
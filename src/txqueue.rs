@@ -0,0 +1,153 @@
+//! Software transmit queue feeding the three hardware mailboxes.
+
+use crate::enums::TxOrder;
+use crate::frame::CanFrame;
+
+#[cfg(test)]
+mod tests;
+
+/// Number of frames that can wait for a free transmit mailbox in the async driver.
+pub(crate) const TX_QUEUE_LEN: usize = 8;
+
+/// Bounded binary heap of frames waiting for a transmit mailbox, holding up to `N`
+/// frames.
+///
+/// Frames come out highest arbitration priority first, so a burst larger than the
+/// three hardware mailboxes still sends the most important traffic first. Frames
+/// with the same priority leave in submission order. Feed the mailboxes from it
+/// with [crate::Can::transmit_queued].
+pub struct TxQueue<const N: usize> {
+    heap: [Option<(u32, CanFrame)>; N],
+    len: usize,
+    next_order: u32,
+    order: TxOrder,
+}
+
+impl<const N: usize> TxQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            heap: [None; N],
+            len: 0,
+            next_order: 0,
+            order: TxOrder::Priority,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub(crate) fn order(&self) -> TxOrder {
+        self.order
+    }
+
+    /// Switches between priority and submission order, reordering queued frames.
+    pub(crate) fn set_order(&mut self, order: TxOrder) {
+        self.order = order;
+        for i in (0..self.len / 2).rev() {
+            self.sift_down(i);
+        }
+    }
+
+    /// Returns the frame back if the queue is full.
+    pub fn push(&mut self, frame: CanFrame) -> Result<(), CanFrame> {
+        if self.is_full() {
+            return Err(frame);
+        }
+
+        self.heap[self.len] = Some((self.next_order, frame));
+        self.next_order = self.next_order.wrapping_add(1);
+        self.len += 1;
+        self.sift_up(self.len - 1);
+
+        Ok(())
+    }
+
+    /// Returns the frame that would win arbitration against all others queued
+    /// without removing it.
+    pub fn peek(&self) -> Option<&CanFrame> {
+        self.heap[..self.len]
+            .first()
+            .and_then(|entry| entry.as_ref().map(|(_, frame)| frame))
+    }
+
+    /// Removes the frame that would win arbitration against all others queued, or
+    /// the oldest frame in [TxOrder::Fifo] mode.
+    pub fn pop(&mut self) -> Option<CanFrame> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.len -= 1;
+        self.heap.swap(0, self.len);
+        let (_, frame) = self.heap[self.len].take()?;
+        self.sift_down(0);
+
+        Some(frame)
+    }
+
+    /// Whether the entry at `a` must leave before the entry at `b`.
+    fn before(&self, a: usize, b: usize) -> bool {
+        let (Some((order_a, frame_a)), Some((order_b, frame_b))) = (&self.heap[a], &self.heap[b])
+        else {
+            return false;
+        };
+
+        if self.order == TxOrder::Priority {
+            let (key_a, key_b) = (frame_a.arbitration_key(), frame_b.arbitration_key());
+            if key_a != key_b {
+                return key_a < key_b;
+            }
+        }
+
+        // Signed distance keeps submission order correct across counter wrap-around
+        (order_a.wrapping_sub(*order_b) as i32) < 0
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !self.before(i, parent) {
+                break;
+            }
+            self.heap.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let (left, right) = (2 * i + 1, 2 * i + 2);
+            let mut first = i;
+            if left < self.len && self.before(left, first) {
+                first = left;
+            }
+            if right < self.len && self.before(right, first) {
+                first = right;
+            }
+            if first == i {
+                break;
+            }
+            self.heap.swap(i, first);
+            i = first;
+        }
+    }
+}
+
+impl<const N: usize> Default for TxQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
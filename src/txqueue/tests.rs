@@ -0,0 +1,117 @@
+//! Host tests of the transmit queue order, by arbitration priority then submission.
+
+use super::*;
+use embedded_can::{ExtendedId, Frame, Id, StandardId};
+
+fn standard(id: u16, tag: u8) -> CanFrame {
+    CanFrame::new(StandardId::new(id).unwrap(), &[tag]).unwrap()
+}
+
+/// Pops the whole queue, returning the identifier and first data byte of each
+/// frame.
+fn drain<const N: usize>(queue: &mut TxQueue<N>) -> Vec<(Id, u8)> {
+    core::iter::from_fn(|| queue.pop())
+        .map(|frame| {
+            (
+                *frame.id(),
+                Frame::data(&frame).first().copied().unwrap_or(0),
+            )
+        })
+        .collect()
+}
+
+fn raw_ids(entries: &[(Id, u8)]) -> Vec<u32> {
+    entries
+        .iter()
+        .map(|(id, _)| match id {
+            Id::Standard(id) => id.as_raw() as u32,
+            Id::Extended(id) => id.as_raw(),
+        })
+        .collect()
+}
+
+#[test]
+fn highest_priority_first() {
+    let mut queue = TxQueue::<8>::new();
+    for id in [0x300, 0x7FF, 0x100, 0x101, 0x000, 0x450] {
+        queue.push(standard(id, 0)).unwrap();
+    }
+    // Same base identifier as 0x100: the standard frame wins on IDE
+    let extended = ExtendedId::new(0x100 << 18).unwrap();
+    queue.push(CanFrame::new(extended, &[]).unwrap()).unwrap();
+    // Same identifier as 0x300: the data frame wins on RTR
+    queue
+        .push(CanFrame::new_remote(StandardId::new(0x300).unwrap(), 0).unwrap())
+        .unwrap();
+
+    assert_eq!(
+        queue.peek().map(|frame| *frame.id()),
+        Some(standard(0, 0).id)
+    );
+    let drained = drain(&mut queue);
+    assert_eq!(
+        raw_ids(&drained),
+        [0x000, 0x100, 0x100 << 18, 0x101, 0x300, 0x300, 0x450, 0x7FF]
+    );
+    assert!(queue.is_empty() && queue.pop().is_none());
+}
+
+#[test]
+fn full_queue_returns_frame() {
+    let mut queue = TxQueue::<2>::new();
+    queue.push(standard(0x200, 1)).unwrap();
+    queue.push(standard(0x100, 2)).unwrap();
+
+    let rejected = queue.push(standard(0x000, 3)).unwrap_err();
+    assert_eq!(Frame::data(&rejected), [3]);
+    assert!(queue.is_full());
+    assert_eq!(drain(&mut queue).len(), 2);
+}
+
+#[test]
+fn same_priority_in_submission_order() {
+    let mut queue = TxQueue::<8>::new();
+    for tag in 0..4 {
+        queue.push(standard(0x200, tag)).unwrap();
+        queue.push(standard(0x100, tag)).unwrap();
+    }
+
+    let tags: Vec<_> = drain(&mut queue).into_iter().map(|(_, tag)| tag).collect();
+    assert_eq!(tags, [0, 1, 2, 3, 0, 1, 2, 3]);
+}
+
+#[test]
+fn submission_order_across_counter_wrap_around() {
+    let mut queue = TxQueue::<8>::new();
+    queue.next_order = u32::MAX - 3;
+    let mut popped = Vec::new();
+    for tag in 0..8 {
+        queue.push(standard(0x123, tag)).unwrap();
+        // Interleaved pops keep frames queued on both sides of the wrap
+        if tag % 3 == 2 {
+            popped.push(Frame::data(&queue.pop().unwrap())[0]);
+        }
+    }
+    assert_eq!(queue.next_order, 4);
+    assert_eq!(popped, [0, 1]);
+
+    let tags: Vec<_> = drain(&mut queue).into_iter().map(|(_, tag)| tag).collect();
+    assert_eq!(tags, [2, 3, 4, 5, 6, 7]);
+}
+
+#[test]
+fn set_order_reorders_queued_frames() {
+    let mut queue = TxQueue::<8>::new();
+    for (tag, id) in [0x500, 0x100, 0x400, 0x200, 0x300].into_iter().enumerate() {
+        queue.push(standard(id, tag as u8)).unwrap();
+    }
+
+    queue.set_order(TxOrder::Fifo);
+    assert_eq!(queue.order(), TxOrder::Fifo);
+    assert_eq!(Frame::data(&queue.pop().unwrap()), [0]);
+    assert_eq!(Frame::data(&queue.pop().unwrap()), [1]);
+
+    queue.push(standard(0x000, 5)).unwrap();
+    queue.set_order(TxOrder::Priority);
+    assert_eq!(raw_ids(&drain(&mut queue)), [0x000, 0x200, 0x300, 0x400]);
+}
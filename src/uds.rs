@@ -0,0 +1,211 @@
+//! Minimal UDS (ISO 14229) diagnostic client over [crate::isotp].
+
+use embedded_hal::delay::DelayNs;
+
+use crate::enums::CanError;
+use crate::frame::CanFrame;
+use crate::isotp::{IsoTp, IsoTpError};
+
+pub const DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
+pub const ECU_RESET: u8 = 0x11;
+pub const READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+pub const SECURITY_ACCESS: u8 = 0x27;
+pub const WRITE_DATA_BY_IDENTIFIER: u8 = 0x2E;
+pub const TESTER_PRESENT: u8 = 0x3E;
+
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_OFFSET: u8 = 0x40;
+const SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
+/// Negative response code telling the request was received but is still processed.
+pub const RESPONSE_PENDING: u8 = 0x78;
+
+/// Diagnostic sessions of [UdsClient::start_session].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum Session {
+    Default = 0x01,
+    Programming = 0x02,
+    Extended = 0x03,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum UdsError {
+    /// The server rejected the request with this negative response code
+    Negative(u8),
+    /// The response did not belong to the request
+    UnexpectedResponse,
+    /// The response is longer than the buffer
+    BufferTooSmall,
+    Transport(IsoTpError),
+}
+
+impl From<IsoTpError> for UdsError {
+    fn from(error: IsoTpError) -> Self {
+        match error {
+            IsoTpError::BufferTooSmall => UdsError::BufferTooSmall,
+            error => UdsError::Transport(error),
+        }
+    }
+}
+
+/// UDS client talking to one server over an ISO-TP channel.
+///
+/// Responses pending ([RESPONSE_PENDING]) are waited for, each for up to the
+/// channel's [crate::isotp::IsoTpConfig::timeout_ms].
+pub struct UdsClient<C, D> {
+    transport: IsoTp<C, D>,
+    session: Session,
+    keep_alive_ms: u32,
+    last_keep_alive_ms: Option<u32>,
+}
+
+impl<C, D> UdsClient<C, D>
+where
+    C: embedded_can::nb::Can<Frame = CanFrame, Error = CanError>,
+    D: DelayNs,
+{
+    /// `keep_alive_ms` is the tester present period of [UdsClient::poll_keep_alive],
+    /// below the server's session timeout (S3, 5 s by default).
+    pub fn new(transport: IsoTp<C, D>, keep_alive_ms: u32) -> Self {
+        Self {
+            transport,
+            session: Session::Default,
+            keep_alive_ms,
+            last_keep_alive_ms: None,
+        }
+    }
+
+    pub fn release(self) -> IsoTp<C, D> {
+        self.transport
+    }
+
+    /// Session last started with [UdsClient::start_session].
+    pub fn session(&self) -> Session {
+        self.session
+    }
+
+    /// Sends `request`, whose first byte is the service identifier, and returns
+    /// the length of the positive response written to `response`, service
+    /// identifier included.
+    pub fn request(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, UdsError> {
+        let service = *request.first().ok_or(UdsError::UnexpectedResponse)?;
+        self.transport.send(request)?;
+
+        loop {
+            let len = self.transport.receive(response)?;
+            match response[..len] {
+                [NEGATIVE_RESPONSE, rejected, RESPONSE_PENDING] if rejected == service => {}
+                [NEGATIVE_RESPONSE, rejected, code] if rejected == service => {
+                    return Err(UdsError::Negative(code))
+                }
+                [sid, ..] if sid == service.wrapping_add(POSITIVE_OFFSET) => return Ok(len),
+                _ => return Err(UdsError::UnexpectedResponse),
+            }
+        }
+    }
+
+    pub fn start_session(&mut self, session: Session) -> Result<(), UdsError> {
+        let mut response = [0; 8];
+        self.request(&[DIAGNOSTIC_SESSION_CONTROL, session as u8], &mut response)?;
+        self.session = session;
+
+        Ok(())
+    }
+
+    /// Resets the server, e.g. with `reset_type` 0x01 for a hard reset. The server
+    /// is back in the default session afterwards.
+    pub fn ecu_reset(&mut self, reset_type: u8) -> Result<(), UdsError> {
+        let mut response = [0; 8];
+        self.request(&[ECU_RESET, reset_type], &mut response)?;
+        self.session = Session::Default;
+
+        Ok(())
+    }
+
+    /// Reads data identifier `did` into `data`, returning its length.
+    pub fn read_data_by_identifier(
+        &mut self,
+        did: u16,
+        data: &mut [u8],
+    ) -> Result<usize, UdsError> {
+        let [high, low] = did.to_be_bytes();
+        let mut response = [0; 64];
+        let len = self.request(&[READ_DATA_BY_IDENTIFIER, high, low], &mut response)?;
+        if len < 3 || response[1..3] != [high, low] {
+            return Err(UdsError::UnexpectedResponse);
+        }
+
+        let value = &response[3..len];
+        data.get_mut(..value.len())
+            .ok_or(UdsError::BufferTooSmall)?
+            .copy_from_slice(value);
+
+        Ok(value.len())
+    }
+
+    /// Writes `data`, of up to 61 bytes, to data identifier `did`.
+    pub fn write_data_by_identifier(&mut self, did: u16, data: &[u8]) -> Result<(), UdsError> {
+        let mut request = [0; 64];
+        if data.len() > request.len() - 3 {
+            return Err(UdsError::BufferTooSmall);
+        }
+
+        request[0] = WRITE_DATA_BY_IDENTIFIER;
+        request[1..3].copy_from_slice(&did.to_be_bytes());
+        request[3..3 + data.len()].copy_from_slice(data);
+        let mut response = [0; 8];
+        self.request(&request[..3 + data.len()], &mut response)?;
+
+        Ok(())
+    }
+
+    /// Unlocks security access `level` (odd, the seed request level).
+    /// `compute_key` writes the key for the seed it is given and returns its
+    /// length, of up to 32 bytes. Already unlocked levels, for which the server
+    /// sends an all-zero seed, are left as they are.
+    pub fn unlock(
+        &mut self,
+        level: u8,
+        compute_key: impl FnOnce(&[u8], &mut [u8]) -> usize,
+    ) -> Result<(), UdsError> {
+        let mut response = [0; 34];
+        let len = self.request(&[SECURITY_ACCESS, level], &mut response)?;
+        if len < 2 || response[1] != level {
+            return Err(UdsError::UnexpectedResponse);
+        }
+
+        let seed = &response[2..len];
+        if seed.iter().all(|&byte| byte == 0) {
+            return Ok(());
+        }
+
+        let mut request = [0; 34];
+        let key_len = compute_key(seed, &mut request[2..]);
+        request[0] = SECURITY_ACCESS;
+        request[1] = level.wrapping_add(1);
+        self.request(&request[..2 + key_len], &mut response)?;
+
+        Ok(())
+    }
+
+    /// Sends a tester present request, without response, once per keep-alive period
+    /// to keep a non-default session open. Call it regularly with the current time
+    /// in ms.
+    pub fn poll_keep_alive(&mut self, now_ms: u32) -> Result<(), UdsError> {
+        if self.session == Session::Default {
+            return Ok(());
+        }
+        if let Some(last) = self.last_keep_alive_ms {
+            if now_ms.wrapping_sub(last) < self.keep_alive_ms {
+                return Ok(());
+            }
+        }
+
+        self.transport
+            .send(&[TESTER_PRESENT, SUPPRESS_POSITIVE_RESPONSE])?;
+        self.last_keep_alive_ms = Some(now_ms);
+
+        Ok(())
+    }
+}
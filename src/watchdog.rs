@@ -0,0 +1,108 @@
+//! Supervision of remote nodes by the traffic they send.
+
+use embedded_can::Id;
+
+use crate::frame::CanFrame;
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+struct Watched {
+    id: Id,
+    max_silence_ms: u32,
+    /// Time the node was last heard of, set on the first poll
+    last_ms: Option<u32>,
+    missing: bool,
+}
+
+/// Watches up to `N` identifiers, each expected at least once per its maximum
+/// silence interval, e.g. the cyclic status frame of a node.
+///
+/// Feed it every received frame with [NodeWatchdog::on_frame] and poll it from a
+/// timer tick with [NodeWatchdog::poll]. A node never heard of counts as missing
+/// once its interval has elapsed from the first poll.
+pub struct NodeWatchdog<const N: usize> {
+    nodes: [Option<Watched>; N],
+}
+
+impl<const N: usize> Default for NodeWatchdog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> NodeWatchdog<N> {
+    pub const fn new() -> Self {
+        Self { nodes: [None; N] }
+    }
+
+    /// Expects a frame with identifier `id` at least every `max_silence_ms`, and
+    /// returns its slot.
+    pub fn watch(&mut self, id: impl Into<Id>, max_silence_ms: u32) -> Result<usize, WatchdogFull> {
+        let slot = self
+            .nodes
+            .iter()
+            .position(Option::is_none)
+            .ok_or(WatchdogFull)?;
+        self.nodes[slot] = Some(Watched {
+            id: id.into(),
+            max_silence_ms,
+            last_ms: None,
+            missing: false,
+        });
+
+        Ok(slot)
+    }
+
+    pub fn unwatch(&mut self, slot: usize) {
+        self.nodes[slot] = None;
+    }
+
+    /// Whether the node in `slot` is currently missing.
+    pub fn is_missing(&self, slot: usize) -> bool {
+        self.nodes[slot].is_some_and(|node| node.missing)
+    }
+
+    /// Handles a received frame, returning [WatchdogEvent::NodeRecovered] if it
+    /// comes from a missing node.
+    pub fn on_frame(&mut self, frame: &CanFrame, now_ms: u32) -> Option<WatchdogEvent> {
+        let node = self
+            .nodes
+            .iter_mut()
+            .flatten()
+            .find(|node| node.id == *frame.id())?;
+
+        node.last_ms = Some(now_ms);
+        match core::mem::take(&mut node.missing) {
+            true => Some(WatchdogEvent::NodeRecovered(node.id)),
+            false => None,
+        }
+    }
+
+    /// Returns [WatchdogEvent::NodeMissing] for a node silent for too long, once
+    /// until it recovers. Call it until it returns `None`.
+    pub fn poll(&mut self, now_ms: u32) -> Option<WatchdogEvent> {
+        let node = self.nodes.iter_mut().flatten().find_map(|node| {
+            let last_ms = *node.last_ms.get_or_insert(now_ms);
+            let silent = !node.missing && now_ms.wrapping_sub(last_ms) > node.max_silence_ms;
+            silent.then_some(node)
+        })?;
+
+        node.missing = true;
+        Some(WatchdogEvent::NodeMissing(node.id))
+    }
+}
+
+/// Error returned by [NodeWatchdog::watch] when all slots are in use.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub struct WatchdogFull;
+
+/// Change of a node watched by [NodeWatchdog].
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum WatchdogEvent {
+    /// Nothing was received with this identifier for longer than allowed
+    NodeMissing(Id),
+    /// A frame was received again with the identifier of a missing node
+    NodeRecovered(Id),
+}
@@ -0,0 +1,159 @@
+//! Minimal XCP on CAN slave, letting calibration tools read variables of a running
+//! device by polling memory.
+//!
+//! Supports CONNECT, DISCONNECT, GET_STATUS, SYNCH, SET_MTA, UPLOAD and SHORT_UPLOAD,
+//! with byte granularity, Intel byte order and 8-byte packets. DAQ lists,
+//! calibration and programming are not supported.
+
+use embedded_can::Id;
+
+use crate::frame::CanFrame;
+
+pub const CONNECT: u8 = 0xFF;
+pub const DISCONNECT: u8 = 0xFE;
+pub const GET_STATUS: u8 = 0xFD;
+pub const SYNCH: u8 = 0xFC;
+pub const SET_MTA: u8 = 0xF6;
+pub const UPLOAD: u8 = 0xF5;
+pub const SHORT_UPLOAD: u8 = 0xF4;
+
+const POSITIVE_RESPONSE: u8 = 0xFF;
+const ERROR_RESPONSE: u8 = 0xFE;
+
+/// Longest command and response packets.
+const MAX_CTO: u8 = 8;
+const PROTOCOL_LAYER_VERSION: u8 = 1;
+const TRANSPORT_LAYER_VERSION: u8 = 1;
+
+/// Error codes of the error responses.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(any(test, not(feature = "minimal")), derive(Debug))]
+pub enum XcpError {
+    /// Answer to SYNCH
+    CommandSynch = 0x00,
+    CommandUnknown = 0x20,
+    CommandSyntax = 0x21,
+    OutOfRange = 0x22,
+    AccessDenied = 0x24,
+}
+
+/// Memory of the device, read by the master.
+pub trait XcpMemory {
+    /// Fills `data` from `address` in the address space `extension`, returning
+    /// `false` if it can't be read.
+    fn read(&mut self, extension: u8, address: u32, data: &mut [u8]) -> bool;
+}
+
+/// XCP slave answering the commands of one master.
+pub struct XcpSlave {
+    command_id: Id,
+    response_id: Id,
+    connected: bool,
+    /// Memory transfer address: extension and address of the next UPLOAD
+    mta: (u8, u32),
+}
+
+impl XcpSlave {
+    /// Receives commands on `command_id` and responds on `response_id`.
+    pub fn new(command_id: impl Into<Id>, response_id: impl Into<Id>) -> Self {
+        Self {
+            command_id: command_id.into(),
+            response_id: response_id.into(),
+            connected: false,
+            mta: (0, 0),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Handles a received frame, returning the response to a command addressed to
+    /// this slave. Only CONNECT is answered until the master connects.
+    pub fn on_frame(&mut self, frame: &CanFrame, memory: &mut impl XcpMemory) -> Option<CanFrame> {
+        if *frame.id() != self.command_id {
+            return None;
+        }
+        let packet = &frame.data()[..frame.dlc()];
+        let (&command, args) = packet.split_first()?;
+        if !self.connected && command != CONNECT {
+            return None;
+        }
+
+        let mut response = [POSITIVE_RESPONSE, 0, 0, 0, 0, 0, 0, 0];
+        let result = match command {
+            CONNECT => {
+                self.connected = true;
+                // No resources, Intel byte order and byte granularity, then MAX_DTO
+                response[1..8].copy_from_slice(&[
+                    0,
+                    0,
+                    MAX_CTO,
+                    MAX_CTO,
+                    0,
+                    PROTOCOL_LAYER_VERSION,
+                    TRANSPORT_LAYER_VERSION,
+                ]);
+                Ok(8)
+            }
+            DISCONNECT => {
+                self.connected = false;
+                Ok(1)
+            }
+            // Session status, resource protection and session configuration ID all 0
+            GET_STATUS => Ok(6),
+            SYNCH => Err(XcpError::CommandSynch),
+            SET_MTA => match args {
+                [_, _, extension, a0, a1, a2, a3, ..] => {
+                    self.mta = (*extension, u32::from_le_bytes([*a0, *a1, *a2, *a3]));
+                    Ok(1)
+                }
+                _ => Err(XcpError::CommandSyntax),
+            },
+            UPLOAD => match args {
+                [len, ..] => {
+                    let (extension, address) = self.mta;
+                    self.upload(memory, extension, address, *len, &mut response)
+                }
+                _ => Err(XcpError::CommandSyntax),
+            },
+            SHORT_UPLOAD => match args {
+                [len, _, extension, a0, a1, a2, a3, ..] => {
+                    let address = u32::from_le_bytes([*a0, *a1, *a2, *a3]);
+                    self.upload(memory, *extension, address, *len, &mut response)
+                }
+                _ => Err(XcpError::CommandSyntax),
+            },
+            _ => Err(XcpError::CommandUnknown),
+        };
+
+        let response = match result {
+            Ok(len) => &response[..len],
+            Err(error) => &[ERROR_RESPONSE, error as u8][..],
+        };
+        CanFrame::new(self.response_id, response)
+    }
+
+    /// Reads `len` bytes into the response after its packet identifier and moves
+    /// the MTA past them.
+    fn upload(
+        &mut self,
+        memory: &mut impl XcpMemory,
+        extension: u8,
+        address: u32,
+        len: u8,
+        response: &mut [u8; 8],
+    ) -> Result<usize, XcpError> {
+        if len == 0 || len >= MAX_CTO {
+            return Err(XcpError::OutOfRange);
+        }
+
+        let len = len as usize;
+        if !memory.read(extension, address, &mut response[1..=len]) {
+            return Err(XcpError::AccessDenied);
+        }
+        self.mta = (extension, address.wrapping_add(len as u32));
+
+        Ok(1 + len)
+    }
+}